@@ -9,9 +9,9 @@ use std::io::BufRead;
 use std::io::Lines;
 use std::mem;
 use tokio_io::codec::Decoder;
-use eventstore_tcp::codec::PackageCodec;
+use eventstore_tcp::codec::{PackageCodec, redact_credentials};
 use eventstore_tcp::package::Package;
-use hex::FromHex;
+use hex::{FromHex, ToHex};
 use regex::Regex;
 
 fn main() {
@@ -35,16 +35,18 @@ fn try_main<R: BufRead>(reader: &mut R) -> io::Result<()> {
     Ok(())
 }
 
+/// The frames are echoed back as hex with the username and password replaced by `*`, so that the
+/// output can be shared.
 #[derive(Debug)]
 enum Decoding {
     /// The whole input was decoded into single package
-    Full { bytes: usize, package: Package },
+    Full { bytes: usize, frame: String, package: Package },
     /// There were unused bytes left undecoded
-    Partial { unused_bytes: usize, package: Package },
+    Partial { unused_bytes: usize, frame: String, package: Package },
     /// Not enough bytes
     Underflow,
     /// There was some error
-    Failure(io::Error),
+    Failure { frame: String, error: io::Error },
 }
 
 struct DecodingIterator<B: BufRead> {
@@ -85,6 +87,11 @@ impl<B: BufRead> Iterator for DecodingIterator<B> {
 
                     if !continues {
                         let combined_len = combined.len();
+
+                        let mut redacted = combined.clone();
+                        redact_credentials(&mut redacted);
+                        let frame = redacted.to_hex();
+
                         let mut buf = mem::replace(&mut combined, Vec::new()).into();
                         let res = PackageCodec.decode(&mut buf);
 
@@ -93,13 +100,13 @@ impl<B: BufRead> Iterator for DecodingIterator<B> {
                                 let len = buf.len();
 
                                 if len > 0 {
-                                    Decoding::Partial { unused_bytes: len, package: pkg }
+                                    Decoding::Partial { unused_bytes: len, frame: frame, package: pkg }
                                 } else {
-                                    Decoding::Full { bytes: combined_len, package: pkg }
+                                    Decoding::Full { bytes: combined_len, frame: frame, package: pkg }
                                 }
                             },
                             Ok(None) => Decoding::Underflow,
-                            Err(e) => Decoding::Failure(e),
+                            Err(e) => Decoding::Failure { frame: frame, error: e },
                         }));
                     }
                 },
@@ -218,11 +225,32 @@ mod tests {
         let mut decoder = DecodingIterator::new(BufReader::new(&input[..]));
 
         match decoder.next() {
-            Some(Ok(Decoding::Failure(_))) => { /* good */ },
+            Some(Ok(Decoding::Failure { .. })) => { /* good */ },
             x => panic!("unexpected value: {:?}", x),
         }
 
         let next = decoder.next();
         assert!(next.is_none(), "unexpected value: {:?}", next);
     }
+
+    #[test]
+    fn credentials_are_redacted() {
+        use std::io::BufReader;
+
+        // ping authenticated as "admin" with the password "changeit"
+        let input = b"0x21, 0x00, 0x00, 0x00, 0x03, 0x01, 0x61, 0xde,
+    0x39, 0x7d, 0x31, 0x4f, 0x69, 0x40, 0x86, 0xd2,
+    0xcb, 0x38, 0x33, 0x94, 0x8b, 0xcc, 0x05, 0x61,
+    0x64, 0x6d, 0x69, 0x6e, 0x08, 0x63, 0x68, 0x61,
+    0x6e, 0x67, 0x65, 0x69, 0x74";
+
+        let mut decoder = DecodingIterator::new(BufReader::new(&input[..]));
+
+        match decoder.next() {
+            Some(Ok(Decoding::Full { ref frame, .. })) => {
+                assert!(frame.ends_with("052a2a2a2a2a082a2a2a2a2a2a2a2a"), "unexpected frame: {}", frame);
+            },
+            x => panic!("unexpected value: {:?}", x),
+        }
+    }
 }
@@ -0,0 +1,36 @@
+/* C declarations of the `ffi` module of eventstore-tcp, see src/ffi.rs for the documentation. */
+
+#ifndef EVENTSTORE_TCP_H
+#define EVENTSTORE_TCP_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct {
+    uint8_t command;
+    uint8_t authenticated;
+    uint8_t correlation_id[16];
+    uint8_t *payload;
+    size_t payload_len;
+    char *description;
+} EventStorePackage;
+
+/* 1 with *out and *consumed set, 0 for an incomplete frame, -1 for an invalid one */
+int32_t eventstore_decode_package(const uint8_t *buf, size_t len, EventStorePackage **out, size_t *consumed);
+
+/* 0 with *out and *out_len set, -1 for an invalid payload */
+int32_t eventstore_encode_package(uint8_t command, const uint8_t *correlation_id, const uint8_t *payload, size_t payload_len, uint8_t **out, size_t *out_len);
+
+void eventstore_free_package(EventStorePackage *package);
+
+void eventstore_free_buffer(uint8_t *buf, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
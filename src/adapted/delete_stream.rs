@@ -0,0 +1,96 @@
+use std::fmt;
+use std::error::Error;
+use raw::client_messages::{OperationResult};
+use LogPosition;
+
+/// Successful response to `Message::DeleteStream`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeleteStreamCompleted {
+    /// Position of the tombstone or the new metadata of the stream in `$all`, if returned
+    pub prepare_position: Option<LogPosition>,
+
+    /// Commit position of the deletion in `$all`, if returned
+    pub commit_position: Option<LogPosition>,
+}
+
+/// Like `OperationResult` on the wire but does not have a success value. Explains the reason for
+/// failure to delete a stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum DeleteStreamFailure {
+    /// Server failed to process the request before timeout
+    PrepareTimeout,
+    /// Server timed out while awaiting commit to be processed
+    CommitTimeout,
+    /// Server timed out while awaiting for a forwarded request to complete
+    ForwardTimeout,
+    /// Optimistic locking failure; stream version was not the expected
+    WrongExpectedVersion,
+    /// Stream has already been hard deleted
+    StreamDeleted,
+    /// No authentication provided or insufficient permissions to a stream
+    AccessDenied,
+}
+
+impl DeleteStreamFailure {
+    /// Return `true` if the operation failed in a transient way that might be resolved by
+    /// retrying.
+    pub fn is_transient(&self) -> bool {
+        use self::DeleteStreamFailure::*;
+        match *self {
+            PrepareTimeout | CommitTimeout | ForwardTimeout => true,
+            _ => false
+        }
+    }
+}
+
+impl From<OperationResult> for DeleteStreamFailure {
+    fn from(or: OperationResult) -> Self {
+        use self::OperationResult::*;
+
+        match or {
+            Success => unreachable!(),
+            InvalidTransaction => unreachable!(),
+            PrepareTimeout => DeleteStreamFailure::PrepareTimeout,
+            CommitTimeout => DeleteStreamFailure::CommitTimeout,
+            ForwardTimeout => DeleteStreamFailure::ForwardTimeout,
+            WrongExpectedVersion => DeleteStreamFailure::WrongExpectedVersion,
+            StreamDeleted => DeleteStreamFailure::StreamDeleted,
+            AccessDenied => DeleteStreamFailure::AccessDenied,
+        }
+    }
+}
+
+impl Into<OperationResult> for DeleteStreamFailure {
+    fn into(self) -> OperationResult {
+        use self::DeleteStreamFailure::*;
+        match self {
+            PrepareTimeout => OperationResult::PrepareTimeout,
+            CommitTimeout => OperationResult::CommitTimeout,
+            ForwardTimeout => OperationResult::ForwardTimeout,
+            WrongExpectedVersion => OperationResult::WrongExpectedVersion,
+            StreamDeleted => OperationResult::StreamDeleted,
+            AccessDenied => OperationResult::AccessDenied
+        }
+    }
+}
+
+impl fmt::Display for DeleteStreamFailure {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.description())
+    }
+}
+
+impl Error for DeleteStreamFailure {
+    fn description(&self) -> &str {
+        use self::DeleteStreamFailure::*;
+        match *self {
+            PrepareTimeout => "Internal server timeout, should be retried",
+            CommitTimeout => "Internal server timeout, should be retried",
+            ForwardTimeout => "Server timed out while awaiting response to forwarded request, should be retried",
+            WrongExpectedVersion => "Stream version was not expected, optimistic locking failure",
+            StreamDeleted => "Stream had already been deleted",
+            AccessDenied => "Access to stream was denied"
+        }
+    }
+}
@@ -5,14 +5,21 @@ use std::convert::{TryFrom, From};
 use std::borrow::Cow;
 use std::ops::Range;
 use errors::{Error, ErrorKind, ResultStatusKind};
-use {CustomTryFrom, CustomTryInto, ReadDirection, EventNumber, StreamVersion, LogPosition};
-//use client_messages::mod_NotHandled::{NotHandledReason, MasterInfo};
+use {CustomTryFrom, CustomTryInto, ReadDirection, EventNumber, StreamVersion, LogPosition, TransactionId};
+use uuid::Uuid;
 use raw;
 use raw::client_messages::{WriteEvents, ResolvedIndexedEvent};
+use package::Package;
 
 mod write_events;
 pub use self::write_events::{WriteEventsCompleted, WriteEventsFailure};
 
+mod delete_stream;
+pub use self::delete_stream::{DeleteStreamCompleted, DeleteStreamFailure};
+
+mod transaction;
+pub use self::transaction::TransactionFailure;
+
 mod read_event;
 pub use self::read_event::{ReadEventError};
 
@@ -20,7 +27,17 @@ mod read_stream;
 pub use self::read_stream::{ReadStreamCompleted, ReadStreamError};
 
 mod read_all;
-pub use self::read_all::{ReadAllCompleted, ReadAllError};
+pub use self::read_all::{ReadAllCompleted, ReadAllError, ResolvedEvent};
+
+mod persistent_subscription;
+pub use self::persistent_subscription::{CreatePersistentSubscriptionFailure, UpdatePersistentSubscriptionFailure};
+
+mod subscription;
+pub use self::subscription::{SubscriptionConfirmation, PersistentSubscriptionConfirmation};
+
+mod not_handled;
+pub use self::not_handled::NotHandledInfo;
+pub use raw::SubscriptionDropReason;
 
 /// Enumeration of converted messages for more oxidized API. Unlike the `RawMessage` variants,
 /// `AdaptedMessage` variants are validated and converted into nicer API. This validation comes at
@@ -42,6 +59,27 @@ pub enum AdaptedMessage<'a> {
     /// Append to stream response, which can fail for a number of reasons
     WriteEventsCompleted(Result<WriteEventsCompleted, WriteEventsFailure>),
 
+    /// Request to delete a stream, soft or hard
+    DeleteStream(raw::client_messages::DeleteStream<'a>),
+    /// Response to a stream deletion, which can fail like appends
+    DeleteStreamCompleted(Result<DeleteStreamCompleted, DeleteStreamFailure>),
+
+    /// Request to start a transaction on a stream
+    TransactionStart(raw::client_messages::TransactionStart<'a>),
+    /// Response to a transaction start, with the id of the started transaction
+    TransactionStartCompleted(Result<TransactionId, TransactionFailure>),
+
+    /// Request to write events within a transaction
+    TransactionWrite(raw::client_messages::TransactionWrite<'a>),
+    /// Response to a write within the transaction
+    TransactionWriteCompleted(TransactionId, Result<(), TransactionFailure>),
+
+    /// Request to commit the events written within a transaction
+    TransactionCommit(raw::client_messages::TransactionCommit),
+    /// Response to a commit of the transaction, with the event numbers and positions of the
+    /// committed events like for appends
+    TransactionCommitCompleted(TransactionId, Result<WriteEventsCompleted, TransactionFailure>),
+
     /// Request to read a single event from a stream
     ReadEvent(raw::client_messages::ReadEvent<'a>),
     /// Response to a single event read
@@ -57,6 +95,34 @@ pub enum AdaptedMessage<'a> {
     /// Response to a read all in given direction
     ReadAllEventsCompleted(ReadDirection, Result<ReadAllCompleted<'a>, ReadAllError<'a>>),
 
+    /// Request for a volatile subscription to a stream, or to all streams
+    SubscribeToStream(raw::client_messages::SubscribeToStream<'a>),
+    /// Response to a subscription request, after which the events are pushed to the subscriber
+    SubscriptionConfirmation(SubscriptionConfirmation),
+    /// Event appended to the subscribed stream, with its position in `$all`
+    StreamEventAppeared(ResolvedEvent<'a>),
+    /// Request to end the subscription started with the same correlation id
+    UnsubscribeFromStream,
+    /// The subscription has ended, either after `UnsubscribeFromStream` or for the given reason.
+    /// Nothing is pushed to the subscriber after this.
+    SubscriptionDropped(SubscriptionDropReason),
+
+    /// Request to connect as a consumer of a persistent subscription group
+    ConnectToPersistentSubscription(raw::client_messages::ConnectToPersistentSubscription<'a>),
+    /// Response to a connection to a persistent subscription, after which the events of the group
+    /// are pushed to the consumer. Nothing is pushed if the group does not exist, the consumer
+    /// gets a `SubscriptionDropped` instead.
+    PersistentSubscriptionConfirmation(PersistentSubscriptionConfirmation<'a>),
+
+    /// Request to create a persistent subscription group on a stream
+    CreatePersistentSubscription(raw::client_messages::CreatePersistentSubscription<'a>),
+    /// Response to the creation of a persistent subscription group
+    CreatePersistentSubscriptionCompleted(Result<(), CreatePersistentSubscriptionFailure<'a>>),
+    /// Request to replace the settings of an existing persistent subscription group
+    UpdatePersistentSubscription(raw::client_messages::UpdatePersistentSubscription<'a>),
+    /// Response to the update of a persistent subscription group
+    UpdatePersistentSubscriptionCompleted(Result<(), UpdatePersistentSubscriptionFailure<'a>>),
+
     /// Request was not understood. Please open an issue!
     BadRequest(BadRequestMessage<'a>),
 
@@ -73,7 +139,13 @@ pub enum AdaptedMessage<'a> {
 
     /// Negative authentication response, or response to any sent request for which used
     /// authentication was not accepted.
-    NotAuthenticated(NotAuthenticatedMessage<'a>)
+    NotAuthenticated(NotAuthenticatedMessage<'a>),
+
+    /// Request to identify the connection with a name and client version.
+    IdentifyClient(raw::client_messages::IdentifyClient<'a>),
+
+    /// Positive response to `IdentifyClient`.
+    ClientIdentified,
 }
 
 impl<'a> CustomTryFrom<raw::RawMessage<'a>> for AdaptedMessage<'a> {
@@ -112,17 +184,38 @@ impl<'a> CustomTryFrom<raw::RawMessage<'a>> for AdaptedMessage<'a> {
             RawMessage::Pong                              => Ok(AdaptedMessage::Pong),
             RawMessage::WriteEvents(e)                    => into_or_rebuild!(e),
             RawMessage::WriteEventsCompleted(e)           => into_or_rebuild!(e),
+            RawMessage::DeleteStream(e)                   => into_or_rebuild!(e),
+            RawMessage::DeleteStreamCompleted(e)          => into_or_rebuild!(e),
+            RawMessage::TransactionStart(e)               => into_or_rebuild!(e),
+            RawMessage::TransactionStartCompleted(e)      => into_or_rebuild!(e),
+            RawMessage::TransactionWrite(e)               => into_or_rebuild!(e),
+            RawMessage::TransactionWriteCompleted(e)      => into_or_rebuild!(e),
+            RawMessage::TransactionCommit(e)              => into_or_rebuild!(e),
+            RawMessage::TransactionCommitCompleted(e)     => into_or_rebuild!(e),
             RawMessage::ReadEvent(e)                      => into_or_rebuild!(e),
             RawMessage::ReadEventCompleted(e)             => into_or_rebuild!(e),
             RawMessage::ReadStreamEvents(dir, e)          => into_or_rebuild!((dir, e)),
             RawMessage::ReadStreamEventsCompleted(dir, e) => into_or_rebuild!((dir, e)),
             RawMessage::ReadAllEvents(dir, e)             => into_or_rebuild!((dir, e)),
             RawMessage::ReadAllEventsCompleted(dir, e)    => into_or_rebuild!((dir, e)),
+            RawMessage::SubscribeToStream(e)              => Ok(AdaptedMessage::SubscribeToStream(e)),
+            RawMessage::SubscriptionConfirmation(e)       => into_or_rebuild!(e),
+            RawMessage::StreamEventAppeared(e)            => into_or_rebuild!(e),
+            RawMessage::UnsubscribeFromStream             => Ok(AdaptedMessage::UnsubscribeFromStream),
+            RawMessage::SubscriptionDropped(e)            => Ok(AdaptedMessage::SubscriptionDropped(e.reason)),
+            RawMessage::ConnectToPersistentSubscription(e) => Ok(AdaptedMessage::ConnectToPersistentSubscription(e)),
+            RawMessage::PersistentSubscriptionConfirmation(e) => into_or_rebuild!(e),
+            RawMessage::CreatePersistentSubscription(e)   => Ok(AdaptedMessage::CreatePersistentSubscription(e)),
+            RawMessage::CreatePersistentSubscriptionCompleted(e) => Ok(create_persistent_subscription_completed(e)),
+            RawMessage::UpdatePersistentSubscription(e)   => Ok(AdaptedMessage::UpdatePersistentSubscription(e)),
+            RawMessage::UpdatePersistentSubscriptionCompleted(e) => Ok(update_persistent_subscription_completed(e)),
             RawMessage::BadRequest(bytes)                 => into_str_or_rebuild!(bytes, BadRequestMessage::from),
             RawMessage::NotHandled(e)                     => into_or_rebuild!(e),
             RawMessage::Authenticate                      => Ok(AdaptedMessage::Authenticate),
             RawMessage::Authenticated                     => Ok(AdaptedMessage::Authenticated),
             RawMessage::NotAuthenticated(reason)          => into_str_or_rebuild!(reason, NotAuthenticatedMessage::from),
+            RawMessage::IdentifyClient(e)                 => Ok(AdaptedMessage::IdentifyClient(e)),
+            RawMessage::ClientIdentified                  => Ok(AdaptedMessage::ClientIdentified),
             RawMessage::Unsupported(d, bytes)             => Err(((d, bytes).into(), ErrorKind::UnsupportedDiscriminator(d).into())),
             unimpl => Err((unimpl, ErrorKind::UnimplementedConversion.into())),
         }
@@ -144,6 +237,15 @@ impl<'a> AdaptedMessage<'a> {
             WriteEvents(ref we) => RawMessage::WriteEvents(we.clone()),
             WriteEventsCompleted(Ok(ref body)) => RawMessage::WriteEventsCompleted(body.as_raw()),
             WriteEventsCompleted(Err(ref err)) => RawMessage::WriteEventsCompleted(err.as_raw()),
+            DeleteStream(ref ds) => RawMessage::DeleteStream(ds.clone()),
+            DeleteStreamCompleted(Ok(ref body)) => RawMessage::DeleteStreamCompleted(body.as_raw()),
+            DeleteStreamCompleted(Err(ref err)) => RawMessage::DeleteStreamCompleted(err.as_raw()),
+            TransactionStart(ref ts) => RawMessage::TransactionStart(ts.clone()),
+            TransactionStartCompleted(ref res) => RawMessage::TransactionStartCompleted(transaction_start_completed(res)),
+            TransactionWrite(ref tw) => RawMessage::TransactionWrite(tw.clone()),
+            TransactionWriteCompleted(id, ref res) => RawMessage::TransactionWriteCompleted(transaction_write_completed(id, res)),
+            TransactionCommit(ref tc) => RawMessage::TransactionCommit(tc.clone()),
+            TransactionCommitCompleted(id, ref res) => RawMessage::TransactionCommitCompleted(transaction_commit_completed(id, res)),
             ReadEvent(ref re) => RawMessage::ReadEvent(re.clone()),
             ReadEventCompleted(Ok(ref event)) => RawMessage::ReadEventCompleted(event.as_raw()),
             ReadEventCompleted(Err(ref err)) => RawMessage::ReadEventCompleted(err.as_raw()),
@@ -153,26 +255,191 @@ impl<'a> AdaptedMessage<'a> {
             ReadAllEvents(ref dir, ref rae) => RawMessage::ReadAllEvents(*dir, rae.clone()),
             ReadAllEventsCompleted(ref dir, Ok(ref body)) => RawMessage::ReadAllEventsCompleted(*dir, body.as_raw()),
             ReadAllEventsCompleted(ref dir, Err(ref err)) => RawMessage::ReadAllEventsCompleted(*dir, err.as_raw()),
+            SubscribeToStream(ref sts) => RawMessage::SubscribeToStream(sts.clone()),
+            SubscriptionConfirmation(ref confirmation) => RawMessage::SubscriptionConfirmation(confirmation.as_raw()),
+            StreamEventAppeared(ref event) => RawMessage::StreamEventAppeared(event.as_raw()),
+            UnsubscribeFromStream => RawMessage::UnsubscribeFromStream,
+            SubscriptionDropped(reason) => RawMessage::SubscriptionDropped(reason.as_raw()),
+            ConnectToPersistentSubscription(ref ctps) => RawMessage::ConnectToPersistentSubscription(ctps.clone()),
+            PersistentSubscriptionConfirmation(ref confirmation) => RawMessage::PersistentSubscriptionConfirmation(confirmation.as_raw()),
+            CreatePersistentSubscription(ref cps) => RawMessage::CreatePersistentSubscription(cps.clone()),
+            CreatePersistentSubscriptionCompleted(Ok(())) => RawMessage::CreatePersistentSubscriptionCompleted(created()),
+            CreatePersistentSubscriptionCompleted(Err(ref err)) => RawMessage::CreatePersistentSubscriptionCompleted(err.as_raw()),
+            UpdatePersistentSubscription(ref ups) => RawMessage::UpdatePersistentSubscription(ups.clone()),
+            UpdatePersistentSubscriptionCompleted(Ok(())) => RawMessage::UpdatePersistentSubscriptionCompleted(updated()),
+            UpdatePersistentSubscriptionCompleted(Err(ref err)) => RawMessage::UpdatePersistentSubscriptionCompleted(err.as_raw()),
             BadRequest(ref msg) => RawMessage::BadRequest(msg.as_raw()),
             NotHandled(ref info) => RawMessage::NotHandled(info.as_raw()),
             NotAuthenticated(ref msg) => RawMessage::NotAuthenticated(msg.as_raw()),
+            IdentifyClient(ref ic) => RawMessage::IdentifyClient(ic.clone()),
+            ClientIdentified => RawMessage::ClientIdentified,
         }
     }
 }
 
-trait AsRawPayload<'a, 'b, P: 'b> {
-    fn as_raw(&'b self) -> P;
+/// Conversion of the adapted values back into the messages on the wire, for servers and proxies
+/// constructing responses from the same types the clients parse them into.
+///
+/// The generated protobuf structs used in between are an implementation detail which might
+/// change with the protocol definitions; implementing this trait keeps downstream crates away
+/// from them.
+///
+/// # Example
+///
+/// ```rust
+/// #![feature(try_from)]
+///
+/// use std::convert::TryFrom;
+/// use eventstore_tcp::{ToMessage, StreamVersion};
+/// use eventstore_tcp::adapted::WriteEventsCompleted;
+///
+/// let completed = WriteEventsCompleted {
+///     event_numbers: StreamVersion::try_from(0).unwrap()..StreamVersion::try_from(2).unwrap(),
+///     prepare_position: None,
+///     commit_position: None,
+/// };
+///
+/// assert_eq!(completed.to_message().discriminator(), 0x83);
+/// ```
+pub trait ToMessage {
+    /// Converts into the message on the wire, borrowing from `self` where possible.
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b>;
+
+    /// Converts into a response package correlated to the request with `correlation_id`. Borrowed
+    /// payloads are copied.
+    fn to_package(&self, correlation_id: Uuid) -> Package {
+        Package {
+            authentication: None,
+            correlation_id: correlation_id,
+            message: self.to_message().into_owned(),
+        }
+    }
+}
+
+impl<'a> ToMessage for AdaptedMessage<'a> {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        self.as_raw()
+    }
+}
+
+impl ToMessage for WriteEventsCompleted {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::WriteEventsCompleted(self.as_raw())
+    }
+}
+
+impl ToMessage for WriteEventsFailure {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::WriteEventsCompleted(self.as_raw())
+    }
+}
+
+impl ToMessage for DeleteStreamCompleted {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::DeleteStreamCompleted(self.as_raw())
+    }
+}
+
+impl ToMessage for DeleteStreamFailure {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::DeleteStreamCompleted(self.as_raw())
+    }
+}
+
+impl<'a> ToMessage for ReadEventError<'a> {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::ReadEventCompleted(self.as_raw())
+    }
+}
+
+impl<'a> ToMessage for NotHandledInfo<'a> {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::NotHandled(self.as_raw())
+    }
+}
+
+impl<'a> ToMessage for (ReadDirection, ReadStreamCompleted<'a>) {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::ReadStreamEventsCompleted(self.0, self.1.as_raw())
+    }
+}
+
+impl<'a> ToMessage for (ReadDirection, ReadStreamError<'a>) {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::ReadStreamEventsCompleted(self.0, self.1.as_raw())
+    }
+}
+
+impl<'a> ToMessage for (ReadDirection, ReadAllCompleted<'a>) {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::ReadAllEventsCompleted(self.0, self.1.as_raw())
+    }
+}
+
+impl<'a> ToMessage for (ReadDirection, ReadAllError<'a>) {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::ReadAllEventsCompleted(self.0, self.1.as_raw())
+    }
+}
+
+impl ToMessage for SubscriptionConfirmation {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::SubscriptionConfirmation(self.as_raw())
+    }
+}
+
+impl<'a> ToMessage for ResolvedEvent<'a> {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::StreamEventAppeared(self.as_raw())
+    }
+}
+
+impl<'a> ToMessage for PersistentSubscriptionConfirmation<'a> {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::PersistentSubscriptionConfirmation(self.as_raw())
+    }
+}
+
+impl ToMessage for SubscriptionDropReason {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        raw::RawMessage::SubscriptionDropped(self.as_raw())
+    }
+}
+
+impl<T: ToMessage, E: ToMessage> ToMessage for Result<T, E> {
+    fn to_message<'b>(&'b self) -> raw::RawMessage<'b> {
+        match *self {
+            Ok(ref x) => x.to_message(),
+            Err(ref e) => e.to_message(),
+        }
+    }
 }
 
-/// Placeholder for NotHandledInfo, unused.
-#[derive(Debug, PartialEq, Clone)]
-pub struct NotHandledInfo<'a> {
-    ph: &'a str
+trait AsRawPayload<'a, 'b, P: 'b> {
+    fn as_raw(&'b self) -> P;
 }
 
 impl<'a, 'b: 'a> AsRawPayload<'a, 'b, raw::client_messages::NotHandled<'b>> for NotHandledInfo<'a> {
     fn as_raw(&'b self) -> raw::client_messages::NotHandled<'b> {
-        unimplemented!()
+        use quick_protobuf::{MessageWrite, Writer};
+        use raw::client_messages::mod_NotHandled::NotHandledReason;
+
+        let (reason, info) = match *self {
+            NotHandledInfo::NotReady => (NotHandledReason::NotReady, None),
+            NotHandledInfo::TooBusy => (NotHandledReason::TooBusy, None),
+            NotHandledInfo::NotMaster(ref info) => (NotHandledReason::NotMaster, info.as_ref()),
+        };
+
+        let additional_info = info.map(|info| {
+            let mut buf = Vec::new();
+            info.write_message(&mut Writer::new(&mut buf)).expect("Writing to a Vec does not fail");
+            Cow::Owned(buf)
+        });
+
+        raw::client_messages::NotHandled {
+            reason: Some(reason),
+            additional_info: additional_info,
+        }
     }
 }
 
@@ -218,7 +485,36 @@ impl<'a> CustomTryFrom<raw::client_messages::NotHandled<'a>> for AdaptedMessage<
     type Err = Error;
 
     fn try_from(msg: raw::client_messages::NotHandled<'a>) -> Result<AdaptedMessage<'a>, (raw::client_messages::NotHandled<'a>, Self::Err)> {
-        Err((msg, ErrorKind::UnimplementedConversion.into()))
+        use raw::client_messages::mod_NotHandled::NotHandledReason;
+
+        let info = match msg.reason {
+            Some(NotHandledReason::NotReady) => NotHandledInfo::NotReady,
+            Some(NotHandledReason::TooBusy) => NotHandledInfo::TooBusy,
+            Some(NotHandledReason::NotMaster) => match master_info(&msg.additional_info) {
+                Ok(info) => NotHandledInfo::NotMaster(info),
+                Err(e) => return Err((msg, e)),
+            },
+            None => return Err((msg, ErrorKind::MissingResultField(ResultStatusKind::NotHandled).into())),
+        };
+
+        Ok(AdaptedMessage::NotHandled(info))
+    }
+}
+
+/// Decodes the endpoints of the master sent as the additional info of `NotMaster`.
+fn master_info<'a>(info: &Option<Cow<'a, [u8]>>) -> Result<Option<raw::client_messages::mod_NotHandled::MasterInfo<'a>>, Error> {
+    use quick_protobuf::BytesReader;
+    use raw::client_messages::mod_NotHandled::MasterInfo;
+
+    fn decode<'b>(bytes: &'b [u8]) -> Result<MasterInfo<'b>, Error> {
+        let mut reader = BytesReader::from_bytes(bytes);
+        MasterInfo::from_reader(&mut reader, bytes).map_err(|_| ErrorKind::InvalidMasterInfo.into())
+    }
+
+    match *info {
+        None => Ok(None),
+        Some(Cow::Borrowed(bytes)) => decode(bytes).map(Some),
+        Some(Cow::Owned(ref bytes)) => decode(bytes).map(|info| Some(info.into_owned())),
     }
 }
 
@@ -309,6 +605,198 @@ impl<'b> AsRawPayload<'static, 'b, raw::client_messages::WriteEventsCompleted<'b
     }
 }
 
+impl<'a> CustomTryFrom<raw::client_messages::DeleteStream<'a>> for AdaptedMessage<'a> {
+    type Err = Error;
+
+    fn try_from(msg: raw::client_messages::DeleteStream<'a>) -> Result<AdaptedMessage<'a>, (raw::client_messages::DeleteStream<'a>, Self::Err)> {
+        Ok(AdaptedMessage::DeleteStream(msg))
+    }
+}
+
+impl<'a> CustomTryFrom<raw::client_messages::DeleteStreamCompleted<'a>> for AdaptedMessage<'a> {
+    type Err = Error;
+
+    fn try_from(msg: raw::client_messages::DeleteStreamCompleted<'a>) -> Result<AdaptedMessage<'a>, (raw::client_messages::DeleteStreamCompleted<'a>, Self::Err)> {
+        use raw::client_messages::OperationResult::*;
+
+        let status = match msg.result {
+            Some(ref status) => status.clone(),
+            None => return Err((msg, ErrorKind::MissingResultField(ResultStatusKind::DeleteStream).into())),
+        };
+
+        let res = match status {
+            Success => Ok(DeleteStreamCompleted {
+                prepare_position: msg.prepare_position.map(|x| x.into()),
+                commit_position: msg.commit_position.map(|x| x.into()),
+            }),
+            InvalidTransaction => return Err((msg, ErrorKind::DeleteStreamInvalidTransaction.into())),
+            other => Err(other.into()),
+        };
+
+        Ok(AdaptedMessage::DeleteStreamCompleted(res))
+    }
+}
+
+impl<'b> AsRawPayload<'static, 'b, raw::client_messages::DeleteStreamCompleted<'b>> for DeleteStreamCompleted {
+    fn as_raw(&'b self) -> raw::client_messages::DeleteStreamCompleted<'b> {
+        raw::client_messages::DeleteStreamCompleted {
+            result: Some(raw::client_messages::OperationResult::Success),
+            message: None,
+            prepare_position: self.prepare_position.map(|x| x.into()),
+            commit_position: self.commit_position.map(|x| x.into()),
+        }
+    }
+}
+
+impl<'b> AsRawPayload<'static, 'b, raw::client_messages::DeleteStreamCompleted<'b>> for DeleteStreamFailure {
+    fn as_raw(&'b self) -> raw::client_messages::DeleteStreamCompleted<'b> {
+        raw::client_messages::DeleteStreamCompleted {
+            result: Some((*self).into()),
+            message: None,
+            prepare_position: None,
+            commit_position: None,
+        }
+    }
+}
+
+impl<'a> CustomTryFrom<raw::client_messages::TransactionStart<'a>> for AdaptedMessage<'a> {
+    type Err = Error;
+
+    fn try_from(msg: raw::client_messages::TransactionStart<'a>) -> Result<AdaptedMessage<'a>, (raw::client_messages::TransactionStart<'a>, Self::Err)> {
+        Ok(AdaptedMessage::TransactionStart(msg))
+    }
+}
+
+impl<'a> CustomTryFrom<raw::client_messages::TransactionStartCompleted<'a>> for AdaptedMessage<'a> {
+    type Err = Error;
+
+    fn try_from(msg: raw::client_messages::TransactionStartCompleted<'a>) -> Result<AdaptedMessage<'a>, (raw::client_messages::TransactionStartCompleted<'a>, Self::Err)> {
+        use raw::client_messages::OperationResult::*;
+
+        let status = match msg.result {
+            Some(ref status) => status.clone(),
+            None => return Err((msg, ErrorKind::MissingResultField(ResultStatusKind::TransactionStart).into())),
+        };
+
+        Ok(AdaptedMessage::TransactionStartCompleted(match status {
+            Success => Ok(TransactionId::from(msg.transaction_id)),
+            other => Err(other.into()),
+        }))
+    }
+}
+
+impl<'a> CustomTryFrom<raw::client_messages::TransactionWrite<'a>> for AdaptedMessage<'a> {
+    type Err = Error;
+
+    fn try_from(msg: raw::client_messages::TransactionWrite<'a>) -> Result<AdaptedMessage<'a>, (raw::client_messages::TransactionWrite<'a>, Self::Err)> {
+        Ok(AdaptedMessage::TransactionWrite(msg))
+    }
+}
+
+impl<'a> CustomTryFrom<raw::client_messages::TransactionWriteCompleted<'a>> for AdaptedMessage<'a> {
+    type Err = Error;
+
+    fn try_from(msg: raw::client_messages::TransactionWriteCompleted<'a>) -> Result<AdaptedMessage<'a>, (raw::client_messages::TransactionWriteCompleted<'a>, Self::Err)> {
+        use raw::client_messages::OperationResult::*;
+
+        let status = match msg.result {
+            Some(ref status) => status.clone(),
+            None => return Err((msg, ErrorKind::MissingResultField(ResultStatusKind::TransactionWrite).into())),
+        };
+
+        Ok(AdaptedMessage::TransactionWriteCompleted(TransactionId::from(msg.transaction_id), match status {
+            Success => Ok(()),
+            other => Err(other.into()),
+        }))
+    }
+}
+
+impl<'a> CustomTryFrom<raw::client_messages::TransactionCommit> for AdaptedMessage<'a> {
+    type Err = Error;
+
+    fn try_from(msg: raw::client_messages::TransactionCommit) -> Result<AdaptedMessage<'a>, (raw::client_messages::TransactionCommit, Self::Err)> {
+        Ok(AdaptedMessage::TransactionCommit(msg))
+    }
+}
+
+impl<'a> CustomTryFrom<raw::client_messages::TransactionCommitCompleted<'a>> for AdaptedMessage<'a> {
+    type Err = Error;
+
+    fn try_from(msg: raw::client_messages::TransactionCommitCompleted<'a>) -> Result<AdaptedMessage<'a>, (raw::client_messages::TransactionCommitCompleted<'a>, Self::Err)> {
+        use raw::client_messages::OperationResult::*;
+
+        let status = match msg.result {
+            Some(ref status) => status.clone(),
+            None => return Err((msg, ErrorKind::MissingResultField(ResultStatusKind::TransactionCommit).into())),
+        };
+
+        let res = match status {
+            Success => {
+                let range = match range_from_parts(msg.first_event_number, msg.last_event_number) {
+                    Ok(x) => x,
+                    Err(e) => return Err((msg, e)),
+                };
+                Ok(WriteEventsCompleted {
+                    event_numbers: range,
+                    prepare_position: msg.prepare_position.map(|x| x.into()),
+                    commit_position: msg.commit_position.map(|x| x.into()),
+                })
+            },
+            other => Err(other.into()),
+        };
+
+        Ok(AdaptedMessage::TransactionCommitCompleted(TransactionId::from(msg.transaction_id), res))
+    }
+}
+
+/// The result of a transaction response on the wire.
+fn transaction_result<T>(res: &Result<T, TransactionFailure>) -> raw::client_messages::OperationResult {
+    match *res {
+        Ok(_) => raw::client_messages::OperationResult::Success,
+        Err(failure) => failure.into(),
+    }
+}
+
+fn transaction_start_completed(res: &Result<TransactionId, TransactionFailure>) -> raw::client_messages::TransactionStartCompleted<'static> {
+    raw::client_messages::TransactionStartCompleted {
+        // failed starts have no transaction
+        transaction_id: match *res {
+            Ok(id) => id.into(),
+            Err(_) => -1,
+        },
+        result: Some(transaction_result(res)),
+        message: None,
+    }
+}
+
+fn transaction_write_completed(id: TransactionId, res: &Result<(), TransactionFailure>) -> raw::client_messages::TransactionWriteCompleted<'static> {
+    raw::client_messages::TransactionWriteCompleted {
+        transaction_id: id.into(),
+        result: Some(transaction_result(res)),
+        message: None,
+    }
+}
+
+fn transaction_commit_completed(id: TransactionId, res: &Result<WriteEventsCompleted, TransactionFailure>) -> raw::client_messages::TransactionCommitCompleted<'static> {
+    let (first, last, prepare_position, commit_position) = match *res {
+        Ok(ref completed) => {
+            let parts = range_to_parts(&completed.event_numbers);
+            (parts.0, parts.1, completed.prepare_position.map(|x| x.into()), completed.commit_position.map(|x| x.into()))
+        },
+        Err(_) => (-1, -1, None, None),
+    };
+
+    raw::client_messages::TransactionCommitCompleted {
+        transaction_id: id.into(),
+        result: Some(transaction_result(res)),
+        message: None,
+        first_event_number: first,
+        last_event_number: last,
+        prepare_position: prepare_position,
+        commit_position: commit_position,
+    }
+}
+
 impl<'a> CustomTryFrom<raw::client_messages::ReadEvent<'a>> for AdaptedMessage<'a> {
     type Err = Error;
 
@@ -419,6 +907,14 @@ impl<'a> CustomTryFrom<(ReadDirection, raw::client_messages::ReadStreamEventsCom
             return Err(((dir, msg), ErrorKind::MissingResultField(ResultStatusKind::ReadStream).into()));
         }
 
+        // clone to avoid borrowing it
+        let result = msg.result.as_ref().unwrap().clone();
+
+        if result != ReadStreamResult::Success {
+            // failures carry no meaningful event numbers
+            return Ok(AdaptedMessage::ReadStreamEventsCompleted(dir, Err((result, msg.error).into())));
+        }
+
         let next_page = if dir == ReadDirection::Backward && msg.next_event_number < 0 {
             None
         } else {
@@ -433,24 +929,14 @@ impl<'a> CustomTryFrom<(ReadDirection, raw::client_messages::ReadStreamEventsCom
             Err(e) => return Err( ((dir, msg), e) ),
         };
 
-        // clone to avoid borrowing it
-        let result = msg.result.as_ref().unwrap().clone();
-
-        match result {
-            ReadStreamResult::Success => {
-                Ok(AdaptedMessage::ReadStreamEventsCompleted(dir, Ok(ReadStreamCompleted {
-                    events: msg.events,
-                    next_page: next_page,
-                    last_event_number: last_event_number,
-                    end_of_stream: msg.is_end_of_stream,
-                    // TODO: use LogPosition
-                    last_commit_position: msg.last_commit_position,
-                })))
-            },
-            other => {
-                Ok(AdaptedMessage::ReadStreamEventsCompleted(dir, Err((other, msg.error).into())))
-            }
-        }
+        Ok(AdaptedMessage::ReadStreamEventsCompleted(dir, Ok(ReadStreamCompleted {
+            events: msg.events,
+            next_page: next_page,
+            last_event_number: last_event_number,
+            end_of_stream: msg.is_end_of_stream,
+            // TODO: use LogPosition
+            last_commit_position: msg.last_commit_position,
+        })))
     }
 }
 
@@ -537,13 +1023,245 @@ impl<'a> CustomTryFrom<(ReadDirection, raw::client_messages::ReadAllEventsComple
 
 impl<'a, 'b: 'a> AsRawPayload<'a, 'b, raw::client_messages::ReadAllEventsCompleted<'b>> for ReadAllCompleted<'a> {
     fn as_raw(&'b self) -> raw::client_messages::ReadAllEventsCompleted<'b> {
-        unimplemented!()
+        use raw::client_messages::mod_ReadAllEventsCompleted::ReadAllResult;
+
+        raw::client_messages::ReadAllEventsCompleted {
+            commit_position: self.commit_position.into(),
+            prepare_position: self.prepare_position.into(),
+            events: self.events.iter().map(|x| raw::client_messages::ResolvedEvent {
+                event: x.event.clone(),
+                link: x.link.clone(),
+                commit_position: x.commit_position.into(),
+                prepare_position: x.prepare_position.into(),
+            }).collect(),
+            next_commit_position: self.next_commit_position.map(|x| x.into()).unwrap_or(-1),
+            next_prepare_position: self.next_prepare_position.map(|x| x.into()).unwrap_or(-1),
+            result: ReadAllResult::Success,
+            error: None,
+        }
     }
 }
 
 impl<'a, 'b: 'a> AsRawPayload<'a, 'b, raw::client_messages::ReadAllEventsCompleted<'b>> for ReadAllError<'a> {
     fn as_raw(&'b self) -> raw::client_messages::ReadAllEventsCompleted<'b> {
-        unimplemented!()
+        use raw::client_messages::mod_ReadAllEventsCompleted::ReadAllResult;
+        use self::ReadAllError::*;
+
+        let (result, error) = match self {
+            &NotModified => (ReadAllResult::NotModified, None),
+            &Error(ref msg) => (ReadAllResult::Error, msg.clone()),
+            &AccessDenied => (ReadAllResult::AccessDenied, None),
+        };
+
+        raw::client_messages::ReadAllEventsCompleted {
+            commit_position: -1,
+            prepare_position: -1,
+            events: vec![],
+            next_commit_position: -1,
+            next_prepare_position: -1,
+            result: result,
+            error: error,
+        }
+    }
+}
+
+impl<'a> CustomTryFrom<raw::client_messages::SubscriptionConfirmation> for AdaptedMessage<'a> {
+    type Err = Error;
+
+    fn try_from(msg: raw::client_messages::SubscriptionConfirmation) -> Result<AdaptedMessage<'a>, (raw::client_messages::SubscriptionConfirmation, Self::Err)> {
+        let last_commit_position = match LogPosition::from_i64_opt(msg.last_commit_position) {
+            Some(x) => x,
+            None => {
+                let e = ErrorKind::InvalidLogPosition(msg.last_commit_position).into();
+                return Err((msg, e));
+            },
+        };
+
+        let last_event_number = match confirmed_event_number(msg.last_event_number) {
+            Ok(x) => x,
+            Err(e) => return Err((msg, e)),
+        };
+
+        Ok(AdaptedMessage::SubscriptionConfirmation(SubscriptionConfirmation {
+            last_commit_position: last_commit_position,
+            last_event_number: last_event_number,
+        }))
+    }
+}
+
+impl<'a> CustomTryFrom<raw::client_messages::PersistentSubscriptionConfirmation<'a>> for AdaptedMessage<'a> {
+    type Err = Error;
+
+    fn try_from(msg: raw::client_messages::PersistentSubscriptionConfirmation<'a>) -> Result<AdaptedMessage<'a>, (raw::client_messages::PersistentSubscriptionConfirmation<'a>, Self::Err)> {
+        let last_commit_position = match LogPosition::from_i64_opt(msg.last_commit_position) {
+            Some(x) => x,
+            None => {
+                let e = ErrorKind::InvalidLogPosition(msg.last_commit_position).into();
+                return Err((msg, e));
+            },
+        };
+
+        let last_event_number = match confirmed_event_number(msg.last_event_number) {
+            Ok(x) => x,
+            Err(e) => return Err((msg, e)),
+        };
+
+        Ok(AdaptedMessage::PersistentSubscriptionConfirmation(PersistentSubscriptionConfirmation {
+            subscription_id: msg.subscription_id,
+            last_commit_position: last_commit_position,
+            last_event_number: last_event_number,
+        }))
+    }
+}
+
+fn create_persistent_subscription_completed<'a>(msg: raw::client_messages::CreatePersistentSubscriptionCompleted<'a>) -> AdaptedMessage<'a> {
+    use raw::client_messages::mod_CreatePersistentSubscriptionCompleted::CreatePersistentSubscriptionResult;
+
+    AdaptedMessage::CreatePersistentSubscriptionCompleted(match msg.result {
+        CreatePersistentSubscriptionResult::Success => Ok(()),
+        result => Err((result, msg.reason).into()),
+    })
+}
+
+fn created() -> raw::client_messages::CreatePersistentSubscriptionCompleted<'static> {
+    use raw::client_messages::mod_CreatePersistentSubscriptionCompleted::CreatePersistentSubscriptionResult;
+
+    raw::client_messages::CreatePersistentSubscriptionCompleted {
+        result: CreatePersistentSubscriptionResult::Success,
+        reason: None,
+    }
+}
+
+impl<'a, 'b: 'a> AsRawPayload<'a, 'b, raw::client_messages::CreatePersistentSubscriptionCompleted<'b>> for CreatePersistentSubscriptionFailure<'a> {
+    fn as_raw(&'b self) -> raw::client_messages::CreatePersistentSubscriptionCompleted<'b> {
+        use raw::client_messages::mod_CreatePersistentSubscriptionCompleted::CreatePersistentSubscriptionResult;
+        use self::CreatePersistentSubscriptionFailure::*;
+
+        let (result, reason) = match *self {
+            AlreadyExists => (CreatePersistentSubscriptionResult::AlreadyExists, None),
+            Fail(ref reason) => (CreatePersistentSubscriptionResult::Fail, reason.clone()),
+            AccessDenied => (CreatePersistentSubscriptionResult::AccessDenied, None),
+        };
+
+        raw::client_messages::CreatePersistentSubscriptionCompleted {
+            result: result,
+            reason: reason,
+        }
+    }
+}
+
+fn update_persistent_subscription_completed<'a>(msg: raw::client_messages::UpdatePersistentSubscriptionCompleted<'a>) -> AdaptedMessage<'a> {
+    use raw::client_messages::mod_UpdatePersistentSubscriptionCompleted::UpdatePersistentSubscriptionResult;
+
+    AdaptedMessage::UpdatePersistentSubscriptionCompleted(match msg.result {
+        UpdatePersistentSubscriptionResult::Success => Ok(()),
+        result => Err((result, msg.reason).into()),
+    })
+}
+
+fn updated() -> raw::client_messages::UpdatePersistentSubscriptionCompleted<'static> {
+    use raw::client_messages::mod_UpdatePersistentSubscriptionCompleted::UpdatePersistentSubscriptionResult;
+
+    raw::client_messages::UpdatePersistentSubscriptionCompleted {
+        result: UpdatePersistentSubscriptionResult::Success,
+        reason: None,
+    }
+}
+
+impl<'a, 'b: 'a> AsRawPayload<'a, 'b, raw::client_messages::UpdatePersistentSubscriptionCompleted<'b>> for UpdatePersistentSubscriptionFailure<'a> {
+    fn as_raw(&'b self) -> raw::client_messages::UpdatePersistentSubscriptionCompleted<'b> {
+        use raw::client_messages::mod_UpdatePersistentSubscriptionCompleted::UpdatePersistentSubscriptionResult;
+        use self::UpdatePersistentSubscriptionFailure::*;
+
+        let (result, reason) = match *self {
+            DoesNotExist => (UpdatePersistentSubscriptionResult::DoesNotExist, None),
+            Fail(ref reason) => (UpdatePersistentSubscriptionResult::Fail, reason.clone()),
+            AccessDenied => (UpdatePersistentSubscriptionResult::AccessDenied, None),
+        };
+
+        raw::client_messages::UpdatePersistentSubscriptionCompleted {
+            result: result,
+            reason: reason,
+        }
+    }
+}
+
+/// The event number of a confirmed subscription, `None` if the stream has no events yet or if the
+/// subscription is to all streams.
+fn confirmed_event_number(number: Option<i32>) -> Result<Option<StreamVersion>, Error> {
+    match number {
+        Some(number) if number >= 0 => StreamVersion::try_from(number).map(Some),
+        _ => Ok(None),
+    }
+}
+
+impl<'b> AsRawPayload<'static, 'b, raw::client_messages::SubscriptionConfirmation> for SubscriptionConfirmation {
+    fn as_raw(&'b self) -> raw::client_messages::SubscriptionConfirmation {
+        raw::client_messages::SubscriptionConfirmation {
+            last_commit_position: self.last_commit_position.into(),
+            last_event_number: self.last_event_number.map(i32::from),
+        }
+    }
+}
+
+impl<'a> CustomTryFrom<raw::client_messages::StreamEventAppeared<'a>> for AdaptedMessage<'a> {
+    type Err = Error;
+
+    fn try_from(msg: raw::client_messages::StreamEventAppeared<'a>) -> Result<AdaptedMessage<'a>, (raw::client_messages::StreamEventAppeared<'a>, Self::Err)> {
+        let commit_position = LogPosition::from_i64_opt(msg.event.commit_position);
+        let prepare_position = LogPosition::from_i64_opt(msg.event.prepare_position);
+
+        match (commit_position, prepare_position) {
+            (Some(commit), Some(prepare)) => {
+                let e = msg.event;
+
+                Ok(AdaptedMessage::StreamEventAppeared(ResolvedEvent {
+                    event: e.event,
+                    link: e.link,
+                    commit_position: commit,
+                    prepare_position: prepare,
+                }))
+            },
+            (None, _) => {
+                let e = ErrorKind::InvalidLogPosition(msg.event.commit_position).into();
+                Err((msg, e))
+            },
+            (_, None) => {
+                let e = ErrorKind::InvalidLogPosition(msg.event.prepare_position).into();
+                Err((msg, e))
+            },
+        }
+    }
+}
+
+impl<'a, 'b: 'a> AsRawPayload<'a, 'b, raw::client_messages::StreamEventAppeared<'b>> for ResolvedEvent<'a> {
+    fn as_raw(&'b self) -> raw::client_messages::StreamEventAppeared<'b> {
+        raw::client_messages::StreamEventAppeared {
+            event: raw::client_messages::ResolvedEvent {
+                event: self.event.clone(),
+                link: self.link.clone(),
+                commit_position: self.commit_position.into(),
+                prepare_position: self.prepare_position.into(),
+            }
+        }
+    }
+}
+
+impl<'a, 'b: 'a> AsRawPayload<'a, 'b, raw::client_messages::PersistentSubscriptionConfirmation<'b>> for PersistentSubscriptionConfirmation<'a> {
+    fn as_raw(&'b self) -> raw::client_messages::PersistentSubscriptionConfirmation<'b> {
+        raw::client_messages::PersistentSubscriptionConfirmation {
+            last_commit_position: self.last_commit_position.into(),
+            subscription_id: Cow::Borrowed(&*self.subscription_id),
+            last_event_number: self.last_event_number.map(i32::from),
+        }
+    }
+}
+
+impl<'b> AsRawPayload<'static, 'b, raw::client_messages::SubscriptionDropped> for SubscriptionDropReason {
+    fn as_raw(&'b self) -> raw::client_messages::SubscriptionDropped {
+        raw::client_messages::SubscriptionDropped {
+            reason: *self,
+        }
     }
 }
 
@@ -561,6 +1279,8 @@ mod tests {
             (RawMessage::Pong, AdaptedMessage::Pong),
             (RawMessage::Authenticate, AdaptedMessage::Authenticate),
             (RawMessage::Authenticated, AdaptedMessage::Authenticated),
+            (RawMessage::ClientIdentified, AdaptedMessage::ClientIdentified),
+            (RawMessage::UnsubscribeFromStream, AdaptedMessage::UnsubscribeFromStream),
         ];
 
         for (input, expected) in values {
@@ -590,6 +1310,40 @@ mod tests {
             AdaptedMessage::NotAuthenticated(Cow::Borrowed("").into()));
     }
 
+    #[test]
+    fn convert_not_handled() {
+        use quick_protobuf::{MessageWrite, Writer};
+        use raw::client_messages::NotHandled;
+        use raw::client_messages::mod_NotHandled::{NotHandledReason, MasterInfo};
+
+        test_conversions(
+            RawMessage::NotHandled(NotHandled { reason: Some(NotHandledReason::TooBusy), additional_info: None }),
+            AdaptedMessage::NotHandled(NotHandledInfo::TooBusy));
+
+        test_conversions(
+            RawMessage::NotHandled(NotHandled { reason: Some(NotHandledReason::NotMaster), additional_info: None }),
+            AdaptedMessage::NotHandled(NotHandledInfo::NotMaster(None)));
+
+        let master = MasterInfo {
+            external_tcp_address: Cow::Borrowed("10.0.0.1"),
+            external_tcp_port: 1113,
+            external_http_address: Cow::Borrowed("10.0.0.1"),
+            external_http_port: 2113,
+            external_secure_tcp_address: None,
+            external_secure_tcp_port: None,
+        };
+
+        let mut info = Vec::new();
+        master.write_message(&mut Writer::new(&mut info)).unwrap();
+
+        test_conversions(
+            RawMessage::NotHandled(NotHandled { reason: Some(NotHandledReason::NotMaster), additional_info: Some(Cow::Borrowed(&info[..])) }),
+            AdaptedMessage::NotHandled(NotHandledInfo::NotMaster(Some(master))));
+
+        failing_conversion(RawMessage::NotHandled(NotHandled { reason: None, additional_info: None }));
+        failing_conversion(RawMessage::NotHandled(NotHandled { reason: Some(NotHandledReason::NotMaster), additional_info: Some(Cow::Borrowed(&[0xff][..])) }));
+    }
+
     #[test]
     fn convert_write_events() {
         use uuid::Uuid;
@@ -687,6 +1441,260 @@ mod tests {
         }
     }
 
+    #[test]
+    fn convert_delete_stream_completed() {
+        use raw::client_messages::{DeleteStreamCompleted, OperationResult};
+        use adapted::delete_stream::DeleteStreamFailure::*;
+
+        let body = |result| DeleteStreamCompleted {
+            result: result,
+            message: None,
+            prepare_position: None,
+            commit_position: None,
+        };
+
+        test_conversions(
+            RawMessage::DeleteStreamCompleted(DeleteStreamCompleted { prepare_position: Some(100), commit_position: Some(120), ..body(Some(OperationResult::Success)) }),
+            AdaptedMessage::DeleteStreamCompleted(Ok(super::DeleteStreamCompleted {
+                prepare_position: Some(LogPosition::from(100)),
+                commit_position: Some(LogPosition::from(120)),
+            })));
+
+        let errors = vec![
+            (OperationResult::PrepareTimeout, PrepareTimeout),
+            (OperationResult::CommitTimeout, CommitTimeout),
+            (OperationResult::ForwardTimeout, ForwardTimeout),
+            (OperationResult::WrongExpectedVersion, WrongExpectedVersion),
+            (OperationResult::StreamDeleted, StreamDeleted),
+            (OperationResult::AccessDenied, AccessDenied),
+        ];
+
+        for (error, mapped) in errors {
+            test_conversions(
+                RawMessage::DeleteStreamCompleted(body(Some(error))),
+                AdaptedMessage::DeleteStreamCompleted(Err(mapped)));
+        }
+
+        failing_conversion(RawMessage::DeleteStreamCompleted(body(Some(OperationResult::InvalidTransaction))));
+        failing_conversion(RawMessage::DeleteStreamCompleted(body(None)));
+    }
+
+    #[test]
+    fn convert_transaction_completed() {
+        use raw::client_messages::{TransactionStartCompleted, TransactionWriteCompleted, TransactionCommitCompleted, OperationResult};
+        use adapted::transaction::TransactionFailure;
+        use TransactionId;
+
+        test_conversions(
+            RawMessage::TransactionStartCompleted(TransactionStartCompleted { transaction_id: 7, result: Some(OperationResult::Success), message: None }),
+            AdaptedMessage::TransactionStartCompleted(Ok(TransactionId::from(7))));
+
+        test_conversions(
+            RawMessage::TransactionStartCompleted(TransactionStartCompleted { transaction_id: -1, result: Some(OperationResult::AccessDenied), message: None }),
+            AdaptedMessage::TransactionStartCompleted(Err(TransactionFailure::AccessDenied)));
+
+        test_conversions(
+            RawMessage::TransactionWriteCompleted(TransactionWriteCompleted { transaction_id: 7, result: Some(OperationResult::Success), message: None }),
+            AdaptedMessage::TransactionWriteCompleted(TransactionId::from(7), Ok(())));
+
+        test_conversions(
+            RawMessage::TransactionWriteCompleted(TransactionWriteCompleted { transaction_id: 7, result: Some(OperationResult::InvalidTransaction), message: None }),
+            AdaptedMessage::TransactionWriteCompleted(TransactionId::from(7), Err(TransactionFailure::InvalidTransaction)));
+
+        let commit = TransactionCommitCompleted {
+            transaction_id: 7,
+            result: Some(OperationResult::Success),
+            message: None,
+            first_event_number: 3,
+            last_event_number: 5,
+            prepare_position: Some(100),
+            commit_position: Some(120),
+        };
+
+        test_conversions(
+            RawMessage::TransactionCommitCompleted(commit.clone()),
+            AdaptedMessage::TransactionCommitCompleted(TransactionId::from(7), Ok(WriteEventsCompleted {
+                event_numbers: StreamVersion::try_from(3).unwrap()..StreamVersion::try_from(6).unwrap(),
+                prepare_position: Some(LogPosition::from(100)),
+                commit_position: Some(LogPosition::from(120)),
+            })));
+
+        test_conversions(
+            RawMessage::TransactionCommitCompleted(TransactionCommitCompleted {
+                result: Some(OperationResult::WrongExpectedVersion),
+                first_event_number: -1,
+                last_event_number: -1,
+                prepare_position: None,
+                commit_position: None,
+                ..commit.clone()
+            }),
+            AdaptedMessage::TransactionCommitCompleted(TransactionId::from(7), Err(TransactionFailure::WrongExpectedVersion)));
+
+        failing_conversion(RawMessage::TransactionCommitCompleted(TransactionCommitCompleted { result: None, ..commit }));
+    }
+
+    #[test]
+    fn convert_subscription_confirmation() {
+        use raw::client_messages::{SubscribeToStream, SubscriptionConfirmation};
+
+        let subscribe = SubscribeToStream {
+            event_stream_id: Cow::Borrowed("orders-1"),
+            resolve_link_tos: true,
+        };
+
+        test_conversions(
+            RawMessage::SubscribeToStream(subscribe.clone()),
+            AdaptedMessage::SubscribeToStream(subscribe));
+
+        test_conversions(
+            RawMessage::SubscriptionConfirmation(SubscriptionConfirmation { last_commit_position: 1024, last_event_number: Some(3) }),
+            AdaptedMessage::SubscriptionConfirmation(super::SubscriptionConfirmation {
+                last_commit_position: LogPosition::from(1024),
+                last_event_number: Some(StreamVersion::try_from(3).unwrap()),
+            }));
+
+        // subscriptions to all streams have no event number
+        test_conversions(
+            RawMessage::SubscriptionConfirmation(SubscriptionConfirmation { last_commit_position: 1024, last_event_number: None }),
+            AdaptedMessage::SubscriptionConfirmation(super::SubscriptionConfirmation {
+                last_commit_position: LogPosition::from(1024),
+                last_event_number: None,
+            }));
+
+        failing_conversion(RawMessage::SubscriptionConfirmation(SubscriptionConfirmation { last_commit_position: -2, last_event_number: None }));
+    }
+
+    #[test]
+    fn convert_persistent_subscription_confirmation() {
+        use raw::client_messages::{ConnectToPersistentSubscription, PersistentSubscriptionConfirmation};
+
+        let connect = ConnectToPersistentSubscription {
+            subscription_id: Cow::Borrowed("billing"),
+            event_stream_id: Cow::Borrowed("orders-1"),
+            allowed_in_flight_messages: 10,
+        };
+
+        test_conversions(
+            RawMessage::ConnectToPersistentSubscription(connect.clone()),
+            AdaptedMessage::ConnectToPersistentSubscription(connect));
+
+        test_conversions(
+            RawMessage::PersistentSubscriptionConfirmation(PersistentSubscriptionConfirmation {
+                last_commit_position: 1024,
+                subscription_id: Cow::Borrowed("orders-1::billing"),
+                last_event_number: Some(3),
+            }),
+            AdaptedMessage::PersistentSubscriptionConfirmation(super::PersistentSubscriptionConfirmation {
+                subscription_id: Cow::Borrowed("orders-1::billing"),
+                last_commit_position: LogPosition::from(1024),
+                last_event_number: Some(StreamVersion::try_from(3).unwrap()),
+            }));
+
+        failing_conversion(RawMessage::PersistentSubscriptionConfirmation(PersistentSubscriptionConfirmation {
+            last_commit_position: -2,
+            subscription_id: Cow::Borrowed("orders-1::billing"),
+            last_event_number: None,
+        }));
+    }
+
+    #[test]
+    fn convert_create_persistent_subscription_completed() {
+        use raw::client_messages::CreatePersistentSubscriptionCompleted;
+        use raw::client_messages::mod_CreatePersistentSubscriptionCompleted::CreatePersistentSubscriptionResult;
+
+        test_conversions(
+            RawMessage::CreatePersistentSubscriptionCompleted(CreatePersistentSubscriptionCompleted {
+                result: CreatePersistentSubscriptionResult::Success,
+                reason: None,
+            }),
+            AdaptedMessage::CreatePersistentSubscriptionCompleted(Ok(())));
+
+        test_conversions(
+            RawMessage::CreatePersistentSubscriptionCompleted(CreatePersistentSubscriptionCompleted {
+                result: CreatePersistentSubscriptionResult::AlreadyExists,
+                reason: None,
+            }),
+            AdaptedMessage::CreatePersistentSubscriptionCompleted(Err(CreatePersistentSubscriptionFailure::AlreadyExists)));
+
+        test_conversions(
+            RawMessage::CreatePersistentSubscriptionCompleted(CreatePersistentSubscriptionCompleted {
+                result: CreatePersistentSubscriptionResult::Fail,
+                reason: Some(Cow::Borrowed("no such stream")),
+            }),
+            AdaptedMessage::CreatePersistentSubscriptionCompleted(Err(CreatePersistentSubscriptionFailure::Fail(Some(Cow::Borrowed("no such stream"))))));
+    }
+
+    #[test]
+    fn convert_update_persistent_subscription_completed() {
+        use raw::client_messages::UpdatePersistentSubscriptionCompleted;
+        use raw::client_messages::mod_UpdatePersistentSubscriptionCompleted::UpdatePersistentSubscriptionResult;
+
+        test_conversions(
+            RawMessage::UpdatePersistentSubscriptionCompleted(UpdatePersistentSubscriptionCompleted {
+                result: UpdatePersistentSubscriptionResult::Success,
+                reason: None,
+            }),
+            AdaptedMessage::UpdatePersistentSubscriptionCompleted(Ok(())));
+
+        test_conversions(
+            RawMessage::UpdatePersistentSubscriptionCompleted(UpdatePersistentSubscriptionCompleted {
+                result: UpdatePersistentSubscriptionResult::DoesNotExist,
+                reason: None,
+            }),
+            AdaptedMessage::UpdatePersistentSubscriptionCompleted(Err(UpdatePersistentSubscriptionFailure::DoesNotExist)));
+
+        test_conversions(
+            RawMessage::UpdatePersistentSubscriptionCompleted(UpdatePersistentSubscriptionCompleted {
+                result: UpdatePersistentSubscriptionResult::AccessDenied,
+                reason: None,
+            }),
+            AdaptedMessage::UpdatePersistentSubscriptionCompleted(Err(UpdatePersistentSubscriptionFailure::AccessDenied)));
+    }
+
+    #[test]
+    fn convert_stream_event_appeared() {
+        use raw::client_messages::StreamEventAppeared;
+
+        let appeared = |commit_position| StreamEventAppeared {
+            event: raw::client_messages::ResolvedEvent {
+                event: record("orders-1", 4),
+                link: None,
+                commit_position: commit_position,
+                prepare_position: 90,
+            }
+        };
+
+        test_conversions(
+            RawMessage::StreamEventAppeared(appeared(100)),
+            AdaptedMessage::StreamEventAppeared(ResolvedEvent {
+                event: record("orders-1", 4),
+                link: None,
+                commit_position: LogPosition::from(100),
+                prepare_position: LogPosition::from(90),
+            }));
+
+        failing_conversion(RawMessage::StreamEventAppeared(appeared(-2)));
+    }
+
+    #[test]
+    fn convert_subscription_dropped() {
+        use raw::client_messages::SubscriptionDropped;
+
+        let reasons = vec![
+            SubscriptionDropReason::Unsubscribed,
+            SubscriptionDropReason::AccessDenied,
+            SubscriptionDropReason::NotFound,
+            SubscriptionDropReason::PersistentSubscriptionDeleted,
+            SubscriptionDropReason::SubscriberMaxCountReached,
+        ];
+
+        for reason in reasons {
+            test_conversions(
+                RawMessage::SubscriptionDropped(SubscriptionDropped { reason: reason }),
+                AdaptedMessage::SubscriptionDropped(reason));
+        }
+    }
+
     #[test]
     fn convert_read_event() {
         let body = raw::client_messages::ReadEvent {
@@ -701,6 +1709,18 @@ mod tests {
             AdaptedMessage::ReadEvent(body.clone()));
     }
 
+    #[test]
+    fn convert_identify_client() {
+        let body = raw::client_messages::IdentifyClient {
+            version: 1,
+            connection_name: Some(Cow::Borrowed("billing-projections")),
+        };
+
+        test_conversions(
+            RawMessage::IdentifyClient(body.clone()),
+            AdaptedMessage::IdentifyClient(body));
+    }
+
     #[test]
     fn convert_bogus_read_completed() {
         use raw::client_messages::{ReadEventCompleted, ResolvedIndexedEvent, EventRecord};
@@ -728,6 +1748,270 @@ mod tests {
         failing_conversion(RawMessage::ReadEventCompleted(bogus));
     }
 
+    #[test]
+    fn convert_read_event_completed() {
+        use raw::client_messages::{ReadEventCompleted, ResolvedIndexedEvent};
+        use raw::client_messages::mod_ReadEventCompleted::ReadEventResult;
+
+        let event = ResolvedIndexedEvent {
+            event: record("foobar", 3),
+            link: None,
+        };
+
+        test_conversions(
+            RawMessage::ReadEventCompleted(ReadEventCompleted {
+                result: Some(ReadEventResult::Success),
+                event: event.clone(),
+                error: None,
+            }),
+            AdaptedMessage::ReadEventCompleted(Ok(event)));
+
+        test_conversions(
+            RawMessage::ReadEventCompleted(ReadEventCompleted {
+                result: Some(ReadEventResult::Error),
+                event: ResolvedIndexedEvent {
+                    event: record("", -1),
+                    link: None,
+                },
+                error: Some(Cow::Borrowed("boom")),
+            }),
+            AdaptedMessage::ReadEventCompleted(Err(ReadEventError::Error(Some(Cow::Borrowed("boom"))))));
+    }
+
+    #[test]
+    fn convert_read_event_failures() {
+        use raw::client_messages::{ReadEventCompleted, ResolvedIndexedEvent};
+        use raw::client_messages::mod_ReadEventCompleted::ReadEventResult;
+
+        for &(result, ref mapped) in &[
+            (ReadEventResult::NotFound, ReadEventError::NotFound),
+            (ReadEventResult::NoStream, ReadEventError::NoStream),
+            (ReadEventResult::StreamDeleted, ReadEventError::StreamDeleted),
+            (ReadEventResult::Error, ReadEventError::Error(None)),
+            (ReadEventResult::AccessDenied, ReadEventError::AccessDenied)] {
+
+            test_conversions(
+                RawMessage::ReadEventCompleted(ReadEventCompleted {
+                    result: Some(result),
+                    event: ResolvedIndexedEvent {
+                        event: record("", -1),
+                        link: None,
+                    },
+                    error: None,
+                }),
+                AdaptedMessage::ReadEventCompleted(Err(mapped.clone())));
+        }
+    }
+
+    #[test]
+    fn convert_read_stream_completed() {
+        use raw::client_messages::{ReadStreamEventsCompleted, ResolvedIndexedEvent};
+        use raw::client_messages::mod_ReadStreamEventsCompleted::ReadStreamResult;
+
+        let event = ResolvedIndexedEvent {
+            event: record("foobar", 0),
+            link: Some(record("$ce-foo", 4)),
+        };
+
+        test_conversions(
+            RawMessage::ReadStreamEventsCompleted(ReadDirection::Forward, ReadStreamEventsCompleted {
+                events: vec![event.clone()],
+                result: Some(ReadStreamResult::Success),
+                next_event_number: 1,
+                last_event_number: 0,
+                is_end_of_stream: true,
+                last_commit_position: 100,
+                error: None,
+            }),
+            AdaptedMessage::ReadStreamEventsCompleted(ReadDirection::Forward, Ok(ReadStreamCompleted {
+                events: vec![event],
+                next_page: Some(StreamVersion::try_from(1).unwrap().into()),
+                last_event_number: StreamVersion::try_from(0).unwrap(),
+                end_of_stream: true,
+                last_commit_position: 100,
+            })));
+
+        for &(result, ref mapped) in &[
+            (ReadStreamResult::NoStream, ReadStreamError::NoStream),
+            (ReadStreamResult::StreamDeleted, ReadStreamError::StreamDeleted),
+            (ReadStreamResult::AccessDenied, ReadStreamError::AccessDenied)] {
+
+            test_conversions(
+                RawMessage::ReadStreamEventsCompleted(ReadDirection::Backward, ReadStreamEventsCompleted {
+                    events: vec![],
+                    result: Some(result),
+                    next_event_number: -1,
+                    last_event_number: -1,
+                    is_end_of_stream: false,
+                    last_commit_position: -1,
+                    error: None,
+                }),
+                AdaptedMessage::ReadStreamEventsCompleted(ReadDirection::Backward, Err(mapped.clone())));
+        }
+    }
+
+    #[test]
+    fn convert_read_stream_backward_to_start() {
+        use raw::client_messages::{ReadStreamEventsCompleted, ResolvedIndexedEvent};
+        use raw::client_messages::mod_ReadStreamEventsCompleted::ReadStreamResult;
+
+        let events = vec![
+            ResolvedIndexedEvent { event: record("foobar", 1), link: None },
+            ResolvedIndexedEvent { event: record("foobar", 0), link: None },
+        ];
+
+        // reading backwards past the first event leaves no next page
+        test_conversions(
+            RawMessage::ReadStreamEventsCompleted(ReadDirection::Backward, ReadStreamEventsCompleted {
+                events: events.clone(),
+                result: Some(ReadStreamResult::Success),
+                next_event_number: -1,
+                last_event_number: 1,
+                is_end_of_stream: true,
+                last_commit_position: 100,
+                error: None,
+            }),
+            AdaptedMessage::ReadStreamEventsCompleted(ReadDirection::Backward, Ok(ReadStreamCompleted {
+                events: events,
+                next_page: None,
+                last_event_number: StreamVersion::try_from(1).unwrap(),
+                end_of_stream: true,
+                last_commit_position: 100,
+            })));
+
+        test_conversions(
+            RawMessage::ReadStreamEventsCompleted(ReadDirection::Backward, ReadStreamEventsCompleted {
+                events: vec![],
+                result: Some(ReadStreamResult::Error),
+                next_event_number: -1,
+                last_event_number: -1,
+                is_end_of_stream: false,
+                last_commit_position: -1,
+                error: Some(Cow::Borrowed("boom")),
+            }),
+            AdaptedMessage::ReadStreamEventsCompleted(ReadDirection::Backward, Err(ReadStreamError::Error(Some(Cow::Borrowed("boom"))))));
+    }
+
+    #[test]
+    fn convert_read_all_completed() {
+        use raw::client_messages::ReadAllEventsCompleted;
+        use raw::client_messages::mod_ReadAllEventsCompleted::ReadAllResult;
+
+        test_conversions(
+            RawMessage::ReadAllEventsCompleted(ReadDirection::Forward, ReadAllEventsCompleted {
+                commit_position: 100,
+                prepare_position: 90,
+                events: vec![raw::client_messages::ResolvedEvent {
+                    event: record("foobar", 0),
+                    link: None,
+                    commit_position: 100,
+                    prepare_position: 90,
+                }],
+                next_commit_position: 200,
+                next_prepare_position: 190,
+                result: ReadAllResult::Success,
+                error: None,
+            }),
+            AdaptedMessage::ReadAllEventsCompleted(ReadDirection::Forward, Ok(ReadAllCompleted {
+                commit_position: LogPosition::from(100),
+                prepare_position: LogPosition::from(90),
+                events: vec![ResolvedEvent {
+                    event: record("foobar", 0),
+                    link: None,
+                    commit_position: LogPosition::from(100),
+                    prepare_position: LogPosition::from(90),
+                }],
+                next_commit_position: Some(LogPosition::from(200)),
+                next_prepare_position: Some(LogPosition::from(190)),
+            })));
+
+        test_conversions(
+            RawMessage::ReadAllEventsCompleted(ReadDirection::Backward, ReadAllEventsCompleted {
+                commit_position: -1,
+                prepare_position: -1,
+                events: vec![],
+                next_commit_position: -1,
+                next_prepare_position: -1,
+                result: ReadAllResult::Error,
+                error: Some(Cow::Borrowed("boom")),
+            }),
+            AdaptedMessage::ReadAllEventsCompleted(ReadDirection::Backward, Err(ReadAllError::Error(Some(Cow::Borrowed("boom"))))));
+    }
+
+    #[test]
+    fn read_all_next_positions() {
+        let page = ReadAllCompleted {
+            commit_position: LogPosition::from(100),
+            prepare_position: LogPosition::from(90),
+            events: vec![],
+            next_commit_position: Some(LogPosition::from(200)),
+            next_prepare_position: Some(LogPosition::from(190)),
+        };
+        assert_eq!(page.next_positions(), Some((LogPosition::from(200), LogPosition::from(190))));
+
+        let partial = ReadAllCompleted { next_prepare_position: None, ..page };
+        assert_eq!(partial.next_positions(), None);
+    }
+
+    fn record(stream: &'static str, number: i32) -> raw::client_messages::EventRecord<'static> {
+        raw::client_messages::EventRecord {
+            event_stream_id: stream.into(),
+            event_number: number,
+            event_id: Cow::Borrowed(&[]),
+            event_type: "".into(),
+            data_content_type: 0,
+            metadata_content_type: 0,
+            data: Cow::Borrowed(&[]),
+            metadata: None,
+            created: None,
+            created_epoch: None,
+        }
+    }
+
+    #[test]
+    fn to_message_matches_as_raw() {
+        use uuid::Uuid;
+
+        let failed: Result<WriteEventsCompleted, WriteEventsFailure> = Err(WriteEventsFailure::StreamDeleted);
+        let adapted = AdaptedMessage::WriteEventsCompleted(failed.clone());
+        assert_eq!(failed.to_message(), adapted.as_raw());
+
+        let read = (ReadDirection::Backward, ReadStreamError::NoStream);
+        let id = Uuid::new_v4();
+        let package = read.to_package(id);
+        assert_eq!(package.correlation_id, id);
+        assert_eq!(package.message, AdaptedMessage::ReadStreamEventsCompleted(ReadDirection::Backward, Err(ReadStreamError::NoStream)).as_raw());
+    }
+
+    #[test]
+    fn failures_display_the_server_message() {
+        assert_eq!(ReadEventError::NotFound.to_string(), "Event was not found");
+        assert_eq!(ReadStreamError::Error(None).to_string(), "Server failed to read the stream");
+        assert_eq!(ReadAllError::Error(Some(Cow::Borrowed("disk full"))).to_string(), "Server failed to read all events: disk full");
+        assert_eq!(WriteEventsFailure::StreamDeleted.to_string(), "Stream had been deleted");
+    }
+
+    #[test]
+    fn completions_are_hashable() {
+        use std::collections::HashSet;
+        use std::convert::TryFrom;
+        use {StreamVersion, LogPosition};
+
+        let completed = WriteEventsCompleted {
+            event_numbers: StreamVersion::try_from(0u32).unwrap()..StreamVersion::try_from(2u32).unwrap(),
+            prepare_position: Some(LogPosition::Exact(100)),
+            commit_position: Some(LogPosition::Exact(100)),
+        };
+
+        let mut seen = HashSet::new();
+        assert!(seen.insert(Ok(completed.clone())));
+        assert!(seen.insert(Err(WriteEventsFailure::WrongExpectedVersion)));
+        assert!(!seen.insert(Ok(completed)));
+
+        let errors = vec![ReadAllError::AccessDenied, ReadAllError::Error(None), ReadAllError::AccessDenied];
+        assert_eq!(errors.into_iter().collect::<HashSet<_>>().len(), 2);
+    }
+
     fn test_conversions<'a, 'b>(input: RawMessage<'a>, expected: AdaptedMessage<'b>) {
         assert_eq!(AdaptedMessage::try_from(input.clone()).unwrap(), expected);
         assert_eq!(expected.as_raw(), input);
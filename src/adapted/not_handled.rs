@@ -0,0 +1,13 @@
+use raw::client_messages::mod_NotHandled::MasterInfo;
+
+/// Reason of `AdaptedMessage::NotHandled`, the response to requests the node did not handle.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotHandledInfo<'a> {
+    /// The node is not ready to handle requests yet; the request can be sent again later
+    NotReady,
+    /// The node is too busy to handle the request; the request can be sent again later
+    TooBusy,
+    /// The request requires the master, which the node is not. Has the endpoints of the master if
+    /// the node knows them.
+    NotMaster(Option<MasterInfo<'a>>),
+}
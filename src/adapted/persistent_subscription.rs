@@ -0,0 +1,95 @@
+use std::fmt;
+use std::error;
+use std::borrow::Cow;
+use raw::client_messages::mod_CreatePersistentSubscriptionCompleted::CreatePersistentSubscriptionResult;
+use raw::client_messages::mod_UpdatePersistentSubscriptionCompleted::UpdatePersistentSubscriptionResult;
+
+/// `CreatePersistentSubscriptionFailure` maps to non-success of
+/// `CreatePersistentSubscriptionResult`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum CreatePersistentSubscriptionFailure<'a> {
+    /// A group of the same name already exists on the stream
+    AlreadyExists,
+    /// Other error, with the reason given by the server
+    Fail(Option<Cow<'a, str>>),
+    /// Access was denied (no credentials provided or insufficient permissions)
+    AccessDenied,
+}
+
+impl<'a> From<(CreatePersistentSubscriptionResult, Option<Cow<'a, str>>)> for CreatePersistentSubscriptionFailure<'a> {
+    fn from((res, reason): (CreatePersistentSubscriptionResult, Option<Cow<'a, str>>)) -> Self {
+        use self::CreatePersistentSubscriptionResult::*;
+        match res {
+            Success => unreachable!(),
+            AlreadyExists => CreatePersistentSubscriptionFailure::AlreadyExists,
+            Fail => CreatePersistentSubscriptionFailure::Fail(reason),
+            AccessDenied => CreatePersistentSubscriptionFailure::AccessDenied,
+        }
+    }
+}
+
+impl<'a> fmt::Display for CreatePersistentSubscriptionFailure<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use std::error::Error;
+        match *self {
+            CreatePersistentSubscriptionFailure::Fail(Some(ref reason)) => write!(fmt, "{}: {}", self.description(), reason),
+            _ => write!(fmt, "{}", self.description()),
+        }
+    }
+}
+
+impl<'a> error::Error for CreatePersistentSubscriptionFailure<'a> {
+    fn description(&self) -> &str {
+        use self::CreatePersistentSubscriptionFailure::*;
+        match *self {
+            AlreadyExists => "Subscription group already exists",
+            Fail(_) => "Server failed to create the subscription group",
+            AccessDenied => "Access to stream was denied",
+        }
+    }
+}
+
+/// `UpdatePersistentSubscriptionFailure` maps to non-success of
+/// `UpdatePersistentSubscriptionResult`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum UpdatePersistentSubscriptionFailure<'a> {
+    /// There is no group of the name on the stream
+    DoesNotExist,
+    /// Other error, with the reason given by the server
+    Fail(Option<Cow<'a, str>>),
+    /// Access was denied (no credentials provided or insufficient permissions)
+    AccessDenied,
+}
+
+impl<'a> From<(UpdatePersistentSubscriptionResult, Option<Cow<'a, str>>)> for UpdatePersistentSubscriptionFailure<'a> {
+    fn from((res, reason): (UpdatePersistentSubscriptionResult, Option<Cow<'a, str>>)) -> Self {
+        use self::UpdatePersistentSubscriptionResult::*;
+        match res {
+            Success => unreachable!(),
+            DoesNotExist => UpdatePersistentSubscriptionFailure::DoesNotExist,
+            Fail => UpdatePersistentSubscriptionFailure::Fail(reason),
+            AccessDenied => UpdatePersistentSubscriptionFailure::AccessDenied,
+        }
+    }
+}
+
+impl<'a> fmt::Display for UpdatePersistentSubscriptionFailure<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use std::error::Error;
+        match *self {
+            UpdatePersistentSubscriptionFailure::Fail(Some(ref reason)) => write!(fmt, "{}: {}", self.description(), reason),
+            _ => write!(fmt, "{}", self.description()),
+        }
+    }
+}
+
+impl<'a> error::Error for UpdatePersistentSubscriptionFailure<'a> {
+    fn description(&self) -> &str {
+        use self::UpdatePersistentSubscriptionFailure::*;
+        match *self {
+            DoesNotExist => "Subscription group does not exist",
+            Fail(_) => "Server failed to update the subscription group",
+            AccessDenied => "Access to stream was denied",
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+use std::error;
 use std::borrow::Cow;
 use raw::client_messages;
 use raw::client_messages::mod_ReadAllEventsCompleted::ReadAllResult;
@@ -19,6 +21,17 @@ pub struct ReadAllCompleted<'a> {
     pub next_prepare_position: Option<LogPosition>,
 }
 
+impl<'a> ReadAllCompleted<'a> {
+    /// The commit and prepare positions to read the next page from in the same direction, for
+    /// example with `ReadAllEventsBuilder::positions`. `None` if the server did not return both.
+    pub fn next_positions(&self) -> Option<(LogPosition, LogPosition)> {
+        match (self.next_commit_position, self.next_prepare_position) {
+            (Some(commit), Some(prepare)) => Some((commit, prepare)),
+            _ => None,
+        }
+    }
+}
+
 /// Read event in `ReadAllSuccess` response
 #[derive(Debug, Clone, PartialEq)]
 pub struct ResolvedEvent<'a> {
@@ -44,7 +57,7 @@ impl<'a> From<client_messages::ResolvedEvent<'a>> for ResolvedEvent<'a> {
 }
 
 /// Failure cases of wire enum `ReadAllResult`.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ReadAllError<'a> {
     /// Unknown when this happens,
     NotModified,
@@ -65,3 +78,24 @@ impl<'a> From<(ReadAllResult, Option<Cow<'a, str>>)> for ReadAllError<'a> {
         }
     }
 }
+
+impl<'a> fmt::Display for ReadAllError<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use std::error::Error;
+        match *self {
+            ReadAllError::Error(Some(ref msg)) => write!(fmt, "{}: {}", self.description(), msg),
+            _ => write!(fmt, "{}", self.description()),
+        }
+    }
+}
+
+impl<'a> error::Error for ReadAllError<'a> {
+    fn description(&self) -> &str {
+        use self::ReadAllError::*;
+        match *self {
+            NotModified => "Stream of all events was not modified",
+            Error(_) => "Server failed to read all events",
+            AccessDenied => "Access to all events was denied",
+        }
+    }
+}
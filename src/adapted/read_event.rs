@@ -1,8 +1,10 @@
+use std::fmt;
+use std::error;
 use std::borrow::Cow;
 use raw::client_messages::mod_ReadEventCompleted::ReadEventResult;
 
 /// `ReadEventError` maps to non-success of `ReadEventResult`
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum ReadEventError<'a> {
     /// Event of requested number was not found (scavenged or never existed)
     NotFound,
@@ -29,3 +31,26 @@ impl<'a> From<(ReadEventResult, Option<Cow<'a, str>>)> for ReadEventError<'a> {
         }
     }
 }
+
+impl<'a> fmt::Display for ReadEventError<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use std::error::Error;
+        match *self {
+            ReadEventError::Error(Some(ref msg)) => write!(fmt, "{}: {}", self.description(), msg),
+            _ => write!(fmt, "{}", self.description()),
+        }
+    }
+}
+
+impl<'a> error::Error for ReadEventError<'a> {
+    fn description(&self) -> &str {
+        use self::ReadEventError::*;
+        match *self {
+            NotFound => "Event was not found",
+            NoStream => "Stream was not found",
+            StreamDeleted => "Stream had been deleted",
+            Error(_) => "Server failed to read the event",
+            AccessDenied => "Access to stream was denied",
+        }
+    }
+}
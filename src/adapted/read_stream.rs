@@ -1,3 +1,5 @@
+use std::fmt;
+use std::error;
 use std::borrow::Cow;
 use raw::client_messages::mod_ReadStreamEventsCompleted::ReadStreamResult;
 use raw::client_messages::ResolvedIndexedEvent;
@@ -25,7 +27,7 @@ pub struct ReadStreamCompleted<'a> {
 
 /// Non-success projection of the `ReadStreamResult` enum on the wire representing
 /// a failed `ReadStreamCompleted` request.
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum ReadStreamError<'a> {
     /// Stream was not found
     NoStream,
@@ -53,3 +55,26 @@ impl<'a> From<(ReadStreamResult, Option<Cow<'a, str>>)> for ReadStreamError<'a>
         }
     }
 }
+
+impl<'a> fmt::Display for ReadStreamError<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use std::error::Error;
+        match *self {
+            ReadStreamError::Error(Some(ref msg)) => write!(fmt, "{}: {}", self.description(), msg),
+            _ => write!(fmt, "{}", self.description()),
+        }
+    }
+}
+
+impl<'a> error::Error for ReadStreamError<'a> {
+    fn description(&self) -> &str {
+        use self::ReadStreamError::*;
+        match *self {
+            NoStream => "Stream was not found",
+            StreamDeleted => "Stream had been deleted",
+            NotModified => "Stream was not modified",
+            Error(_) => "Server failed to read the stream",
+            AccessDenied => "Access to stream was denied",
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use std::borrow::Cow;
+use {LogPosition, StreamVersion};
+
+/// Successful response to `Message::SubscribeToStream`, after which the events appended to the
+/// stream are pushed as `StreamEventAppeared` with the same correlation id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionConfirmation {
+    /// Position of the last commit in `$all` when the subscription was made
+    pub last_commit_position: LogPosition,
+    /// The latest event number of the subscribed stream, for reading the events from before the
+    /// subscription. `None` for subscriptions to all streams and for streams without events.
+    pub last_event_number: Option<StreamVersion>,
+}
+
+/// Successful response to `Message::ConnectToPersistentSubscription`, after which the events of
+/// the group are pushed to the consumer until they are acknowledged or not.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PersistentSubscriptionConfirmation<'a> {
+    /// Identifies the subscription in the `PersistentSubscriptionAckEvents` and
+    /// `PersistentSubscriptionNakEvents` of the consumer
+    pub subscription_id: Cow<'a, str>,
+    /// Position of the last commit in `$all` when the consumer connected
+    pub last_commit_position: LogPosition,
+    /// The latest event number of the subscribed stream, `None` for streams without events
+    pub last_event_number: Option<StreamVersion>,
+}
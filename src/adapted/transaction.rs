@@ -0,0 +1,90 @@
+use std::fmt;
+use std::error::Error;
+use raw::client_messages::{OperationResult};
+
+/// Like `OperationResult` on the wire but does not have a success value. Explains the reason for
+/// failure of `TransactionStart`, `TransactionWrite` or `TransactionCommit`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum TransactionFailure {
+    /// Server failed to process the request before timeout
+    PrepareTimeout,
+    /// Server timed out while awaiting commit to be processed
+    CommitTimeout,
+    /// Server timed out while awaiting for a forwarded request to complete
+    ForwardTimeout,
+    /// Optimistic locking failure; stream version was not the expected
+    WrongExpectedVersion,
+    /// Stream has been deleted
+    StreamDeleted,
+    /// The transaction is not known to the server, it has been committed already or was started
+    /// on another node
+    InvalidTransaction,
+    /// No authentication provided or insufficient permissions to a stream
+    AccessDenied,
+}
+
+impl TransactionFailure {
+    /// Return `true` if the operation failed in a transient way that might be resolved by
+    /// retrying.
+    pub fn is_transient(&self) -> bool {
+        use self::TransactionFailure::*;
+        match *self {
+            PrepareTimeout | CommitTimeout | ForwardTimeout => true,
+            _ => false
+        }
+    }
+}
+
+impl From<OperationResult> for TransactionFailure {
+    fn from(or: OperationResult) -> Self {
+        use self::OperationResult::*;
+
+        match or {
+            Success => unreachable!(),
+            PrepareTimeout => TransactionFailure::PrepareTimeout,
+            CommitTimeout => TransactionFailure::CommitTimeout,
+            ForwardTimeout => TransactionFailure::ForwardTimeout,
+            WrongExpectedVersion => TransactionFailure::WrongExpectedVersion,
+            StreamDeleted => TransactionFailure::StreamDeleted,
+            InvalidTransaction => TransactionFailure::InvalidTransaction,
+            AccessDenied => TransactionFailure::AccessDenied,
+        }
+    }
+}
+
+impl Into<OperationResult> for TransactionFailure {
+    fn into(self) -> OperationResult {
+        use self::TransactionFailure::*;
+        match self {
+            PrepareTimeout => OperationResult::PrepareTimeout,
+            CommitTimeout => OperationResult::CommitTimeout,
+            ForwardTimeout => OperationResult::ForwardTimeout,
+            WrongExpectedVersion => OperationResult::WrongExpectedVersion,
+            StreamDeleted => OperationResult::StreamDeleted,
+            InvalidTransaction => OperationResult::InvalidTransaction,
+            AccessDenied => OperationResult::AccessDenied
+        }
+    }
+}
+
+impl fmt::Display for TransactionFailure {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.description())
+    }
+}
+
+impl Error for TransactionFailure {
+    fn description(&self) -> &str {
+        use self::TransactionFailure::*;
+        match *self {
+            PrepareTimeout => "Internal server timeout, should be retried",
+            CommitTimeout => "Internal server timeout, should be retried",
+            ForwardTimeout => "Server timed out while awaiting response to forwarded request, should be retried",
+            WrongExpectedVersion => "Stream version was not expected, optimistic locking failure",
+            StreamDeleted => "Stream had been deleted",
+            InvalidTransaction => "Transaction is not known to the server",
+            AccessDenied => "Access to stream was denied"
+        }
+    }
+}
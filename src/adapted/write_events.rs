@@ -5,7 +5,7 @@ use raw::client_messages::{OperationResult};
 use {StreamVersion, LogPosition};
 
 /// Successful response to `Message::WriteEvents`
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct WriteEventsCompleted {
     /// The event number range assigned to the written events
     pub event_numbers: Range<StreamVersion>,
@@ -19,7 +19,8 @@ pub struct WriteEventsCompleted {
 
 /// Like `OperationResult` on the wire but does not have a success value. Explains the reason for
 /// failure.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum WriteEventsFailure {
     /// Server failed to process the request before timeout
     PrepareTimeout,
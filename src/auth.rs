@@ -1,27 +1,69 @@
 use std::borrow::Cow;
 use std::fmt;
 use std::io;
+use std::error::Error;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{self, Ordering};
 use byteorder::{ReadBytesExt, WriteBytesExt};
+use futures::{future, Future};
+use operation;
 
 /// Username and password authentication token embedded in requests as there is no concept of
 /// session in the TCP protocol, every request must be authenticated.
-#[derive(Clone, PartialEq, Eq)]
-pub struct UsernamePassword(pub Cow<'static, str>, pub Cow<'static, str>);
+///
+/// The password is kept in a buffer which is overwritten with zeros when the value is dropped, so
+/// that it does not linger in the memory of a long running process. Neither the username nor the
+/// password is included in the `Debug` output.
+///
+/// The encoded credentials are not covered by this: they are written into the write buffer of the
+/// framed connection, which is advanced but not zeroed once flushed, so the bytes stay in memory
+/// until later frames overwrite them or the connection is dropped.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct UsernamePassword(Cow<'static, str>, Secret);
 
 impl fmt::Debug for UsernamePassword {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "({:?}, PASSWORD)", self.0)
+        write!(fmt, "(USERNAME, PASSWORD)")
     }
 }
 
+/// String which is overwritten with zeros when dropped.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Secret(String);
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        zeroize(unsafe { self.0.as_mut_vec() });
+    }
+}
+
+/// Overwrites the bytes with zeros in a way the compiler will not optimize away.
+pub fn zeroize(bytes: &mut [u8]) {
+    for b in bytes.iter_mut() {
+        unsafe { ptr::write_volatile(b, 0) };
+    }
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
 impl UsernamePassword {
-    /// Create a new value
+    /// Create a new value. The password is copied into a buffer owned by the value.
     pub fn new<S: Into<Cow<'static, str>>>(username: S, password: S) -> UsernamePassword {
         let username = username.into();
         let password = password.into();
         assert!(username.len() < 255);
         assert!(password.len() < 255);
-        UsernamePassword(username, password)
+        UsernamePassword(username, Secret(password.into_owned()))
+    }
+
+    /// The username
+    pub fn username(&self) -> &str {
+        &self.0
+    }
+
+    /// The password
+    pub fn password(&self) -> &str {
+        &(self.1).0
     }
 
     #[doc(hidden)]
@@ -29,7 +71,9 @@ impl UsernamePassword {
         use std::string;
 
         fn convert_utf8_err(e: string::FromUtf8Error) -> io::Error {
-            io::Error::new(io::ErrorKind::InvalidData, e.utf8_error())
+            let err = e.utf8_error();
+            zeroize(&mut e.into_bytes());
+            io::Error::new(io::ErrorKind::InvalidData, err)
         }
 
         let len = buf.read_u8()?;
@@ -40,24 +84,188 @@ impl UsernamePassword {
         let len = buf.read_u8()?;
         let mut password = vec![0u8; len as usize];
         buf.read_exact(&mut password[..])?;
-        let password = String::from_utf8(password).map_err(convert_utf8_err)?;
+        let password = Secret(String::from_utf8(password).map_err(convert_utf8_err)?);
 
-        Ok(UsernamePassword(Cow::Owned(username), Cow::Owned(password)))
+        Ok(UsernamePassword(Cow::Owned(username), password))
     }
 
     #[doc(hidden)]
     pub fn encode<W: WriteBytesExt>(&self, buf: &mut W) -> io::Result<usize> {
         buf.write_u8(self.0.len() as u8)?;
         buf.write_all(self.0.as_bytes())?;
-        buf.write_u8(self.1.len() as u8)?;
-        buf.write_all(self.1.as_bytes())?;
+        buf.write_u8(self.password().len() as u8)?;
+        buf.write_all(self.password().as_bytes())?;
+
+        Ok(self.encoded_len())
+    }
 
-        Ok(1 + self.0.len() + 1 + self.1.len())
+    #[doc(hidden)]
+    pub fn encoded_len(&self) -> usize {
+        1 + self.0.len() + 1 + self.password().len()
     }
 }
 
 impl Into<(String, String)> for UsernamePassword {
     fn into(self) -> (String, String) {
-        (self.0.into_owned(), self.1.into_owned())
+        let mut secret = self.1;
+        (self.0.into_owned(), mem::replace(&mut secret.0, String::new()))
+    }
+}
+
+/// Source of the credentials for the operations sent without any, consulted for every operation
+/// so that the credentials can be rotated while the client is running. See
+/// `ConnectionSettingsBuilder::credentials_provider`.
+pub trait CredentialsProvider {
+    /// Returns the credentials to use for the next operation.
+    fn get(&self) -> Box<Future<Item = UsernamePassword, Error = io::Error>>;
+
+    /// Returns the credentials to use for the next operation targeting `stream_id`, see
+    /// `RawMessage::stream_id`. Defaults to `get`, ignoring the stream.
+    fn get_for_stream(&self, stream_id: Option<&str>) -> Box<Future<Item = UsernamePassword, Error = io::Error>> {
+        let _ = stream_id;
+        self.get()
+    }
+}
+
+/// Static credentials.
+impl CredentialsProvider for UsernamePassword {
+    fn get(&self) -> Box<Future<Item = UsernamePassword, Error = io::Error>> {
+        Box::new(future::ok(self.clone()))
+    }
+}
+
+/// `CredentialsProvider` choosing the credentials by the prefix of the targeted stream, for
+/// example to use elevated credentials for the system streams starting with `$` while the
+/// regular streams use the default credentials. When multiple prefixes match, the longest one
+/// wins; operations matching none use the default provider.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::{ConnectionSettings, CredentialsMap, UsernamePassword};
+///
+/// let credentials = CredentialsMap::new(UsernamePassword::new("app", "secret"))
+///     .prefix("$", UsernamePassword::new("admin", "changeit"));
+///
+/// let settings = ConnectionSettings::builder()
+///     .credentials_provider(credentials)
+///     .build();
+/// # let _ = settings;
+/// ```
+pub struct CredentialsMap {
+    default: Box<CredentialsProvider + Send + Sync>,
+    prefixes: Vec<(String, UsernamePassword)>,
+}
+
+impl CredentialsMap {
+    /// Creates a map using `default` for the operations not matching any prefix.
+    pub fn new<P: CredentialsProvider + Send + Sync + 'static>(default: P) -> Self {
+        CredentialsMap {
+            default: Box::new(default),
+            prefixes: Vec::new(),
+        }
+    }
+
+    /// Uses `credentials` for the operations targeting streams starting with `prefix`.
+    pub fn prefix<S: Into<String>>(mut self, prefix: S, credentials: UsernamePassword) -> Self {
+        self.prefixes.push((prefix.into(), credentials));
+        self
+    }
+
+    fn lookup(&self, stream_id: &str) -> Option<&UsernamePassword> {
+        self.prefixes.iter()
+            .filter(|&&(ref prefix, _)| stream_id.starts_with(prefix.as_str()))
+            .max_by_key(|&&(ref prefix, _)| prefix.len())
+            .map(|&(_, ref credentials)| credentials)
+    }
+}
+
+impl fmt::Debug for CredentialsMap {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let prefixes = self.prefixes.iter().map(|&(ref prefix, _)| prefix).collect::<Vec<_>>();
+        write!(fmt, "CredentialsMap {{ prefixes: {:?} }}", prefixes)
+    }
+}
+
+impl CredentialsProvider for CredentialsMap {
+    fn get(&self) -> Box<Future<Item = UsernamePassword, Error = io::Error>> {
+        self.default.get()
+    }
+
+    fn get_for_stream(&self, stream_id: Option<&str>) -> Box<Future<Item = UsernamePassword, Error = io::Error>> {
+        match stream_id.and_then(|id| self.lookup(id)) {
+            Some(credentials) => Box::new(future::ok(credentials.clone())),
+            None => self.default.get_for_stream(stream_id),
+        }
+    }
+}
+
+/// The server did not accept the credentials of an operation, answering it with
+/// `NotAuthenticated`. Operations failing because of this are completed with an `io::Error` of
+/// kind `PermissionDenied` wrapping this value, see `AuthenticationFailed::find`, and the
+/// connection reports it as `ConnectionEvent::AuthenticationFailed`.
+///
+/// The server may also close the connection over bad credentials. The operations in flight when a
+/// connection closes, which were sent with the same credentials as an operation answered with
+/// `NotAuthenticated` on that connection, fail with this as well instead of being sent again. The
+/// other operations, including an `Authenticate` with other credentials, are handled like after
+/// any lost connection.
+///
+/// Unlike losing the connection this is never transient: sending the operation again with the
+/// same credentials fails the same way, so the operation is not retried by the connection and
+/// should not be retried by the application either.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticationFailed {
+    /// The reason given by the server
+    pub reason: String,
+}
+
+impl AuthenticationFailed {
+    /// Create a new value with the given reason.
+    pub fn new<S: Into<String>>(reason: S) -> Self {
+        AuthenticationFailed { reason: reason.into() }
+    }
+
+    /// Returns the `AuthenticationFailed` wrapped by the given error, if any.
+    pub fn find(e: &io::Error) -> Option<&AuthenticationFailed> {
+        operation::find::<AuthenticationFailed>(e)
+    }
+}
+
+impl fmt::Display for AuthenticationFailed {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: {}", self.description(), self.reason)
+    }
+}
+
+impl Error for AuthenticationFailed {
+    fn description(&self) -> &str {
+        "Authentication failed"
+    }
+}
+
+impl From<AuthenticationFailed> for io::Error {
+    fn from(failed: AuthenticationFailed) -> io::Error {
+        io::Error::new(io::ErrorKind::PermissionDenied, failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+    use super::{CredentialsMap, CredentialsProvider, UsernamePassword};
+
+    #[test]
+    fn credentials_map_longest_prefix() {
+        let map = CredentialsMap::new(UsernamePassword::new("app", "1"))
+            .prefix("$", UsernamePassword::new("admin", "2"))
+            .prefix("$$", UsernamePassword::new("ops", "3"));
+
+        let username = |stream_id| map.get_for_stream(stream_id).wait().unwrap().username().to_owned();
+
+        assert_eq!(username(Some("orders-1")), "app");
+        assert_eq!(username(Some("$all")), "admin");
+        assert_eq!(username(Some("$$orders-1")), "ops");
+        assert_eq!(username(None), "app");
     }
 }
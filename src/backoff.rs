@@ -0,0 +1,138 @@
+//! Schedules for the delays between retried attempts, such as reconnecting.
+
+use std::cmp;
+use std::time::Duration;
+
+use rand::{self, Rng};
+
+/// Schedule of delays between consecutive failed attempts. A value is created for each sequence of
+/// attempts through `NewBackoff`, and reset once an attempt succeeds.
+pub trait Backoff {
+    /// Returns the delay to wait before the next attempt, after an attempt has failed.
+    fn next_delay(&mut self) -> Duration;
+
+    /// Called after a successful attempt; the next delay should start the schedule over.
+    fn reset(&mut self);
+}
+
+/// Factory of `Backoff` values, stored in `ConnectionSettings`.
+pub trait NewBackoff {
+    /// Creates a new `Backoff` starting from the beginning of the schedule.
+    fn new_backoff(&self) -> Box<Backoff>;
+}
+
+impl<F> NewBackoff for F where F: Fn() -> Box<Backoff> {
+    fn new_backoff(&self) -> Box<Backoff> {
+        self()
+    }
+}
+
+/// Exponential backoff with "decorrelated jitter": each delay is picked at random between `base`
+/// and three times the previous delay, capped at `cap`. The randomness keeps many clients from
+/// reconnecting in lockstep after a server restart.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use eventstore_tcp::{Backoff, DecorrelatedJitter};
+///
+/// let mut backoff = DecorrelatedJitter::new(Duration::from_millis(100), Duration::from_secs(1));
+/// let delay = backoff.next_delay();
+///
+/// assert!(delay >= Duration::from_millis(100));
+/// assert!(delay <= Duration::from_secs(1));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecorrelatedJitter {
+    base: Duration,
+    cap: Duration,
+    previous: Duration,
+}
+
+impl DecorrelatedJitter {
+    /// Creates a new schedule with the smallest delay `base` and largest delay `cap`. Panics if
+    /// `base` is larger than `cap`.
+    pub fn new(base: Duration, cap: Duration) -> Self {
+        assert!(base <= cap);
+        DecorrelatedJitter {
+            base: base,
+            cap: cap,
+            previous: base,
+        }
+    }
+}
+
+impl Default for DecorrelatedJitter {
+    /// Delays between 100ms and 10s.
+    fn default() -> Self {
+        DecorrelatedJitter::new(Duration::from_millis(100), Duration::from_secs(10))
+    }
+}
+
+impl Backoff for DecorrelatedJitter {
+    fn next_delay(&mut self) -> Duration {
+        let base = as_millis(self.base);
+        let upper = cmp::max(base + 1, as_millis(self.previous).saturating_mul(3));
+        let delay = cmp::min(self.cap, Duration::from_millis(rand::thread_rng().gen_range(base, upper)));
+
+        self.previous = delay;
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.previous = self.base;
+    }
+}
+
+impl NewBackoff for DecorrelatedJitter {
+    fn new_backoff(&self) -> Box<Backoff> {
+        let mut fresh = self.clone();
+        fresh.reset();
+        Box::new(fresh)
+    }
+}
+
+fn as_millis(d: Duration) -> u64 {
+    d.as_secs().saturating_mul(1000) + (d.subsec_nanos() / 1_000_000) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{Backoff, NewBackoff, DecorrelatedJitter};
+
+    #[test]
+    fn delays_stay_within_bounds() {
+        let base = Duration::from_millis(10);
+        let cap = Duration::from_millis(500);
+        let mut backoff = DecorrelatedJitter::new(base, cap);
+
+        for _ in 0..100 {
+            let delay = backoff.next_delay();
+            assert!(delay >= base, "{:?} < {:?}", delay, base);
+            assert!(delay <= cap, "{:?} > {:?}", delay, cap);
+        }
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let base = Duration::from_millis(10);
+        let mut backoff = DecorrelatedJitter::new(base, Duration::from_secs(60));
+
+        for _ in 0..20 {
+            backoff.next_delay();
+        }
+
+        backoff.reset();
+
+        // the first delay is picked from base..3*base
+        assert!(backoff.next_delay() < base * 3);
+    }
+
+    #[test]
+    fn new_backoff_from_closure() {
+        let new = || Box::new(DecorrelatedJitter::new(Duration::from_millis(1), Duration::from_millis(1))) as Box<Backoff>;
+        assert_eq!(new.new_backoff().next_delay(), Duration::from_millis(1));
+    }
+}
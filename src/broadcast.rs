@@ -0,0 +1,162 @@
+//! Feeding the items of a single stream, such as a subscription, to multiple consumers.
+
+use std::sync::Arc;
+
+use futures::{Async, AsyncSink, Future, Poll, Sink, Stream};
+use futures::sync::mpsc;
+
+/// Future forwarding every item of the stream to all of the consumers. The items are wrapped in
+/// an `Arc` once and only the `Arc` is cloned for each consumer, so events with large payloads are
+/// never copied.
+///
+/// The next item is read only after every consumer has accepted the previous one, so the slowest
+/// consumer sets the pace. Consumers are removed once their receiver has been dropped. Completes
+/// when the stream ends, after which the receivers end once they have been drained, or when all
+/// of the consumers are gone. Fails with the error of the stream.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate futures;
+/// extern crate eventstore_tcp;
+///
+/// use futures::{stream, Future, Stream};
+/// use eventstore_tcp::Broadcast;
+///
+/// # fn main() {
+/// let mut broadcast = Broadcast::new(stream::iter_ok::<_, ()>(vec!["a", "b"]));
+/// let first = broadcast.subscribe(2);
+/// let second = broadcast.subscribe(2);
+///
+/// broadcast.wait().unwrap();
+///
+/// assert_eq!(first.map(|item| *item).collect().wait().unwrap(), vec!["a", "b"]);
+/// assert_eq!(second.map(|item| *item).collect().wait().unwrap(), vec!["a", "b"]);
+/// # }
+/// ```
+pub struct Broadcast<S: Stream> {
+    stream: S,
+    consumers: Vec<Consumer<S::Item>>,
+}
+
+struct Consumer<T> {
+    tx: mpsc::Sender<Arc<T>>,
+    /// Item not yet accepted by the consumer
+    item: Option<Arc<T>>,
+}
+
+impl<S: Stream> Broadcast<S> {
+    /// Creates a broadcast of `stream` without any consumers.
+    pub fn new(stream: S) -> Self {
+        Broadcast {
+            stream: stream,
+            consumers: Vec::new(),
+        }
+    }
+
+    /// Adds a consumer receiving the items following the ones already forwarded, buffering up to
+    /// `buffer` items in addition to one item per consumer.
+    pub fn subscribe(&mut self, buffer: usize) -> mpsc::Receiver<Arc<S::Item>> {
+        let (tx, rx) = mpsc::channel(buffer);
+        self.consumers.push(Consumer { tx: tx, item: None });
+        rx
+    }
+
+    /// Offers the current item to the consumers which have not accepted it yet. Returns `true` if
+    /// every remaining consumer has accepted it.
+    fn flush(&mut self) -> bool {
+        let mut accepted = true;
+        let mut i = 0;
+
+        while i < self.consumers.len() {
+            let gone = {
+                let consumer = &mut self.consumers[i];
+
+                let sent = match consumer.item.take() {
+                    Some(item) => match consumer.tx.start_send(item) {
+                        Ok(AsyncSink::Ready) => Ok(()),
+                        Ok(AsyncSink::NotReady(item)) => {
+                            consumer.item = Some(item);
+                            accepted = false;
+                            Ok(())
+                        },
+                        Err(_) => Err(()),
+                    },
+                    None => Ok(()),
+                };
+
+                sent.is_err() || consumer.tx.poll_complete().is_err()
+            };
+
+            if gone {
+                self.consumers.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        accepted
+    }
+}
+
+impl<S: Stream> Future for Broadcast<S> {
+    type Item = ();
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<(), S::Error> {
+        loop {
+            if !self.flush() {
+                return Ok(Async::NotReady);
+            }
+
+            if self.consumers.is_empty() {
+                return Ok(Async::Ready(()));
+            }
+
+            match self.stream.poll()? {
+                Async::Ready(Some(item)) => {
+                    let item = Arc::new(item);
+
+                    for consumer in &mut self.consumers {
+                        consumer.item = Some(item.clone());
+                    }
+                },
+                Async::Ready(None) => return Ok(Async::Ready(())),
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use futures::{stream, Future, Stream};
+    use super::Broadcast;
+
+    #[test]
+    fn items_are_shared() {
+        let mut broadcast = Broadcast::new(stream::iter_ok::<_, ()>(vec![vec![1u8; 1024], vec![2u8; 1024]]));
+        let first = broadcast.subscribe(2);
+        let second = broadcast.subscribe(2);
+
+        broadcast.wait().unwrap();
+
+        let first = first.collect().wait().unwrap();
+        let second = second.collect().wait().unwrap();
+
+        assert_eq!(first.len(), 2);
+        assert!(first.iter().zip(second.iter()).all(|(a, b)| Arc::ptr_eq(a, b)));
+    }
+
+    #[test]
+    fn dropped_consumers_are_removed() {
+        let mut broadcast = Broadcast::new(stream::iter_ok::<_, ()>(0..10));
+        let kept = broadcast.subscribe(10);
+        drop(broadcast.subscribe(0));
+
+        broadcast.wait().unwrap();
+
+        assert_eq!(kept.map(|item| *item).collect().wait().unwrap(), (0..10).collect::<Vec<_>>());
+    }
+}
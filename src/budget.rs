@@ -0,0 +1,190 @@
+//! Limits on the memory held by the packages buffered for streaming requests.
+
+use std::io;
+use std::fmt;
+use std::error::Error;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use operation;
+
+/// Limits on the bytes of packages received for streaming requests but not yet taken by the
+/// consumers, see `EventStoreClient::call_streaming`. A slow consumer of a busy stream such as a
+/// subscription to `$all` would otherwise buffer packages until the process runs out of memory.
+///
+/// There is a limit per stream and a total limit shared by every stream of the connections using
+/// the same settings. The size of a package is the size of its encoded message. When a package
+/// would exceed a limit the policy given to `MemoryBudget::on_exceeded` decides what happens.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::{ConnectionSettings, MemoryBudget, OverBudget};
+///
+/// let settings = ConnectionSettings::builder()
+///     .stream_memory_budget(MemoryBudget::new(16 << 20, 256 << 20)
+///         .on_exceeded(|_| OverBudget::Drop))
+///     .build();
+///
+/// assert_eq!(settings.stream_memory_budget().unwrap().used(), 0);
+/// ```
+pub struct MemoryBudget {
+    per_stream: usize,
+    total: usize,
+    used: AtomicUsize,
+    policy: Box<Fn(&BudgetExceeded) -> OverBudget + Send + Sync>,
+}
+
+/// What to do with a package exceeding a `MemoryBudget`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OverBudget {
+    /// Drop the package, and keep the stream
+    Drop,
+    /// End the stream with `BudgetExceeded` as the last error
+    Close,
+}
+
+impl MemoryBudget {
+    /// Creates a budget of `per_stream` bytes per stream and `total` bytes in all, closing the
+    /// streams exceeding it. A single package larger than either limit always exceeds the budget.
+    pub fn new(per_stream: usize, total: usize) -> Self {
+        MemoryBudget {
+            per_stream: per_stream,
+            total: total,
+            used: AtomicUsize::new(0),
+            policy: Box::new(|_: &BudgetExceeded| OverBudget::Close),
+        }
+    }
+
+    /// Sets the hook deciding what happens to a package exceeding the budget. The hook can also
+    /// be used to log or count the occurrences.
+    ///
+    /// Defaults to `OverBudget::Close`.
+    pub fn on_exceeded<F: Fn(&BudgetExceeded) -> OverBudget + Send + Sync + 'static>(mut self, policy: F) -> Self {
+        self.policy = Box::new(policy);
+        self
+    }
+
+    /// The bytes currently buffered by all of the streams.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    /// Runs the policy hook.
+    pub fn exceeded(&self, exceeded: &BudgetExceeded) -> OverBudget {
+        (*self.policy)(exceeded)
+    }
+}
+
+impl fmt::Debug for MemoryBudget {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("MemoryBudget")
+            .field("per_stream", &self.per_stream)
+            .field("total", &self.total)
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+/// Reservation of bytes of a `MemoryBudget` for a buffered package, released when dropped.
+#[doc(hidden)]
+pub struct Charge {
+    bytes: usize,
+    stream: Arc<AtomicUsize>,
+    budget: Arc<MemoryBudget>,
+}
+
+impl Drop for Charge {
+    fn drop(&mut self) {
+        self.stream.fetch_sub(self.bytes, Ordering::SeqCst);
+        self.budget.used.fetch_sub(self.bytes, Ordering::SeqCst);
+    }
+}
+
+/// Reserves `bytes` for a package buffered by the stream currently holding `stream` bytes.
+#[doc(hidden)]
+pub fn charge(budget: &Arc<MemoryBudget>, stream: &Arc<AtomicUsize>, bytes: usize) -> Result<Charge, BudgetExceeded> {
+    let stream_bytes = stream.fetch_add(bytes, Ordering::SeqCst) + bytes;
+    let total_bytes = budget.used.fetch_add(bytes, Ordering::SeqCst) + bytes;
+
+    let charge = Charge {
+        bytes: bytes,
+        stream: stream.clone(),
+        budget: budget.clone(),
+    };
+
+    if stream_bytes > budget.per_stream || total_bytes > budget.total {
+        // dropping the charge releases the bytes again
+        return Err(BudgetExceeded {
+            package_bytes: bytes,
+            stream_bytes: stream_bytes - bytes,
+            total_bytes: total_bytes - bytes,
+        });
+    }
+
+    Ok(charge)
+}
+
+/// A package did not fit in the `MemoryBudget`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetExceeded {
+    /// The size of the package
+    pub package_bytes: usize,
+    /// The bytes buffered by the stream before the package
+    pub stream_bytes: usize,
+    /// The bytes buffered by all of the streams before the package
+    pub total_bytes: usize,
+}
+
+impl BudgetExceeded {
+    /// Returns the `BudgetExceeded` wrapped by the given error, if any.
+    pub fn find(e: &io::Error) -> Option<&BudgetExceeded> {
+        operation::find::<BudgetExceeded>(e)
+    }
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: {} bytes with {} bytes buffered by the stream and {} in total",
+               self.description(), self.package_bytes, self.stream_bytes, self.total_bytes)
+    }
+}
+
+impl Error for BudgetExceeded {
+    fn description(&self) -> &str {
+        "Memory budget exceeded"
+    }
+}
+
+impl From<BudgetExceeded> for io::Error {
+    fn from(exceeded: BudgetExceeded) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, exceeded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use super::{MemoryBudget, charge};
+
+    #[test]
+    fn charges_are_released_on_drop() {
+        let budget = Arc::new(MemoryBudget::new(100, 150));
+        let (a, b) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+
+        let first = charge(&budget, &a, 80).unwrap();
+        let exceeded = charge(&budget, &a, 30).err().unwrap();
+        assert_eq!(exceeded.stream_bytes, 80);
+        assert_eq!(a.load(Ordering::SeqCst), 80);
+
+        let second = charge(&budget, &b, 60).unwrap();
+        assert!(charge(&budget, &b, 20).is_err(), "total should be exceeded");
+        assert_eq!(budget.used(), 140);
+
+        drop(first);
+        drop(second);
+        assert_eq!(budget.used(), 0);
+        assert_eq!(a.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,477 @@
+//! Buffering writes locally while the server cannot be reached, for producers with intermittent
+//! connectivity.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
+use bytes::BytesMut;
+use futures::{future, stream, Future, Stream};
+use futures::future::Loop;
+use tokio_core::reactor::{Handle, Interval};
+use tokio_io::codec::{Decoder, Encoder};
+use tokio_service::Service;
+use uuid::Uuid;
+
+use adapted::{AdaptedMessage, WriteEventsFailure};
+use codec::PackageCodec;
+use package::Package;
+use raw::RawMessage;
+use response::{adapt, unexpected};
+
+/// Storage of the writes accepted by a `WriteBuffer` but not yet written to the server, oldest
+/// first. `MemoryWriteQueue` keeps them for the lifetime of the process and `FileWriteQueue`
+/// across restarts; other storage such as an embedded database can be plugged in by
+/// implementing this trait.
+pub trait WriteQueue {
+    /// Adds the write to the back of the queue. The write must be stored once this returns.
+    fn push(&mut self, write: Package) -> io::Result<()>;
+
+    /// The write at the front of the queue, `None` if the queue is empty.
+    fn front(&mut self) -> io::Result<Option<Package>>;
+
+    /// Removes the write at the front of the queue, once the server has answered it.
+    fn pop(&mut self) -> io::Result<()>;
+
+    /// The number of writes in the queue.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the queue is empty.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// `WriteQueue` in memory; the buffered writes are lost with the process.
+#[derive(Debug, Default)]
+pub struct MemoryWriteQueue {
+    writes: VecDeque<Package>,
+}
+
+impl MemoryWriteQueue {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        MemoryWriteQueue::default()
+    }
+}
+
+impl WriteQueue for MemoryWriteQueue {
+    fn push(&mut self, write: Package) -> io::Result<()> {
+        self.writes.push_back(write);
+        Ok(())
+    }
+
+    fn front(&mut self) -> io::Result<Option<Package>> {
+        Ok(self.writes.front().cloned())
+    }
+
+    fn pop(&mut self) -> io::Result<()> {
+        self.writes.pop_front();
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.writes.len()
+    }
+}
+
+/// `WriteQueue` in a file, surviving restarts of the process and of the machine.
+///
+/// The writes are appended to the file as encoded frames, and synced to the disk before `push`
+/// returns. The offset of the first write not yet removed is kept in a second file with `.ack`
+/// appended to the path, and the file is truncated whenever the queue becomes empty. A frame
+/// left incomplete by a crash during `push` is dropped when the queue is opened again; that write
+/// was never accepted. The credentials of the writes, if any, are stored as they are, so the
+/// file should be as protected as the credentials, or the credentials left to the
+/// `CredentialsProvider` of the connection.
+#[derive(Debug)]
+pub struct FileWriteQueue {
+    file: File,
+    ack: File,
+    /// The writes with the offset following each in the file
+    writes: VecDeque<(Package, u64)>,
+    end: u64,
+}
+
+impl FileWriteQueue {
+    /// Opens the queue at `path`, creating the file if it does not exist, and reads the writes
+    /// in it which have not been removed.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        let mut ack = OpenOptions::new().read(true).write(true).create(true).open(ack_path(path))?;
+
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+
+        let mut acked = Vec::new();
+        ack.read_to_end(&mut acked)?;
+
+        // the offset is past the end if the queue was truncated, but not yet acknowledged as empty
+        let start = match acked.len() {
+            8 => io::Cursor::new(&acked).read_u64::<LittleEndian>()?,
+            _ => 0,
+        };
+        let start = if start > contents.len() as u64 { 0 } else { start };
+
+        let mut buf = BytesMut::from(contents[start as usize..].to_vec());
+        let mut writes = VecDeque::new();
+        let mut end = start;
+
+        loop {
+            let before = buf.len();
+
+            match PackageCodec.decode(&mut buf)? {
+                Some(write) => {
+                    end += (before - buf.len()) as u64;
+                    writes.push_back((write, end));
+                },
+                None => break,
+            }
+        }
+
+        if end < contents.len() as u64 {
+            warn!("dropping an incomplete write of {} bytes at the end of {}", contents.len() as u64 - end, path.display());
+            file.set_len(end)?;
+        }
+
+        Ok(FileWriteQueue {
+            file: file,
+            ack: ack,
+            writes: writes,
+            end: end,
+        })
+    }
+
+    fn acknowledge(&mut self, offset: u64) -> io::Result<()> {
+        self.ack.seek(SeekFrom::Start(0))?;
+        self.ack.write_u64::<LittleEndian>(offset)?;
+        self.ack.sync_data()
+    }
+}
+
+fn ack_path(path: &Path) -> PathBuf {
+    let mut ack = path.as_os_str().to_owned();
+    ack.push(".ack");
+    PathBuf::from(ack)
+}
+
+impl WriteQueue for FileWriteQueue {
+    fn push(&mut self, write: Package) -> io::Result<()> {
+        let mut buf = BytesMut::new();
+        PackageCodec.encode(write.clone(), &mut buf)?;
+
+        self.file.write_all(&buf)?;
+        self.file.sync_data()?;
+
+        self.end += buf.len() as u64;
+        self.writes.push_back((write, self.end));
+        Ok(())
+    }
+
+    fn front(&mut self) -> io::Result<Option<Package>> {
+        Ok(self.writes.front().map(|&(ref write, _)| write.clone()))
+    }
+
+    fn pop(&mut self) -> io::Result<()> {
+        let offset = match self.writes.pop_front() {
+            Some((_, offset)) => offset,
+            None => return Ok(()),
+        };
+
+        if !self.writes.is_empty() {
+            return self.acknowledge(offset);
+        }
+
+        self.file.set_len(0)?;
+        self.file.sync_data()?;
+        self.end = 0;
+        self.acknowledge(0)
+    }
+
+    fn len(&self) -> usize {
+        self.writes.len()
+    }
+}
+
+/// Outcome of `WriteBuffer::flush`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Flushed {
+    /// Writes completed by the server
+    pub written: usize,
+    /// Writes the server refused for good, such as the ones with a wrong expected version, with
+    /// their streams; these are removed from the queue as well
+    pub rejected: Vec<(String, WriteEventsFailure)>,
+}
+
+/// Accepts writes regardless of the connection, storing them in a `WriteQueue` first, and sends
+/// them to the server in the order they were accepted with `WriteBuffer::flush`.
+///
+/// The writes are `WriteEvents` packages as built with `Builder::write_events`. The ids of their
+/// events are fixed when they are built, so a write sent again after its response was lost is
+/// recognized by the server as a duplicate while it remembers the earlier write. The expected
+/// version of the writes is checked only when they are flushed, which may be long after they
+/// were accepted; `ExpectedVersion::Any` suits most buffered producers.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// extern crate futures;
+/// extern crate tokio_core;
+/// extern crate uuid;
+/// extern crate eventstore_tcp;
+///
+/// use std::time::Duration;
+/// use futures::Stream;
+/// use tokio_core::reactor::Core;
+/// use uuid::Uuid;
+/// use eventstore_tcp::{Builder, ContentType, EventStoreClient, ExpectedVersion, WriteBuffer, FileWriteQueue};
+///
+/// fn main() {
+///     let mut core = Core::new().unwrap();
+///     let handle = core.handle();
+///
+///     let client = core.run(EventStoreClient::connect(&"127.0.0.1:1113".parse().unwrap(), &handle)).unwrap();
+///     let buffer = WriteBuffer::new(client, FileWriteQueue::open("/var/lib/sensor/writes").unwrap());
+///
+///     buffer.write(Builder::write_events()
+///         .stream_id("sensor-1")
+///         .expected_version(ExpectedVersion::Any)
+///         .new_event()
+///             .event_id(Uuid::new_v4())
+///             .event_type("reading")
+///             .data("{\"celsius\":21.5}")
+///             .data_content_type(ContentType::Json)
+///         .done()
+///         .build_package(None, None)).unwrap();
+///
+///     core.run(buffer.flush_every(Duration::from_secs(5), &handle).for_each(|_| Ok(()))).unwrap();
+/// }
+/// ```
+pub struct WriteBuffer<S, Q> {
+    service: Rc<S>,
+    queue: Rc<RefCell<Q>>,
+    flushing: Rc<Cell<bool>>,
+}
+
+impl<S, Q> Clone for WriteBuffer<S, Q> {
+    fn clone(&self) -> Self {
+        WriteBuffer {
+            service: self.service.clone(),
+            queue: self.queue.clone(),
+            flushing: self.flushing.clone(),
+        }
+    }
+}
+
+/// Marks the buffer as flushing until dropped, also when the flush is dropped before completing.
+struct Flushing(Rc<Cell<bool>>);
+
+impl Drop for Flushing {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+impl<S, Q> WriteBuffer<S, Q>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static,
+          Q: WriteQueue + 'static
+{
+    /// Creates a buffer sending the writes through `service`, continuing with the writes already
+    /// in `queue`.
+    pub fn new(service: S, queue: Q) -> Self {
+        WriteBuffer {
+            service: Rc::new(service),
+            queue: Rc::new(RefCell::new(queue)),
+            flushing: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Stores the write in the queue, to be sent with the next flush. Fails with `InvalidInput`
+    /// for packages other than `WriteEvents`, and with the error of the queue if the write could
+    /// not be stored.
+    pub fn write(&self, write: Package) -> io::Result<()> {
+        let is_write = match write.message {
+            RawMessage::WriteEvents(_) => true,
+            _ => false,
+        };
+
+        if !is_write {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Only writes can be buffered, not {:?}", write.message)));
+        }
+
+        self.queue.borrow_mut().push(write)
+    }
+
+    /// The number of writes not yet flushed.
+    pub fn len(&self) -> usize {
+        self.queue.borrow().len()
+    }
+
+    /// Returns `true` if every write has been flushed.
+    pub fn is_empty(&self) -> bool {
+        self.queue.borrow().is_empty()
+    }
+
+    /// Sends the queued writes one at a time, oldest first, removing each from the queue once the
+    /// server has answered it, until the queue is empty.
+    ///
+    /// Fails with the first error of the connection or transient failure of a write, such as a
+    /// `CommitTimeout`, leaving that write and the ones after it in the queue for the next flush.
+    /// Resolves to an empty `Flushed` right away while another flush of the buffer is running.
+    pub fn flush(&self) -> Box<Future<Item = Flushed, Error = io::Error>> {
+        if self.flushing.get() {
+            return Box::new(future::ok(Flushed::default()));
+        }
+
+        self.flushing.set(true);
+        let flushing = Flushing(self.flushing.clone());
+
+        let service = self.service.clone();
+        let queue = self.queue.clone();
+
+        let flushed = future::loop_fn(Flushed::default(), move |mut flushed| -> Box<Future<Item = Loop<Flushed, Flushed>, Error = io::Error>> {
+            let mut write = match queue.borrow_mut().front() {
+                Ok(Some(write)) => write,
+                Ok(None) => return Box::new(future::ok(Loop::Break(flushed))),
+                Err(e) => return Box::new(future::err(e)),
+            };
+
+            let stream_id = match write.message {
+                RawMessage::WriteEvents(ref we) => we.event_stream_id.to_string(),
+                _ => String::new(),
+            };
+
+            write.correlation_id = Uuid::new_v4();
+            let queue = queue.clone();
+
+            Box::new(service.call(write).and_then(move |response| {
+                match adapt(response)? {
+                    AdaptedMessage::WriteEventsCompleted(Ok(_)) => flushed.written += 1,
+                    AdaptedMessage::WriteEventsCompleted(Err(failure)) => {
+                        if failure.is_transient() {
+                            return Err(io::Error::new(io::ErrorKind::Other, failure));
+                        }
+
+                        warn!("dropping the buffered write to {}: {}", stream_id, failure);
+                        flushed.rejected.push((stream_id, failure));
+                    },
+                    other => return Err(unexpected(other)),
+                }
+
+                queue.borrow_mut().pop()?;
+                Ok(Loop::Continue(flushed))
+            }))
+        });
+
+        Box::new(flushed.then(move |res| {
+            drop(flushing);
+            res
+        }))
+    }
+
+    /// Flushes the buffer every `interval`, yielding the outcome of the flushes which sent
+    /// anything. Failed flushes are logged and tried again after the interval, so the writes go
+    /// out once the connection is back.
+    pub fn flush_every(&self, interval: Duration, handle: &Handle) -> Box<Stream<Item = Flushed, Error = io::Error>> {
+        let ticks = match Interval::new(interval, handle) {
+            Ok(ticks) => ticks,
+            Err(e) => return Box::new(stream::once::<Flushed, io::Error>(Err(e))),
+        };
+
+        let buffer = self.clone();
+
+        Box::new(ticks.and_then(move |_| buffer.flush().then(|res| -> io::Result<Flushed> {
+            match res {
+                Ok(flushed) => Ok(flushed),
+                Err(e) => {
+                    debug!("flushing the buffered writes failed: {}", e);
+                    Ok(Flushed::default())
+                },
+            }
+        })).filter(|flushed| flushed.written > 0 || !flushed.rejected.is_empty()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::env;
+    use futures::Future;
+    use uuid::Uuid;
+    use adapted::WriteEventsFailure;
+    use server::{MemoryStore, StoreService};
+    use package::Package;
+    use {Builder, ContentType, ExpectedVersion};
+    use super::{WriteBuffer, WriteQueue, MemoryWriteQueue, FileWriteQueue, ack_path};
+
+    fn write(stream_id: &'static str, expected_version: ExpectedVersion) -> Package {
+        Builder::write_events()
+            .stream_id(stream_id)
+            .expected_version(expected_version)
+            .new_event()
+                .event_id(Uuid::new_v4())
+                .event_type("reading")
+                .data("{}")
+                .data_content_type(ContentType::Json)
+            .done()
+            .build_package(None, None)
+    }
+
+    #[test]
+    fn flushes_in_order_dropping_rejected_writes() {
+        let buffer = WriteBuffer::new(StoreService::new(MemoryStore::new()), MemoryWriteQueue::new());
+
+        buffer.write(write("sensor-1", ExpectedVersion::NoStream)).unwrap();
+        buffer.write(write("sensor-1", ExpectedVersion::NoStream)).unwrap();
+        buffer.write(write("sensor-1", ExpectedVersion::Any)).unwrap();
+        assert!(buffer.write(Builder::ping().build_package(None, None)).is_err());
+        assert_eq!(buffer.len(), 3);
+
+        let flushed = buffer.flush().wait().unwrap();
+        assert_eq!(flushed.written, 2);
+        assert_eq!(flushed.rejected, vec![("sensor-1".to_owned(), WriteEventsFailure::WrongExpectedVersion)]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn file_queue_survives_reopening() {
+        let path = env::temp_dir().join(format!("eventstore-tcp-writes-{}", Uuid::new_v4()));
+        let writes = (0..3).map(|_| write("sensor-1", ExpectedVersion::Any)).collect::<Vec<_>>();
+
+        {
+            let mut queue = FileWriteQueue::open(&path).unwrap();
+            for w in &writes {
+                queue.push(w.clone()).unwrap();
+            }
+            queue.pop().unwrap();
+        }
+
+        // a push interrupted halfway through the frame
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[40, 0, 0, 0, 0x82]).unwrap();
+
+        {
+            let mut queue = FileWriteQueue::open(&path).unwrap();
+            assert_eq!(queue.len(), 2);
+            assert_eq!(queue.front().unwrap().unwrap().message, writes[1].message);
+
+            queue.pop().unwrap();
+            queue.pop().unwrap();
+            assert!(queue.is_empty());
+        }
+
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        assert!(FileWriteQueue::open(&path).unwrap().is_empty());
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(ack_path(&path)).unwrap();
+    }
+}
@@ -7,19 +7,31 @@
 //! On `build_package` the contents are not cloned but are moved into the returned `Package`
 //! which is not apparent from the use of `&mut self`.
 //!
-//! `UsernamePassword` can be used to authenticate specific requests. Specifying credentials for
-//! every request is currently the only supported way to authenticate.
+//! `build_package` does not validate the events of the write builders; use `try_build_package`
+//! to have them checked with `validate` first.
+//!
+//! `UsernamePassword` can be used to authenticate specific requests. The requests built without
+//! credentials are sent with the credentials of `ConnectionSettingsBuilder::credentials_provider`
+//! if one is set, for example a `CredentialsMap` choosing them by the targeted stream.
 //!
 //! Any `Option<Uuid>` can always be left unset, in which case `Uuid::new_v4()` (random Uuid) is
 //! generated.
 
 use std::borrow::Cow;
+use std::time::Duration;
 
 use uuid::Uuid;
 use package::Package;
-use {UsernamePassword, ReadDirection, ExpectedVersion, EventNumber, LogPosition, ContentType};
-use raw::client_messages::{WriteEvents, NewEvent, ReadEvent, ReadStreamEvents, ReadAllEvents, DeleteStream};
+use {UsernamePassword, ReadDirection, ExpectedVersion, EventNumber, LogPosition, ContentType, TransactionId,
+     PersistentSubscriptionSettings};
+use raw::client_messages::{WriteEvents, NewEvent, ReadEvent, ReadStreamEvents, ReadAllEvents, DeleteStream, IdentifyClient};
+use raw::client_messages::{TransactionStart, TransactionWrite, TransactionCommit, SubscribeToStream};
+use raw::client_messages::{CreatePersistentSubscription, UpdatePersistentSubscription, DeletePersistentSubscription};
+use raw::client_messages::{ConnectToPersistentSubscription, PersistentSubscriptionAckEvents, PersistentSubscriptionNakEvents};
+use raw::client_messages::mod_PersistentSubscriptionNakEvents::NakAction;
 use raw::RawMessage;
+use unsupported::encoded;
+use validate::{self, InvalidWrite};
 
 macro_rules! common_stream_id {
     () => {
@@ -101,6 +113,19 @@ macro_rules! common_build_package {
     }
 }
 
+macro_rules! common_try_build_package {
+    () => {
+        /// Build a package like `build_package` if `validate` finds no violations, otherwise
+        /// leaves the builder as it was.
+        pub fn try_build_package(&mut self, authentication: Option<UsernamePassword>, correlation_id: Option<Uuid>) -> Result<Package, InvalidWrite> {
+            self.validate()?;
+            Ok(self.build_package(authentication, correlation_id))
+        }
+    }
+}
+
+/// The client version sent in `IdentifyClient`, same as the one used by the .NET client.
+const CLIENT_VERSION: i32 = 1;
 
 /// Factory factory for creating builders.
 pub struct Builder;
@@ -117,6 +142,15 @@ impl Builder {
         SimpleBuilder(RawMessage::Authenticate)
     }
 
+    /// Simple builder for an IdentifyClient message which names the connection. Requires server
+    /// version 4.0 or later.
+    pub fn identify_client<S: Into<Cow<'static, str>>>(connection_name: S) -> SimpleBuilder {
+        SimpleBuilder(RawMessage::IdentifyClient(IdentifyClient {
+            version: CLIENT_VERSION,
+            connection_name: Some(connection_name.into()),
+        }))
+    }
+
     /// Builder for `WriteEvents`, which allows writing multiple events to a stream, with expected
     /// current version of the stream.
     pub fn write_events() -> WriteEventsBuilder {
@@ -144,6 +178,84 @@ impl Builder {
     pub fn read_all_events() -> ReadAllEventsBuilder {
         ReadAllEventsBuilder::new()
     }
+
+    /// Builder for `SubscribeToStream` which starts a volatile subscription to the new events of
+    /// a stream, or of all streams.
+    pub fn subscribe_to_stream() -> SubscribeToStreamBuilder {
+        SubscribeToStreamBuilder::new()
+    }
+
+    /// Simple builder for an UnsubscribeFromStream message, which ends the subscription started
+    /// with the same correlation id.
+    pub fn unsubscribe_from_stream() -> SimpleBuilder {
+        SimpleBuilder(RawMessage::UnsubscribeFromStream)
+    }
+
+    /// Builder for `TransactionStart` which starts a transaction for writing events to a stream
+    /// over multiple requests.
+    pub fn transaction_start() -> TransactionStartBuilder {
+        TransactionStartBuilder::new()
+    }
+
+    /// Builder for `TransactionWrite` which writes events within the transaction, the id of which
+    /// was returned by `TransactionStartCompleted`.
+    pub fn transaction_write(transaction_id: TransactionId) -> TransactionWriteBuilder {
+        TransactionWriteBuilder::new(transaction_id)
+    }
+
+    /// Builder for `TransactionCommit` which commits the events written within the transaction.
+    pub fn transaction_commit(transaction_id: TransactionId) -> TransactionCommitBuilder {
+        TransactionCommitBuilder::new(transaction_id)
+    }
+
+    /// Builder for `CreatePersistentSubscription` which creates a subscription group on a stream.
+    pub fn create_persistent_subscription() -> PersistentSubscriptionBuilder {
+        PersistentSubscriptionBuilder::new(false)
+    }
+
+    /// Builder for `UpdatePersistentSubscription` which replaces the settings of an existing
+    /// subscription group, taking the same `PersistentSubscriptionSettings` as the creation. The
+    /// settings left unset are reset to their defaults.
+    pub fn update_persistent_subscription() -> PersistentSubscriptionBuilder {
+        PersistentSubscriptionBuilder::new(true)
+    }
+
+    /// Builder for `DeletePersistentSubscription` which deletes a subscription group.
+    pub fn delete_persistent_subscription() -> DeletePersistentSubscriptionBuilder {
+        DeletePersistentSubscriptionBuilder::new()
+    }
+
+    /// Builder for `ConnectToPersistentSubscription` which joins a subscription group as a
+    /// consumer.
+    pub fn connect_to_persistent_subscription() -> ConnectToPersistentSubscriptionBuilder {
+        ConnectToPersistentSubscriptionBuilder::new()
+    }
+
+    /// Simple builder for acknowledging the events received by a persistent subscription as
+    /// processed. `subscription_id` is the one returned by `PersistentSubscriptionConfirmation`.
+    pub fn persistent_subscription_ack<S, I>(subscription_id: S, event_ids: I) -> SimpleBuilder
+        where S: Into<Cow<'static, str>>,
+              I: IntoIterator<Item = Uuid>
+    {
+        SimpleBuilder(encoded(0xCC, &PersistentSubscriptionAckEvents {
+            subscription_id: subscription_id.into(),
+            processed_event_ids: event_ids.into_iter().map(uuid_bytes).collect(),
+        }))
+    }
+
+    /// Simple builder for negatively acknowledging the events received by a persistent
+    /// subscription, with the action the server should take on them.
+    pub fn persistent_subscription_nak<S, I>(subscription_id: S, event_ids: I, action: NakAction) -> SimpleBuilder
+        where S: Into<Cow<'static, str>>,
+              I: IntoIterator<Item = Uuid>
+    {
+        SimpleBuilder(encoded(0xCD, &PersistentSubscriptionNakEvents {
+            subscription_id: subscription_id.into(),
+            processed_event_ids: event_ids.into_iter().map(uuid_bytes).collect(),
+            message: None,
+            action: action,
+        }))
+    }
 }
 
 /// Builder for messages without any additional contents.
@@ -211,11 +323,6 @@ impl WriteEventsBuilder {
         NewEventBuilder::new(self)
     }
 
-    fn push_event(&mut self, event: NewEvent<'static>) -> &mut Self {
-        self.events.push(event);
-        self
-    }
-
     fn build_command(&mut self) -> WriteEvents<'static> {
         use std::mem;
 
@@ -232,11 +339,33 @@ impl WriteEventsBuilder {
     }
 
     common_build_package!();
+
+    /// Checks the stream id and the events for mistakes the server would reject the write for,
+    /// returning all of them.
+    pub fn validate(&self) -> Result<(), InvalidWrite> {
+        validate::check(Some(self.event_stream_id.as_ref().map(|id| &id[..]).unwrap_or("")), &self.events)
+    }
+
+    common_try_build_package!();
+}
+
+impl EventsBuilder for WriteEventsBuilder {
+    fn push_event(&mut self, event: NewEvent<'static>) -> &mut Self {
+        self.events.push(event);
+        self
+    }
 }
 
-/// Builder for specifying an event when using `WriteEventsBuilder`.
-pub struct NewEventBuilder<'a> {
-    parent: &'a mut WriteEventsBuilder,
+/// Builders of requests carrying new events, `WriteEventsBuilder` and `TransactionWriteBuilder`,
+/// to which `NewEventBuilder` adds the events.
+pub trait EventsBuilder {
+    /// Adds the completed event.
+    fn push_event(&mut self, event: NewEvent<'static>) -> &mut Self;
+}
+
+/// Builder for specifying an event when using `WriteEventsBuilder` or `TransactionWriteBuilder`.
+pub struct NewEventBuilder<'a, P: EventsBuilder + 'a = WriteEventsBuilder> {
+    parent: &'a mut P,
 
     event_id: Option<Uuid>,
     event_type: Option<Cow<'static, str>>,
@@ -246,8 +375,8 @@ pub struct NewEventBuilder<'a> {
     metadata: Option<Cow<'static, [u8]>>,
 }
 
-impl<'a> NewEventBuilder<'a> {
-    fn new(parent: &'a mut WriteEventsBuilder) -> NewEventBuilder<'a> {
+impl<'a, P: EventsBuilder + 'a> NewEventBuilder<'a, P> {
+    fn new(parent: &'a mut P) -> NewEventBuilder<'a, P> {
         NewEventBuilder {
             parent: parent,
             event_id: None,
@@ -296,18 +425,12 @@ impl<'a> NewEventBuilder<'a> {
         self
     }
 
-    /// Completes building a new event for `WriteEventsBuilder` by adding a new
+    /// Completes building a new event for the parent builder by adding a new
     /// event to the builder and returning it.
     ///
-    /// The server has a hard limit on the size of new events accepted, but this method currently
-    /// builder currently does no size validation. Expect attempting to write over about 16MiB
-    /// events (data + metadata + on-disk framing) to fail.
-    pub fn done(self) -> &'a mut WriteEventsBuilder {
-
-        fn uuid_bytes(uuid: Uuid) -> Cow<'static, [u8]> {
-            Cow::Owned(uuid.as_bytes().into_iter().cloned().collect::<Vec<u8>>())
-        }
-
+    /// The server has a hard limit on the size of new events accepted, which this method does not
+    /// check; `validate` on the parent builder does, along with the other mistakes.
+    pub fn done(self) -> &'a mut P {
         let event = NewEvent {
             event_id: uuid_bytes(self.event_id.unwrap_or_else(|| Uuid::new_v4())),
             event_type: self.event_type.unwrap(),
@@ -320,8 +443,8 @@ impl<'a> NewEventBuilder<'a> {
         self.parent.push_event(event)
     }
 
-    /// Cancels building this new event returning the `WriteEventsBuilder` unmodified.
-    pub fn cancel(self) -> &'a mut WriteEventsBuilder {
+    /// Cancels building this new event returning the parent builder unmodified.
+    pub fn cancel(self) -> &'a mut P {
         self.parent
     }
 }
@@ -577,6 +700,457 @@ impl DeleteStreamBuilder {
     common_build_package!();
 }
 
+/// Builder for a volatile subscription `SubscribeToStream`. The subscription is to all streams
+/// if no stream id is set.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::Builder;
+///
+/// let package = Builder::subscribe_to_stream()
+///     .stream_id("my_stream-1")
+///     .resolve_link_tos(true) // default
+///     .build_package(None, None);
+/// ```
+pub struct SubscribeToStreamBuilder {
+    event_stream_id: Option<Cow<'static, str>>,
+    resolve_link_tos: Option<bool>,
+}
+
+impl SubscribeToStreamBuilder {
+    fn new() -> Self {
+        SubscribeToStreamBuilder {
+            event_stream_id: None,
+            resolve_link_tos: None,
+        }
+    }
+
+    common_stream_id!();
+
+    common_resolve_link_tos!();
+
+    fn build_message(&mut self) -> RawMessage<'static> {
+        RawMessage::SubscribeToStream(SubscribeToStream {
+            event_stream_id: self.event_stream_id.take().unwrap_or(Cow::Borrowed("")),
+            resolve_link_tos: self.resolve_link_tos.unwrap_or(true),
+        })
+    }
+
+    common_build_package!();
+}
+
+/// Builder for `TransactionStart`.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::{Builder, ExpectedVersion};
+///
+/// let package = Builder::transaction_start()
+///     .stream_id("my_stream-1")
+///     .expected_version(ExpectedVersion::NoStream)
+///     .require_master(false) // default
+///     .build_package(None, None);
+/// ```
+pub struct TransactionStartBuilder {
+    event_stream_id: Option<Cow<'static, str>>,
+    expected_version: Option<ExpectedVersion>,
+    require_master: Option<bool>,
+}
+
+impl TransactionStartBuilder {
+    fn new() -> Self {
+        TransactionStartBuilder {
+            event_stream_id: None,
+            expected_version: None,
+            require_master: None,
+        }
+    }
+
+    common_stream_id!();
+
+    common_expected_version!();
+
+    common_require_master!();
+
+    fn build_message(&mut self) -> RawMessage<'static> {
+        RawMessage::TransactionStart(TransactionStart {
+            event_stream_id: self.event_stream_id.take().expect("event_stream_id not set"),
+            expected_version: self.expected_version.take().unwrap_or(ExpectedVersion::Any).into(),
+            require_master: self.require_master.unwrap_or(false),
+        })
+    }
+
+    common_build_package!();
+}
+
+/// Builder for `TransactionWrite`, which writes events within a transaction like
+/// `WriteEventsBuilder` writes them to a stream.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::{Builder, ContentType, TransactionId};
+///
+/// let package = Builder::transaction_write(TransactionId::from(7)) // from TransactionStartCompleted
+///     .new_event()
+///         .event_type("meaning_of_life")
+///         .data("{ 'meaning': 42 }".as_bytes())
+///         .data_content_type(ContentType::Json)
+///     .done()
+///     .build_package(None, None);
+/// ```
+pub struct TransactionWriteBuilder {
+    transaction_id: TransactionId,
+    require_master: Option<bool>,
+    events: Vec<NewEvent<'static>>,
+}
+
+impl TransactionWriteBuilder {
+    fn new(transaction_id: TransactionId) -> Self {
+        TransactionWriteBuilder {
+            transaction_id: transaction_id,
+            require_master: None,
+            events: Vec::new(),
+        }
+    }
+
+    common_require_master!();
+
+    /// Start creating a new event using `NewEventBuilder`.
+    pub fn new_event<'b>(&'b mut self) -> NewEventBuilder<'b, Self> {
+        NewEventBuilder::new(self)
+    }
+
+    fn build_message(&mut self) -> RawMessage<'static> {
+        use std::mem;
+
+        RawMessage::TransactionWrite(TransactionWrite {
+            transaction_id: self.transaction_id.into(),
+            events: mem::replace(&mut self.events, Vec::new()),
+            require_master: self.require_master.unwrap_or(false),
+        })
+    }
+
+    common_build_package!();
+
+    /// Checks the events for mistakes the server would reject the write for, returning all of
+    /// them.
+    pub fn validate(&self) -> Result<(), InvalidWrite> {
+        validate::check(None, &self.events)
+    }
+
+    common_try_build_package!();
+}
+
+impl EventsBuilder for TransactionWriteBuilder {
+    fn push_event(&mut self, event: NewEvent<'static>) -> &mut Self {
+        self.events.push(event);
+        self
+    }
+}
+
+/// Builder for `TransactionCommit`.
+pub struct TransactionCommitBuilder {
+    transaction_id: TransactionId,
+    require_master: Option<bool>,
+}
+
+impl TransactionCommitBuilder {
+    fn new(transaction_id: TransactionId) -> Self {
+        TransactionCommitBuilder {
+            transaction_id: transaction_id,
+            require_master: None,
+        }
+    }
+
+    common_require_master!();
+
+    fn build_message(&mut self) -> RawMessage<'static> {
+        RawMessage::TransactionCommit(TransactionCommit {
+            transaction_id: self.transaction_id.into(),
+            require_master: self.require_master.unwrap_or(false),
+        })
+    }
+
+    common_build_package!();
+}
+
+/// Builder for `CreatePersistentSubscription` and `UpdatePersistentSubscription`, which share
+/// the `PersistentSubscriptionSettings` of the subscription group. The unset settings get the
+/// defaults of the .NET client.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use eventstore_tcp::{Builder, EventNumber};
+///
+/// let package = Builder::create_persistent_subscription()
+///     .stream_id("my_stream-1")
+///     .group_name("billing")
+///     .start_from(EventNumber::First)
+///     .message_timeout(Duration::from_secs(10))
+///     .max_retry_count(3)
+///     .build_package(None, None);
+/// ```
+pub struct PersistentSubscriptionBuilder {
+    update: bool,
+    event_stream_id: Option<Cow<'static, str>>,
+    group_name: Option<Cow<'static, str>>,
+    settings: PersistentSubscriptionSettings,
+}
+
+impl PersistentSubscriptionBuilder {
+    fn new(update: bool) -> Self {
+        PersistentSubscriptionBuilder {
+            update: update,
+            event_stream_id: None,
+            group_name: None,
+            settings: PersistentSubscriptionSettings::default(),
+        }
+    }
+
+    common_stream_id!();
+
+    /// Sets the name of the subscription group (required). Panics if the name is an empty
+    /// string.
+    pub fn group_name<S: Into<Cow<'static, str>>>(&mut self, name: S) -> &mut Self {
+        let name = name.into();
+        assert!(name.len() > 0);
+        self.group_name = Some(name);
+        self
+    }
+
+    /// Replaces all of the settings of the group, for example with settings kept in the
+    /// configuration of an application. The other setters change single settings.
+    pub fn settings(&mut self, settings: PersistentSubscriptionSettings) -> &mut Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Whether or not the server should resolve links found in the stream to events of other
+    /// streams. Defaults to `false`.
+    pub fn resolve_link_tos(&mut self, resolve: bool) -> &mut Self {
+        self.settings.resolve_link_tos = resolve;
+        self
+    }
+
+    /// The event the group starts from. Defaults to `EventNumber::Last`, only the events written
+    /// after the group was created.
+    pub fn start_from<N: Into<EventNumber>>(&mut self, number: N) -> &mut Self {
+        self.settings.start_from = number.into();
+        self
+    }
+
+    /// How long the server waits for an ack before retrying the event. Defaults to 30 seconds.
+    pub fn message_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.settings.message_timeout = timeout;
+        self
+    }
+
+    /// Should the server keep statistics of the group. Defaults to `false`.
+    pub fn record_statistics(&mut self, record: bool) -> &mut Self {
+        self.settings.record_statistics = record;
+        self
+    }
+
+    /// Sets the number of events buffered while the group is live, the number of events read at a
+    /// time while catching up and the number of events buffered while catching up. Panics if any
+    /// of the sizes is zero.
+    ///
+    /// Defaults to 500, 20 and 500.
+    pub fn buffer_sizes(&mut self, live: u16, read_batch: u16, history: u16) -> &mut Self {
+        assert!(live > 0 && read_batch > 0 && history > 0);
+        self.settings.live_buffer_size = live;
+        self.settings.read_batch_size = read_batch;
+        self.settings.history_buffer_size = history;
+        self
+    }
+
+    /// How many times an event is retried before it is parked. Defaults to 10.
+    pub fn max_retry_count(&mut self, count: u16) -> &mut Self {
+        self.settings.max_retry_count = count;
+        self
+    }
+
+    /// Sets how often the position of the group is checkpointed, and the minimum and maximum
+    /// number of acked events between the checkpoints. Panics if `min` is larger than `max`.
+    ///
+    /// Defaults to 2 seconds, 10 and 1000.
+    pub fn checkpoint(&mut self, after: Duration, min: u16, max: u16) -> &mut Self {
+        assert!(min <= max);
+        self.settings.checkpoint_after = after;
+        self.settings.min_checkpoint_count = min;
+        self.settings.max_checkpoint_count = max;
+        self
+    }
+
+    /// Limits the number of consumers of the group. Defaults to 0, unlimited.
+    pub fn max_subscribers(&mut self, count: u16) -> &mut Self {
+        self.settings.max_subscriber_count = count;
+        self
+    }
+
+    /// Sets the strategy of distributing the events between the consumers, a name such as
+    /// `"RoundRobin"`, `"DispatchToSingle"` or `"Pinned"`. Defaults to `"RoundRobin"`.
+    pub fn consumer_strategy<S: Into<Cow<'static, str>>>(&mut self, strategy: S) -> &mut Self {
+        self.settings.consumer_strategy = strategy.into();
+        self
+    }
+
+    fn build_command(&mut self) -> CreatePersistentSubscription<'static> {
+        let settings = &self.settings;
+
+        CreatePersistentSubscription {
+            subscription_group_name: self.group_name.take().expect("group_name not set"),
+            event_stream_id: self.event_stream_id.take().expect("event_stream_id not set"),
+            resolve_link_tos: settings.resolve_link_tos,
+            start_from: settings.start_from.into(),
+            message_timeout_milliseconds: millis(settings.message_timeout),
+            record_statistics: settings.record_statistics,
+            live_buffer_size: settings.live_buffer_size.into(),
+            read_batch_size: settings.read_batch_size.into(),
+            buffer_size: settings.history_buffer_size.into(),
+            max_retry_count: settings.max_retry_count.into(),
+            prefer_round_robin: settings.consumer_strategy == "RoundRobin",
+            checkpoint_after_time: millis(settings.checkpoint_after),
+            checkpoint_max_count: settings.max_checkpoint_count.into(),
+            checkpoint_min_count: settings.min_checkpoint_count.into(),
+            subscriber_max_count: settings.max_subscriber_count.into(),
+            named_consumer_strategy: Some(settings.consumer_strategy.clone()),
+        }
+    }
+
+    fn build_message(&mut self) -> RawMessage<'static> {
+        let create = self.build_command();
+
+        if !self.update {
+            return RawMessage::CreatePersistentSubscription(create);
+        }
+
+        RawMessage::UpdatePersistentSubscription(UpdatePersistentSubscription {
+            subscription_group_name: create.subscription_group_name,
+            event_stream_id: create.event_stream_id,
+            resolve_link_tos: create.resolve_link_tos,
+            start_from: create.start_from,
+            message_timeout_milliseconds: create.message_timeout_milliseconds,
+            record_statistics: create.record_statistics,
+            live_buffer_size: create.live_buffer_size,
+            read_batch_size: create.read_batch_size,
+            buffer_size: create.buffer_size,
+            max_retry_count: create.max_retry_count,
+            prefer_round_robin: create.prefer_round_robin,
+            checkpoint_after_time: create.checkpoint_after_time,
+            checkpoint_max_count: create.checkpoint_max_count,
+            checkpoint_min_count: create.checkpoint_min_count,
+            subscriber_max_count: create.subscriber_max_count,
+            named_consumer_strategy: create.named_consumer_strategy,
+        })
+    }
+
+    common_build_package!();
+}
+
+/// Builder for `DeletePersistentSubscription`.
+pub struct DeletePersistentSubscriptionBuilder {
+    event_stream_id: Option<Cow<'static, str>>,
+    group_name: Option<Cow<'static, str>>,
+}
+
+impl DeletePersistentSubscriptionBuilder {
+    fn new() -> Self {
+        DeletePersistentSubscriptionBuilder {
+            event_stream_id: None,
+            group_name: None,
+        }
+    }
+
+    common_stream_id!();
+
+    /// Sets the name of the subscription group (required).
+    pub fn group_name<S: Into<Cow<'static, str>>>(&mut self, name: S) -> &mut Self {
+        self.group_name = Some(name.into());
+        self
+    }
+
+    fn build_message(&mut self) -> RawMessage<'static> {
+        encoded(0xCA, &DeletePersistentSubscription {
+            subscription_group_name: self.group_name.take().expect("group_name not set"),
+            event_stream_id: self.event_stream_id.take().expect("event_stream_id not set"),
+        })
+    }
+
+    common_build_package!();
+}
+
+/// Builder for `ConnectToPersistentSubscription`.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::Builder;
+///
+/// let package = Builder::connect_to_persistent_subscription()
+///     .stream_id("my_stream-1")
+///     .group_name("billing")
+///     .allowed_in_flight_messages(10) // default
+///     .build_package(None, None);
+/// ```
+pub struct ConnectToPersistentSubscriptionBuilder {
+    event_stream_id: Option<Cow<'static, str>>,
+    group_name: Option<Cow<'static, str>>,
+    allowed_in_flight_messages: Option<u16>,
+}
+
+impl ConnectToPersistentSubscriptionBuilder {
+    fn new() -> Self {
+        ConnectToPersistentSubscriptionBuilder {
+            event_stream_id: None,
+            group_name: None,
+            allowed_in_flight_messages: None,
+        }
+    }
+
+    common_stream_id!();
+
+    /// Sets the name of the subscription group (required).
+    pub fn group_name<S: Into<Cow<'static, str>>>(&mut self, name: S) -> &mut Self {
+        self.group_name = Some(name.into());
+        self
+    }
+
+    /// The number of events the server sends before waiting for acks. Panics if the argument is
+    /// zero.
+    ///
+    /// Defaults to 10.
+    pub fn allowed_in_flight_messages(&mut self, count: u16) -> &mut Self {
+        assert!(count > 0);
+        self.allowed_in_flight_messages = Some(count);
+        self
+    }
+
+    fn build_message(&mut self) -> RawMessage<'static> {
+        RawMessage::ConnectToPersistentSubscription(ConnectToPersistentSubscription {
+            subscription_id: self.group_name.take().expect("group_name not set"),
+            event_stream_id: self.event_stream_id.take().expect("event_stream_id not set"),
+            allowed_in_flight_messages: self.allowed_in_flight_messages.unwrap_or(10).into(),
+        })
+    }
+
+    common_build_package!();
+}
+
+fn uuid_bytes(uuid: Uuid) -> Cow<'static, [u8]> {
+    Cow::Owned(uuid.as_bytes().into_iter().cloned().collect::<Vec<u8>>())
+}
+
+fn millis(duration: Duration) -> i32 {
+    let millis = duration.as_secs().saturating_mul(1000) + u64::from(duration.subsec_nanos() / 1_000_000);
+    if millis > i32::max_value() as u64 { i32::max_value() } else { millis as i32 }
+}
+
 fn build_package<M: Into<RawMessage<'static>>>(msg: M, authentication: Option<UsernamePassword>, correlation_id: Option<Uuid>) -> Package {
     Package {
         authentication: authentication,
@@ -596,3 +1170,67 @@ fn build_new_event_for_write_events() {
         .done()
         .build_package(None, None);
 }
+
+#[test]
+fn build_new_event_for_transaction_write() {
+    let package = Builder::transaction_write(TransactionId::from(7))
+        .new_event()
+            .event_type("foo")
+            .data(vec![0u8])
+        .done()
+        .require_master(true)
+        .build_package(None, None);
+
+    assert_eq!(package.message.discriminator(), 0x86);
+}
+
+#[test]
+fn build_create_persistent_subscription_with_settings() {
+    let settings = PersistentSubscriptionSettings {
+        start_from: EventNumber::First,
+        max_retry_count: 3,
+        consumer_strategy: Cow::Borrowed("Pinned"),
+        ..PersistentSubscriptionSettings::default()
+    };
+
+    let package = Builder::create_persistent_subscription()
+        .stream_id("foobar")
+        .group_name("billing")
+        .settings(settings)
+        .checkpoint(Duration::from_secs(5), 1, 100)
+        .build_package(None, None);
+
+    match package.message {
+        RawMessage::CreatePersistentSubscription(ref create) => {
+            assert_eq!(create.subscription_group_name, "billing");
+            assert_eq!(create.start_from, 0);
+            assert_eq!(create.max_retry_count, 3);
+            assert_eq!(create.message_timeout_milliseconds, 30_000);
+            assert_eq!((create.checkpoint_after_time, create.checkpoint_min_count, create.checkpoint_max_count), (5_000, 1, 100));
+            assert_eq!(create.named_consumer_strategy, Some(Cow::Borrowed("Pinned")));
+            assert!(!create.prefer_round_robin);
+        },
+        ref x => panic!("unexpected {:?}", x),
+    }
+}
+
+#[test]
+fn build_update_persistent_subscription() {
+    let package = Builder::update_persistent_subscription()
+        .stream_id("foobar")
+        .group_name("billing")
+        .max_retry_count(3)
+        .consumer_strategy("Pinned")
+        .build_package(None, None);
+
+    match package.message {
+        RawMessage::UpdatePersistentSubscription(ref update) => {
+            assert_eq!(update.subscription_group_name, "billing");
+            assert_eq!(update.max_retry_count, 3);
+            assert_eq!(update.start_from, -1);
+            assert_eq!(update.message_timeout_milliseconds, 30_000);
+            assert!(!update.prefer_round_robin);
+        },
+        ref x => panic!("unexpected {:?}", x),
+    }
+}
@@ -0,0 +1,151 @@
+//! Small object-safe traits of the basic operations on the events, for applications to code
+//! against instead of a concrete client.
+//!
+//! `EventWriter` and `EventReader` are implemented by every `Service` of `Package`s, which covers
+//! `EventStoreClient`, `ClusterClient` and the `StoreService` of the mock server, so that tests can
+//! run the code of the application against the mock server without a connection.
+//! `EventSubscriber` needs the streaming responses of `EventStoreClient`.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate futures;
+//! extern crate uuid;
+//! extern crate eventstore_tcp;
+//!
+//! use std::io;
+//! use futures::{Future, Stream};
+//! use uuid::Uuid;
+//! use eventstore_tcp::{EventNumber, EventWriter, EventReader, ExpectedVersion, NewEvent};
+//! use eventstore_tcp::server::{MemoryStore, StoreService};
+//!
+//! fn record_order<W: EventWriter + ?Sized>(writer: &W) -> Box<Future<Item = (), Error = io::Error>> {
+//!     let created = NewEvent {
+//!         event_id: Uuid::new_v4().as_bytes().to_vec().into(),
+//!         event_type: "created".into(),
+//!         data: b"{}".to_vec().into(),
+//!         ..NewEvent::default()
+//!     };
+//!
+//!     Box::new(writer.append("orders-1", ExpectedVersion::NoStream, vec![created]).map(|_| ()))
+//! }
+//!
+//! # fn main() {
+//! let store = StoreService::new(MemoryStore::new());
+//!
+//! record_order(&store).wait().unwrap();
+//!
+//! let events = store.read_stream("orders-1", EventNumber::First).collect().wait().unwrap();
+//! assert_eq!(events[0].event.event_type, "created");
+//! # }
+//! ```
+
+use std::io;
+
+use futures::{future, Future, Stream};
+use tokio_service::Service;
+
+use adapted::WriteEventsCompleted;
+use builder::EventsBuilder;
+use feed::{self, StreamEvent};
+use package::Package;
+use response::written;
+use {Builder, EventNumber, EventStoreClient, ExpectedVersion, NewEvent};
+
+/// Appends events to streams.
+pub trait EventWriter {
+    /// Appends the events to the stream if the stream is at `expected_version`. A failed write,
+    /// such as a wrong expected version, fails the future with the `WriteEventsFailure` as the
+    /// inner error.
+    fn append(&self, stream_id: &str, expected_version: ExpectedVersion, events: Vec<NewEvent<'static>>) -> Box<Future<Item = WriteEventsCompleted, Error = io::Error>>;
+}
+
+/// Reads the events of streams.
+pub trait EventReader {
+    /// Reads the events of the stream from the event `from` until the end of the stream, with
+    /// links resolved. A stream which does not exist has no events.
+    fn read_stream(&self, stream_id: &str, from: EventNumber) -> Box<Stream<Item = StreamEvent, Error = io::Error>>;
+}
+
+/// Follows the events appended to streams.
+pub trait EventSubscriber {
+    /// Yields the events appended to the stream from now on, see `feed::subscribe`.
+    fn subscribe(&self, stream_id: &str) -> Box<Stream<Item = StreamEvent, Error = io::Error>>;
+}
+
+/// All of `EventWriter`, `EventReader` and `EventSubscriber`, implemented for every type
+/// implementing the three.
+pub trait EventBus: EventWriter + EventReader + EventSubscriber {}
+
+impl<T: EventWriter + EventReader + EventSubscriber + ?Sized> EventBus for T {}
+
+impl<S> EventWriter for S
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + Clone + 'static,
+          S::Future: 'static
+{
+    fn append(&self, stream_id: &str, expected_version: ExpectedVersion, events: Vec<NewEvent<'static>>) -> Box<Future<Item = WriteEventsCompleted, Error = io::Error>> {
+        let mut builder = Builder::write_events();
+        builder.stream_id(stream_id.to_owned())
+            .expected_version(expected_version);
+
+        for event in events {
+            builder.push_event(event);
+        }
+
+        let package = match builder.try_build_package(None, None) {
+            Ok(package) => package,
+            Err(invalid) => return Box::new(future::err(invalid.into())),
+        };
+
+        Box::new(self.call(package).and_then(written))
+    }
+}
+
+impl<S> EventReader for S
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + Clone + 'static,
+          S::Future: 'static
+{
+    fn read_stream(&self, stream_id: &str, from: EventNumber) -> Box<Stream<Item = StreamEvent, Error = io::Error>> {
+        feed::read_forward(self.clone(), stream_id.to_owned().into(), from)
+    }
+}
+
+impl EventSubscriber for EventStoreClient {
+    fn subscribe(&self, stream_id: &str) -> Box<Stream<Item = StreamEvent, Error = io::Error>> {
+        feed::subscribe(self, stream_id.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use futures::{Future, Stream};
+    use uuid::Uuid;
+    use server::{MemoryStore, StoreService};
+    use adapted::WriteEventsFailure;
+    use {EventNumber, ExpectedVersion, NewEvent, StreamVersion};
+    use super::{EventWriter, EventReader};
+
+    #[test]
+    fn mock_server_is_a_writer_and_reader() {
+        let store = StoreService::new(MemoryStore::new());
+        let writer: &EventWriter = &store;
+
+        let events = || vec![NewEvent {
+            event_id: Uuid::new_v4().as_bytes().to_vec().into(),
+            event_type: "created".into(),
+            ..NewEvent::default()
+        }];
+
+        let completed = writer.append("orders-1", ExpectedVersion::NoStream, events()).wait().unwrap();
+        assert_eq!(completed.event_numbers.start, StreamVersion::try_from(0u32).unwrap());
+
+        let failed = writer.append("orders-1", ExpectedVersion::NoStream, events()).wait().unwrap_err();
+        assert_eq!(failed.get_ref().and_then(|e| e.downcast_ref::<WriteEventsFailure>()), Some(&WriteEventsFailure::WrongExpectedVersion));
+
+        let reader: &EventReader = &store;
+        let read = reader.read_stream("orders-1", EventNumber::First).collect().wait().unwrap();
+        assert_eq!(read.len(), 1);
+        assert!(reader.read_stream("orders-2", EventNumber::First).collect().wait().unwrap().is_empty());
+    }
+}
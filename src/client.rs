@@ -1,25 +1,63 @@
 use std::io;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::time::Instant;
+use std::borrow::Cow;
+#[cfg(feature = "tower")]
+use std::sync::Mutex;
 
-use futures::Future;
+use futures::{future, Future, Stream, Async, Poll};
+#[cfg(feature = "tower")]
+use futures::task;
+use futures::sync::{mpsc, oneshot};
 
 use tokio_core::reactor::Handle;
-use tokio_io::{AsyncWrite, AsyncRead};
-use tokio_io::codec::{Encoder, Decoder, Framed};
-use tokio_core::net::TcpStream;
-use tokio_proto::TcpClient;
-use tokio_proto::multiplex::{ClientProto, ClientService, NewRequestIdSource, RequestIdSource};
 use tokio_service::Service;
-use bytes::BytesMut;
+#[cfg(feature = "tower")]
+use tower_service;
+use uuid::Uuid;
 
 use package::Package;
-use codec::PackageCodec;
-
-use uuid::Uuid;
+use adapted::{AdaptedMessage, ResolvedEvent};
+use feed::Dropped;
+use connection::{Connection, ConnectionClosed, ConnectionEvent, Command, Request, Reply};
+use budget::Charge;
+use response::{adapt, unexpected};
+use diagnostics::Diagnostics;
+use deadline::Deadline;
+use {Builder, ConnectionSettings};
 
-/// `tokio_service::Service` implementation of the client.
+/// `tokio_service::Service` implementation of the client, and with the `tower` feature also
+/// `tower_service::Service`.
+///
+/// The values are cheap handles to a connection task running on the reactor: cloned handles share
+/// the same connection, which is closed once all of the handles have been dropped. The connection
+/// task reconnects when the connection is lost, up to
+/// `ConnectionSettings::max_reconnect_attempts` times in a row, after which all operations fail
+/// with `ConnectionClosed`.
 pub struct EventStoreClient {
-    inner: ClientService<TcpStream, PackageProto>,
+    sender: mpsc::UnboundedSender<Command>,
+    settings: ConnectionSettings,
+    /// Requests sent through `tower_service::Service` by this and the cloned handles
+    #[cfg(feature = "tower")]
+    in_flight: Arc<InFlight>,
+    /// Slot reserved by `poll_ready` for the next `call`
+    #[cfg(feature = "tower")]
+    ready: Option<Permit>,
+}
+
+impl Clone for EventStoreClient {
+    fn clone(&self) -> Self {
+        EventStoreClient {
+            sender: self.sender.clone(),
+            settings: self.settings.clone(),
+            #[cfg(feature = "tower")]
+            in_flight: self.in_flight.clone(),
+            #[cfg(feature = "tower")]
+            ready: None,
+        }
+    }
 }
 
 impl EventStoreClient {
@@ -28,13 +66,184 @@ impl EventStoreClient {
     /// Returns a future representing the client which can be used to send and receive `Package`
     /// values.
     pub fn connect(addr: &SocketAddr, handle: &Handle) -> Box<Future<Item = Self, Error = io::Error>> {
-        let ret = TcpClient::new(PackageProto)
-            .connect(addr, handle)
-            .map(|client_service| {
-                EventStoreClient { inner: client_service }
-            });
+        Self::connect_with_settings(addr, handle, ConnectionSettings::default())
+    }
+
+    /// Connect like `connect` but using the given `settings`. If the settings contain a
+    /// connection name, the returned future will complete only after the server has acknowledged
+    /// `IdentifyClient`. Failed connection attempts are retried like lost connections.
+    pub fn connect_with_settings(addr: &SocketAddr, handle: &Handle, settings: ConnectionSettings) -> Box<Future<Item = Self, Error = io::Error>> {
+        let (sender, requests) = mpsc::unbounded();
+        let (ready_tx, ready_rx) = oneshot::channel();
+
+        handle.spawn(Connection::new(*addr, handle.clone(), settings.clone(), requests, ready_tx));
+
+        let client = EventStoreClient {
+            sender: sender,
+            settings: settings,
+            #[cfg(feature = "tower")]
+            in_flight: Arc::new(InFlight::default()),
+            #[cfg(feature = "tower")]
+            ready: None,
+        };
+
+        Box::new(ready_rx.then(move |res| match res {
+            Ok(Ok(())) => Ok(client),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(stopped()),
+        }))
+    }
+
+    /// The settings this client was connected with.
+    pub fn settings(&self) -> &ConnectionSettings {
+        &self.settings
+    }
+
+    /// Moves the connection shared by this and the cloned handles to another node, for example
+    /// when the cluster has elected a new leader. New requests are held back until the requests in
+    /// flight have completed, after which the connection is closed and the new node is connected
+    /// to. Does nothing if the connection has been closed for good.
+    pub fn migrate(&self, addr: SocketAddr) {
+        // a stopped connection task fails the following calls
+        let _ = self.sender.unbounded_send(Command::Migrate(addr));
+    }
+
+    /// Sends the request like `call`, but fails it with `DeadlineExceeded` unless the response has
+    /// arrived by `deadline`. The deadline covers the whole operation: waiting for the connection to
+    /// be made or remade, sending a read again after the connection was lost, and waiting for the
+    /// response. A write failed this way might still have been written.
+    pub fn call_with_deadline(&self, req: Package, deadline: Instant) -> Box<Future<Item = Package, Error = io::Error>> {
+        self.call_with(req, Some(Deadline::new(deadline)))
+    }
+
+    fn call_with(&self, mut req: Package, deadline: Option<Deadline>) -> Box<Future<Item = Package, Error = io::Error>> {
+        let provider = match self.settings.credentials_provider() {
+            Some(provider) if req.authentication.is_none() => provider,
+            _ => return send(&self.sender, req, deadline),
+        };
+
+        let sender = self.sender.clone();
+        let credentials = provider.get_for_stream(req.message.stream_id());
+
+        Box::new(credentials.and_then(move |credentials| {
+            req.authentication = Some(credentials);
+            send(&sender, req, deadline)
+        }))
+    }
+
+    /// Takes a snapshot of the requests of the connection, see `Diagnostics`. Fails with
+    /// `ConnectionClosed` once the connection task has stopped.
+    pub fn diagnostics(&self) -> Box<Future<Item = Diagnostics, Error = io::Error>> {
+        let (tx, rx) = oneshot::channel();
+
+        if self.sender.unbounded_send(Command::Diagnostics(tx)).is_err() {
+            return Box::new(future::err(stopped()));
+        }
+
+        Box::new(rx.map_err(|_| stopped()))
+    }
+
+    /// Stream of the changes in the state of the connection shared by this and the cloned handles
+    /// from now on: connecting, losing the connection, the server rejecting credentials and
+    /// closing the connection for good. The stream ends once the connection task has stopped.
+    ///
+    /// `ConnectionEvent::AuthenticationFailed` is sent whenever the server answers an operation
+    /// with `NotAuthenticated`, also when nothing waits for the response anymore, for noticing a
+    /// rotated or revoked password without retrying the operations in a loop.
+    pub fn connection_events(&self) -> Box<Stream<Item = ConnectionEvent, Error = io::Error>> {
+        use futures::stream;
+
+        let (tx, rx) = mpsc::unbounded();
+
+        if self.sender.unbounded_send(Command::Events(tx)).is_err() {
+            return Box::new(stream::once(Err(stopped())));
+        }
+
+        Box::new(rx.map_err(|_| stopped()))
+    }
+
+    /// Sends the request and returns every package correlated to it, for operations answered with
+    /// more than one package such as subscriptions. The packages keep coming until the returned
+    /// stream is dropped, which frees the request in the connection and unsubscribes a
+    /// subscription with `UnsubscribeFromStream`.
+    ///
+    /// Unlike `call` the request is sent again after the connection is lost only if it is a
+    /// subscription the server has not answered yet. Once the first package has arrived the stream
+    /// fails with the error of the connection and ends, as subscribing again would skip the events
+    /// appended meanwhile; the same happens when the connection is moved to another node. The packages waiting to be taken
+    /// from the stream count against `ConnectionSettingsBuilder::stream_memory_budget`.
+    pub fn call_streaming(&self, mut req: Package) -> Box<Stream<Item = Package, Error = io::Error>> {
+        let provider = match self.settings.credentials_provider() {
+            Some(provider) if req.authentication.is_none() => provider,
+            _ => return send_streaming(&self.sender, req),
+        };
+
+        let sender = self.sender.clone();
+        let credentials = provider.get_for_stream(req.message.stream_id());
+
+        Box::new(credentials.map(move |credentials| {
+            req.authentication = Some(credentials);
+            send_streaming(&sender, req)
+        }).flatten_stream())
+    }
+
+    /// A `Service` answering every request with the stream of `call_streaming`, for composing
+    /// subscriptions with code written against `tokio_service::Service`.
+    pub fn streaming(&self) -> StreamingClient {
+        StreamingClient(self.clone())
+    }
+
+    /// Subscribes to the events appended to the stream from now on, or to every stream if
+    /// `stream_id` is empty. The events pushed by the server are routed to the returned stream by
+    /// the correlation id of the subscription, as with `call_streaming`.
+    ///
+    /// The stream fails with `feed::Dropped` when the server ends the subscription, and with the
+    /// error of the connection when it is lost; the subscription is not renewed. Dropping the
+    /// stream unsubscribes.
+    pub fn subscribe_to_stream<I>(&self, stream_id: I, resolve_link_tos: bool) -> Box<Stream<Item = ResolvedEvent<'static>, Error = io::Error>>
+        where I: Into<Cow<'static, str>>
+    {
+        let stream_id = stream_id.into();
+        let mut builder = Builder::subscribe_to_stream();
+        builder.resolve_link_tos(resolve_link_tos);
+
+        // the builder takes only named streams, an empty id subscribes to all
+        if !stream_id.is_empty() {
+            builder.stream_id(stream_id);
+        }
+
+        let package = builder.build_package(None, None);
+        Box::new(self.call_streaming(package).and_then(appeared).filter_map(|e| e))
+    }
+}
 
-        Box::new(ret)
+/// Adapts a package pushed to a subscription, `None` for the confirmation.
+fn appeared(package: Package) -> io::Result<Option<ResolvedEvent<'static>>> {
+    match adapt(package)? {
+        AdaptedMessage::SubscriptionConfirmation(_) => Ok(None),
+        AdaptedMessage::StreamEventAppeared(e) => Ok(Some(e)),
+        AdaptedMessage::SubscriptionDropped(reason) => Err(Dropped { reason: reason }.into()),
+        other => Err(unexpected(other)),
+    }
+}
+
+/// `tokio_service::Service` of the requests answered with more than one package, created with
+/// `EventStoreClient::streaming`.
+///
+/// Every request is answered with the stream of the packages correlated to it, see
+/// `EventStoreClient::call_streaming`. For a subscription the stream has the confirmation, the
+/// events as they appear, and the `SubscriptionDropped` after which the stream ends.
+#[derive(Clone)]
+pub struct StreamingClient(EventStoreClient);
+
+impl Service for StreamingClient {
+    type Request = Package;
+    type Response = Box<Stream<Item = Package, Error = io::Error>>;
+    type Error = io::Error;
+    type Future = future::FutureResult<Self::Response, io::Error>;
+
+    fn call(&self, req: Package) -> Self::Future {
+        future::ok(self.0.call_streaming(req))
     }
 }
 
@@ -45,82 +254,469 @@ impl Service for EventStoreClient {
     type Future = Box<Future<Item = Package, Error = io::Error>>;
 
     fn call(&self, req: Package) -> Self::Future {
-        self.inner.call(req).boxed()
+        self.call_with(req, None)
     }
 }
 
-/*
-/// Simple middleware
-struct Heartbeats<T> {
-    inner: T,
-}
-
-impl<T> Stream for Heartbeats<T>
-    where T: Service<Request = Package, Response = Package, Error = io::Error>,
-          T::Future: 'static
-{
-    type Request = Package;
+/// `tower_service::Service` implementation of the client, limiting the requests in flight to
+/// `ConnectionSettings::max_in_flight`, shared by the cloned handles. Like `call` of
+/// `tokio_service::Service`, the credentials provider of the settings is used for the requests
+/// without credentials.
+#[cfg(feature = "tower")]
+impl tower_service::Service<Package> for EventStoreClient {
     type Response = Package;
     type Error = io::Error;
     type Future = Box<Future<Item = Package, Error = io::Error>>;
 
-    fn call(&self, req: Package) -> Self::Future {
-        if self.credentials.as_ref().is_some() && req.authentication.is_none() {
-            req.authentication = self.credentials.clone();
+    fn poll_ready(&mut self) -> Poll<(), io::Error> {
+        if self.ready.is_none() {
+            self.ready = match InFlight::poll_acquire(&self.in_flight, self.settings.max_in_flight()) {
+                Async::Ready(permit) => Some(permit),
+                Async::NotReady => return Ok(Async::NotReady),
+            };
         }
 
-        self.inner.call(req)
+        Ok(Async::Ready(()))
+    }
+
+    /// Sends the request in the slot reserved by `poll_ready`; without one the request is sent
+    /// anyway, over the limit.
+    fn call(&mut self, req: Package) -> Self::Future {
+        let permit = match self.ready.take() {
+            Some(permit) => permit,
+            None => InFlight::acquire(&self.in_flight),
+        };
+
+        Box::new(Service::call(&*self, req).then(move |res| {
+            drop(permit);
+            res
+        }))
     }
 }
-*/
 
-pub struct Separator;
+/// Count of the requests in flight, with the handles waiting for one to complete.
+#[cfg(feature = "tower")]
+#[derive(Default)]
+struct InFlight(Mutex<(usize, Vec<task::Task>)>);
 
-impl Decoder for Separator {
-    type Item = (Uuid, Package);
-    type Error = io::Error;
+/// Slot of a request in `InFlight`, released when dropped.
+#[cfg(feature = "tower")]
+struct Permit(Arc<InFlight>);
+
+#[cfg(feature = "tower")]
+impl InFlight {
+    /// Takes a slot if fewer than `max` requests are in flight; otherwise the current task is
+    /// notified once a request completes. A task polling again is kept waiting only once.
+    fn poll_acquire(in_flight: &Arc<InFlight>, max: usize) -> Async<Permit> {
+        let mut state = in_flight.0.lock().expect("InFlight poisoned");
 
-    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Self::Item>> {
-        PackageCodec.decode(buf).map(|x| x.map(|x| (x.correlation_id, x)))
+        if state.0 < max {
+            state.0 += 1;
+            Async::Ready(Permit(in_flight.clone()))
+        } else {
+            if !state.1.iter().any(|waiting| waiting.will_notify_current()) {
+                state.1.push(task::current());
+            }
+            Async::NotReady
+        }
+    }
+
+    fn acquire(in_flight: &Arc<InFlight>) -> Permit {
+        in_flight.0.lock().expect("InFlight poisoned").0 += 1;
+        Permit(in_flight.clone())
     }
 }
 
-impl Encoder for Separator {
-    type Item = (Uuid, Package);
-    type Error = io::Error;
+#[cfg(feature = "tower")]
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = (self.0).0.lock().expect("InFlight poisoned");
+        state.0 -= 1;
 
-    fn encode(&mut self, msg: (Uuid, Package), buf: &mut BytesMut) -> io::Result<()> {
-        let (id, pkg) = msg;
-        assert_eq!(id, pkg.correlation_id);
-        PackageCodec.encode(pkg, buf)
+        for waiting in state.1.drain(..) {
+            waiting.notify();
+        }
     }
 }
 
-impl RequestIdSource<Uuid, Package> for Separator {
-    fn next(&mut self, pkg: &Package) -> Uuid {
-        pkg.correlation_id
+fn send(sender: &mpsc::UnboundedSender<Command>, req: Package, deadline: Option<Deadline>) -> Box<Future<Item = Package, Error = io::Error>> {
+    let (tx, rx) = oneshot::channel();
+
+    if sender.unbounded_send(Command::Send(Request { package: req, tx: Reply::Once(tx), deadline: deadline, retries: 0 })).is_err() {
+        return Box::new(future::err(stopped()));
     }
+
+    Box::new(rx.then(|res| match res {
+        Ok(res) => res,
+        Err(_) => Err(stopped()),
+    }))
 }
 
-impl NewRequestIdSource<Uuid, Package> for Uuid {
-    type RequestIdSource = Separator;
+fn send_streaming(sender: &mpsc::UnboundedSender<Command>, req: Package) -> Box<Stream<Item = Package, Error = io::Error>> {
+    use futures::stream;
+
+    let (tx, rx) = mpsc::unbounded();
+    let buffered = Arc::new(AtomicUsize::new(0));
+    let id = req.correlation_id;
 
-    fn requestid_source() -> Self::RequestIdSource {
-        Separator
+    if sender.unbounded_send(Command::Send(Request { package: req, tx: Reply::Stream(tx, buffered), deadline: None, retries: 0 })).is_err() {
+        return Box::new(stream::once(Err(stopped())));
     }
+
+    Box::new(Streaming { packages: rx, sender: sender.clone(), id: id })
 }
 
-struct PackageProto;
+/// Packages of a streaming request, which is cancelled when this is dropped.
+struct Streaming {
+    packages: mpsc::UnboundedReceiver<(io::Result<Package>, Option<Charge>)>,
+    sender: mpsc::UnboundedSender<Command>,
+    id: Uuid,
+}
 
-impl<T: AsyncRead + AsyncWrite + 'static> ClientProto<T> for PackageProto {
-    type Request = Package;
-    type Response = Package;
-    type RequestId = Uuid;
+impl Stream for Streaming {
+    type Item = Package;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Package>, io::Error> {
+        // taking the package releases its charge to the memory budget
+        match self.packages.poll() {
+            Ok(Async::Ready(Some((res, _charge)))) => res.map(|package| Async::Ready(Some(package))),
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(()) => Err(stopped()),
+        }
+    }
+}
+
+impl Drop for Streaming {
+    fn drop(&mut self) {
+        // a stopped connection has forgotten the request already
+        let _ = self.sender.unbounded_send(Command::Cancel(self.id));
+    }
+}
+
+fn stopped() -> io::Error {
+    ConnectionClosed::new("connection task has stopped").into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::borrow::Cow;
+    use futures::{Future, Stream};
+    use tokio_service::Service;
+    use server::{Fault, ScriptedServer};
+    use transport::{scripted_client, scripted_client_with};
+    use {Builder, RawMessage};
+
+    #[test]
+    fn diagnostics_count_unanswered_requests() {
+        let server = ScriptedServer::new()
+            .expect(0x03).fault(Fault::NoResponse);
+        let script = server.script();
+
+        let (mut core, client) = scripted_client(server);
+
+        let before = core.run(client.diagnostics()).unwrap();
+        assert!(before.connected);
+        assert_eq!(before.pending_total(), 0);
+        assert_eq!(before.oldest_pending, None);
+
+        let _pong = client.call(Builder::ping().build_package(None, None));
+        let after = core.run(client.diagnostics()).unwrap();
+
+        assert_eq!(after.pending.get(&0x03), Some(&1));
+        assert!(after.oldest_pending.is_some());
+        assert_eq!((after.queued, after.subscriptions), (0, 0));
+
+        script.assert_complete();
+    }
+
+    #[test]
+    fn subscription_stream_ends_when_dropped() {
+        use raw::{SubscriptionConfirmation, SubscriptionDropped};
+
+        let server = ScriptedServer::new()
+            .expect(0xC0)
+            .respond(RawMessage::SubscriptionConfirmation(SubscriptionConfirmation::default()))
+            .respond(RawMessage::SubscriptionDropped(SubscriptionDropped::default()));
+        let script = server.script();
+
+        let (mut core, client) = scripted_client(server);
+
+        let subscribe = Builder::subscribe_to_stream().stream_id("orders-1").build_package(None, None);
+        let packages = core.run(client.streaming().call(subscribe).and_then(|packages| packages.collect())).unwrap();
+
+        assert_eq!(packages.iter().map(|p| p.message.discriminator()).collect::<Vec<_>>(), vec![0xC1, 0xC4]);
+        script.assert_complete();
+    }
+
+    #[test]
+    fn subscription_yields_appeared_events() {
+        use adapted::ResolvedEvent;
+        use feed::Dropped;
+        use raw::{self, EventRecord, StreamEventAppeared, SubscriptionConfirmation, SubscriptionDropped, SubscriptionDropReason};
+        use LogPosition;
+
+        let record = EventRecord { event_stream_id: "orders-1".into(), event_number: 4, event_type: "paid".into(), ..Default::default() };
+
+        let server = ScriptedServer::new()
+            .expect(0xC0)
+            .respond(RawMessage::SubscriptionConfirmation(SubscriptionConfirmation { last_commit_position: 10, last_event_number: Some(3) }))
+            .respond(RawMessage::StreamEventAppeared(StreamEventAppeared {
+                event: raw::ResolvedEvent { event: record.clone(), link: None, commit_position: 20, prepare_position: 20 },
+            }))
+            .respond(RawMessage::SubscriptionDropped(SubscriptionDropped { reason: SubscriptionDropReason::AccessDenied }));
+        let script = server.script();
+
+        let (mut core, client) = scripted_client(server);
+
+        let (first, rest) = core.run(client.subscribe_to_stream("orders-1", false).into_future()).map_err(|(e, _)| e).unwrap();
+
+        assert_eq!(first, Some(ResolvedEvent {
+            event: record,
+            link: None,
+            commit_position: LogPosition::from(20),
+            prepare_position: LogPosition::from(20),
+        }));
+
+        match core.run(rest.into_future()) {
+            Err((e, _)) => assert_eq!(Dropped::find(&e).map(|d| d.reason), Some(SubscriptionDropReason::AccessDenied)),
+            Ok((next, _)) => panic!("unexpected {:?}", next),
+        }
+
+        script.assert_complete();
+    }
+
+    #[test]
+    fn dropped_subscription_is_unsubscribed() {
+        use raw::{SubscriptionConfirmation, SubscriptionDropped, SubscriptionDropReason};
+
+        let server = ScriptedServer::new()
+            .expect(0xC0)
+            .respond(RawMessage::SubscriptionConfirmation(SubscriptionConfirmation { last_commit_position: 10, last_event_number: Some(3) }))
+            .expect(0xC3)
+            .respond(RawMessage::SubscriptionDropped(SubscriptionDropped { reason: SubscriptionDropReason::Unsubscribed }))
+            .expect(0x03).respond(RawMessage::Pong);
+        let script = server.script();
+
+        let (mut core, client) = scripted_client(server);
+
+        let subscribe = Builder::subscribe_to_stream().stream_id("orders-1").build_package(None, None);
+        let (confirmation, packages) = core.run(client.streaming().call(subscribe).and_then(|packages| packages.into_future().map_err(|(e, _)| e))).unwrap();
+        assert_eq!(confirmation.map(|p| p.message.discriminator()), Some(0xC1));
+
+        drop(packages);
+
+        // the unsubscribe is written before the ping
+        core.run(client.call(Builder::ping().build_package(None, None))).unwrap();
+
+        let diagnostics = core.run(client.diagnostics()).unwrap();
+        assert_eq!((diagnostics.subscriptions, diagnostics.pending_total()), (0, 0));
+        script.assert_complete();
+    }
+
+    #[test]
+    fn subscriptions_are_made_again_or_failed_after_reconnecting() {
+        use std::time::Duration;
+        use raw::{self, EventRecord, StreamEventAppeared, SubscriptionConfirmation};
+        use DecorrelatedJitter;
+
+        let confirmation = || RawMessage::SubscriptionConfirmation(SubscriptionConfirmation { last_commit_position: 10, last_event_number: Some(3) });
+        let record = EventRecord { event_stream_id: "orders-1".into(), event_number: 4, event_type: "paid".into(), ..Default::default() };
+
+        let server = ScriptedServer::new()
+            .expect(0xC0).fault(Fault::Disconnect)
+            .expect(0xC0)
+            .respond(confirmation())
+            .respond(RawMessage::StreamEventAppeared(StreamEventAppeared {
+                event: raw::ResolvedEvent { event: record.clone(), link: None, commit_position: 20, prepare_position: 20 },
+            }))
+            .fault(Fault::Disconnect)
+            .expect(0x03).respond(RawMessage::Pong);
+        let script = server.script();
+
+        let (mut core, client) = scripted_client_with(server, |settings| {
+            settings.backoff(DecorrelatedJitter::new(Duration::from_millis(1), Duration::from_millis(1)));
+        });
+
+        // the first subscription was lost before the confirmation, so it is made again
+        let (first, rest) = core.run(client.subscribe_to_stream("orders-1", false).into_future()).map_err(|(e, _)| e).unwrap();
+        assert_eq!(first.map(|e| e.event), Some(record));
+
+        // the confirmed one fails with the connection
+        match core.run(rest.into_future()) {
+            Err((e, _)) => assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted),
+            Ok((next, _)) => panic!("unexpected {:?}", next),
+        }
+
+        core.run(client.call(Builder::ping().build_package(None, None))).unwrap();
+        script.assert_complete();
+    }
+
+    #[test]
+    fn connection_closed_over_bad_credentials_fails_with_authentication_failed() {
+        use std::time::Duration;
+        use {AuthenticationFailed, ConnectionEvent, DecorrelatedJitter, UsernamePassword};
+
+        let server = ScriptedServer::new()
+            .expect(0x03)
+            .respond(RawMessage::NotAuthenticated(Cow::Borrowed(&b"Bad password"[..]).into()))
+            .fault(Fault::Disconnect)
+            .expect(0x03).respond(RawMessage::Pong)
+            .expect(0x03).respond(RawMessage::Pong);
+        let script = server.script();
+
+        let (mut core, client) = scripted_client_with(server, |settings| {
+            settings.backoff(DecorrelatedJitter::new(Duration::from_millis(1), Duration::from_millis(1)));
+        });
+        let events = client.connection_events();
+
+        let credentials = UsernamePassword::new("admin", "wrong");
+        let answered = client.call(Builder::ping().build_package(Some(credentials.clone()), None));
+        let dropped = client.call(Builder::ping().build_package(Some(credentials), None));
+        let other = client.call(Builder::ping().build_package(Some(UsernamePassword::new("reader", "right")), None));
+
+        let ((answered, dropped), other) = core.run(answered.then(Ok::<_, ()>).join(dropped.then(Ok::<_, ()>)).join(other.then(Ok::<_, ()>))).unwrap();
+        let failed = AuthenticationFailed::new("Bad password");
+
+        // the ping lost with the connection is not sent again with the same credentials
+        assert_eq!(AuthenticationFailed::find(&answered.unwrap_err()), Some(&failed));
+        assert_eq!(AuthenticationFailed::find(&dropped.unwrap_err()), Some(&failed));
+
+        // while the one with other credentials is
+        assert_eq!(other.unwrap().message, RawMessage::Pong);
+
+        core.run(client.call(Builder::ping().build_package(None, None))).unwrap();
+
+        let events = core.run(events.take(3).collect()).unwrap();
+        assert_eq!(events[0], ConnectionEvent::AuthenticationFailed(failed));
+        match (&events[1], &events[2]) {
+            (&ConnectionEvent::Disconnected(..), &ConnectionEvent::Connected(_)) => {},
+            other => panic!("unexpected {:?}", other),
+        }
+
+        script.assert_complete();
+    }
+
+    #[test]
+    fn authenticate_lost_with_the_connection_is_sent_again() {
+        use std::time::Duration;
+        use {DecorrelatedJitter, UsernamePassword};
+
+        let server = ScriptedServer::new()
+            .expect(0xF2).fault(Fault::Disconnect)
+            .expect(0xF2).respond(RawMessage::Authenticated);
+        let script = server.script();
+
+        let (mut core, client) = scripted_client_with(server, |settings| {
+            settings.backoff(DecorrelatedJitter::new(Duration::from_millis(1), Duration::from_millis(1)));
+        });
+
+        let authenticate = Builder::authenticate().build_package(Some(UsernamePassword::new("admin", "changeit")), None);
+        assert_eq!(core.run(client.call(authenticate)).unwrap().message, RawMessage::Authenticated);
+
+        script.assert_complete();
+    }
+
+    #[test]
+    fn not_ready_reads_are_retried_with_growing_delays() {
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+        use raw::{NotHandled, NotHandledReason};
+        use Backoff;
+
+        /// Doubles the delay every time, recording the delays.
+        struct Doubling(Duration, Arc<Mutex<Vec<Duration>>>);
+
+        impl Backoff for Doubling {
+            fn next_delay(&mut self) -> Duration {
+                let delay = self.0;
+                self.0 = delay * 2;
+                self.1.lock().unwrap().push(delay);
+                delay
+            }
+
+            fn reset(&mut self) {
+                self.0 = Duration::from_millis(1);
+            }
+        }
+
+        let not_ready = |reason| RawMessage::NotHandled(NotHandled { reason: Some(reason), additional_info: None });
+
+        let server = ScriptedServer::new()
+            .expect(0x03).respond(not_ready(NotHandledReason::NotReady))
+            .expect(0x03).respond(not_ready(NotHandledReason::TooBusy))
+            .expect(0x03).respond(RawMessage::Pong);
+        let script = server.script();
+
+        let delays = Arc::new(Mutex::new(Vec::new()));
+        let recorded = delays.clone();
+        let (mut core, client) = scripted_client_with(server, |settings| {
+            settings.backoff(move || Box::new(Doubling(Duration::from_millis(1), recorded.clone())) as Box<Backoff>);
+        });
+
+        let pong = core.run(client.call(Builder::ping().build_package(None, None))).unwrap();
+        assert_eq!(pong.message, RawMessage::Pong);
+
+        assert_eq!(*delays.lock().unwrap(), vec![Duration::from_millis(1), Duration::from_millis(2)]);
+        script.assert_complete();
+    }
+
+    #[test]
+    fn deadline_fails_unanswered_request() {
+        use std::time::{Duration, Instant};
+        use DeadlineExceeded;
+
+        let server = ScriptedServer::new()
+            .expect(0x03).fault(Fault::NoResponse);
+        let script = server.script();
+
+        let (mut core, client) = scripted_client(server);
+
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let e = core.run(client.call_with_deadline(Builder::ping().build_package(None, None), deadline)).unwrap_err();
+
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        assert!(Instant::now() >= deadline);
+
+        let exceeded = DeadlineExceeded::find(&e).unwrap();
+        assert_eq!(exceeded.attempts, 1);
+        assert_eq!(exceeded.elapsed, exceeded.queued + exceeded.awaiting_response);
+
+        assert_eq!(core.run(client.diagnostics()).unwrap().pending_total(), 0);
+        script.assert_complete();
+    }
+
+    #[cfg(feature = "tower")]
+    #[test]
+    fn poll_ready_waits_for_requests_in_flight() {
+        use futures::future;
+        use tower_service::Service as TowerService;
+
+        let server = ScriptedServer::new()
+            .expect(0x03).respond(RawMessage::Pong)
+            .expect(0x03).respond(RawMessage::Pong);
+        let script = server.script();
+
+        let (mut core, mut client) = scripted_client_with(server, |settings| { settings.max_in_flight(1); });
+        let mut other = client.clone();
+
+        core.run(future::poll_fn(|| client.poll_ready())).unwrap();
+        let pong = TowerService::call(&mut client, Builder::ping().build_package(None, None));
+
+        let ready = core.run(future::lazy(|| {
+            let first = other.poll_ready()?;
+            let second = other.poll_ready()?;
+            Ok::<_, io::Error>(first.is_ready() || second.is_ready())
+        })).unwrap();
+        assert!(!ready);
+        assert_eq!(client.in_flight.0.lock().unwrap().1.len(), 1);
 
-    type Transport = Framed<T, Separator>;
-    type BindTransport = Result<Self::Transport, io::Error>;
+        core.run(pong).unwrap();
+        core.run(future::poll_fn(|| other.poll_ready())).unwrap();
+        core.run(TowerService::call(&mut other, Builder::ping().build_package(None, None))).unwrap();
 
-    fn bind_transport(&self, io: T) -> Self::BindTransport {
-        Ok(io.framed(Separator))
+        script.assert_complete();
     }
 }
@@ -0,0 +1,259 @@
+//! Conversions between events and the [CloudEvents 1.0](https://cloudevents.io/) structured JSON
+//! format, available with the `cloudevents` feature.
+//!
+//! The attributes are mapped as follows:
+//!
+//!  * `id` is the event id
+//!  * `type` is the event type
+//!  * `subject` is the stream id
+//!  * `time` is the creation time of the event
+//!  * `data` holds JSON data as is, other data is carried in `data_base64`
+//!  * `source` and the extension attributes are kept in the JSON metadata of the event, as is
+//!    `time` when converting to `EventData`
+//!
+//! The source of a recorded event is the `"source"` field of its metadata if present, otherwise the
+//! source given to `to_cloud_event`.
+
+use std::io;
+
+use base64;
+use serde_json::{self, Map, Value};
+use uuid::Uuid;
+
+use eventsourcing::EventData;
+use {ContentType, EventRecord};
+
+/// The attributes which are not extensions. `subject` is included as it is set from the stream id.
+const CONTEXT_ATTRIBUTES: &[&str] = &[
+    "specversion", "id", "type", "source", "subject", "time",
+    "datacontenttype", "dataschema", "data", "data_base64",
+];
+
+/// Converts an event read from a stream into a structured mode CloudEvent.
+pub fn to_cloud_event(record: &EventRecord, source: &str) -> io::Result<Value> {
+    let mut event = Map::new();
+
+    let id = Uuid::from_bytes(&record.event_id).map_err(|_| invalid("Event id is not an UUID"))?;
+    let metadata = match record.metadata {
+        Some(ref metadata) if record.metadata_content_type == i32::from(ContentType::Json) => {
+            match serde_json::from_slice(metadata) {
+                Ok(Value::Object(metadata)) => metadata,
+                _ => Map::new(),
+            }
+        },
+        _ => Map::new(),
+    };
+
+    let source = metadata.get("source").and_then(|s| s.as_str()).unwrap_or(source).to_owned();
+
+    event.insert("specversion".into(), "1.0".into());
+    event.insert("id".into(), id.to_string().into());
+    event.insert("type".into(), record.event_type.to_string().into());
+    event.insert("source".into(), source.into());
+    event.insert("subject".into(), record.event_stream_id.to_string().into());
+
+    if let Some(created) = record.created_epoch {
+        event.insert("time".into(), rfc3339(created).into());
+    }
+
+    for (name, value) in metadata {
+        if is_extension(&name) && is_scalar(&value) {
+            event.insert(name, value);
+        }
+    }
+
+    if record.data_content_type == i32::from(ContentType::Json) {
+        let data: Value = serde_json::from_slice(&record.data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        event.insert("datacontenttype".into(), "application/json".into());
+        event.insert("data".into(), data);
+    } else {
+        event.insert("datacontenttype".into(), "application/octet-stream".into());
+        event.insert("data_base64".into(), base64::encode(&record.data).into());
+    }
+
+    Ok(Value::Object(event))
+}
+
+/// Converts a structured mode CloudEvent into an event to be written. Events with an `id` which is
+/// not an UUID get a random event id, with the original kept in the metadata as `"id"`.
+pub fn from_cloud_event(event: &Value) -> io::Result<EventData> {
+    let event = match *event {
+        Value::Object(ref event) => event,
+        _ => return Err(invalid("CloudEvent is not an object")),
+    };
+
+    match attribute(event, "specversion")? {
+        "1.0" => (),
+        other => return Err(invalid(&format!("Unsupported CloudEvents version: {}", other))),
+    }
+
+    let id = attribute(event, "id")?;
+    let event_type = attribute(event, "type")?.to_owned();
+    attribute(event, "source")?;
+
+    let mut metadata = Map::new();
+
+    for (name, value) in event {
+        if name == "source" || name == "time" || is_extension(name) {
+            metadata.insert(name.clone(), value.clone());
+        }
+    }
+
+    let event_id = match Uuid::parse_str(id) {
+        Ok(uuid) => uuid,
+        Err(_) => {
+            metadata.insert("id".into(), id.into());
+            Uuid::new_v4()
+        },
+    };
+
+    let (data, data_content_type) = match (event.get("data"), event.get("data_base64")) {
+        (Some(&Value::String(ref data)), None) if !is_json(event) => (data.clone().into_bytes(), ContentType::Bytes),
+        (Some(data), None) => (serde_json::to_vec(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?, ContentType::Json),
+        (None, Some(&Value::String(ref data))) => {
+            (base64::decode(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?, ContentType::Bytes)
+        },
+        (None, None) => (Vec::new(), ContentType::Bytes),
+        _ => return Err(invalid("CloudEvent has both data and data_base64, or data_base64 is not a string")),
+    };
+
+    let metadata = serde_json::to_vec(&Value::Object(metadata)).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    Ok(EventData {
+        event_id: event_id,
+        event_type: event_type.into(),
+        data: data,
+        data_content_type: data_content_type,
+        metadata: Some(metadata),
+        metadata_content_type: ContentType::Json,
+    })
+}
+
+fn attribute<'a>(event: &'a Map<String, Value>, name: &str) -> io::Result<&'a str> {
+    event.get(name)
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| invalid(&format!("CloudEvent is missing the {} attribute", name)))
+}
+
+fn is_json(event: &Map<String, Value>) -> bool {
+    match event.get("datacontenttype").and_then(|value| value.as_str()) {
+        Some(content_type) => content_type == "application/json" || content_type.ends_with("+json"),
+        // data is JSON unless told otherwise
+        None => true,
+    }
+}
+
+/// Extension attribute names consist of lower case letters and digits.
+fn is_extension(name: &str) -> bool {
+    !name.is_empty()
+        && name.bytes().all(|b| (b'a' <= b && b <= b'z') || (b'0' <= b && b <= b'9'))
+        && !CONTEXT_ATTRIBUTES.contains(&name)
+}
+
+fn is_scalar(value: &Value) -> bool {
+    match *value {
+        Value::String(_) | Value::Number(_) | Value::Bool(_) => true,
+        _ => false,
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+/// Formats milliseconds since the Unix epoch as an RFC 3339 timestamp in UTC.
+fn rfc3339(epoch_millis: i64) -> String {
+    let (secs, millis) = div_floor(epoch_millis, 1000);
+    let (days, secs_of_day) = div_floor(secs, 86_400);
+
+    // from days since the epoch to the civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let (era, doe) = div_floor(days + 719_468, 146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year, month, day,
+            secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60, millis)
+}
+
+/// Returns the quotient rounded towards negative infinity and the non-negative remainder.
+fn div_floor(a: i64, b: i64) -> (i64, i64) {
+    let (q, r) = (a / b, a % b);
+    if r < 0 { (q - 1, r + b) } else { (q, r) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use serde_json::{self, Value};
+    use uuid::Uuid;
+    use {ContentType, EventRecord};
+    use super::{to_cloud_event, from_cloud_event, rfc3339};
+
+    fn record() -> EventRecord<'static> {
+        EventRecord {
+            event_stream_id: "orders-1".into(),
+            event_number: 0,
+            event_id: Cow::Owned(Uuid::parse_str("5f2b7c3a-1b7e-4c33-9a43-0d5e8b1c2f10").unwrap().as_bytes().to_vec()),
+            event_type: "order_placed".into(),
+            data_content_type: ContentType::Json.into(),
+            metadata_content_type: ContentType::Json.into(),
+            data: Cow::Borrowed(&br#"{"total":42}"#[..]),
+            metadata: Some(Cow::Borrowed(&br#"{"traceid":"abc","nested":{"x":1}}"#[..])),
+            created: None,
+            created_epoch: Some(1_500_000_000_123),
+        }
+    }
+
+    #[test]
+    fn recorded_to_cloud_event() {
+        let event = to_cloud_event(&record(), "esdb://localhost").unwrap();
+
+        let expected: Value = serde_json::from_str(r#"{
+            "specversion": "1.0",
+            "id": "5f2b7c3a-1b7e-4c33-9a43-0d5e8b1c2f10",
+            "type": "order_placed",
+            "source": "esdb://localhost",
+            "subject": "orders-1",
+            "time": "2017-07-14T02:40:00.123Z",
+            "traceid": "abc",
+            "datacontenttype": "application/json",
+            "data": { "total": 42 }
+        }"#).unwrap();
+
+        assert_eq!(event, expected);
+    }
+
+    #[test]
+    fn cloud_event_to_event_data() {
+        let event: Value = serde_json::from_str(r#"{
+            "specversion": "1.0",
+            "id": "A234-1234-1234",
+            "type": "com.example.someevent",
+            "source": "/mycontext",
+            "traceid": "abc",
+            "data_base64": "AQID"
+        }"#).unwrap();
+
+        let data = from_cloud_event(&event).unwrap();
+        assert_eq!(data.event_type, "com.example.someevent");
+        assert_eq!(data.data, vec![1, 2, 3]);
+        assert_eq!(data.data_content_type, ContentType::Bytes);
+
+        let metadata: Value = serde_json::from_slice(data.metadata.as_ref().unwrap()).unwrap();
+        assert_eq!(metadata, serde_json::from_str::<Value>(r#"{"id":"A234-1234-1234","source":"/mycontext","traceid":"abc"}"#).unwrap());
+
+        assert!(from_cloud_event(&serde_json::from_str(r#"{"specversion":"0.3"}"#).unwrap()).is_err());
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(rfc3339(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(rfc3339(951_782_400_000), "2000-02-29T00:00:00.000Z");
+        assert_eq!(rfc3339(-1), "1969-12-31T23:59:59.999Z");
+    }
+}
@@ -0,0 +1,392 @@
+//! Connecting to a cluster of EventStore nodes: discovering the members and choosing the nodes to
+//! connect to.
+
+use std::io;
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use futures::{future, Future, Stream};
+use futures::future::Loop;
+use tokio_core::reactor::{Handle, Interval, Timeout};
+use tokio_service::Service;
+
+use package::Package;
+use raw::RawMessage;
+use {EventStoreClient, ConnectionSettings, ConnectionClosed};
+
+/// Role of a cluster member.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum NodeRole {
+    /// The member accepting writes, called master by server versions before 20.6
+    Leader,
+    /// Member replicating the leader, which can be elected as the leader
+    Follower,
+    /// Member replicating the leader which never takes part in elections (server 20.6+)
+    ReadOnlyReplica,
+}
+
+/// Member of a cluster as returned by `Discovery`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ClusterMember {
+    /// Role of the member
+    pub role: NodeRole,
+    /// Address of the external TCP interface of the member
+    pub tcp_addr: SocketAddr,
+}
+
+/// The kind of member preferred for handling reads.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "kebab-case"))]
+pub enum NodePreference {
+    /// Send reads to the leader as well
+    Leader,
+    /// Prefer followers, then read-only replicas
+    Follower,
+    /// Prefer read-only replicas, then followers
+    ReadOnlyReplica,
+}
+
+impl Default for NodePreference {
+    fn default() -> Self {
+        NodePreference::Leader
+    }
+}
+
+impl NodePreference {
+    /// Selects the member to read from, falling back to the other roles when no member of the
+    /// preferred role is found. Returns `None` only when there are no members.
+    pub fn select<'a>(&self, members: &'a [ClusterMember]) -> Option<&'a ClusterMember> {
+        use self::NodeRole::*;
+
+        let order: &[NodeRole] = match *self {
+            NodePreference::Leader => &[Leader, Follower, ReadOnlyReplica],
+            NodePreference::Follower => &[Follower, ReadOnlyReplica, Leader],
+            NodePreference::ReadOnlyReplica => &[ReadOnlyReplica, Follower, Leader],
+        };
+
+        order.iter()
+            .filter_map(|role| members.iter().find(|m| m.role == *role))
+            .next()
+    }
+}
+
+/// Source of the current members of a cluster. The gossip protocol is served over HTTP, which
+/// `GossipDiscovery` of the `gossip` feature queries; otherwise querying it is left for the
+/// implementors of this trait. `Vec<ClusterMember>` implements this as a static list of members.
+pub trait Discovery {
+    /// Returns the members of the cluster as currently known.
+    fn discover(&self) -> Box<Future<Item = Vec<ClusterMember>, Error = io::Error>>;
+}
+
+impl Discovery for Vec<ClusterMember> {
+    fn discover(&self) -> Box<Future<Item = Vec<ClusterMember>, Error = io::Error>> {
+        Box::new(future::ok(self.clone()))
+    }
+}
+
+/// Client which splits reads from writes: writes and all other operations are sent to the leader,
+/// and reads to the member selected by `ConnectionSettings::node_preference`. With the default
+/// preference only the leader is connected to.
+///
+/// With `ConnectionSettings::topology_refresh` the members are discovered again periodically,
+/// and the connections are migrated when the leader or the preferred member changes. This way the
+/// connections move before writes start failing with `NotHandled` because of a new leader.
+///
+/// Read-only replicas and followers do not handle reads which require the master, so reads sent
+/// through this client should not use `require_master(true)`.
+#[derive(Clone)]
+pub struct ClusterClient {
+    leader: EventStoreClient,
+    reads: Option<EventStoreClient>,
+    /// Keeps the topology refresh task running
+    alive: Arc<()>,
+}
+
+/// Addresses of the leader and the member for reads, if not the leader.
+type Targets = (SocketAddr, Option<SocketAddr>);
+
+impl ClusterClient {
+    /// Discovers the members of the cluster and connects to the leader, and to the preferred member
+    /// for reads unless that is the leader.
+    ///
+    /// Discovering is retried with the delays of `ConnectionSettings::backoff` while it fails or
+    /// finds no leader, and the connecting fails with `ConnectionClosed` once
+    /// `ConnectionSettings::max_discovery_attempts` have been made.
+    pub fn connect<D: Discovery + 'static>(discovery: D, handle: &Handle, settings: ConnectionSettings) -> Box<Future<Item = Self, Error = io::Error>> {
+        let handle = handle.clone();
+        let discovery = Rc::new(discovery);
+        let discovered = discover_targets(discovery.clone(), &handle, &settings);
+
+        Box::new(discovered.and_then(move |targets| {
+            let leader = EventStoreClient::connect_with_settings(&targets.0, &handle, settings.clone());
+
+            let connected: Box<Future<Item = Self, Error = io::Error>> = match targets.1 {
+                Some(addr) => {
+                    debug!("[{}] sending reads to {}", settings.log_label(), addr);
+                    let reads = EventStoreClient::connect_with_settings(&addr, &handle, settings.clone());
+                    Box::new(leader.join(reads).map(|(leader, reads)| ClusterClient::new(leader, Some(reads))))
+                },
+                None => Box::new(leader.map(|leader| ClusterClient::new(leader, None))),
+            };
+
+            connected.and_then(move |client| {
+                if let Some(interval) = settings.topology_refresh() {
+                    client.spawn_refresh(discovery, interval, targets, &handle)?;
+                }
+                Ok(client)
+            })
+        }))
+    }
+
+    /// Sends the request to the member handling it like `call`, failing it with
+    /// `DeadlineExceeded` unless the response has arrived by `deadline`, see
+    /// `EventStoreClient::call_with_deadline`. Moving to a new leader or preferred member does not
+    /// extend the deadline: the request waiting for the connection to move fails once the deadline
+    /// passes.
+    pub fn call_with_deadline(&self, req: Package, deadline: Instant) -> Box<Future<Item = Package, Error = io::Error>> {
+        self.client_for(&req).call_with_deadline(req, deadline)
+    }
+
+    fn client_for(&self, req: &Package) -> &EventStoreClient {
+        match self.reads {
+            Some(ref reads) if is_read(&req.message) => reads,
+            _ => &self.leader,
+        }
+    }
+
+    fn new(leader: EventStoreClient, reads: Option<EventStoreClient>) -> Self {
+        ClusterClient {
+            leader: leader,
+            reads: reads,
+            alive: Arc::new(()),
+        }
+    }
+
+    /// Spawns the task discovering the members every `interval`, running until all of the clones
+    /// of this client have been dropped.
+    fn spawn_refresh<D: Discovery + 'static>(&self, discovery: Rc<D>, interval: Duration, initial: Targets, handle: &Handle) -> io::Result<()> {
+        let alive = Arc::downgrade(&self.alive);
+        let leader = self.leader.clone();
+        let reads = self.reads.clone();
+        let preference = leader.settings().node_preference();
+        let label = leader.settings().log_label().to_owned();
+        let error_label = label.clone();
+        let current = Rc::new(Cell::new(initial));
+
+        let task = Interval::new(interval, handle)?
+            .take_while(move |_| -> io::Result<bool> { Ok(alive.upgrade().is_some()) })
+            .for_each(move |_| {
+                let leader = leader.clone();
+                let reads = reads.clone();
+                let label = label.clone();
+                let current = current.clone();
+
+                discovery.discover().then(move |res| -> io::Result<()> {
+                    let next = match res.map(|members| targets(&members, preference)) {
+                        Ok(Some(next)) => next,
+                        Ok(None) => {
+                            warn!("[{}] topology refresh found no leader", label);
+                            return Ok(());
+                        },
+                        Err(e) => {
+                            warn!("[{}] topology refresh failed: {}", label, e);
+                            return Ok(());
+                        },
+                    };
+
+                    let previous = current.replace(next);
+
+                    if next.0 != previous.0 {
+                        info!("[{}] leader changed from {} to {}", label, previous.0, next.0);
+                        leader.migrate(next.0);
+                    }
+
+                    if let Some(ref reads) = reads {
+                        // read from the leader when there is no other member left
+                        let (previous, next) = (previous.1.unwrap_or(previous.0), next.1.unwrap_or(next.0));
+                        if next != previous {
+                            info!("[{}] preferred member for reads changed from {} to {}", label, previous, next);
+                            reads.migrate(next);
+                        }
+                    }
+
+                    Ok(())
+                })
+            });
+
+        handle.spawn(task.map_err(move |e| warn!("[{}] topology refresh stopped: {}", error_label, e)));
+        Ok(())
+    }
+}
+
+impl Service for ClusterClient {
+    type Request = Package;
+    type Response = Package;
+    type Error = io::Error;
+    type Future = Box<Future<Item = Package, Error = io::Error>>;
+
+    fn call(&self, req: Package) -> Self::Future {
+        self.client_for(&req).call(req)
+    }
+}
+
+/// Discovers the members until a leader is found, up to `ConnectionSettings::max_discovery_attempts`
+/// times.
+fn discover_targets<D: Discovery + 'static>(discovery: Rc<D>, handle: &Handle, settings: &ConnectionSettings) -> Box<Future<Item = Targets, Error = io::Error>> {
+    let handle = handle.clone();
+    let preference = settings.node_preference();
+    let max_attempts = settings.max_discovery_attempts();
+    let label = settings.log_label().to_owned();
+    let backoff = Rc::new(RefCell::new(settings.backoff()));
+
+    Box::new(future::loop_fn(1, move |attempt| {
+        let handle = handle.clone();
+        let label = label.clone();
+        let backoff = backoff.clone();
+
+        discovery.discover().then(move |res| -> Box<Future<Item = Loop<Targets, usize>, Error = io::Error>> {
+            let e = match res.map(|members| targets(&members, preference)) {
+                Ok(Some(targets)) => return Box::new(future::ok(Loop::Break(targets))),
+                Ok(None) => io::Error::new(io::ErrorKind::NotFound, "No leader found in the cluster"),
+                Err(e) => e,
+            };
+
+            if attempt >= max_attempts {
+                warn!("[{}] discovering the cluster failed: {}, giving up", label, e);
+                return Box::new(future::err(ConnectionClosed::new(
+                    format!("Discovery limit of {} attempts reached, last error: {}", max_attempts, e)).into()));
+            }
+
+            let delay = backoff.borrow_mut().next_delay();
+            warn!("[{}] discovering the cluster failed: {}, retrying in {:?} (attempt {}/{})", label, e, delay, attempt, max_attempts);
+
+            match Timeout::new(delay, &handle) {
+                Ok(timer) => Box::new(timer.map(move |_| Loop::Continue(attempt + 1))),
+                Err(e) => Box::new(future::err(e)),
+            }
+        })
+    }))
+}
+
+fn targets(members: &[ClusterMember], preference: NodePreference) -> Option<Targets> {
+    let leader = match members.iter().find(|m| m.role == NodeRole::Leader) {
+        Some(m) => m.tcp_addr,
+        None => return None,
+    };
+
+    let reads = match preference {
+        NodePreference::Leader => None,
+        preference => preference.select(members)
+            .map(|m| m.tcp_addr)
+            .and_then(|addr| if addr != leader { Some(addr) } else { None }),
+    };
+
+    Some((leader, reads))
+}
+
+fn is_read(message: &RawMessage) -> bool {
+    match *message {
+        RawMessage::ReadEvent(_)
+        | RawMessage::ReadStreamEvents(..)
+        | RawMessage::ReadAllEvents(..) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::rc::Rc;
+    use std::cell::Cell;
+    use futures::{future, Future};
+    use tokio_core::reactor::Core;
+    use super::{NodeRole, NodePreference, ClusterMember, Discovery, targets, discover_targets};
+    use {ConnectionSettings, ConnectionClosed};
+
+    fn member(role: NodeRole, port: u16) -> ClusterMember {
+        ClusterMember {
+            role: role,
+            tcp_addr: format!("127.0.0.1:{}", port).parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn select_prefers_replicas() {
+        let members = vec![
+            member(NodeRole::Follower, 1),
+            member(NodeRole::Leader, 2),
+            member(NodeRole::ReadOnlyReplica, 3),
+        ];
+
+        assert_eq!(NodePreference::Leader.select(&members), Some(&members[1]));
+        assert_eq!(NodePreference::Follower.select(&members), Some(&members[0]));
+        assert_eq!(NodePreference::ReadOnlyReplica.select(&members), Some(&members[2]));
+    }
+
+    #[test]
+    fn select_falls_back() {
+        let members = vec![member(NodeRole::Leader, 1), member(NodeRole::Follower, 2)];
+
+        assert_eq!(NodePreference::ReadOnlyReplica.select(&members), Some(&members[1]));
+        assert_eq!(NodePreference::Follower.select(&members[..1]), Some(&members[0]));
+        assert_eq!(NodePreference::Leader.select(&[]), None);
+    }
+
+    #[test]
+    fn targets_split_reads() {
+        let members = vec![member(NodeRole::Leader, 1), member(NodeRole::ReadOnlyReplica, 2)];
+
+        assert_eq!(targets(&members, NodePreference::Leader), Some((members[0].tcp_addr, None)));
+        assert_eq!(targets(&members, NodePreference::ReadOnlyReplica), Some((members[0].tcp_addr, Some(members[1].tcp_addr))));
+        assert_eq!(targets(&members[..1], NodePreference::Follower), Some((members[0].tcp_addr, None)));
+        assert_eq!(targets(&members[1..], NodePreference::Follower), None);
+    }
+
+    /// Fails the first `failures` discoveries.
+    struct Flaky {
+        failures: usize,
+        attempts: Cell<usize>,
+        members: Vec<ClusterMember>,
+    }
+
+    impl Discovery for Flaky {
+        fn discover(&self) -> Box<Future<Item = Vec<ClusterMember>, Error = io::Error>> {
+            self.attempts.set(self.attempts.get() + 1);
+
+            if self.attempts.get() <= self.failures {
+                Box::new(future::err(io::Error::new(io::ErrorKind::ConnectionRefused, "seed unavailable")))
+            } else {
+                Box::new(future::ok(self.members.clone()))
+            }
+        }
+    }
+
+    #[test]
+    fn discovery_is_retried_up_to_the_limit() {
+        use std::time::Duration;
+        use backoff::DecorrelatedJitter;
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let settings = ConnectionSettings::builder()
+            .max_discovery_attempts(3)
+            .backoff(DecorrelatedJitter::new(Duration::from_millis(1), Duration::from_millis(1)))
+            .build();
+        let members = vec![member(NodeRole::Leader, 1)];
+
+        let flaky = Rc::new(Flaky { failures: 2, attempts: Cell::new(0), members: members.clone() });
+        let found = core.run(discover_targets(flaky.clone(), &handle, &settings)).unwrap();
+        assert_eq!((found, flaky.attempts.get()), ((members[0].tcp_addr, None), 3));
+
+        let flaky = Rc::new(Flaky { failures: 3, attempts: Cell::new(0), members: members });
+        let e = core.run(discover_targets(flaky.clone(), &handle, &settings)).unwrap_err();
+        assert_eq!(flaky.attempts.get(), 3);
+        assert!(ConnectionClosed::find(&e).unwrap().reason.contains("seed unavailable"));
+    }
+}
@@ -8,18 +8,49 @@ use tokio_io::codec::{Encoder, Decoder};
 use bytes::{BytesMut, BufMut};
 
 use errors::ErrorKind;
-use package::Package;
+use package::{Package, BorrowedPackage};
 use {UsernamePassword};
 use raw::RawMessage;
 
 bitflags!{
-    /// `TcpFlags` describes if optional fields (authentication) is present.
+    /// `TcpFlags` describes if optional fields (authentication) is present, and whether the
+    /// package is a trusted write.
     pub flags TcpFlags: u8 {
         /// No authentication information present
         const FLAG_NONE = 0x00,
         /// Package contains authentication
         const FLAG_AUTHENTICATED = 0x01,
-        //const FLAG_TRUSTED_WRITE = 0x02, // only in core
+        /// Write bypassing the access checks of the server, meant only for components of the
+        /// cluster itself. Only sent when enabled with
+        /// `ConnectionSettingsBuilder::dangerous_trusted_writes`.
+        const FLAG_TRUSTED_WRITE = 0x02,
+    }
+}
+
+/// Overwrites the username and password of an encoded frame, starting from the length prefix, with
+/// `*` so that the frame can be logged or dumped. Frames without credentials are left as is, as
+/// are the bytes following a truncated authentication block.
+pub fn redact_credentials(frame: &mut [u8]) {
+    // length prefix, discriminator, flags and correlation id
+    const HEADER_LEN: usize = 4 + 1 + 1 + 16;
+
+    if frame.len() < HEADER_LEN || frame[5] & FLAG_AUTHENTICATED.bits() == 0 {
+        return;
+    }
+
+    let mut pos = HEADER_LEN;
+
+    // username and password, both prefixed with an u8 length
+    for _ in 0..2 {
+        if pos >= frame.len() {
+            return;
+        }
+
+        let end = ::std::cmp::min(pos + 1 + frame[pos] as usize, frame.len());
+        for b in &mut frame[pos + 1..end] {
+            *b = b'*';
+        }
+        pos = end;
     }
 }
 
@@ -27,7 +58,13 @@ bitflags!{
 pub struct PackageCodec;
 
 impl PackageCodec {
-    fn decode_inner(&mut self, buf: &mut BytesMut) -> io::Result<Option<Package>> {
+    /// Decodes the first frame of `buf` without copying the payloads of the message, which
+    /// borrow from `buf` instead. Returns `None` if `buf` does not yet contain a whole frame,
+    /// otherwise the package and the length of the frame in bytes.
+    ///
+    /// `Decoder::decode` copies the payloads as the frame is removed from the buffer; this is
+    /// useful when the whole input is already in memory, for example when inspecting dumps.
+    pub fn decode_borrowed<'a>(&mut self, buf: &'a [u8]) -> io::Result<Option<(BorrowedPackage<'a>, usize)>> {
         if buf.len() < 4 + 1 + 1 + 16 {
             return Ok(None);
         }
@@ -42,21 +79,61 @@ impl PackageCodec {
             return Ok(None);
         }
 
-        let decoded_frame = self.decode_body(&buf[4..(4 + len)]);
-        decoded_frame.and_then(|(c, a, m)| {
-            buf.split_to(4 + len);
-            Ok(Some(Package {
-                correlation_id: c,
+        let body = &buf[4..(4 + len)];
+        let (d, c, a, pos) = self.decode_header(body)?;
+        let message = RawMessage::decode(d, &body[pos..])?;
+
+        Ok(Some((BorrowedPackage {
+            correlation_id: c,
+            authentication: a,
+            message: message,
+        }, 4 + len)))
+    }
+
+    /// Decodes the header of the first frame like `Decoder::decode` but leaves the message
+    /// unparsed, as `RawMessage::Unsupported` with the discriminator and the payload. The message
+    /// can be parsed later, on another thread, with `parse_unparsed`.
+    #[doc(hidden)]
+    pub fn decode_unparsed(&mut self, buf: &mut BytesMut) -> io::Result<Option<Package>> {
+        use std::borrow::Cow;
+
+        let (package, len) = {
+            if buf.len() < 4 + 1 + 1 + 16 {
+                return Ok(None);
+            }
+
+            let len = io::Cursor::new(&buf[0..4]).read_u32::<LittleEndian>()? as usize;
+
+            if len < 18 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "length is too small"))
+            }
+
+            if buf.len() < len + 4 {
+                return Ok(None);
+            }
+
+            let body = &buf[4..(4 + len)];
+            let (d, c, a, pos) = self.decode_header(body)?;
+
+            (Package {
                 authentication: a,
-                message: m.into(),
-            }))
-        })
+                correlation_id: c,
+                message: RawMessage::Unsupported(d, Cow::Owned(body[pos..].to_vec())),
+            }, 4 + len)
+        };
+
+        buf.split_to(len);
+        Ok(Some(package))
     }
 
-    fn decode_body(&mut self, buf: &[u8]) -> io::Result<(Uuid, Option<UsernamePassword>, RawMessage<'static>)> {
-        let (d, c, a, pos) = self.decode_header(buf)?;
-        let message = RawMessage::decode(d, &buf[pos..])?.into_owned();
-        Ok((c, a, message))
+    fn decode_inner(&mut self, buf: &mut BytesMut) -> io::Result<Option<Package>> {
+        let (package, len) = match self.decode_borrowed(&buf[..])? {
+            Some((package, len)) => (package.into_owned(), len),
+            None => return Ok(None),
+        };
+
+        buf.split_to(len);
+        Ok(Some(package))
     }
 
     fn decode_header(&mut self, buf: &[u8]) -> io::Result<(u8, Uuid, Option<UsernamePassword>, usize)> {
@@ -90,32 +167,44 @@ impl PackageCodec {
 
     #[doc(hidden)]
     pub fn encode_parts<'a>(&self, cursor: &mut io::Cursor<Vec<u8>>, correlation_id: &Uuid, authentication: Option<&UsernamePassword>, raw: &RawMessage<'a>) -> io::Result<()> {
-        let mut flags = FLAG_NONE;
-        if authentication.is_some() {
-            flags.insert(FLAG_AUTHENTICATED);
-        }
+        self.encode_parts_with_flags(cursor, correlation_id, authentication, raw, FLAG_NONE)
+    }
 
-        cursor.write_u32::<LittleEndian>(0)?; // placeholder for prefix
-        cursor.write_u8(raw.discriminator())?;
-        cursor.write_u8(flags.bits())?;
-        cursor.write_all(correlation_id.as_bytes())?;
-        if flags.contains(FLAG_AUTHENTICATED) {
-            authentication
-                .expect("According to flag authentication token is present")
-                .encode(cursor)?;
-        } else {
-            assert!(authentication.is_none());
-        }
+    fn encode_parts_with_flags<'a>(&self, cursor: &mut io::Cursor<Vec<u8>>, correlation_id: &Uuid, authentication: Option<&UsernamePassword>, raw: &RawMessage<'a>, flags: TcpFlags) -> io::Result<()> {
+        let len = frame_len(authentication, raw);
+
+        // reserving the exact size keeps the buffer holding the credentials from growing, leaving
+        // copies of them behind
+        cursor.get_mut().reserve(4 + len);
+        write_frame(cursor, len, correlation_id, authentication, raw, flags)
+    }
+}
 
-        raw.encode(cursor)?;
+/// The length of the frame following the length prefix, computed without encoding the message.
+fn frame_len(authentication: Option<&UsernamePassword>, raw: &RawMessage) -> usize {
+    let auth_len = authentication.map(|auth| auth.encoded_len()).unwrap_or(0);
+    1 + 1 + 16 + auth_len + raw.encoded_len()
+}
 
-        let at_end = cursor.position();
-        let len = at_end as u32 - 4;
+/// Writes the length prefix followed by the frame of `len` bytes, in one pass.
+fn write_frame<W: Write>(w: &mut W, len: usize, correlation_id: &Uuid, authentication: Option<&UsernamePassword>, raw: &RawMessage, mut flags: TcpFlags) -> io::Result<()> {
+    if authentication.is_some() {
+        flags.insert(FLAG_AUTHENTICATED);
+    }
 
-        cursor.set_position(0);
-        cursor.write_u32::<LittleEndian>(len)?;
-        Ok(())
+    w.write_u32::<LittleEndian>(len as u32)?;
+    w.write_u8(raw.discriminator())?;
+    w.write_u8(flags.bits())?;
+    w.write_all(correlation_id.as_bytes())?;
+    if flags.contains(FLAG_AUTHENTICATED) {
+        authentication
+            .expect("According to flag authentication token is present")
+            .encode(w)?;
+    } else {
+        assert!(authentication.is_none());
     }
+
+    raw.encode(w)
 }
 
 impl Decoder for PackageCodec {
@@ -132,16 +221,56 @@ impl Encoder for PackageCodec {
     type Error = io::Error;
 
     fn encode(&mut self, msg: Package, buf: &mut BytesMut) -> io::Result<()> {
-        let mut cursor = io::Cursor::new(Vec::new());
+        self.encode_with_flags(msg, FLAG_NONE, buf)
+    }
+}
 
-        self.encode_parts(&mut cursor, &msg.correlation_id, msg.authentication.as_ref(), &msg.message)?;
+impl PackageCodec {
+    /// Encodes like `Encoder::encode` with the additional `flags`. The authentication flag is set
+    /// according to the package in any case.
+    #[doc(hidden)]
+    pub fn encode_with_flags(&mut self, msg: Package, flags: TcpFlags, buf: &mut BytesMut) -> io::Result<()> {
+        let len = frame_len(msg.authentication.as_ref(), &msg.message);
+
+        // the frame is written straight into the reserved space, without an intermediate buffer
+        // which would need growing for large messages, or zeroing for the credentials. The writer
+        // does not grow `buf`, so a frame longer than computed fails to be written unless the
+        // reservation left extra capacity; a wrong length is checked in any case so that a length
+        // prefix not matching the frame is never sent.
+        buf.reserve(4 + len);
+        let before = buf.len();
+
+        let result = write_frame(&mut buf.writer(), len, &msg.correlation_id, msg.authentication.as_ref(), &msg.message, flags);
+        let written = buf.len() - before;
+
+        if let Err(e) = result {
+            buf.truncate(before);
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("Frame of 0x{:02X} is longer than the computed {} bytes: {}", msg.message.discriminator(), 4 + len, e)));
+        }
+
+        if written != 4 + len {
+            buf.truncate(before);
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("Frame length computed wrong for 0x{:02X}: {} bytes, written {}", msg.message.discriminator(), 4 + len, written)));
+        }
 
-        let tmp = cursor.into_inner();
-        buf.put_slice(&tmp);
         Ok(())
     }
 }
 
+/// Parses the message of a package decoded with `PackageCodec::decode_unparsed`. Packages with
+/// other messages are returned as is.
+#[doc(hidden)]
+pub fn parse_unparsed(package: Package) -> io::Result<Package> {
+    let message = match package.message {
+        RawMessage::Unsupported(d, ref payload) => RawMessage::decode(d, payload)?.into_owned(),
+        _ => return Ok(package),
+    };
+
+    Ok(Package { message: message, ..package })
+}
+
 #[cfg(test)]
 mod tests {
     use std::fmt::Debug;
@@ -152,6 +281,7 @@ mod tests {
     use package::Package;
     use raw::RawMessage;
     use raw::client_messages::{WriteEventsCompleted, OperationResult};
+    use test::{Bencher, black_box};
 
     #[test]
     fn decode_ping() {
@@ -242,6 +372,278 @@ mod tests {
 
     }
 
+    #[test]
+    fn read_event_round_trip() {
+        use std::borrow::Cow;
+        use raw::client_messages::{ReadEvent, ReadEventCompleted, ResolvedIndexedEvent, EventRecord};
+        use raw::client_messages::mod_ReadEventCompleted::ReadEventResult;
+
+        let request = Package {
+            authentication: None,
+            correlation_id: Uuid::new_v4(),
+            message: RawMessage::ReadEvent(ReadEvent {
+                event_stream_id: Cow::Borrowed("orders-1"),
+                event_number: 3,
+                resolve_link_tos: true,
+                require_master: false,
+            }),
+        };
+
+        let response = Package {
+            message: RawMessage::ReadEventCompleted(ReadEventCompleted {
+                result: Some(ReadEventResult::Success),
+                event: ResolvedIndexedEvent {
+                    event: EventRecord {
+                        event_stream_id: "orders-1".into(),
+                        event_number: 3,
+                        event_id: Cow::Owned(Uuid::new_v4().as_bytes().to_vec()),
+                        event_type: "created".into(),
+                        data: Cow::Borrowed(&b"{}"[..]),
+                        ..EventRecord::default()
+                    },
+                    link: None,
+                },
+                error: None,
+            }),
+            ..request.clone()
+        };
+
+        test_round_trip(request, 0xB0);
+        test_round_trip(response, 0xB1);
+    }
+
+    #[test]
+    fn read_stream_events_forward_round_trip() {
+        use std::borrow::Cow;
+        use raw::client_messages::{ReadStreamEvents, ReadStreamEventsCompleted, ResolvedIndexedEvent, EventRecord};
+        use raw::client_messages::mod_ReadStreamEventsCompleted::ReadStreamResult;
+        use ReadDirection;
+
+        let request = Package {
+            authentication: None,
+            correlation_id: Uuid::new_v4(),
+            message: RawMessage::ReadStreamEvents(ReadDirection::Forward, ReadStreamEvents {
+                event_stream_id: Cow::Borrowed("orders-1"),
+                from_event_number: 0,
+                max_count: 2,
+                resolve_link_tos: true,
+                require_master: false,
+            }),
+        };
+
+        let event = |number| ResolvedIndexedEvent {
+            event: EventRecord {
+                event_stream_id: "orders-1".into(),
+                event_number: number,
+                event_id: Cow::Owned(Uuid::new_v4().as_bytes().to_vec()),
+                event_type: "created".into(),
+                ..EventRecord::default()
+            },
+            link: None,
+        };
+
+        let response = Package {
+            message: RawMessage::ReadStreamEventsCompleted(ReadDirection::Forward, ReadStreamEventsCompleted {
+                events: vec![event(0), event(1)],
+                result: Some(ReadStreamResult::Success),
+                next_event_number: 2,
+                last_event_number: 4,
+                is_end_of_stream: false,
+                last_commit_position: 1024,
+                error: None,
+            }),
+            ..request.clone()
+        };
+
+        test_round_trip(request, 0xB2);
+        test_round_trip(response, 0xB3);
+    }
+
+    #[test]
+    fn transaction_round_trip() {
+        use std::borrow::Cow;
+        use raw::client_messages::{TransactionStart, TransactionStartCompleted, TransactionWrite, TransactionWriteCompleted,
+                                   TransactionCommit, TransactionCommitCompleted, NewEvent, OperationResult};
+
+        let package = |message| Package {
+            authentication: None,
+            correlation_id: Uuid::new_v4(),
+            message: message,
+        };
+
+        test_round_trip(package(RawMessage::TransactionStart(TransactionStart {
+            event_stream_id: Cow::Borrowed("orders-1"),
+            expected_version: -1,
+            require_master: false,
+        })), 0x84);
+        test_round_trip(package(RawMessage::TransactionStartCompleted(TransactionStartCompleted {
+            transaction_id: 7,
+            result: Some(OperationResult::Success),
+            message: None,
+        })), 0x85);
+        test_round_trip(package(RawMessage::TransactionWrite(TransactionWrite {
+            transaction_id: 7,
+            events: vec![NewEvent {
+                event_id: Cow::Owned(Uuid::new_v4().as_bytes().to_vec()),
+                event_type: Cow::Borrowed("created"),
+                data_content_type: 1,
+                metadata_content_type: 0,
+                data: Cow::Borrowed(&b"{}"[..]),
+                metadata: None,
+            }],
+            require_master: false,
+        })), 0x86);
+        test_round_trip(package(RawMessage::TransactionWriteCompleted(TransactionWriteCompleted {
+            transaction_id: 7,
+            result: Some(OperationResult::Success),
+            message: None,
+        })), 0x87);
+        test_round_trip(package(RawMessage::TransactionCommit(TransactionCommit {
+            transaction_id: 7,
+            require_master: false,
+        })), 0x88);
+        test_round_trip(package(RawMessage::TransactionCommitCompleted(TransactionCommitCompleted {
+            transaction_id: 7,
+            result: Some(OperationResult::Success),
+            message: None,
+            first_event_number: 0,
+            last_event_number: 0,
+            prepare_position: Some(100),
+            commit_position: Some(100),
+        })), 0x89);
+    }
+
+    #[test]
+    fn subscription_round_trip() {
+        use std::borrow::Cow;
+        use raw::client_messages::{SubscribeToStream, SubscriptionConfirmation, SubscriptionDropped};
+        use raw::SubscriptionDropReason;
+
+        let request = Package {
+            authentication: None,
+            correlation_id: Uuid::new_v4(),
+            message: RawMessage::SubscribeToStream(SubscribeToStream {
+                event_stream_id: Cow::Borrowed("orders-1"),
+                resolve_link_tos: true,
+            }),
+        };
+
+        let response = Package {
+            message: RawMessage::SubscriptionConfirmation(SubscriptionConfirmation {
+                last_commit_position: 1024,
+                last_event_number: Some(3),
+            }),
+            ..request.clone()
+        };
+
+        let unsubscribe = Package {
+            message: RawMessage::UnsubscribeFromStream,
+            ..request.clone()
+        };
+
+        let dropped = Package {
+            message: RawMessage::SubscriptionDropped(SubscriptionDropped { reason: SubscriptionDropReason::NotFound }),
+            ..request.clone()
+        };
+
+        test_round_trip(request, 0xC0);
+        test_round_trip(response, 0xC1);
+        test_round_trip(unsubscribe, 0xC3);
+        test_round_trip(dropped, 0xC4);
+    }
+
+    #[test]
+    fn persistent_subscription_round_trip() {
+        use std::borrow::Cow;
+        use raw::client_messages::{ConnectToPersistentSubscription, PersistentSubscriptionConfirmation};
+        use raw::client_messages::{CreatePersistentSubscription, CreatePersistentSubscriptionCompleted};
+        use raw::client_messages::{UpdatePersistentSubscription, UpdatePersistentSubscriptionCompleted};
+        use raw::{CreatePersistentSubscriptionResult, UpdatePersistentSubscriptionResult};
+
+        let request = Package {
+            authentication: None,
+            correlation_id: Uuid::new_v4(),
+            message: RawMessage::ConnectToPersistentSubscription(ConnectToPersistentSubscription {
+                subscription_id: Cow::Borrowed("billing"),
+                event_stream_id: Cow::Borrowed("orders-1"),
+                allowed_in_flight_messages: 10,
+            }),
+        };
+
+        let response = Package {
+            message: RawMessage::PersistentSubscriptionConfirmation(PersistentSubscriptionConfirmation {
+                last_commit_position: 1024,
+                subscription_id: Cow::Borrowed("orders-1::billing"),
+                last_event_number: Some(3),
+            }),
+            ..request.clone()
+        };
+
+        test_round_trip(request, 0xC5);
+        test_round_trip(response, 0xC6);
+
+        let create = Package {
+            message: RawMessage::CreatePersistentSubscription(CreatePersistentSubscription {
+                subscription_group_name: Cow::Borrowed("billing"),
+                event_stream_id: Cow::Borrowed("orders-1"),
+                max_retry_count: 10,
+                named_consumer_strategy: Some(Cow::Borrowed("RoundRobin")),
+                ..CreatePersistentSubscription::default()
+            }),
+            ..request.clone()
+        };
+
+        let created = Package {
+            message: RawMessage::CreatePersistentSubscriptionCompleted(CreatePersistentSubscriptionCompleted {
+                result: CreatePersistentSubscriptionResult::AlreadyExists,
+                reason: Some(Cow::Borrowed("exists")),
+            }),
+            ..request.clone()
+        };
+
+        let update = Package {
+            message: RawMessage::UpdatePersistentSubscription(UpdatePersistentSubscription {
+                subscription_group_name: Cow::Borrowed("billing"),
+                event_stream_id: Cow::Borrowed("orders-1"),
+                max_retry_count: 3,
+                ..UpdatePersistentSubscription::default()
+            }),
+            ..request.clone()
+        };
+
+        let updated = Package {
+            message: RawMessage::UpdatePersistentSubscriptionCompleted(UpdatePersistentSubscriptionCompleted {
+                result: UpdatePersistentSubscriptionResult::DoesNotExist,
+                reason: None,
+            }),
+            ..request.clone()
+        };
+
+        test_round_trip(create, 0xC8);
+        test_round_trip(created, 0xC9);
+        test_round_trip(update, 0xCE);
+        test_round_trip(updated, 0xCF);
+    }
+
+    #[test]
+    fn identify_client() {
+        use std::borrow::Cow;
+        use raw::client_messages::IdentifyClient;
+
+        let input = "1c000000f5007b50a1b034b9224e8f9d708c394fab2d08011206666f6f626172";
+        let expected = Package {
+            authentication: None,
+            correlation_id: Uuid::parse_str("7b50a1b0-34b9-224e-8f9d-708c394fab2d").unwrap(),
+            message: RawMessage::IdentifyClient(IdentifyClient {
+                version: 1,
+                connection_name: Some(Cow::Borrowed("foobar")),
+            }),
+        };
+
+        test_decoding_hex(input, PackageCodec, expected.clone());
+        test_encoding_hex(input, PackageCodec, expected);
+    }
+
     #[test]
     fn decode_authenticated_package() {
         use bytes::BytesMut;
@@ -263,6 +665,170 @@ mod tests {
         assert_eq!(msg, decoded);
     }
 
+    #[test]
+    fn redact_authenticated_frame() {
+        use bytes::BytesMut;
+        use auth::UsernamePassword;
+        use super::redact_credentials;
+
+        let mut buf = BytesMut::with_capacity(1024);
+
+        let msg = Package::with_auth(RawMessage::Ping, UsernamePassword::new("foobar", "abbacd"));
+
+        PackageCodec.encode(msg.clone(), &mut buf).unwrap();
+        redact_credentials(&mut buf[..]);
+
+        let redacted = PackageCodec.decode(&mut buf).unwrap().unwrap();
+        let auth = redacted.authentication.unwrap();
+
+        assert_eq!(auth.username(), "******");
+        assert_eq!(auth.password(), "******");
+        assert_eq!(redacted.message, RawMessage::Ping);
+
+        assert!(!format!("{:?} {}", msg, msg).contains("foobar"));
+    }
+
+    #[test]
+    fn trusted_write_flag() {
+        use bytes::BytesMut;
+        use super::FLAG_TRUSTED_WRITE;
+
+        let mut buf = BytesMut::with_capacity(1024);
+
+        let msg = Package::new(RawMessage::Ping);
+
+        PackageCodec.encode_with_flags(msg.clone(), FLAG_TRUSTED_WRITE, &mut buf).unwrap();
+        assert_eq!(buf[5], 0x02);
+
+        // the flag is accepted but not kept
+        assert_eq!(PackageCodec.decode(&mut buf).unwrap(), Some(msg));
+    }
+
+    #[test]
+    fn parse_later() {
+        use bytes::BytesMut;
+        use super::parse_unparsed;
+
+        let frame = Vec::from_hex("2200000083009b59d8734e9fd84eb8a421f2666a3aa40800181e20272884d6bc563084d6bc56").unwrap();
+
+        let mut buf = BytesMut::from(&frame[..]);
+        let unparsed = PackageCodec.decode_unparsed(&mut buf).unwrap().unwrap();
+        assert!(buf.is_empty());
+
+        match unparsed.message {
+            RawMessage::Unsupported(0x83, _) => (),
+            ref other => panic!("unexpected message: {:?}", other),
+        }
+
+        let mut buf = BytesMut::from(&frame[..]);
+        let parsed = PackageCodec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(parse_unparsed(unparsed).unwrap(), parsed);
+    }
+
+    #[test]
+    fn decode_borrowed_payload() {
+        use std::borrow::Cow;
+
+        let frame = Vec::from_hex("15000000f4007b50a1b034b9224e8f9d708c394fab2d616263").unwrap();
+        let (package, len) = PackageCodec.decode_borrowed(&frame[..]).unwrap().unwrap();
+        assert_eq!(len, frame.len());
+
+        match package.message.clone() {
+            RawMessage::NotAuthenticated(reason) => match Cow::from(reason) {
+                Cow::Borrowed(reason) => {
+                    assert_eq!(reason, b"abc");
+                    assert_eq!(reason.as_ptr(), frame[22..].as_ptr());
+                },
+                Cow::Owned(_) => panic!("payload was copied"),
+            },
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        let owned = package.into_owned();
+        assert_eq!(owned.correlation_id, Uuid::parse_str("7b50a1b0-34b9-224e-8f9d-708c394fab2d").unwrap());
+
+        assert!(PackageCodec.decode_borrowed(&frame[..frame.len() - 1]).unwrap().is_none());
+    }
+
+    #[test]
+    fn encode_into_reserved_space() {
+        use bytes::BytesMut;
+        use auth::UsernamePassword;
+
+        let mut package = write_events(100);
+        package.authentication = Some(UsernamePassword::new("admin", "changeit"));
+
+        let mut buf = BytesMut::new();
+        PackageCodec.encode(package.clone(), &mut buf).unwrap();
+
+        assert_eq!(buf.len(), buf.capacity());
+        assert_eq!(buf[0..4], [(buf.len() - 4) as u8, ((buf.len() - 4) >> 8) as u8, 0, 0]);
+        assert_eq!(PackageCodec.decode(&mut buf).unwrap(), Some(package));
+    }
+
+    fn write_events(count: usize) -> Package {
+        use builder::Builder;
+        use ExpectedVersion;
+
+        let mut builder = Builder::write_events();
+        builder.stream_id("foo").expected_version(ExpectedVersion::Any);
+
+        for i in 0..count {
+            builder.new_event()
+                .event_type("bench")
+                .data(format!("{{\"i\":{}}}", i).into_bytes())
+                .done();
+        }
+
+        builder.build_package(None, None)
+    }
+
+    #[bench]
+    fn encode_write_events_in_one_pass(b: &mut Bencher) {
+        use bytes::BytesMut;
+
+        let package = write_events(500);
+
+        b.iter(|| {
+            let mut buf = BytesMut::new();
+            PackageCodec.encode(package.clone(), &mut buf).unwrap();
+            black_box(buf)
+        });
+    }
+
+    /// The former way of encoding the message into a growing buffer, which was then copied.
+    #[bench]
+    fn encode_write_events_through_growing_buffer(b: &mut Bencher) {
+        use std::io;
+        use bytes::{BytesMut, BufMut};
+
+        let package = write_events(500);
+
+        b.iter(|| {
+            let package = package.clone();
+            let mut body = io::Cursor::new(Vec::new());
+            package.message.encode(&mut body).unwrap();
+
+            let mut buf = BytesMut::new();
+            buf.reserve(22 + body.get_ref().len());
+            buf.put_slice(&[0u8; 22]);
+            buf.put_slice(body.get_ref());
+            black_box(buf)
+        });
+    }
+
+    /// Encodes and decodes the package, checking the discriminator of the frame.
+    fn test_round_trip(package: Package, discriminator: u8) {
+        use bytes::BytesMut;
+
+        let mut buf = BytesMut::new();
+        PackageCodec.encode(package.clone(), &mut buf).unwrap();
+        assert_eq!(buf[4], discriminator);
+
+        assert_eq!(PackageCodec.decode(&mut buf).unwrap(), Some(package));
+        assert!(buf.is_empty());
+    }
+
     fn test_decoding_hex<C: Decoder>(input: &str, codec: C, expected: C::Item)
         where C::Item: Debug + PartialEq, C::Error: Debug
     {
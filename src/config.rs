@@ -0,0 +1,425 @@
+//! Configuring the client from the environment or from configuration files, without code
+//! changes between deployments.
+
+use std::io;
+use std::fmt;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::error::Error;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use auth::UsernamePassword;
+use cluster::NodePreference;
+use settings::ConnectionSettings;
+use tls::{TlsSettings, ClientIdentity};
+
+/// Configuration of the client as plain values. With the `serialize` feature of this crate the
+/// configuration can be deserialized with serde, for example from the TOML or YAML files of a
+/// deployment; every field is optional and unknown fields are rejected. `ClientConfig::from_env`
+/// reads the same values from environment variables.
+///
+/// The configuration refers to secrets instead of containing them: passwords are read from the
+/// environment variables named in the configuration, and certificates from files, when the
+/// settings are created with `ClientConfig::settings`.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::ClientConfig;
+///
+/// let config = ClientConfig {
+///     endpoints: vec!["127.0.0.1:1113".into()],
+///     connection_name: Some("billing".into()),
+///     max_reconnect_attempts: Some(3),
+///     ..ClientConfig::default()
+/// };
+///
+/// let settings = config.settings().unwrap();
+/// assert_eq!(settings.connection_name(), Some("billing"));
+/// assert_eq!(settings.max_reconnect_attempts(), 3);
+/// assert_eq!(config.endpoints().unwrap()[0].port(), 1113);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default, deny_unknown_fields))]
+pub struct ClientConfig {
+    /// Addresses of the nodes as `host:port`, `EVENTSTORE_ENDPOINTS` separated by commas
+    pub endpoints: Vec<String>,
+    /// See `ConnectionSettingsBuilder::connection_name`, `EVENTSTORE_CONNECTION_NAME`
+    pub connection_name: Option<String>,
+    /// Default credentials of the operations, `EVENTSTORE_USERNAME` with the password in
+    /// `EVENTSTORE_PASSWORD`
+    pub credentials: Option<CredentialsConfig>,
+    /// See `ConnectionSettingsBuilder::heartbeat_interval`, `EVENTSTORE_HEARTBEAT_INTERVAL_MS`
+    pub heartbeat_interval_ms: Option<u64>,
+    /// See `ConnectionSettingsBuilder::heartbeat_timeout`, `EVENTSTORE_HEARTBEAT_TIMEOUT_MS`
+    pub heartbeat_timeout_ms: Option<u64>,
+    /// See `ConnectionSettingsBuilder::max_reconnect_attempts`,
+    /// `EVENTSTORE_MAX_RECONNECT_ATTEMPTS`
+    pub max_reconnect_attempts: Option<usize>,
+    /// See `ConnectionSettingsBuilder::node_preference`, `EVENTSTORE_NODE_PREFERENCE`; in the
+    /// variable and in configuration files one of `leader`, `follower` and `read-only-replica`
+    pub node_preference: Option<NodePreference>,
+    /// Encrypting the connections, `EVENTSTORE_TLS_DOMAIN` and the other `EVENTSTORE_TLS_`
+    /// variables
+    pub tls: Option<TlsConfig>,
+}
+
+/// Credentials with the password kept out of the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Deserialize))]
+#[cfg_attr(feature = "serialize", serde(deny_unknown_fields))]
+pub struct CredentialsConfig {
+    /// The username
+    pub username: String,
+    /// Name of the environment variable containing the password
+    pub password_env: String,
+}
+
+/// TLS options, see `TlsSettingsBuilder`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default, deny_unknown_fields))]
+pub struct TlsConfig {
+    /// The domain the certificates of the servers are issued for, `EVENTSTORE_TLS_DOMAIN`
+    pub domain: String,
+    /// Paths of DER encoded certificates to trust, `EVENTSTORE_TLS_ROOT_CERTIFICATES` separated
+    /// by commas
+    pub root_certificates: Vec<String>,
+    /// `false` disables the hostname verification, `EVENTSTORE_TLS_VERIFY_HOSTNAME`
+    pub verify_hostname: Option<bool>,
+    /// Hex encoded SHA-256 fingerprints of the accepted server certificates,
+    /// `EVENTSTORE_TLS_PINNED_SHA256` separated by commas
+    pub pinned_sha256: Vec<String>,
+    /// Path of the PKCS #12 archive of the client certificate, `EVENTSTORE_TLS_CLIENT_IDENTITY`
+    pub client_identity: Option<String>,
+    /// Name of the environment variable containing the password of the archive,
+    /// `EVENTSTORE_TLS_CLIENT_IDENTITY_PASSWORD_ENV`
+    pub client_identity_password_env: Option<String>,
+}
+
+impl ClientConfig {
+    /// Reads the configuration from the `EVENTSTORE_` environment variables named in the
+    /// documentation of the fields. Unset and empty variables leave the fields at their defaults.
+    pub fn from_env() -> Result<ClientConfig, InvalidConfig> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Result<ClientConfig, InvalidConfig> {
+        let var = |name: &str| var(name).and_then(|value| {
+            let value = value.trim().to_owned();
+            if value.is_empty() { None } else { Some(value) }
+        });
+
+        let list = |name: &str| var(name)
+            .map(|value| value.split(',').map(|item| item.trim().to_owned()).filter(|item| !item.is_empty()).collect::<Vec<_>>())
+            .unwrap_or_else(Vec::new);
+
+        let credentials = var("EVENTSTORE_USERNAME").map(|username| CredentialsConfig {
+            username: username,
+            password_env: "EVENTSTORE_PASSWORD".into(),
+        });
+
+        let node_preference = match var("EVENTSTORE_NODE_PREFERENCE") {
+            Some(value) => Some(match value.as_str() {
+                "leader" => NodePreference::Leader,
+                "follower" => NodePreference::Follower,
+                "read-only-replica" => NodePreference::ReadOnlyReplica,
+                _ => return Err(InvalidConfig::new("EVENTSTORE_NODE_PREFERENCE", format!("unknown node preference {:?}", value))),
+            }),
+            None => None,
+        };
+
+        let tls = match var("EVENTSTORE_TLS_DOMAIN") {
+            Some(domain) => Some(TlsConfig {
+                domain: domain,
+                root_certificates: list("EVENTSTORE_TLS_ROOT_CERTIFICATES"),
+                verify_hostname: parsed("EVENTSTORE_TLS_VERIFY_HOSTNAME", var("EVENTSTORE_TLS_VERIFY_HOSTNAME"))?,
+                pinned_sha256: list("EVENTSTORE_TLS_PINNED_SHA256"),
+                client_identity: var("EVENTSTORE_TLS_CLIENT_IDENTITY"),
+                client_identity_password_env: var("EVENTSTORE_TLS_CLIENT_IDENTITY_PASSWORD_ENV"),
+            }),
+            None => None,
+        };
+
+        Ok(ClientConfig {
+            endpoints: list("EVENTSTORE_ENDPOINTS"),
+            connection_name: var("EVENTSTORE_CONNECTION_NAME"),
+            credentials: credentials,
+            heartbeat_interval_ms: parsed("EVENTSTORE_HEARTBEAT_INTERVAL_MS", var("EVENTSTORE_HEARTBEAT_INTERVAL_MS"))?,
+            heartbeat_timeout_ms: parsed("EVENTSTORE_HEARTBEAT_TIMEOUT_MS", var("EVENTSTORE_HEARTBEAT_TIMEOUT_MS"))?,
+            max_reconnect_attempts: parsed("EVENTSTORE_MAX_RECONNECT_ATTEMPTS", var("EVENTSTORE_MAX_RECONNECT_ATTEMPTS"))?,
+            node_preference: node_preference,
+            tls: tls,
+        })
+    }
+
+    /// Resolves the endpoints to addresses, using the first address of every endpoint.
+    pub fn endpoints(&self) -> Result<Vec<SocketAddr>, InvalidConfig> {
+        self.endpoints.iter().map(|endpoint| {
+            let mut addrs = endpoint.to_socket_addrs()
+                .map_err(|e| InvalidConfig::new("endpoints", format!("{}: {}", endpoint, e)))?;
+
+            addrs.next().ok_or_else(|| InvalidConfig::new("endpoints", format!("{} has no addresses", endpoint)))
+        }).collect()
+    }
+
+    /// Creates the settings, reading the passwords from the environment and the certificates from
+    /// their files. Fields left unset keep the defaults of `ConnectionSettings`.
+    pub fn settings(&self) -> Result<ConnectionSettings, InvalidConfig> {
+        let mut builder = ConnectionSettings::builder();
+
+        if let Some(ref name) = self.connection_name {
+            if name.is_empty() {
+                return Err(InvalidConfig::new("connection_name", "the name is empty"));
+            }
+            builder.connection_name(name.clone());
+        }
+
+        if let Some(ref credentials) = self.credentials {
+            let password = secret("credentials.password_env", &credentials.password_env)?;
+
+            if credentials.username.len() >= 255 || password.len() >= 255 {
+                return Err(InvalidConfig::new("credentials", "the username and the password must be under 255 bytes"));
+            }
+            builder.credentials_provider(UsernamePassword::new(credentials.username.clone(), password));
+        }
+
+        if let Some(interval) = self.heartbeat_interval_ms {
+            builder.heartbeat_interval(positive_millis("heartbeat_interval_ms", interval)?);
+        }
+
+        if let Some(timeout) = self.heartbeat_timeout_ms {
+            builder.heartbeat_timeout(positive_millis("heartbeat_timeout_ms", timeout)?);
+        }
+
+        if let Some(attempts) = self.max_reconnect_attempts {
+            builder.max_reconnect_attempts(attempts);
+        }
+
+        if let Some(preference) = self.node_preference {
+            builder.node_preference(preference);
+        }
+
+        if let Some(ref tls) = self.tls {
+            builder.tls(tls.settings()?);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+impl TlsConfig {
+    fn settings(&self) -> Result<TlsSettings, InvalidConfig> {
+        if self.domain.is_empty() {
+            return Err(InvalidConfig::new("tls.domain", "the domain is empty"));
+        }
+
+        let mut builder = TlsSettings::builder(self.domain.clone());
+
+        for path in &self.root_certificates {
+            builder.add_root_certificate(read_file("tls.root_certificates", path)?);
+        }
+
+        if self.verify_hostname == Some(false) {
+            builder.danger_disable_hostname_verification();
+        }
+
+        for fingerprint in &self.pinned_sha256 {
+            builder.pin_sha256(sha256_hex(fingerprint)
+                .ok_or_else(|| InvalidConfig::new("tls.pinned_sha256", format!("{:?} is not 64 hex digits", fingerprint)))?);
+        }
+
+        if let Some(ref path) = self.client_identity {
+            let password = match self.client_identity_password_env {
+                Some(ref name) => secret("tls.client_identity_password_env", name)?,
+                None => String::new(),
+            };
+
+            builder.client_identity(ClientIdentity::from_pkcs12(read_file("tls.client_identity", path)?, password));
+        }
+
+        Ok(builder.build())
+    }
+}
+
+impl ConnectionSettings {
+    /// Creates the settings from the `EVENTSTORE_` environment variables, see
+    /// `ClientConfig::from_env`. The endpoints are left to `ClientConfig::endpoints`.
+    pub fn from_env() -> Result<ConnectionSettings, InvalidConfig> {
+        ClientConfig::from_env()?.settings()
+    }
+}
+
+/// Returned when a configuration has an invalid value, or refers to a secret or a file which
+/// cannot be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidConfig {
+    /// The field or the environment variable with the invalid value
+    pub key: String,
+    /// What is wrong with the value
+    pub reason: String,
+}
+
+impl InvalidConfig {
+    fn new<K: Into<String>, R: Into<String>>(key: K, reason: R) -> Self {
+        InvalidConfig { key: key.into(), reason: reason.into() }
+    }
+}
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: {}: {}", self.description(), self.key, self.reason)
+    }
+}
+
+impl Error for InvalidConfig {
+    fn description(&self) -> &str {
+        "Invalid configuration"
+    }
+}
+
+impl From<InvalidConfig> for io::Error {
+    fn from(invalid: InvalidConfig) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, invalid)
+    }
+}
+
+fn parsed<T: ::std::str::FromStr>(name: &str, value: Option<String>) -> Result<Option<T>, InvalidConfig> {
+    match value {
+        Some(value) => value.parse().map(Some).map_err(|_| InvalidConfig::new(name, format!("invalid value {:?}", value))),
+        None => Ok(None),
+    }
+}
+
+fn positive_millis(key: &str, millis: u64) -> Result<Duration, InvalidConfig> {
+    if millis == 0 {
+        Err(InvalidConfig::new(key, "the duration is zero"))
+    } else {
+        Ok(Duration::from_millis(millis))
+    }
+}
+
+fn secret(key: &str, name: &str) -> Result<String, InvalidConfig> {
+    env::var(name).map_err(|e| InvalidConfig::new(key, format!("{}: {}", name, e)))
+}
+
+fn read_file(key: &str, path: &str) -> Result<Vec<u8>, InvalidConfig> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|e| InvalidConfig::new(key, format!("{}: {}", path, e)))?;
+    Ok(bytes)
+}
+
+fn sha256_hex(hex: &str) -> Option<[u8; 32]> {
+    let digits = match hex.bytes().filter(|&b| b != b':').map(|b| (b as char).to_digit(16)).collect::<Option<Vec<_>>>() {
+        Some(ref digits) if digits.len() == 64 => digits.clone(),
+        _ => return None,
+    };
+
+    let mut fingerprint = [0u8; 32];
+    for (i, pair) in digits.chunks(2).enumerate() {
+        fingerprint[i] = (pair[0] * 16 + pair[1]) as u8;
+    }
+    Some(fingerprint)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use cluster::NodePreference;
+    use super::{ClientConfig, CredentialsConfig, sha256_hex};
+
+    fn from_vars(vars: &[(&str, &str)]) -> ClientConfig {
+        let vars = vars.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect::<HashMap<_, _>>();
+        ClientConfig::from_vars(|name| vars.get(name).cloned()).unwrap()
+    }
+
+    #[test]
+    fn variables_fill_the_config() {
+        let config = from_vars(&[
+            ("EVENTSTORE_ENDPOINTS", "10.0.0.1:1113, 10.0.0.2:1113,"),
+            ("EVENTSTORE_USERNAME", "admin"),
+            ("EVENTSTORE_HEARTBEAT_TIMEOUT_MS", "3000"),
+            ("EVENTSTORE_NODE_PREFERENCE", "follower"),
+            ("EVENTSTORE_CONNECTION_NAME", " "),
+        ]);
+
+        assert_eq!(config, ClientConfig {
+            endpoints: vec!["10.0.0.1:1113".into(), "10.0.0.2:1113".into()],
+            credentials: Some(CredentialsConfig { username: "admin".into(), password_env: "EVENTSTORE_PASSWORD".into() }),
+            heartbeat_timeout_ms: Some(3000),
+            node_preference: Some(NodePreference::Follower),
+            ..ClientConfig::default()
+        });
+
+        let settings = ClientConfig { credentials: None, ..config }.settings().unwrap();
+        assert_eq!(settings.heartbeat_timeout(), Duration::from_millis(3000));
+        assert_eq!(settings.heartbeat_interval(), Duration::from_millis(750));
+        assert_eq!(settings.node_preference(), NodePreference::Follower);
+    }
+
+    #[test]
+    #[cfg(all(feature = "serialize", feature = "serde_json"))]
+    fn files_fill_the_config() {
+        use std::env;
+        use std::fs::{self, File};
+        use std::io::Write;
+        use serde_json;
+        use uuid::Uuid;
+
+        let path = env::temp_dir().join(format!("eventstore-tcp-config-{}.json", Uuid::new_v4()));
+        File::create(&path).unwrap().write_all(br#"{
+            "endpoints": ["10.0.0.1:1113"],
+            "heartbeat_timeout_ms": 3000,
+            "node_preference": "read-only-replica"
+        }"#).unwrap();
+
+        let config: ClientConfig = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config, ClientConfig {
+            endpoints: vec!["10.0.0.1:1113".into()],
+            heartbeat_timeout_ms: Some(3000),
+            node_preference: Some(NodePreference::ReadOnlyReplica),
+            ..ClientConfig::default()
+        });
+
+        // the same spelling as EVENTSTORE_NODE_PREFERENCE
+        assert_eq!(config, from_vars(&[
+            ("EVENTSTORE_ENDPOINTS", "10.0.0.1:1113"),
+            ("EVENTSTORE_HEARTBEAT_TIMEOUT_MS", "3000"),
+            ("EVENTSTORE_NODE_PREFERENCE", "read-only-replica"),
+        ]));
+
+        assert!(serde_json::from_str::<ClientConfig>(r#"{"node_preference":"ReadOnlyReplica"}"#).is_err());
+    }
+
+    #[test]
+    fn invalid_values_are_reported() {
+        let vars = |name: &str| if name == "EVENTSTORE_MAX_RECONNECT_ATTEMPTS" { Some("many".to_owned()) } else { None };
+        assert_eq!(ClientConfig::from_vars(vars).unwrap_err().key, "EVENTSTORE_MAX_RECONNECT_ATTEMPTS");
+
+        let config = ClientConfig { heartbeat_interval_ms: Some(0), ..ClientConfig::default() };
+        assert_eq!(config.settings().unwrap_err().key, "heartbeat_interval_ms");
+
+        let config = ClientConfig {
+            credentials: Some(CredentialsConfig { username: "admin".into(), password_env: "EVENTSTORE_TCP_TEST_UNSET_PASSWORD".into() }),
+            ..ClientConfig::default()
+        };
+        assert_eq!(config.settings().unwrap_err().key, "credentials.password_env");
+    }
+
+    #[test]
+    fn fingerprints_are_hex() {
+        let hex = "00112233445566778899aabbccddeeff00112233445566778899AABBCCDDEEFF";
+        assert_eq!(sha256_hex(hex).unwrap()[..4], [0x00, 0x11, 0x22, 0x33]);
+        assert_eq!(sha256_hex(hex).unwrap()[31], 0xff);
+        assert!(sha256_hex(&hex[2..]).is_none());
+        assert!(sha256_hex("zz").is_none());
+    }
+}
@@ -0,0 +1,1487 @@
+//! The connection task owning the socket: writes the requests of `EventStoreClient` handles,
+//! correlates the responses back to them, keeps the connection alive with heartbeats and
+//! reconnects when the connection is lost.
+
+use std::io;
+use std::fmt;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::cell::Cell;
+use std::mem;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::time::{Duration, Instant};
+
+use futures::{Future, Stream, Sink, Poll, Async, StartSend, AsyncSink};
+use futures::sync::{mpsc, oneshot};
+use tokio_core::reactor::{Handle, Interval, Timeout};
+use tokio_io::AsyncRead;
+use tokio_io::codec::{Framed, Encoder, Decoder};
+use bytes::BytesMut;
+use uuid::Uuid;
+
+use package::Package;
+use codec::{PackageCodec, FLAG_NONE, FLAG_TRUSTED_WRITE, parse_unparsed};
+use raw::{RawMessage, NotHandledReason};
+use backoff::Backoff;
+use pending::Pending;
+use budget::{Charge, OverBudget, charge};
+use operation::{self, Context};
+use transport::AsyncIo;
+use diagnostics::Diagnostics;
+use deadline::Deadline;
+use {Builder, ConnectionSettings, AuthenticationFailed, UsernamePassword};
+
+/// Terminal error of a connection: the connection has been closed and will not be reconnected.
+/// Operations failing because of this are completed with an `io::Error` of kind `NotConnected`
+/// wrapping this value, see `ConnectionClosed::find`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionClosed {
+    /// Explanation why the connection was closed
+    pub reason: String,
+}
+
+impl ConnectionClosed {
+    /// Create a new value with the given reason.
+    pub fn new<S: Into<String>>(reason: S) -> Self {
+        ConnectionClosed { reason: reason.into() }
+    }
+
+    /// Returns the `ConnectionClosed` wrapped by the given error, if any.
+    pub fn find(e: &io::Error) -> Option<&ConnectionClosed> {
+        operation::find::<ConnectionClosed>(e)
+    }
+}
+
+impl fmt::Display for ConnectionClosed {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: {}", self.description(), self.reason)
+    }
+}
+
+impl Error for ConnectionClosed {
+    fn description(&self) -> &str {
+        "Connection closed"
+    }
+}
+
+impl From<ConnectionClosed> for io::Error {
+    fn from(closed: ConnectionClosed) -> io::Error {
+        io::Error::new(io::ErrorKind::NotConnected, closed)
+    }
+}
+
+/// Change in the state of a connection, see `EventStoreClient::connection_events`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// Connected to the node at the address
+    Connected(SocketAddr),
+    /// The connection to the node was lost, or could not be made, with the description of the
+    /// error. The connection is made again unless followed by `Closed`.
+    Disconnected(SocketAddr, String),
+    /// The server rejected the credentials of an operation, answering it with `NotAuthenticated`
+    AuthenticationFailed(AuthenticationFailed),
+    /// The connection was closed for good
+    Closed(ConnectionClosed),
+}
+
+/// Commands sent by `EventStoreClient` to the connection task.
+pub enum Command {
+    /// Send the request and complete it with the response
+    Send(Request),
+    /// Move the connection to another address once the requests in flight have completed
+    Migrate(SocketAddr),
+    /// Answer with a snapshot of the state of the connection
+    Diagnostics(oneshot::Sender<Diagnostics>),
+    /// Stop the streaming request with the correlation id, its stream has been dropped
+    Cancel(Uuid),
+    /// Send the following `ConnectionEvent`s
+    Events(mpsc::UnboundedSender<ConnectionEvent>),
+}
+
+/// Request to be sent, with the sender of the response.
+pub struct Request {
+    pub package: Package,
+    pub tx: Reply,
+    /// Fail the request with `DeadlineExceeded` unless completed by then
+    pub deadline: Option<Deadline>,
+    /// How many times the request was answered with `NotHandled` and sent again
+    pub retries: usize,
+}
+
+/// Where the packages correlated to a request are sent.
+pub enum Reply {
+    /// The request is completed by the first package
+    Once(oneshot::Sender<io::Result<Package>>),
+    /// Every package is sent until the receiver is dropped, for requests answered with more than
+    /// one package. The packages are charged to the memory budget until taken from the receiver,
+    /// with the bytes buffered by the stream kept in the counter.
+    Stream(mpsc::UnboundedSender<(io::Result<Package>, Option<Charge>)>, Arc<AtomicUsize>),
+}
+
+impl Reply {
+    fn is_stream(&self) -> bool {
+        match *self {
+            Reply::Once(_) => false,
+            Reply::Stream(..) => true,
+        }
+    }
+
+    /// Completes the request, or sends the last item of the stream.
+    fn send(self, res: io::Result<Package>) {
+        // the caller might no longer be interested
+        match self {
+            Reply::Once(tx) => { let _ = tx.send(res); },
+            Reply::Stream(tx, _) => { let _ = tx.unbounded_send((res, None)); },
+        }
+    }
+}
+
+/// Request written to the current connection.
+struct InFlight {
+    /// Copy of the request to be sent again in case the connection is lost, if the request can be
+    /// safely retried. Kept for a subscription until its first package arrives.
+    retry: Option<Package>,
+    tx: Reply,
+    /// Attached to the errors failing the request
+    context: Context,
+    /// When the request was written to the connection
+    sent: Instant,
+    deadline: Option<Deadline>,
+    retries: usize,
+    /// The credentials the request was sent with
+    credentials: Option<UsernamePassword>,
+}
+
+impl InFlight {
+    /// Fails the request with the error, wrapped with the context of the request.
+    fn fail(self, endpoint: SocketAddr, e: io::Error) {
+        self.tx.send(Err(self.context.error(endpoint, e)));
+    }
+}
+
+type Transport = Heartbeats<Framed<Box<AsyncIo>, ClientCodec>>;
+
+/// Future driving a connection, to be spawned on the reactor. Completes once all of the
+/// `EventStoreClient` handles have been dropped and all of their operations have completed.
+pub struct Connection {
+    state: State,
+    dispatch: Dispatch,
+}
+
+enum State {
+    Connecting(Box<Future<Item = Box<AsyncIo>, Error = io::Error>>),
+    Connected(Transport),
+    /// Waiting before the next connection attempt
+    Delaying(Timeout),
+    Closed(ConnectionClosed),
+}
+
+struct Dispatch {
+    addr: SocketAddr,
+    handle: Handle,
+    settings: ConnectionSettings,
+    requests: mpsc::UnboundedReceiver<Command>,
+    handles_gone: bool,
+    /// Requests which have not yet been written to a connection
+    queued: VecDeque<Request>,
+    /// Requests answered with `NotHandled` by a node not ready or too busy, to be queued again at
+    /// the instants
+    retrying: Vec<(Instant, Request)>,
+    /// Requests written to the current connection awaiting for a response, and the streaming
+    /// requests receiving packages. Owned by the connection task alone, so routing the received
+    /// packages takes no locks.
+    pending: Pending<InFlight>,
+    /// Address to move to once the requests in flight have completed
+    migrate_to: Option<SocketAddr>,
+    /// Correlation id of the `IdentifyClient` sent on the current connection
+    identify: Option<Uuid>,
+    /// The credentials the server has answered with `NotAuthenticated` on the current connection
+    rejected: Vec<(UsernamePassword, AuthenticationFailed)>,
+    /// Receivers of the `ConnectionEvent`s
+    listeners: Vec<mpsc::UnboundedSender<ConnectionEvent>>,
+    /// Completed once the first connection has been made or the connection is closed
+    ready: Option<oneshot::Sender<io::Result<()>>>,
+    /// Failed connection attempts since the last successful connection
+    attempts: usize,
+    backoff: Box<Backoff>,
+    /// Delays between sending the requests again, reset by the other responses
+    retry_backoff: Box<Backoff>,
+    /// Waiting for a snapshot of the connection
+    diagnostics: Vec<oneshot::Sender<Diagnostics>>,
+    /// Ticks of `ConnectionSettings::diagnostics_interval`
+    log_diagnostics: Option<Interval>,
+    /// Fires at the earliest deadline of the requests
+    deadline_timer: Option<Timeout>,
+    /// Fires when the next request in `retrying` is due
+    retry_timer: Option<Timeout>,
+}
+
+impl Connection {
+    /// Creates the connection task which will immediatedly start connecting to `addr`.
+    pub fn new(addr: SocketAddr,
+               handle: Handle,
+               settings: ConnectionSettings,
+               requests: mpsc::UnboundedReceiver<Command>,
+               ready: oneshot::Sender<io::Result<()>>) -> Self {
+        let mut dispatch = Dispatch {
+            addr: addr,
+            handle: handle,
+            requests: requests,
+            handles_gone: false,
+            queued: VecDeque::new(),
+            retrying: Vec::new(),
+            pending: Pending::new(),
+            migrate_to: None,
+            identify: None,
+            rejected: Vec::new(),
+            listeners: Vec::new(),
+            ready: Some(ready),
+            attempts: 0,
+            backoff: settings.backoff(),
+            retry_backoff: settings.backoff(),
+            diagnostics: Vec::new(),
+            log_diagnostics: None,
+            deadline_timer: None,
+            retry_timer: None,
+            settings: settings,
+        };
+
+        if let Some(interval) = dispatch.settings.diagnostics_interval() {
+            match Interval::new(interval, &dispatch.handle) {
+                Ok(interval) => dispatch.log_diagnostics = Some(interval),
+                Err(e) => warn!("[{}] diagnostics will not be logged: {}", dispatch.label(), e),
+            }
+        }
+
+        Connection {
+            state: dispatch.connect(),
+            dispatch: dispatch,
+        }
+    }
+}
+
+impl Future for Connection {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        self.dispatch.poll_requests();
+        self.dispatch.poll_deadlines();
+        self.dispatch.poll_retries();
+
+        // the snapshots are taken once the requests received have been written
+        let polled = self.poll_state();
+        self.poll_diagnostics();
+        polled
+    }
+}
+
+impl Connection {
+    fn poll_state(&mut self) -> Poll<(), ()> {
+        loop {
+            if self.dispatch.is_done() {
+                debug!("[{}] all handles dropped, closing", self.dispatch.label());
+                return Ok(Async::Ready(()));
+            }
+
+            if self.dispatch.migrate_to.is_some() {
+                // a connected transport is drained first, a closed one stays closed
+                let immediately = match self.state {
+                    State::Connected(_) | State::Closed(_) => false,
+                    State::Connecting(_) | State::Delaying(_) => true,
+                };
+
+                if immediately {
+                    self.state = self.dispatch.migrate();
+                }
+            }
+
+            let next = match self.state {
+                State::Connecting(ref mut connecting) => {
+                    match connecting.poll() {
+                        Ok(Async::Ready(stream)) => self.dispatch.connected(stream),
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(e) => self.dispatch.disconnected(e),
+                    }
+                },
+                State::Connected(ref mut transport) => {
+                    match self.dispatch.poll_transport(transport) {
+                        Ok(Async::Ready(())) => continue,
+                        Ok(Async::NotReady) if self.dispatch.is_drained() => self.dispatch.migrate(),
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(e) => self.dispatch.disconnected(e),
+                    }
+                },
+                State::Delaying(ref mut timer) => {
+                    match timer.poll() {
+                        Ok(Async::Ready(())) => self.dispatch.connect(),
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(e) => self.dispatch.disconnected(e),
+                    }
+                },
+                State::Closed(ref closed) => {
+                    self.dispatch.fail_all(closed);
+
+                    return Ok(if self.dispatch.handles_gone {
+                        Async::Ready(())
+                    } else {
+                        Async::NotReady
+                    });
+                },
+            };
+
+            self.state = next;
+        }
+    }
+
+    /// Answers the requested snapshots, and logs one if it is time to.
+    fn poll_diagnostics(&mut self) {
+        let connected = match self.state {
+            State::Connected(_) => true,
+            _ => false,
+        };
+
+        for tx in mem::replace(&mut self.dispatch.diagnostics, Vec::new()) {
+            let _ = tx.send(self.dispatch.snapshot(connected));
+        }
+
+        let mut ticked = false;
+
+        if let Some(ref mut interval) = self.dispatch.log_diagnostics {
+            while let Ok(Async::Ready(Some(()))) = interval.poll() {
+                ticked = true;
+            }
+        }
+
+        if ticked {
+            info!("[{}] {}", self.dispatch.label(), self.dispatch.snapshot(connected));
+        }
+    }
+}
+
+impl Dispatch {
+    fn label(&self) -> &str {
+        self.settings.log_label()
+    }
+
+    fn is_done(&self) -> bool {
+        self.handles_gone && self.queued.is_empty() && self.retrying.is_empty() && self.pending.is_empty()
+    }
+
+    fn poll_requests(&mut self) {
+        while !self.handles_gone {
+            match self.requests.poll() {
+                Ok(Async::Ready(Some(Command::Send(req)))) => self.queued.push_back(req),
+                Ok(Async::Ready(Some(Command::Migrate(addr)))) => {
+                    self.migrate_to = if addr != self.addr { Some(addr) } else { None };
+                },
+                Ok(Async::Ready(Some(Command::Diagnostics(tx)))) => self.diagnostics.push(tx),
+                Ok(Async::Ready(Some(Command::Cancel(id)))) => self.cancel(id),
+                Ok(Async::Ready(Some(Command::Events(tx)))) => self.listeners.push(tx),
+                Ok(Async::Ready(None)) | Err(()) => self.handles_gone = true,
+                Ok(Async::NotReady) => break,
+            }
+        }
+    }
+
+    /// Sends the event to the listeners, forgetting the ones gone.
+    fn emit(&mut self, event: ConnectionEvent) {
+        self.listeners.retain(|tx| tx.unbounded_send(event.clone()).is_ok());
+    }
+
+    /// Forgets the streaming request whose stream was dropped, freeing its slot. A subscription
+    /// already sent is ended with `UnsubscribeFromStream` under the correlation id of the
+    /// subscription, so that the server stops pushing the events.
+    fn cancel(&mut self, id: Uuid) {
+        let queued = self.queued.len();
+        self.queued.retain(|req| req.package.correlation_id != id);
+
+        if self.queued.len() < queued {
+            return;
+        }
+
+        let subscription = match self.pending.get(&id) {
+            Some(in_flight) if in_flight.tx.is_stream() => is_subscription(in_flight.context.command()),
+            _ => return,
+        };
+
+        trace!("[{}] stream {} was dropped", self.label(), id);
+        self.pending.remove(&id);
+
+        if subscription {
+            // the SubscriptionDropped answering this only frees the slot again
+            let (tx, _) = oneshot::channel();
+            let package = Builder::unsubscribe_from_stream().build_package(None, Some(id));
+            self.queued.push_front(Request { package: package, tx: Reply::Once(tx), deadline: None, retries: 0 });
+        }
+    }
+
+    /// Fails the requests whose deadline has passed, and sets the timer to the next deadline.
+    fn poll_deadlines(&mut self) {
+        loop {
+            self.expire(Instant::now());
+
+            let next = self.queued.iter()
+                .chain(self.retrying.iter().map(|&(_, ref req)| req))
+                .filter_map(|req| req.deadline.as_ref())
+                .chain(self.pending.values().filter_map(|in_flight| in_flight.deadline.as_ref()))
+                .map(|deadline| deadline.at())
+                .min();
+
+            let next = match next {
+                Some(next) => next,
+                None => {
+                    self.deadline_timer = None;
+                    return;
+                },
+            };
+
+            match fires(&mut self.deadline_timer, next, &self.handle) {
+                Ok(true) => continue,
+                Ok(false) => return,
+                Err(e) => {
+                    warn!("[{}] deadline timer failed: {}", self.label(), e);
+                    return;
+                },
+            }
+        }
+    }
+
+    /// Queues the requests in `retrying` which are due, and sets the timer to the next one.
+    fn poll_retries(&mut self) {
+        loop {
+            let now = Instant::now();
+
+            for (at, req) in mem::replace(&mut self.retrying, Vec::new()) {
+                if at <= now {
+                    self.queued.push_back(req);
+                } else {
+                    self.retrying.push((at, req));
+                }
+            }
+
+            let next = match self.retrying.iter().map(|&(at, _)| at).min() {
+                Some(next) => next,
+                None => {
+                    self.retry_timer = None;
+                    return;
+                },
+            };
+
+            match fires(&mut self.retry_timer, next, &self.handle) {
+                Ok(true) => continue,
+                Ok(false) => return,
+                Err(e) => {
+                    warn!("[{}] retry timer failed: {}", self.label(), e);
+                    return;
+                },
+            }
+        }
+    }
+
+    /// Sends the request answered with `NotHandled` again after the next delay of the backoff.
+    fn retry_later(&mut self, mut in_flight: InFlight) {
+        let mut package = in_flight.retry.take().expect("retry_later called without a copy of the request");
+        package.correlation_id = Uuid::new_v4();
+
+        let delay = self.retry_backoff.next_delay();
+        let now = Instant::now();
+        debug!("[{}] {} was not handled by {}, sending it again in {:?}", self.label(), package, self.addr, delay);
+
+        let deadline = in_flight.deadline.map(|mut deadline| {
+            deadline.requeued(now);
+            deadline
+        });
+
+        let req = Request { package: package, tx: in_flight.tx, deadline: deadline, retries: in_flight.retries + 1 };
+        self.retrying.push((now + delay, req));
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let addr = self.addr;
+        let expired = |deadline: &Option<Deadline>| match *deadline {
+            Some(ref deadline) if deadline.is_expired(now) => Some(deadline.exceeded(now)),
+            _ => None,
+        };
+
+        if self.queued.iter().any(|req| expired(&req.deadline).is_some()) {
+            for req in mem::replace(&mut self.queued, VecDeque::new()) {
+                let exceeded = expired(&req.deadline);
+
+                match exceeded {
+                    Some(exceeded) => req.tx.send(Err(Context::of(&req.package).error(addr, exceeded.into()))),
+                    None => self.queued.push_back(req),
+                }
+            }
+        }
+
+        if self.retrying.iter().any(|&(_, ref req)| expired(&req.deadline).is_some()) {
+            for (at, req) in mem::replace(&mut self.retrying, Vec::new()) {
+                let exceeded = expired(&req.deadline);
+
+                match exceeded {
+                    Some(exceeded) => req.tx.send(Err(Context::of(&req.package).error(addr, exceeded.into()))),
+                    None => self.retrying.push((at, req)),
+                }
+            }
+        }
+
+        let failed = self.pending.iter()
+            .filter_map(|(id, in_flight)| expired(&in_flight.deadline).map(|exceeded| (*id, exceeded)))
+            .collect::<Vec<_>>();
+
+        for (id, exceeded) in failed {
+            if let Some(in_flight) = self.pending.remove(&id) {
+                in_flight.fail(addr, exceeded.into());
+            }
+        }
+    }
+
+    fn snapshot(&self, connected: bool) -> Diagnostics {
+        let now = Instant::now();
+        let mut pending = BTreeMap::new();
+        let mut oldest = None;
+        let mut subscriptions = 0;
+
+        for in_flight in self.pending.values() {
+            if in_flight.tx.is_stream() {
+                subscriptions += 1;
+                continue;
+            }
+
+            *pending.entry(in_flight.context.command()).or_insert(0) += 1;
+            oldest = Some(oldest.map_or(in_flight.sent, |oldest: Instant| oldest.min(in_flight.sent)));
+        }
+
+        Diagnostics {
+            endpoint: self.addr,
+            connected: connected,
+            pending: pending,
+            oldest_pending: oldest.map(|sent| now.duration_since(sent)),
+            queued: self.queued.len() + self.retrying.len(),
+            subscriptions: subscriptions,
+        }
+    }
+
+    /// True when a migration has been requested and no more responses are expected from the
+    /// current connection.
+    fn is_drained(&self) -> bool {
+        self.migrate_to.is_some()
+            && self.pending.values().all(|in_flight| in_flight.tx.is_stream())
+            && self.identify.is_none()
+    }
+
+    fn migrate(&mut self) -> State {
+        let addr = self.migrate_to.take().expect("migrate called without address");
+        info!("[{}] moving the connection from {} to {}", self.label(), self.addr, addr);
+
+        // streams never complete by themselves and are not carried over
+        for (_, in_flight) in self.pending.drain() {
+            in_flight.fail(self.addr, io::Error::new(io::ErrorKind::ConnectionAborted, format!("Connection moved to {}", addr)));
+        }
+
+        self.addr = addr;
+        self.attempts = 0;
+        self.backoff.reset();
+        self.connect()
+    }
+
+    fn connect(&mut self) -> State {
+        debug!("[{}] connecting to {}", self.label(), self.addr);
+        State::Connecting(self.settings.transport().connect(&self.addr, &self.handle, &self.settings))
+    }
+
+    fn connected(&mut self, stream: Box<AsyncIo>) -> State {
+        info!("[{}] connected to {}", self.label(), self.addr);
+        let addr = self.addr;
+        self.rejected.clear();
+        self.emit(ConnectionEvent::Connected(addr));
+
+        let codec = ClientCodec::new(&self.settings);
+        let progress = codec.progress.clone();
+        let framed = stream.framed(codec);
+        let heartbeats = Heartbeats::new(framed, &self.handle, self.settings.heartbeat_interval(), self.settings.heartbeat_timeout(), self.label().to_owned())
+            .map(|heartbeats| heartbeats.with_read_progress(progress));
+
+        let mut transport = match heartbeats {
+            Ok(transport) => transport,
+            Err(e) => return self.disconnected(e),
+        };
+
+        self.attempts = 0;
+        self.backoff.reset();
+
+        match self.identify(&mut transport) {
+            Ok(()) => State::Connected(transport),
+            Err(e) => self.disconnected(e),
+        }
+    }
+
+    /// Sends `IdentifyClient` if the connection is named, otherwise the connection is ready
+    /// immediatedly.
+    fn identify(&mut self, transport: &mut Transport) -> io::Result<()> {
+        let name = match self.settings.connection_name().map(|name| name.to_owned()) {
+            Some(name) => name,
+            None => {
+                self.signal_ready(Ok(()));
+                return Ok(());
+            }
+        };
+
+        let package = Builder::identify_client(name).build_package(None, None);
+        self.identify = Some(package.correlation_id);
+
+        match transport.start_send(package)? {
+            AsyncSink::Ready => Ok(()),
+            AsyncSink::NotReady(_) => Err(io::Error::new(io::ErrorKind::Other, "New connection did not accept IdentifyClient")),
+        }
+    }
+
+    fn disconnected(&mut self, e: io::Error) -> State {
+        let mut retried = 0;
+        let addr = self.addr;
+        let rejected = mem::replace(&mut self.rejected, Vec::new());
+
+        for (_, mut in_flight) in self.pending.drain() {
+            // the server may close the connection over bad credentials, in which case sending the
+            // request again with the same credentials would only fail the same way
+            let failed = in_flight.credentials.as_ref().and_then(|credentials| {
+                rejected.iter().find(|&&(ref rejected, _)| rejected == credentials).map(|&(_, ref failed)| failed.clone())
+            });
+
+            if let Some(failed) = failed {
+                in_flight.fail(addr, failed.into());
+                continue;
+            }
+
+            match in_flight.retry.take() {
+                Some(mut package) => {
+                    // responses to the old id might still arrive if the server was only slow, but
+                    // a subscription keeps the id its stream is cancelled with
+                    if !in_flight.tx.is_stream() {
+                        package.correlation_id = Uuid::new_v4();
+                    }
+                    let deadline = in_flight.deadline.map(|mut deadline| {
+                        deadline.requeued(Instant::now());
+                        deadline
+                    });
+                    self.queued.push_front(Request { package: package, tx: in_flight.tx, deadline: deadline, retries: in_flight.retries });
+                    retried += 1;
+                },
+                None => {
+                    in_flight.fail(self.addr, io::Error::new(e.kind(), format!("Connection lost: {}", e)));
+                }
+            }
+        }
+
+        if retried > 0 {
+            debug!("[{}] {} operations will be sent again after reconnecting", self.label(), retried);
+        }
+
+        self.emit(ConnectionEvent::Disconnected(addr, e.to_string()));
+        self.identify = None;
+        self.attempts += 1;
+
+        let max_attempts = self.settings.max_reconnect_attempts();
+
+        if self.attempts > max_attempts {
+            warn!("[{}] connection to {} failed: {}, giving up", self.label(), self.addr, e);
+            return self.closed(ConnectionClosed::new(
+                format!("Reconnection limit of {} attempts reached, last error: {}", max_attempts, e)));
+        }
+
+        let delay = self.backoff.next_delay();
+        warn!("[{}] connection to {} failed: {}, reconnecting in {:?} (attempt {}/{})",
+              self.label(), self.addr, e, delay, self.attempts, max_attempts);
+
+        match Timeout::new(delay, &self.handle) {
+            Ok(timer) => State::Delaying(timer),
+            Err(e) => self.closed(ConnectionClosed::new(format!("Failed to create reconnection timer: {}", e))),
+        }
+    }
+
+    fn closed(&mut self, closed: ConnectionClosed) -> State {
+        self.emit(ConnectionEvent::Closed(closed.clone()));
+        State::Closed(closed)
+    }
+
+    fn poll_transport(&mut self, transport: &mut Transport) -> Poll<(), io::Error> {
+        // while draining, new requests wait for the next connection
+        while self.migrate_to.is_none() {
+            let Request { package, tx, deadline, retries } = match self.queued.pop_front() {
+                Some(req) => req,
+                None => break,
+            };
+
+            let id = package.correlation_id;
+            trace!("[{}] sending {}", self.label(), package);
+            let retriable = if tx.is_stream() { is_subscription(package.message.discriminator()) } else { is_retriable(&package.message) };
+            let retry = if retriable { Some(package.clone()) } else { None };
+            let now = Instant::now();
+            let written = deadline.clone().map(|mut deadline| {
+                deadline.written(now);
+                deadline
+            });
+            // insert first so that the request is handled with the others on error
+            let credentials = package.authentication.clone();
+            self.pending.insert(id, InFlight { retry: retry, tx: tx, context: Context::of(&package), sent: now, deadline: written, retries: retries, credentials: credentials });
+
+            if let AsyncSink::NotReady(package) = transport.start_send(package)? {
+                let InFlight { tx, .. } = self.pending.remove(&id).unwrap();
+                self.queued.push_front(Request { package: package, tx: tx, deadline: deadline, retries: retries });
+                break;
+            }
+        }
+
+        transport.poll_complete()?;
+
+        loop {
+            match transport.poll()? {
+                Async::Ready(Some(package)) => self.received(package),
+                Async::Ready(None) => return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "Connection closed by the server")),
+                Async::NotReady => break,
+            }
+        }
+
+        Ok(if self.is_done() { Async::Ready(()) } else { Async::NotReady })
+    }
+
+    fn received(&mut self, package: Package) {
+        let id = package.correlation_id;
+
+        if let RawMessage::NotAuthenticated(ref reason) = package.message {
+            let failed = AuthenticationFailed::new(String::from_utf8_lossy(reason.as_ref()).into_owned());
+            warn!("[{}] {}", self.label(), failed);
+
+            let credentials = self.pending.get(&id).and_then(|in_flight| in_flight.credentials.clone());
+            if let Some(credentials) = credentials {
+                self.rejected.push((credentials, failed.clone()));
+            }
+
+            self.emit(ConnectionEvent::AuthenticationFailed(failed));
+        }
+        let streaming = self.pending.get(&id).map(|in_flight| in_flight.tx.is_stream());
+
+        if streaming == Some(true) {
+            let open = match self.pending.get(&id) {
+                Some(in_flight) => self.stream(in_flight, package),
+                None => false,
+            };
+
+            if !open {
+                trace!("[{}] stream {} was dropped", self.label(), id);
+                self.pending.remove(&id);
+            } else if let Some(in_flight) = self.pending.get_mut(&id) {
+                // subscribing again would silently skip the events appended while disconnected
+                in_flight.retry = None;
+            }
+        } else if let Some(in_flight) = self.pending.remove(&id) {
+            let retried = in_flight.retry.is_some() && in_flight.retries < self.settings.max_operation_retries();
+
+            if retried && is_not_ready(&package.message) {
+                self.retry_later(in_flight);
+            } else {
+                self.retry_backoff.reset();
+                self.respond(in_flight, package);
+            }
+        } else if self.identify == Some(id) {
+            self.identify = None;
+
+            match package.message {
+                RawMessage::ClientIdentified => {
+                    info!("[{}] connection identified", self.label());
+                    self.signal_ready(Ok(()));
+                },
+                other => {
+                    warn!("[{}] identifying the connection failed: {:?}", self.label(), other);
+                    self.signal_ready(Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("Unexpected response to IdentifyClient: {:?}", other))));
+                }
+            }
+        } else {
+            warn!("[{}] dropping uncorrelated package {}", self.label(), package);
+        }
+    }
+
+    /// Sends a package to a streaming request within the memory budget. Returns `false` if the
+    /// stream is gone or was closed, or if the package was the last one of the stream.
+    fn stream(&self, in_flight: &InFlight, package: Package) -> bool {
+        let (tx, buffered) = match in_flight.tx {
+            Reply::Stream(ref tx, ref buffered) => (tx, buffered),
+            Reply::Once(_) => return false,
+        };
+
+        // nothing follows SubscriptionDropped, so the stream ends with it
+        let last = package.message.discriminator() == 0xC4;
+
+        let addr = self.addr;
+        let res = parse_unparsed(package).and_then(response).map_err(|e| in_flight.context.error(addr, e));
+
+        let charged = match (self.settings.stream_memory_budget(), res.as_ref()) {
+            (Some(budget), Ok(package)) => match charge(budget, buffered, package.message.encoded_len()) {
+                Ok(charge) => Some(charge),
+                Err(exceeded) => match budget.exceeded(&exceeded) {
+                    OverBudget::Drop => {
+                        debug!("[{}] dropping a package over the memory budget: {}", self.label(), exceeded);
+                        return !last;
+                    },
+                    OverBudget::Close => {
+                        warn!("[{}] closing a stream over the memory budget: {}", self.label(), exceeded);
+                        let _ = tx.unbounded_send((Err(in_flight.context.error(self.addr, exceeded.into())), None));
+                        return false;
+                    },
+                },
+            },
+            _ => None,
+        };
+
+        tx.unbounded_send((res, charged)).is_ok() && !last
+    }
+
+    /// Parses frames left unparsed by the codec on the decode pool before responding.
+    #[cfg(feature = "cpupool")]
+    fn respond(&self, in_flight: InFlight, package: Package) {
+        let unparsed = match package.message {
+            RawMessage::Unsupported(..) => true,
+            _ => false,
+        };
+
+        match self.settings.decode_pool() {
+            Some((pool, _)) if unparsed => {
+                let addr = self.addr;
+                let parsed = pool.spawn_fn(move || parse_unparsed(package));
+
+                self.handle.spawn(parsed.then(move |res| -> Result<(), ()> {
+                    match res.and_then(response) {
+                        Ok(package) => in_flight.tx.send(Ok(package)),
+                        Err(e) => in_flight.fail(addr, e),
+                    }
+                    Ok(())
+                }));
+            },
+            _ => self.complete(in_flight, response(package)),
+        }
+    }
+
+    #[cfg(not(feature = "cpupool"))]
+    fn respond(&self, in_flight: InFlight, package: Package) {
+        self.complete(in_flight, response(package));
+    }
+
+    fn complete(&self, in_flight: InFlight, res: io::Result<Package>) {
+        match res {
+            Ok(package) => in_flight.tx.send(Ok(package)),
+            Err(e) => in_flight.fail(self.addr, e),
+        }
+    }
+
+    fn fail_all(&mut self, closed: &ConnectionClosed) {
+        let retrying = self.retrying.drain(..).map(|(_, req)| req);
+
+        for Request { package, tx, .. } in self.queued.drain(..).chain(retrying) {
+            tx.send(Err(Context::of(&package).error(self.addr, closed.clone().into())));
+        }
+
+        for (_, in_flight) in self.pending.drain() {
+            in_flight.fail(self.addr, closed.clone().into());
+        }
+
+        self.signal_ready(Err(closed.clone().into()));
+    }
+
+    fn signal_ready(&mut self, res: io::Result<()>) {
+        if let Some(tx) = self.ready.take() {
+            let _ = tx.send(res);
+        }
+    }
+}
+
+/// Completes requests answered with `NotAuthenticated` with `AuthenticationFailed` instead of the
+/// response, so that bad credentials cannot be mistaken for a failure worth retrying.
+fn response(package: Package) -> io::Result<Package> {
+    let reason = match package.message {
+        RawMessage::NotAuthenticated(ref reason) => Some(String::from_utf8_lossy(reason.as_ref()).into_owned()),
+        _ => None,
+    };
+
+    match reason {
+        Some(reason) => Err(AuthenticationFailed::new(reason).into()),
+        None => Ok(package),
+    }
+}
+
+/// Returns true for the `NotHandled` responses of a node which can handle the request later.
+fn is_not_ready(message: &RawMessage) -> bool {
+    match *message {
+        RawMessage::NotHandled(ref not_handled) => match not_handled.reason {
+            Some(NotHandledReason::NotReady) | Some(NotHandledReason::TooBusy) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Sets the timer to fire at `at`, creating it if there is none. Returns true if it has fired.
+fn fires(timer: &mut Option<Timeout>, at: Instant, handle: &Handle) -> io::Result<bool> {
+    let mut armed = match timer.take() {
+        Some(mut armed) => {
+            armed.reset(at);
+            armed
+        },
+        None => Timeout::new_at(at, handle)?,
+    };
+
+    let fired = armed.poll()?.is_ready();
+    *timer = Some(armed);
+    Ok(fired)
+}
+
+/// Returns true for the requests ended with `UnsubscribeFromStream`.
+fn is_subscription(command: u8) -> bool {
+    command == 0xC0 || command == 0xC5
+}
+
+/// Returns true for the requests which can be sent again without changing the outcome, should the
+/// connection be lost before the response arrives. Writes and deletes could have been completed by
+/// the server, so the outcome is left for the user to decide.
+fn is_retriable(message: &RawMessage) -> bool {
+    match *message {
+        RawMessage::Ping
+        | RawMessage::ReadEvent(_)
+        | RawMessage::ReadStreamEvents(..)
+        | RawMessage::ReadAllEvents(..)
+        | RawMessage::Authenticate => true,
+        _ => false,
+    }
+}
+
+/// `PackageCodec` setting the TrustedWrite flag on writes when enabled in the settings, and
+/// managing the capacity of the read buffer.
+struct ClientCodec {
+    trusted_writes: bool,
+    read_buffer_size: usize,
+    max_read_buffer_retained: usize,
+    /// Frames larger than this are left unparsed, to be parsed on the decode pool
+    unparsed_above: Option<usize>,
+    /// Bytes of the decoded frames
+    consumed: u64,
+    /// Bytes read so far, the decoded frames and the buffered part of the next frame
+    progress: Rc<Cell<u64>>,
+}
+
+impl ClientCodec {
+    fn new(settings: &ConnectionSettings) -> Self {
+        ClientCodec {
+            trusted_writes: settings.trusted_writes(),
+            read_buffer_size: settings.read_buffer_size(),
+            max_read_buffer_retained: settings.max_read_buffer_retained(),
+            unparsed_above: unparsed_above(settings),
+            consumed: 0,
+            progress: Rc::new(Cell::new(0)),
+        }
+    }
+}
+
+#[cfg(feature = "cpupool")]
+fn unparsed_above(settings: &ConnectionSettings) -> Option<usize> {
+    settings.decode_pool().map(|(_, threshold)| threshold)
+}
+
+#[cfg(not(feature = "cpupool"))]
+fn unparsed_above(_settings: &ConnectionSettings) -> Option<usize> {
+    None
+}
+
+/// The length of the first frame including the length prefix, if the prefix has been read.
+fn frame_len(buf: &[u8]) -> Option<usize> {
+    if buf.len() < 4 {
+        return None;
+    }
+
+    Some(4 + (buf[0] as usize | (buf[1] as usize) << 8 | (buf[2] as usize) << 16 | (buf[3] as usize) << 24))
+}
+
+impl Decoder for ClientCodec {
+    type Item = Package;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Package>> {
+        let first_len = frame_len(&buf[..]);
+
+        let decoded = match (first_len, self.unparsed_above) {
+            (Some(len), Some(threshold)) if len > threshold => PackageCodec.decode_unparsed(buf)?,
+            _ => PackageCodec.decode(buf)?,
+        };
+
+        if let (Some(_), Some(first_len)) = (decoded.as_ref(), first_len) {
+            self.consumed = self.consumed.wrapping_add(first_len as u64);
+        }
+
+        if let (None, Some(first_len)) = (decoded.as_ref(), first_len) {
+            // reserve the whole frame at once instead of growing a read at a time
+            let len = buf.len();
+            buf.reserve(first_len.saturating_sub(len));
+        } else if buf.capacity() > self.max_read_buffer_retained && buf.len() <= self.read_buffer_size {
+            let mut shrunk = BytesMut::with_capacity(self.read_buffer_size);
+            shrunk.extend_from_slice(&buf[..]);
+            *buf = shrunk;
+        }
+
+        if buf.capacity() < self.read_buffer_size {
+            let len = buf.len();
+            buf.reserve(self.read_buffer_size - len);
+        }
+
+        self.progress.set(self.consumed.wrapping_add(buf.len() as u64));
+        Ok(decoded)
+    }
+}
+
+impl Encoder for ClientCodec {
+    type Item = Package;
+    type Error = io::Error;
+
+    fn encode(&mut self, msg: Package, buf: &mut BytesMut) -> io::Result<()> {
+        let flags = if self.trusted_writes && is_write(&msg.message) {
+            FLAG_TRUSTED_WRITE
+        } else {
+            FLAG_NONE
+        };
+
+        PackageCodec.encode_with_flags(msg, flags, buf)
+    }
+}
+
+fn is_write(message: &RawMessage) -> bool {
+    match *message {
+        RawMessage::WriteEvents(_) | RawMessage::DeleteStream(_) => true,
+        _ => false,
+    }
+}
+
+/// Transport middleware answering the heartbeat requests of the server and initiating heartbeats
+/// when nothing has been received for `ConnectionSettings::heartbeat_interval`. If nothing is
+/// received within `ConnectionSettings::heartbeat_timeout` after sending a heartbeat request the
+/// transport fails with `io::ErrorKind::TimedOut`.
+///
+/// Heartbeat packages are never passed through, as they are not correlated to any requests made
+/// by the user, and the heartbeat requests of the server are answered as soon as they are read,
+/// ahead of the packages waiting to be sent. Used for the connections accepted by
+/// `server::Server` as well.
+///
+/// Sending only buffers the packages, including the heartbeats, which are written out together by
+/// `poll_complete` and at the end of `poll`: chatty workloads with many small packages take one
+/// write for all of the packages sent during a turn of the reactor instead of a write per package.
+pub struct Heartbeats<T> {
+    inner: T,
+    interval: Duration,
+    timeout: Duration,
+    /// Either the idle deadline or the heartbeat response deadline, depending on `awaiting`
+    timer: Timeout,
+    /// Correlation id of a sent heartbeat request
+    awaiting: Option<Uuid>,
+    /// Heartbeat requests and responses waiting to be sent; these take priority over requests
+    outgoing: VecDeque<Package>,
+    /// Bytes read by the inner transport with the count last seen, see `with_read_progress`
+    progress: Option<(Rc<Cell<u64>>, u64)>,
+    label: String,
+}
+
+impl<T> Heartbeats<T>
+    where T: Stream<Item = Package, Error = io::Error>,
+          T: Sink<SinkItem = Package, SinkError = io::Error>
+{
+    /// Sends a heartbeat request after `interval` without receiving anything, failing with
+    /// `TimedOut` if there is no response within `timeout`. The `label` prefixes the log messages.
+    pub fn new(inner: T, handle: &Handle, interval: Duration, timeout: Duration, label: String) -> io::Result<Self> {
+        Ok(Heartbeats {
+            inner: inner,
+            interval: interval,
+            timeout: timeout,
+            timer: Timeout::new(interval, handle)?,
+            awaiting: None,
+            outgoing: VecDeque::new(),
+            progress: None,
+            label: label,
+        })
+    }
+
+    /// Counts the bytes read as received, by the count of bytes read by the inner transport kept
+    /// in `progress`. A large frame can take longer than the timeout to arrive, during which no
+    /// package is received and the heartbeats of the server queue up behind the frame.
+    pub fn with_read_progress(mut self, progress: Rc<Cell<u64>>) -> Self {
+        let seen = progress.get();
+        self.progress = Some((progress, seen));
+        self
+    }
+
+    fn received(&mut self) {
+        self.awaiting = None;
+        self.timer.reset(Instant::now() + self.interval);
+
+        if let Some((ref progress, ref mut seen)) = self.progress {
+            *seen = progress.get();
+        }
+    }
+
+    /// Have bytes been read since the last check.
+    fn read_more(&mut self) -> bool {
+        match self.progress {
+            Some((ref progress, ref mut seen)) => {
+                let read = progress.get();
+                let more = read != *seen;
+                *seen = read;
+                more
+            },
+            None => false,
+        }
+    }
+
+    fn poll_timer(&mut self) -> io::Result<()> {
+        while let Async::Ready(()) = self.timer.poll()? {
+            if self.read_more() {
+                trace!("[{}] still reading a frame", self.label);
+                self.received();
+                continue;
+            }
+
+            if let Some(id) = self.awaiting {
+                warn!("[{}] no response to heartbeat request {} within {:?}", self.label, id, self.timeout);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "Heartbeat timeout"));
+            }
+
+            let id = Uuid::new_v4();
+            trace!("[{}] sending heartbeat request {}", self.label, id);
+            self.awaiting = Some(id);
+            self.outgoing.push_back(heartbeat(RawMessage::HeartbeatRequest, id));
+            self.timer.reset(Instant::now() + self.timeout);
+            self.send_outgoing()?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the heartbeat packages to the buffer of the inner sink without flushing it.
+    fn send_outgoing(&mut self) -> io::Result<()> {
+        while let Some(pkg) = self.outgoing.pop_front() {
+            if let AsyncSink::NotReady(pkg) = self.inner.start_send(pkg)? {
+                self.outgoing.push_front(pkg);
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Stream for Heartbeats<T>
+    where T: Stream<Item = Package, Error = io::Error>,
+          T: Sink<SinkItem = Package, SinkError = io::Error>
+{
+    type Item = Package;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Package>, io::Error> {
+        loop {
+            match self.inner.poll()? {
+                Async::Ready(Some(pkg)) => {
+                    self.received();
+
+                    match pkg.message {
+                        RawMessage::HeartbeatRequest => {
+                            trace!("[{}] responding to heartbeat request {}", self.label, pkg.correlation_id);
+                            self.outgoing.push_back(heartbeat(RawMessage::HeartbeatResponse, pkg.correlation_id));
+                            self.send_outgoing()?;
+                        },
+                        RawMessage::HeartbeatResponse => {
+                            trace!("[{}] received heartbeat response {}", self.label, pkg.correlation_id);
+                        },
+                        _ => return Ok(Async::Ready(Some(pkg))),
+                    }
+                },
+                Async::Ready(None) => return Ok(Async::Ready(None)),
+                Async::NotReady => break,
+            }
+        }
+
+        self.poll_timer()?;
+        // write out the heartbeats queued above; the caller might not flush before the next turn
+        self.poll_complete()?;
+        Ok(Async::NotReady)
+    }
+}
+
+impl<T> Sink for Heartbeats<T>
+    where T: Stream<Item = Package, Error = io::Error>,
+          T: Sink<SinkItem = Package, SinkError = io::Error>
+{
+    type SinkItem = Package;
+    type SinkError = io::Error;
+
+    fn start_send(&mut self, item: Package) -> StartSend<Package, io::Error> {
+        self.send_outgoing()?;
+
+        if !self.outgoing.is_empty() {
+            return Ok(AsyncSink::NotReady(item));
+        }
+
+        self.inner.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), io::Error> {
+        self.send_outgoing()?;
+
+        if !self.outgoing.is_empty() {
+            return Ok(Async::NotReady);
+        }
+
+        self.inner.poll_complete()
+    }
+}
+
+fn heartbeat(message: RawMessage<'static>, correlation_id: Uuid) -> Package {
+    Package {
+        authentication: None,
+        correlation_id: correlation_id,
+        message: message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use raw::RawMessage;
+    use builder::Builder;
+    use {ConnectionSettings, EventNumber, ExpectedVersion};
+    use super::{is_retriable, response, ClientCodec};
+
+    #[test]
+    fn only_reads_and_pings_are_retried() {
+        assert!(is_retriable(&RawMessage::Ping));
+        assert!(is_retriable(&Builder::read_event().stream_id("foo").event_number(EventNumber::First).build_package(None, None).message));
+        assert!(!is_retriable(&Builder::delete_stream().stream_id("foo").expected_version(ExpectedVersion::Any).build_package(None, None).message));
+        assert!(!is_retriable(&RawMessage::Unsupported(0xff, Cow::Borrowed(&[][..]))));
+    }
+
+    #[test]
+    fn trusted_write_flag_only_on_writes() {
+        use bytes::BytesMut;
+        use tokio_io::codec::Encoder;
+
+        let flags = |trusted_writes, package| {
+            let mut buf = BytesMut::with_capacity(1024);
+            let mut codec = ClientCodec::new(&ConnectionSettings::default());
+            codec.trusted_writes = trusted_writes;
+            codec.encode(package, &mut buf).unwrap();
+            buf[5]
+        };
+
+        let delete = || Builder::delete_stream().stream_id("foo").expected_version(ExpectedVersion::Any).build_package(None, None);
+        let ping = || Builder::ping().build_package(None, None);
+
+        assert_eq!(flags(false, delete()), 0x00);
+        assert_eq!(flags(true, delete()), 0x02);
+        assert_eq!(flags(true, ping()), 0x00);
+    }
+
+    #[test]
+    fn read_buffer_is_shrunk_after_large_frames() {
+        use bytes::{BytesMut, BufMut};
+        use tokio_io::codec::{Decoder, Encoder};
+
+        let settings = ConnectionSettings::builder()
+            .read_buffer_size(64)
+            .max_read_buffer_retained(256)
+            .build();
+        let mut codec = ClientCodec::new(&settings);
+
+        let mut frame = BytesMut::with_capacity(1024);
+        let large = Builder::write_events()
+            .stream_id("foo")
+            .expected_version(ExpectedVersion::Any)
+            .new_event()
+                .event_type("large")
+                .data(vec![0u8; 512])
+            .done()
+            .build_package(None, None);
+        codec.encode(large, &mut frame).unwrap();
+
+        let mut buf = BytesMut::with_capacity(16);
+        buf.put_slice(&frame[..8]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert!(buf.capacity() >= frame.len());
+
+        buf.put_slice(&frame[8..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= 64 && buf.capacity() < 256, "{}", buf.capacity());
+    }
+
+    #[test]
+    fn sent_packages_are_written_together() {
+        use std::io;
+        use futures::{Async, AsyncSink, Poll, Sink, StartSend, Stream};
+        use tokio_core::reactor::Core;
+        use package::Package;
+        use super::Heartbeats;
+
+        #[derive(Default)]
+        struct Transport {
+            buffered: usize,
+            writes: Vec<usize>,
+        }
+
+        impl Stream for Transport {
+            type Item = Package;
+            type Error = io::Error;
+
+            fn poll(&mut self) -> Poll<Option<Package>, io::Error> {
+                Ok(Async::NotReady)
+            }
+        }
+
+        impl Sink for Transport {
+            type SinkItem = Package;
+            type SinkError = io::Error;
+
+            fn start_send(&mut self, _: Package) -> StartSend<Package, io::Error> {
+                self.buffered += 1;
+                Ok(AsyncSink::Ready)
+            }
+
+            fn poll_complete(&mut self) -> Poll<(), io::Error> {
+                if self.buffered > 0 {
+                    self.writes.push(self.buffered);
+                    self.buffered = 0;
+                }
+                Ok(Async::Ready(()))
+            }
+        }
+
+        let core = Core::new().unwrap();
+        let settings = ConnectionSettings::default();
+        let mut transport = Heartbeats::new(Transport::default(), &core.handle(), settings.heartbeat_interval(), settings.heartbeat_timeout(), "test".into()).unwrap();
+
+        for _ in 0..3 {
+            assert!(transport.start_send(Builder::ping().build_package(None, None)).unwrap().is_ready());
+        }
+
+        assert!(transport.inner.writes.is_empty());
+        assert!(transport.poll_complete().unwrap().is_ready());
+        assert_eq!(transport.inner.writes, vec![3]);
+    }
+
+    #[test]
+    fn heartbeats_are_answered_around_large_frames() {
+        // 4 MB arriving in 64 KiB chunks takes over a second, far longer than the heartbeat timeout
+        heartbeats_around_large_frame(4 * 1024 * 1024, 64 * 1024);
+    }
+
+    #[test]
+    #[ignore]
+    fn heartbeats_are_answered_around_50_mb_frames() {
+        heartbeats_around_large_frame(50 * 1024 * 1024, 1024 * 1024);
+    }
+
+    /// Scripts a `ReadStreamEventsCompleted` of `size` bytes of event data arriving from the
+    /// server a `chunk` at a time, between two heartbeat requests of the server. The first is
+    /// answered while the large frame is still arriving, the second once it has been read.
+    fn heartbeats_around_large_frame(size: usize, chunk: usize) {
+        use std::io::{self, Read, Write};
+        use std::time::Duration;
+        use bytes::BytesMut;
+        use futures::{future, stream, Future, Stream};
+        use tokio_core::reactor::{Core, Interval};
+        use tokio_io::AsyncRead;
+        use tokio_io::codec::{Decoder, Encoder};
+        use uuid::Uuid;
+        use codec::PackageCodec;
+        use package::Package;
+        use raw::{EventRecord, ResolvedIndexedEvent, ReadStreamEventsCompleted, ReadStreamResult};
+        use transport::duplex;
+        use ReadDirection;
+        use super::{heartbeat, Heartbeats};
+
+        /// The heartbeat responses the client has written so far.
+        fn answered(server: &mut Read) -> Vec<Uuid> {
+            let mut written = Vec::new();
+            let res = server.read_to_end(&mut written);
+            assert_eq!(res.unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+            let mut written = BytesMut::from(written);
+            let mut answered = Vec::new();
+            while let Some(package) = PackageCodec.decode(&mut written).unwrap() {
+                if package.message == RawMessage::HeartbeatResponse {
+                    answered.push(package.correlation_id);
+                }
+            }
+            answered
+        }
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let (client, server) = duplex();
+
+        let codec = ClientCodec::new(&ConnectionSettings::default());
+        let progress = codec.progress.clone();
+        let mut transport = Heartbeats::new(client.framed(codec), &handle, Duration::from_millis(50), Duration::from_millis(50), "test".into())
+            .unwrap()
+            .with_read_progress(progress);
+
+        let page = ReadStreamEventsCompleted {
+            events: vec![ResolvedIndexedEvent {
+                event: EventRecord { event_stream_id: "foo".into(), event_type: "large".into(), data: vec![0u8; size].into(), ..Default::default() },
+                link: None,
+            }],
+            result: Some(ReadStreamResult::Success),
+            is_end_of_stream: true,
+            ..Default::default()
+        };
+
+        let (before, after) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut frames = BytesMut::new();
+        PackageCodec.encode(heartbeat(RawMessage::HeartbeatRequest, before), &mut frames).unwrap();
+        PackageCodec.encode(Package::new(RawMessage::ReadStreamEventsCompleted(ReadDirection::Forward, page)), &mut frames).unwrap();
+        PackageCodec.encode(heartbeat(RawMessage::HeartbeatRequest, after), &mut frames).unwrap();
+
+        let chunks = frames.chunks(chunk).map(|chunk| chunk.to_vec()).collect::<Vec<_>>();
+        let last = chunks.len() - 1;
+
+        // the responses are collected before writing the last chunk, completing the large frame
+        let writing = Interval::new(Duration::from_millis(20), &handle).unwrap()
+            .zip(stream::iter_ok::<_, io::Error>(chunks.into_iter().enumerate()))
+            .fold((server, Vec::new()), move |(mut server, mut during), ((), (i, chunk))| {
+                if i == last {
+                    during = answered(&mut server);
+                }
+                server.write_all(&chunk).map(|()| (server, during))
+            });
+
+        let ((mut server, during), received) = core.run(writing.join(future::poll_fn(|| transport.poll()))).unwrap();
+        assert_eq!(received.map(|package| package.message.discriminator()), Some(0xB3));
+        assert_eq!(during, vec![before]);
+
+        // the heartbeat request following the frame is answered without waiting for more packages
+        assert!(core.run(future::lazy(|| transport.poll())).unwrap().is_not_ready());
+
+        let after_frame = core.run(future::lazy(|| Ok::<_, ()>(answered(&mut server)))).unwrap();
+        assert_eq!(after_frame, vec![after]);
+    }
+
+    #[test]
+    fn not_authenticated_fails_with_authentication_failed() {
+        use AuthenticationFailed;
+
+        let mut package = Builder::ping().build_package(None, None);
+        package.message = RawMessage::NotAuthenticated(Cow::Borrowed(&b"Bad password"[..]).into());
+
+        let e = response(package).unwrap_err();
+        assert_eq!(AuthenticationFailed::find(&e), Some(&AuthenticationFailed::new("Bad password")));
+
+        assert!(response(Builder::ping().build_package(None, None)).is_ok());
+    }
+}
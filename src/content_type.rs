@@ -1,5 +1,6 @@
 /// Content type of the event `data` or `metadata`.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum ContentType {
     /// Raw bytes
     Bytes,
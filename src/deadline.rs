@@ -0,0 +1,177 @@
+use std::io;
+use std::fmt;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use operation;
+
+/// Error of an operation which did not complete by its deadline, see
+/// `EventStoreClient::call_with_deadline`. The operations failing because of this are completed
+/// with an `io::Error` of kind `TimedOut` wrapping this value, see `DeadlineExceeded::find`.
+///
+/// The elapsed time is broken down into the time spent waiting to be written, which covers
+/// connecting and reconnecting to the node, and the time spent waiting for the responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadlineExceeded {
+    /// Time from the call until the operation was failed
+    pub elapsed: Duration,
+    /// Part of `elapsed` spent waiting to be written to a connection
+    pub queued: Duration,
+    /// Part of `elapsed` spent waiting for a response, over all of the attempts
+    pub awaiting_response: Duration,
+    /// How many times the request was written; more than once when it was sent again after the
+    /// connection was lost
+    pub attempts: usize,
+}
+
+impl DeadlineExceeded {
+    /// Returns the `DeadlineExceeded` wrapped by the given error, if any.
+    pub fn find(e: &io::Error) -> Option<&DeadlineExceeded> {
+        operation::find::<DeadlineExceeded>(e)
+    }
+}
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} after {} (queued {}, awaiting response {} over {} attempts)",
+               self.description(), Seconds(self.elapsed), Seconds(self.queued), Seconds(self.awaiting_response), self.attempts)
+    }
+}
+
+impl Error for DeadlineExceeded {
+    fn description(&self) -> &str {
+        "Deadline exceeded"
+    }
+}
+
+impl From<DeadlineExceeded> for io::Error {
+    fn from(e: DeadlineExceeded) -> io::Error {
+        io::Error::new(io::ErrorKind::TimedOut, e)
+    }
+}
+
+struct Seconds(Duration);
+
+impl fmt::Display for Seconds {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}.{:03}s", self.0.as_secs(), self.0.subsec_nanos() / 1_000_000)
+    }
+}
+
+/// Deadline of a request carried along with it by the connection, keeping the account of where
+/// the time went.
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    at: Instant,
+    started: Instant,
+    /// Start of the current phase
+    since: Instant,
+    written: bool,
+    queued: Duration,
+    awaiting_response: Duration,
+    attempts: usize,
+}
+
+impl Deadline {
+    /// Starts the clock of a request which must complete by `at`.
+    pub fn new(at: Instant) -> Self {
+        let now = Instant::now();
+
+        Deadline {
+            at: at,
+            started: now,
+            since: now,
+            written: false,
+            queued: Duration::from_secs(0),
+            awaiting_response: Duration::from_secs(0),
+            attempts: 0,
+        }
+    }
+
+    /// The instant the request must complete by.
+    pub fn at(&self) -> Instant {
+        self.at
+    }
+
+    /// Has the deadline passed at `now`.
+    pub fn is_expired(&self, now: Instant) -> bool {
+        now >= self.at
+    }
+
+    /// Records that the request was written to a connection at `now`.
+    pub fn written(&mut self, now: Instant) {
+        self.end_phase(now);
+        self.written = true;
+        self.attempts += 1;
+    }
+
+    /// Records that the request is waiting to be written again from `now` on.
+    pub fn requeued(&mut self, now: Instant) {
+        self.end_phase(now);
+        self.written = false;
+    }
+
+    /// The error failing the request at `now`.
+    pub fn exceeded(&self, now: Instant) -> DeadlineExceeded {
+        let mut timings = self.clone();
+        timings.end_phase(now);
+
+        DeadlineExceeded {
+            elapsed: now.duration_since(self.started),
+            queued: timings.queued,
+            awaiting_response: timings.awaiting_response,
+            attempts: timings.attempts,
+        }
+    }
+
+    fn end_phase(&mut self, now: Instant) {
+        let phase = now.duration_since(self.since);
+
+        if self.written {
+            self.awaiting_response += phase;
+        } else {
+            self.queued += phase;
+        }
+
+        self.since = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::{Deadline, DeadlineExceeded};
+
+    #[test]
+    fn time_is_accounted_to_phases() {
+        let start = Instant::now();
+        let mut deadline = Deadline::new(start + Duration::from_secs(1));
+        let started = deadline.started;
+
+        assert!(!deadline.is_expired(started));
+        assert!(deadline.is_expired(started + Duration::from_secs(1)));
+
+        deadline.written(started + Duration::from_millis(100));
+        deadline.requeued(started + Duration::from_millis(400));
+        deadline.written(started + Duration::from_millis(600));
+
+        assert_eq!(deadline.exceeded(started + Duration::from_millis(1000)), DeadlineExceeded {
+            elapsed: Duration::from_millis(1000),
+            queued: Duration::from_millis(300),
+            awaiting_response: Duration::from_millis(700),
+            attempts: 2,
+        });
+    }
+
+    #[test]
+    fn display() {
+        let exceeded = DeadlineExceeded {
+            elapsed: Duration::from_millis(1250),
+            queued: Duration::from_millis(1000),
+            awaiting_response: Duration::from_millis(250),
+            attempts: 1,
+        };
+
+        assert_eq!(exceeded.to_string(), "Deadline exceeded after 1.250s (queued 1.000s, awaiting response 0.250s over 1 attempts)");
+    }
+}
@@ -0,0 +1,75 @@
+//! Inspecting the state of a connection: the requests awaiting responses and the packages
+//! waiting to be sent.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::collections::BTreeMap;
+
+/// Snapshot of the state of a connection, returned by `EventStoreClient::diagnostics` and logged
+/// every `ConnectionSettings::diagnostics_interval`, for finding out why operations hang.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostics {
+    /// The address of the node connected to, or being connected to
+    pub endpoint: SocketAddr,
+    /// Is the connection established at the moment
+    pub connected: bool,
+    /// Requests written to the connection and awaiting a response, counted by the discriminator
+    /// of the request, see `TcpCommand`
+    pub pending: BTreeMap<u8, usize>,
+    /// How long the oldest of the pending requests has been waiting for its response
+    pub oldest_pending: Option<Duration>,
+    /// Requests waiting to be written, for example until the connection has been made
+    pub queued: usize,
+    /// Streaming requests receiving packages, such as subscriptions
+    pub subscriptions: usize,
+}
+
+impl Diagnostics {
+    /// The number of pending requests of all commands.
+    pub fn pending_total(&self) -> usize {
+        self.pending.values().sum()
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} {}, {} pending", if self.connected { "connected to" } else { "connecting to" }, self.endpoint, self.pending_total())?;
+
+        if let Some(oldest) = self.oldest_pending {
+            write!(fmt, " (oldest {}.{:03}s", oldest.as_secs(), oldest.subsec_nanos() / 1_000_000)?;
+            for (command, count) in &self.pending {
+                write!(fmt, ", 0x{:02X}: {}", command, count)?;
+            }
+            write!(fmt, ")")?;
+        }
+
+        write!(fmt, ", {} queued, {} subscriptions", self.queued, self.subscriptions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use std::collections::BTreeMap;
+    use super::Diagnostics;
+
+    #[test]
+    fn summary_line() {
+        let mut pending = BTreeMap::new();
+        pending.insert(0x82, 2);
+        pending.insert(0xB0, 1);
+
+        let diagnostics = Diagnostics {
+            endpoint: "127.0.0.1:1113".parse().unwrap(),
+            connected: true,
+            pending: pending,
+            oldest_pending: Some(Duration::from_millis(1250)),
+            queued: 0,
+            subscriptions: 1,
+        };
+
+        assert_eq!(diagnostics.pending_total(), 3);
+        assert_eq!(diagnostics.to_string(), "connected to 127.0.0.1:1113, 3 pending (oldest 1.250s, 0x82: 2, 0xB0: 1), 0 queued, 1 subscriptions");
+    }
+}
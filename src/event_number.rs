@@ -3,7 +3,8 @@ use StreamVersion;
 /// `EventNumber` is similar to `StreamVersion` and `ExpectedVersion` but is used when specifying a
 /// position to read from in the stream. Allows specifying the first or last (when reading
 /// backwards) event in addition to exact event number.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum EventNumber {
     /// The first event in a stream
     First,
@@ -0,0 +1,156 @@
+//! Correlation and causation ids in the metadata of events, available with the `json` feature.
+//!
+//! The ids use the names of the `$correlationId` and `$causationId` metadata fields recognized by
+//! the projections of EventStore.
+
+use std::io;
+use std::collections::HashMap;
+
+use serde_json::{self, Map, Value};
+use uuid::Uuid;
+
+use {ContentType, EventRecord};
+use super::EventData;
+
+const CORRELATION_ID: &str = "$correlationId";
+const CAUSATION_ID: &str = "$causationId";
+
+impl EventData {
+    /// Marks the event as caused by `cause`, for example an event handled by a process manager.
+    /// The causation id is set to the id of `cause`, and the correlation id is copied from
+    /// `cause`, or set to its id if `cause` has none. Other fields of JSON metadata are kept.
+    ///
+    /// Fails if the event has metadata which is not a JSON object.
+    pub fn caused_by(mut self, cause: &EventRecord) -> io::Result<Self> {
+        let cause_id = event_id(cause)?;
+        let correlation_id = correlation_id(cause).unwrap_or(cause_id);
+
+        let mut metadata = match self.metadata {
+            Some(ref metadata) if self.metadata_content_type == ContentType::Json => {
+                match serde_json::from_slice(metadata) {
+                    Ok(Value::Object(metadata)) => metadata,
+                    _ => return Err(not_an_object()),
+                }
+            },
+            Some(_) => return Err(not_an_object()),
+            None => Map::new(),
+        };
+
+        metadata.insert(CORRELATION_ID.into(), correlation_id.to_string().into());
+        metadata.insert(CAUSATION_ID.into(), cause_id.to_string().into());
+
+        self.metadata = Some(serde_json::to_vec(&Value::Object(metadata)).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?);
+        self.metadata_content_type = ContentType::Json;
+        Ok(self)
+    }
+}
+
+/// The correlation id in the metadata of the event, if any.
+pub fn correlation_id(event: &EventRecord) -> Option<Uuid> {
+    metadata_id(event, CORRELATION_ID)
+}
+
+/// The causation id in the metadata of the event, if any.
+pub fn causation_id(event: &EventRecord) -> Option<Uuid> {
+    metadata_id(event, CAUSATION_ID)
+}
+
+/// Follows the causation ids from `event` through `events`, returning `event` first and the root
+/// cause last. The chain ends at an event without a causation id or with a cause not in `events`.
+/// Useful when debugging sagas, with `events` read from for example a `$bc-` correlation stream.
+pub fn causation_chain<'a, 'b>(event: &'a EventRecord<'b>, events: &'a [EventRecord<'b>]) -> Vec<&'a EventRecord<'b>> {
+    let by_id = events.iter()
+        .filter_map(|e| Uuid::from_bytes(&e.event_id).ok().map(|id| (id, e)))
+        .collect::<HashMap<_, _>>();
+
+    let mut chain = vec![event];
+    let mut current = event;
+
+    // bounded in case the causation ids form a cycle
+    while chain.len() <= events.len() {
+        current = match causation_id(current).and_then(|id| by_id.get(&id)) {
+            Some(cause) => *cause,
+            None => break,
+        };
+
+        chain.push(current);
+    }
+
+    chain
+}
+
+fn metadata_id(event: &EventRecord, field: &str) -> Option<Uuid> {
+    let metadata = match event.metadata {
+        Some(ref metadata) if event.metadata_content_type == i32::from(ContentType::Json) => metadata,
+        _ => return None,
+    };
+
+    match serde_json::from_slice::<Value>(metadata) {
+        Ok(metadata) => metadata.get(field).and_then(|id| id.as_str()).and_then(|id| Uuid::parse_str(id).ok()),
+        Err(_) => None,
+    }
+}
+
+fn event_id(event: &EventRecord) -> io::Result<Uuid> {
+    Uuid::from_bytes(&event.event_id).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Event id is not an UUID"))
+}
+
+fn not_an_object() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "Metadata is not a JSON object")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use uuid::Uuid;
+    use {ContentType, EventRecord};
+    use eventsourcing::EventData;
+    use super::{correlation_id, causation_id, causation_chain};
+
+    fn recorded(data: EventData) -> EventRecord<'static> {
+        EventRecord {
+            event_id: Cow::Owned(data.event_id.as_bytes().to_vec()),
+            event_type: data.event_type.into_owned().into(),
+            metadata: data.metadata.map(Cow::Owned),
+            metadata_content_type: data.metadata_content_type.into(),
+            ..EventRecord::default()
+        }
+    }
+
+    #[test]
+    fn ids_are_propagated() {
+        let root = recorded(EventData::json("order_placed", "{}"));
+        let root_id = Uuid::from_bytes(&root.event_id).unwrap();
+
+        let reserved = EventData::json("stock_reserved", "{}")
+            .caused_by(&root)
+            .unwrap();
+        let reserved = recorded(reserved);
+
+        assert_eq!(correlation_id(&reserved), Some(root_id));
+        assert_eq!(causation_id(&reserved), Some(root_id));
+
+        let mut shipped = EventData::json("order_shipped", "{}");
+        shipped.metadata = Some(br#"{"user":"ops"}"#.to_vec());
+        shipped.metadata_content_type = ContentType::Json;
+        let shipped = recorded(shipped.caused_by(&reserved).unwrap());
+
+        assert_eq!(correlation_id(&shipped), Some(root_id));
+        assert_eq!(causation_id(&shipped), Some(Uuid::from_bytes(&reserved.event_id).unwrap()));
+        assert!(String::from_utf8_lossy(shipped.metadata.as_ref().unwrap()).contains("\"user\":\"ops\""));
+
+        let events = vec![shipped.clone(), root.clone(), reserved.clone()];
+        let chain = causation_chain(&shipped, &events);
+        let types = chain.iter().map(|e| &*e.event_type).collect::<Vec<_>>();
+        assert_eq!(types, vec!["order_shipped", "stock_reserved", "order_placed"]);
+    }
+
+    #[test]
+    fn binary_metadata_is_rejected() {
+        let cause = recorded(EventData::json("order_placed", "{}"));
+        let mut data = EventData::bytes("x", vec![]);
+        data.metadata = Some(vec![1, 2, 3]);
+
+        assert!(data.caused_by(&cause).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Associating Rust types with the `event_type` strings of the events.
+
+use std::io;
+use std::fmt;
+use std::collections::HashMap;
+
+use EventRecord;
+use super::EventData;
+
+/// Rust type of the payload of events with the given `event_type`. Implementing this is a one
+/// liner, which makes deriving it straightforward as well:
+///
+/// ```rust
+/// use eventstore_tcp::eventsourcing::EventType;
+///
+/// struct OrderPlaced;
+///
+/// impl EventType for OrderPlaced {
+///     const EVENT_TYPE: &'static str = "order_placed";
+/// }
+/// ```
+pub trait EventType {
+    /// The `event_type` of the events with payloads of this type
+    const EVENT_TYPE: &'static str;
+}
+
+/// Decoding function registered to `EventTypes`.
+type Decode<T> = Box<Fn(&EventRecord) -> io::Result<T>>;
+
+/// Registry of the event types of a stream, decoding each event according to its `event_type`
+/// into a common type `T`, usually an enumeration of all of the events. Events of types not
+/// registered fail to decode, as does writing them through `EventTypes::validate`.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::EventRecord;
+/// use eventstore_tcp::eventsourcing::{EventType, EventTypes, EventData};
+///
+/// struct Opened;
+///
+/// impl EventType for Opened {
+///     const EVENT_TYPE: &'static str = "opened";
+/// }
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Account {
+///     Opened,
+/// }
+///
+/// let types = EventTypes::new()
+///     .register::<Opened, _>(|_| Ok(Account::Opened));
+///
+/// let record = EventRecord { event_type: "opened".into(), ..EventRecord::default() };
+/// assert_eq!(types.decode(&record).unwrap(), Account::Opened);
+/// assert!(types.validate(&EventData::json("closed", "{}")).is_err());
+/// ```
+pub struct EventTypes<T> {
+    decoders: HashMap<&'static str, Decode<T>>,
+}
+
+impl<T> fmt::Debug for EventTypes<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "EventTypes {{ event_types: {:?} }}", self.decoders.keys().collect::<Vec<_>>())
+    }
+}
+
+impl<T> EventTypes<T> {
+    /// Creates a registry without any event types.
+    pub fn new() -> Self {
+        EventTypes {
+            decoders: HashMap::new(),
+        }
+    }
+
+    /// Registers the event type of `E`, decoding the events of the type with `decode`. Replaces
+    /// any decoding function registered earlier for the same event type.
+    pub fn register<E, F>(mut self, decode: F) -> Self
+        where E: EventType,
+              F: Fn(&EventRecord) -> io::Result<T> + 'static
+    {
+        self.decoders.insert(E::EVENT_TYPE, Box::new(decode));
+        self
+    }
+
+    /// Returns true if the event type has been registered.
+    pub fn contains(&self, event_type: &str) -> bool {
+        self.decoders.contains_key(event_type)
+    }
+
+    /// Decodes the event with the function registered for its type.
+    pub fn decode(&self, record: &EventRecord) -> io::Result<T> {
+        match self.decoders.get(&*record.event_type) {
+            Some(decode) => (**decode)(record),
+            None => Err(unknown(&record.event_type)),
+        }
+    }
+
+    /// Checks that the type of an event about to be written has been registered, catching typos
+    /// in the event types before they end up in the streams.
+    pub fn validate(&self, data: &EventData) -> io::Result<()> {
+        if self.contains(&data.event_type) {
+            Ok(())
+        } else {
+            Err(unknown(&data.event_type))
+        }
+    }
+}
+
+impl<T> Default for EventTypes<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventData {
+    /// Creates an event like `EventData::json` with the event type of `E`.
+    pub fn json_of<E: EventType, D: Into<Vec<u8>>>(data: D) -> Self {
+        EventData::json(E::EVENT_TYPE, data)
+    }
+}
+
+fn unknown(event_type: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Unknown event type: {:?}", event_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use EventRecord;
+    use eventsourcing::EventData;
+    use super::{EventType, EventTypes};
+
+    struct Deposited;
+
+    impl EventType for Deposited {
+        const EVENT_TYPE: &'static str = "deposited";
+    }
+
+    struct Withdrawn;
+
+    impl EventType for Withdrawn {
+        const EVENT_TYPE: &'static str = "withdrawn";
+    }
+
+    #[test]
+    fn dispatch_by_event_type() {
+        let types = EventTypes::new()
+            .register::<Deposited, _>(|record| Ok(record.data.len() as i64))
+            .register::<Withdrawn, _>(|record| Ok(-(record.data.len() as i64)));
+
+        let record = |event_type: &'static str| EventRecord {
+            event_type: event_type.into(),
+            data: (&b"abc"[..]).into(),
+            ..EventRecord::default()
+        };
+
+        assert_eq!(types.decode(&record("deposited")).unwrap(), 3);
+        assert_eq!(types.decode(&record("withdrawn")).unwrap(), -3);
+        assert!(types.decode(&record("unknown")).is_err());
+    }
+
+    #[test]
+    fn validate_written_types() {
+        let types = EventTypes::new().register::<Deposited, _>(|_| Ok(()));
+
+        assert!(types.validate(&EventData::json_of::<Deposited, _>("{}")).is_ok());
+        assert!(types.validate(&EventData::json_of::<Withdrawn, _>("{}")).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Skipping events which have already been handled.
+
+use std::io;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use futures::future::{self, Future};
+use uuid::Uuid;
+
+/// Set of the ids of the events already handled by a consumer. Subscriptions deliver events at
+/// least once, so consumers consult the store before handling an event and skip the event if it
+/// has been seen before, see `handle_once`.
+pub trait IdempotencyStore {
+    /// Returns `true` if the event has been handled, and has not been forgotten since.
+    fn contains(&mut self, event_id: &Uuid) -> bool;
+
+    /// Marks the event as handled.
+    fn insert(&mut self, event_id: Uuid);
+}
+
+/// `IdempotencyStore` keeping the ids of the most recently handled events in memory. The ids are
+/// forgotten after the time to live, or when more than the capacity of ids would be kept in which
+/// case the oldest are forgotten first.
+#[derive(Debug)]
+pub struct MemoryIdempotencyStore {
+    capacity: usize,
+    ttl: Duration,
+    seen: HashMap<Uuid, Instant>,
+    // insertion order; an id inserted again stays here with the older instant until popped
+    order: VecDeque<(Uuid, Instant)>,
+}
+
+impl MemoryIdempotencyStore {
+    /// Creates a store keeping up to `capacity` ids for `ttl` each.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        assert!(capacity > 0);
+        MemoryIdempotencyStore {
+            capacity: capacity,
+            ttl: ttl,
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// The number of ids currently kept, including the expired ones not yet forgotten.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Returns `true` if no ids are kept.
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    fn evict(&mut self, now: Instant) {
+        loop {
+            let (id, at) = match self.order.front() {
+                Some(&entry) => entry,
+                None => break,
+            };
+
+            let latest = self.seen.get(&id) == Some(&at);
+            let expired = now.duration_since(at) >= self.ttl;
+
+            if latest && !expired && self.seen.len() <= self.capacity {
+                break;
+            }
+
+            self.order.pop_front();
+
+            if latest {
+                self.seen.remove(&id);
+            }
+        }
+    }
+}
+
+impl IdempotencyStore for MemoryIdempotencyStore {
+    fn contains(&mut self, event_id: &Uuid) -> bool {
+        let now = Instant::now();
+        self.evict(now);
+
+        match self.seen.get(event_id) {
+            Some(at) => now.duration_since(*at) < self.ttl,
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, event_id: Uuid) {
+        let now = Instant::now();
+        self.seen.insert(event_id, now);
+        self.order.push_back((event_id, now));
+        self.evict(now);
+    }
+}
+
+/// Runs `handler` unless the event has already been handled according to `store`. The event is
+/// marked as handled only once the handler completes successfully, so failed events are handled
+/// again when redelivered. Resolves to `true` if the handler was run.
+pub fn handle_once<S, F, R>(store: Rc<RefCell<S>>, event_id: Uuid, handler: F) -> Box<Future<Item = bool, Error = io::Error>>
+    where S: IdempotencyStore + 'static,
+          F: FnOnce() -> R,
+          R: Future<Error = io::Error> + 'static
+{
+    if store.borrow_mut().contains(&event_id) {
+        return Box::new(future::ok(false));
+    }
+
+    Box::new(handler().map(move |_| {
+        store.borrow_mut().insert(event_id);
+        true
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use std::time::Duration;
+    use futures::{future, Future};
+    use uuid::Uuid;
+    use super::{IdempotencyStore, MemoryIdempotencyStore, handle_once};
+
+    #[test]
+    fn oldest_are_forgotten_over_capacity() {
+        let mut store = MemoryIdempotencyStore::new(2, Duration::from_secs(60));
+        let ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+
+        store.insert(ids[0]);
+        store.insert(ids[1]);
+        store.insert(ids[0]);
+        store.insert(ids[2]);
+
+        assert!(store.contains(&ids[0]));
+        assert!(!store.contains(&ids[1]));
+        assert!(store.contains(&ids[2]));
+        assert_eq!(store.len(), 2);
+    }
+
+    #[test]
+    fn expired_are_forgotten() {
+        let mut store = MemoryIdempotencyStore::new(10, Duration::from_secs(0));
+        let id = Uuid::new_v4();
+
+        store.insert(id);
+        assert!(!store.contains(&id));
+        assert_eq!(store.len(), 0);
+    }
+
+    #[test]
+    fn handled_only_once_after_success() {
+        let store = Rc::new(RefCell::new(MemoryIdempotencyStore::new(10, Duration::from_secs(60))));
+        let id = Uuid::new_v4();
+
+        let failed = handle_once(store.clone(), id, || future::err::<(), _>(io::Error::new(io::ErrorKind::Other, "boom")));
+        assert!(failed.wait().is_err());
+
+        assert!(handle_once(store.clone(), id, || future::ok::<(), io::Error>(())).wait().unwrap());
+        assert!(!handle_once(store.clone(), id, || future::ok::<(), io::Error>(())).wait().unwrap());
+    }
+}
@@ -0,0 +1,648 @@
+//! Event sourced aggregates on top of any client `Service`, available with the `eventsourcing`
+//! feature. An aggregate is loaded by folding all of the events of its stream, and the events
+//! produced by handling a command are written with the version of the loaded stream as the
+//! expected version.
+
+use std::io;
+use std::fmt;
+use std::rc::Rc;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+
+use futures::future::{self, Future, Loop};
+use tokio_service::Service;
+use uuid::Uuid;
+
+use package::Package;
+use {Builder, ContentType, EventNumber, EventRecord, ExpectedVersion, ReadDirection, StreamId, StreamVersion};
+
+mod upcast;
+pub use self::upcast::{Upcasters, schema_version};
+
+mod event_type;
+pub use self::event_type::{EventType, EventTypes};
+
+mod payload;
+pub use self::payload::PayloadCodec;
+#[cfg(feature = "json")]
+pub use self::payload::Json;
+
+mod typed;
+pub use self::typed::{TypedEvent, Payload};
+
+mod idempotency;
+pub use self::idempotency::{IdempotencyStore, MemoryIdempotencyStore, handle_once};
+
+#[cfg(feature = "json")]
+mod causation;
+#[cfg(feature = "json")]
+pub use self::causation::{correlation_id, causation_id, causation_chain};
+
+mod relay;
+pub use self::relay::Relay;
+
+mod replay;
+pub use self::replay::{Replay, ReplayProgress, replay_stream};
+
+mod projection;
+pub use self::projection::{ProjectionSource, ProjectionCheckpoint, project, read_projection};
+
+use json::json_u32;
+use response::{read_page, written};
+
+/// State rebuilt from the events of a single stream.
+pub trait Aggregate: Default {
+    /// The events of the stream
+    type Event;
+
+    /// Applies an event to the state. Called for every event read from the stream, and for every
+    /// event recorded with `Loaded::record`.
+    fn apply(&mut self, event: &Self::Event);
+
+    /// Converts an event read from the stream.
+    fn decode(record: &EventRecord) -> io::Result<Self::Event>;
+
+    /// Converts an event to be written to the stream.
+    fn encode(event: &Self::Event) -> io::Result<EventData>;
+}
+
+/// Aggregate which can be stored as a snapshot, see `Repository::snapshot_every`.
+pub trait Snapshot: Aggregate {
+    /// Converts the state into a snapshot event.
+    fn encode_snapshot(&self) -> io::Result<EventData>;
+
+    /// Converts a snapshot event back into the state.
+    fn decode_snapshot(record: &EventRecord) -> io::Result<Self>;
+}
+
+/// Event to be written, as returned by `Aggregate::encode`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventData {
+    /// Identifier of the event, used by the server to detect duplicate writes
+    pub event_id: Uuid,
+    /// Type of the event
+    pub event_type: Cow<'static, str>,
+    /// Payload of the event
+    pub data: Vec<u8>,
+    /// Content type of `data`
+    pub data_content_type: ContentType,
+    /// Optional metadata of the event
+    pub metadata: Option<Vec<u8>>,
+    /// Content type of `metadata`
+    pub metadata_content_type: ContentType,
+}
+
+impl EventData {
+    /// Creates an event with a random identifier and JSON data without metadata.
+    pub fn json<S: Into<Cow<'static, str>>, D: Into<Vec<u8>>>(event_type: S, data: D) -> Self {
+        EventData {
+            event_id: Uuid::new_v4(),
+            event_type: event_type.into(),
+            data: data.into(),
+            data_content_type: ContentType::Json,
+            metadata: None,
+            metadata_content_type: ContentType::Bytes,
+        }
+    }
+
+    /// Creates an event with a random identifier and binary data without metadata.
+    pub fn bytes<S: Into<Cow<'static, str>>, D: Into<Vec<u8>>>(event_type: S, data: D) -> Self {
+        EventData {
+            data_content_type: ContentType::Bytes,
+            ..EventData::json(event_type, data)
+        }
+    }
+}
+
+/// An aggregate loaded by `Repository::load` with the events recorded since loading.
+#[derive(Debug)]
+pub struct Loaded<A: Aggregate> {
+    stream_id: String,
+    state: A,
+    version: ExpectedVersion,
+    uncommitted: Vec<A::Event>,
+}
+
+impl<A: Aggregate> Loaded<A> {
+    fn new(stream_id: String) -> Self {
+        Loaded {
+            stream_id: stream_id,
+            state: A::default(),
+            version: ExpectedVersion::NoStream,
+            uncommitted: Vec::new(),
+        }
+    }
+
+    /// The stream the aggregate was loaded from.
+    pub fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    /// The current state, including the recorded events.
+    pub fn state(&self) -> &A {
+        &self.state
+    }
+
+    /// The version of the stream as loaded or last saved, used as the expected version when
+    /// saving. `ExpectedVersion::NoStream` if the stream did not exist.
+    pub fn version(&self) -> ExpectedVersion {
+        self.version
+    }
+
+    /// The events recorded but not yet saved.
+    pub fn uncommitted(&self) -> &[A::Event] {
+        &self.uncommitted
+    }
+
+    /// Applies the event to the state and records it to be written by `Repository::save`.
+    pub fn record(&mut self, event: A::Event) {
+        self.state.apply(&event);
+        self.uncommitted.push(event);
+    }
+}
+
+/// Loads and saves aggregates of type `A` through the client `S`.
+///
+/// Saving fails with an error wrapping `WriteEventsFailure::WrongExpectedVersion` if the stream
+/// has been written to since loading. `Repository::execute` handles this by loading the aggregate
+/// again and rerunning the command, for as long as the hook given to `Repository::on_conflict`
+/// allows.
+pub struct Repository<A, S> {
+    service: Rc<S>,
+    page_size: u8,
+    on_conflict: Rc<Fn(&str, usize) -> bool>,
+    snapshots: Option<Snapshots<A>>,
+    upcasters: Option<Rc<Upcasters>>,
+    aggregate: PhantomData<fn() -> A>,
+}
+
+/// The `Snapshot` implementation of the aggregate, stored so that the methods of `Repository` do
+/// not require the trait.
+struct Snapshots<A> {
+    every: u32,
+    encode: fn(&A) -> io::Result<EventData>,
+    decode: fn(&EventRecord) -> io::Result<A>,
+}
+
+impl<A> Clone for Snapshots<A> {
+    fn clone(&self) -> Self {
+        Snapshots {
+            every: self.every,
+            encode: self.encode,
+            decode: self.decode,
+        }
+    }
+}
+
+impl<A, S> Clone for Repository<A, S> {
+    fn clone(&self) -> Self {
+        Repository {
+            service: self.service.clone(),
+            page_size: self.page_size,
+            on_conflict: self.on_conflict.clone(),
+            snapshots: self.snapshots.clone(),
+            upcasters: self.upcasters.clone(),
+            aggregate: PhantomData,
+        }
+    }
+}
+
+impl<A, S> Repository<A, S>
+    where A: Aggregate + 'static,
+          A::Event: 'static,
+          S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    /// Creates a repository reading 100 events at a time and retrying conflicting commands up to
+    /// three times.
+    pub fn new(service: S) -> Self {
+        Repository {
+            service: Rc::new(service),
+            page_size: 100,
+            on_conflict: Rc::new(|_: &str, attempt: usize| attempt < 3),
+            snapshots: None,
+            upcasters: None,
+            aggregate: PhantomData,
+        }
+    }
+
+    /// Sets the number of events read at a time when loading. Panics if the size is zero.
+    ///
+    /// Defaults to 100.
+    pub fn page_size(mut self, size: u8) -> Self {
+        assert!(size > 0);
+        self.page_size = size;
+        self
+    }
+
+    /// Sets the hook called by `execute` when saving conflicted with a concurrent write, with the
+    /// stream id and the number of conflicts so far. The command is retried if the hook returns
+    /// `true`; otherwise `execute` fails with the conflict. The hook can also be used to log the
+    /// conflicts or to count them.
+    ///
+    /// Defaults to retrying up to three times.
+    pub fn on_conflict<F: Fn(&str, usize) -> bool + 'static>(mut self, hook: F) -> Self {
+        self.on_conflict = Rc::new(hook);
+        self
+    }
+
+    /// Transforms the events read from the streams with `upcasters` before decoding them.
+    ///
+    /// Defaults to decoding the events as read.
+    pub fn upcasters(mut self, upcasters: Upcasters) -> Self {
+        self.upcasters = Some(Rc::new(upcasters));
+        self
+    }
+
+    /// Reads all of the events of the stream and folds them into the aggregate. A stream which
+    /// does not exist loads as the default value of the aggregate. With snapshots enabled the
+    /// latest snapshot is read first, and only the events following it are folded.
+    pub fn load<I: Into<String>>(&self, stream_id: I) -> Box<Future<Item = Loaded<A>, Error = io::Error>> {
+        let service = self.service.clone();
+        let page_size = self.page_size;
+        let upcasters = self.upcasters.clone();
+        let loaded = Loaded::new(stream_id.into());
+
+        let start: Box<Future<Item = (Loaded<A>, EventNumber), Error = io::Error>> = match self.snapshots {
+            Some(ref snapshots) => {
+                let snapshot = read_snapshot(&*self.service, &loaded.stream_id, snapshots.decode);
+
+                Box::new(snapshot.and_then(move |snapshot| match snapshot {
+                    Some((state, version)) => {
+                        let next = stream_version(i32::from(version) + 1)?;
+                        let loaded = Loaded { state: state, version: ExpectedVersion::Exact(version), ..loaded };
+                        Ok((loaded, EventNumber::Exact(next)))
+                    },
+                    None => Ok((loaded, EventNumber::First)),
+                }))
+            },
+            None => Box::new(future::ok((loaded, EventNumber::First))),
+        };
+
+        Box::new(start.and_then(move |start| future::loop_fn(start, move |(mut loaded, from)| {
+            let package = Builder::read_stream_events()
+                .direction(ReadDirection::Forward)
+                .stream_id(loaded.stream_id.clone())
+                .from_event_number(from)
+                .max_count(page_size)
+                .resolve_link_tos(false)
+                .build_package(None, None);
+
+            let upcasters = upcasters.clone();
+
+            service.call(package).and_then(move |response| {
+                let page = match read_page(response)? {
+                    Some(page) => page,
+                    None => return Ok(Loop::Break(loaded)),
+                };
+
+                for event in page.events {
+                    let version = stream_version(event.event.event_number)?;
+                    let record = match upcasters {
+                        Some(ref upcasters) => upcasters.upcast(event.event)?,
+                        None => event.event,
+                    };
+
+                    loaded.state.apply(&A::decode(&record)?);
+                    loaded.version = ExpectedVersion::Exact(version);
+                }
+
+                match page.next_page {
+                    Some(next) if !page.end_of_stream => Ok(Loop::Continue((loaded, next))),
+                    _ => Ok(Loop::Break(loaded)),
+                }
+            })
+        })))
+    }
+
+    /// Loads the aggregate of the entity from its `category-id` stream, see
+    /// `StreamId::from_category`.
+    pub fn load_entity<C: AsRef<str>, I: fmt::Display>(&self, category: C, id: I) -> Box<Future<Item = Loaded<A>, Error = io::Error>> {
+        self.load(StreamId::from_category(category, id))
+    }
+
+    /// Writes the recorded events of the aggregate, expecting the stream to be at the loaded
+    /// version. Completes with the aggregate at the new version without uncommitted events.
+    ///
+    /// With snapshots enabled a snapshot is written after the events whenever the stream crosses
+    /// a multiple of the configured number of events. Failing to write the snapshot is only
+    /// logged, as the events have been saved.
+    pub fn save(&self, mut loaded: Loaded<A>) -> Box<Future<Item = Loaded<A>, Error = io::Error>> {
+        if loaded.uncommitted.is_empty() {
+            return Box::new(future::ok(loaded));
+        }
+
+        let package = match write_package(&loaded) {
+            Ok(package) => package,
+            Err(e) => return Box::new(future::err(e)),
+        };
+
+        let service = self.service.clone();
+        let snapshots = self.snapshots.clone();
+        let previous = loaded.version;
+
+        Box::new(self.service.call(package).and_then(move |response| {
+            let completed = written(response)?;
+            let last = i32::from(completed.event_numbers.end) - 1;
+
+            loaded.version = ExpectedVersion::Exact(stream_version(last)?);
+            loaded.uncommitted.clear();
+            Ok(loaded)
+        }).and_then(move |loaded| -> Box<Future<Item = Loaded<A>, Error = io::Error>> {
+            let snapshots = match snapshots {
+                Some(ref snapshots) if crosses(previous, loaded.version, snapshots.every) => snapshots.clone(),
+                _ => return Box::new(future::ok(loaded)),
+            };
+
+            let package = match snapshot_package(&loaded, snapshots.encode) {
+                Ok(package) => package,
+                Err(e) => {
+                    warn!("encoding a snapshot of {} failed: {}", loaded.stream_id, e);
+                    return Box::new(future::ok(loaded));
+                },
+            };
+
+            Box::new(service.call(package).then(move |res| -> io::Result<Loaded<A>> {
+                if let Err(e) = res.and_then(written) {
+                    warn!("writing a snapshot of {} failed: {}", loaded.stream_id, e);
+                }
+                Ok(loaded)
+            }))
+        }))
+    }
+
+    /// Loads the aggregate, records the events returned by `command` and saves them. Conflicting
+    /// concurrent writes are handled as configured with `on_conflict`.
+    pub fn execute<I, F>(&self, stream_id: I, command: F) -> Box<Future<Item = Loaded<A>, Error = io::Error>>
+        where I: Into<String>,
+              F: Fn(&A) -> io::Result<Vec<A::Event>> + 'static
+    {
+        let repository = self.clone();
+        let stream_id = stream_id.into();
+        let command = Rc::new(command);
+
+        Box::new(future::loop_fn(0, move |conflicts| {
+            let saver = repository.clone();
+            let on_conflict = repository.on_conflict.clone();
+            let stream_id = stream_id.clone();
+            let command = command.clone();
+
+            repository.load(stream_id.clone())
+                .and_then(move |mut loaded| {
+                    for event in (*command)(&loaded.state)? {
+                        loaded.record(event);
+                    }
+                    Ok(loaded)
+                })
+                .and_then(move |loaded| saver.save(loaded))
+                .then(move |res| match res {
+                    Ok(loaded) => Ok(Loop::Break(loaded)),
+                    Err(ref e) if is_conflict(e) && (*on_conflict)(&stream_id, conflicts + 1) => {
+                        debug!("retrying command on {} after a conflicting write", stream_id);
+                        Ok(Loop::Continue(conflicts + 1))
+                    },
+                    Err(e) => Err(e),
+                })
+        }))
+    }
+}
+
+impl<A, S> Repository<A, S>
+    where A: Snapshot + 'static,
+          A::Event: 'static,
+          S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    /// Writes a snapshot of the aggregate to the stream `<stream>-snapshot` every `events`
+    /// events, and loads aggregates starting from the latest snapshot. The snapshot events carry
+    /// the version of the stream they were taken at in their metadata as `{"version":N}`.
+    ///
+    /// Only the latest snapshot is ever read, so setting `$maxCount` on the snapshot streams keeps
+    /// them from growing. Panics if `events` is zero.
+    ///
+    /// Defaults to no snapshots.
+    pub fn snapshot_every(mut self, events: u32) -> Self {
+        assert!(events > 0);
+        self.snapshots = Some(Snapshots {
+            every: events,
+            encode: A::encode_snapshot,
+            decode: A::decode_snapshot,
+        });
+        self
+    }
+}
+
+/// Returns true if the error is a failed write because of an unexpected stream version.
+pub fn is_conflict(e: &io::Error) -> bool {
+    use adapted::WriteEventsFailure;
+
+    e.get_ref()
+        .and_then(|inner| inner.downcast_ref::<WriteEventsFailure>())
+        .map(|failure| *failure == WriteEventsFailure::WrongExpectedVersion)
+        .unwrap_or(false)
+}
+
+fn write_package<A: Aggregate>(loaded: &Loaded<A>) -> io::Result<Package> {
+    let mut builder = Builder::write_events();
+    builder.stream_id(loaded.stream_id.clone())
+        .expected_version(loaded.version);
+
+    for event in &loaded.uncommitted {
+        let data = A::encode(event)?;
+
+        let new_event = builder.new_event()
+            .event_id(data.event_id)
+            .event_type(data.event_type)
+            .data(data.data)
+            .data_content_type(data.data_content_type);
+
+        let new_event = match data.metadata {
+            Some(metadata) => new_event.metadata(metadata).metadata_content_type(data.metadata_content_type),
+            None => new_event,
+        };
+
+        new_event.done();
+    }
+
+    Ok(builder.build_package(None, None))
+}
+
+fn snapshot_stream(stream_id: &str) -> String {
+    format!("{}-snapshot", stream_id)
+}
+
+/// Returns true if a multiple of `every` events was reached when the stream moved from `previous`
+/// to `current`.
+fn crosses(previous: ExpectedVersion, current: ExpectedVersion, every: u32) -> bool {
+    fn count(version: ExpectedVersion) -> u32 {
+        match version {
+            ExpectedVersion::Exact(version) => u32::from(version) + 1,
+            _ => 0,
+        }
+    }
+
+    count(current) / every > count(previous) / every
+}
+
+fn snapshot_package<A>(loaded: &Loaded<A>, encode: fn(&A) -> io::Result<EventData>) -> io::Result<Package>
+    where A: Aggregate
+{
+    let version = match loaded.version {
+        ExpectedVersion::Exact(version) => u32::from(version),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "No events to snapshot")),
+    };
+
+    let data = encode(&loaded.state)?;
+
+    Ok(Builder::write_events()
+        .stream_id(snapshot_stream(&loaded.stream_id))
+        .expected_version(ExpectedVersion::Any)
+        .new_event()
+            .event_id(data.event_id)
+            .event_type(data.event_type)
+            .data(data.data)
+            .data_content_type(data.data_content_type)
+            .metadata(format!("{{\"version\":{}}}", version))
+            .metadata_content_type(ContentType::Json)
+        .done()
+        .build_package(None, None))
+}
+
+/// Reads the latest snapshot with the version of the stream it was taken at.
+fn read_snapshot<A, S>(service: &S, stream_id: &str, decode: fn(&EventRecord) -> io::Result<A>) -> Box<Future<Item = Option<(A, StreamVersion)>, Error = io::Error>>
+    where A: 'static,
+          S: Service<Request = Package, Response = Package, Error = io::Error>,
+          S::Future: 'static
+{
+    let package = Builder::read_stream_events()
+        .direction(ReadDirection::Backward)
+        .stream_id(snapshot_stream(stream_id))
+        .from_event_number(EventNumber::Last)
+        .max_count(1)
+        .resolve_link_tos(false)
+        .build_package(None, None);
+
+    Box::new(service.call(package).and_then(move |response| {
+        let page = match read_page(response)? {
+            Some(page) => page,
+            None => return Ok(None),
+        };
+
+        match page.events.first() {
+            Some(event) => Ok(Some((decode(&event.event)?, snapshot_version(&event.event)?))),
+            None => Ok(None),
+        }
+    }))
+}
+
+fn snapshot_version(record: &EventRecord) -> io::Result<StreamVersion> {
+    let version = record.metadata.as_ref().and_then(|metadata| json_u32(metadata, "version"));
+
+    match version {
+        Some(version) => StreamVersion::try_from(version).map_err(|e| e.into()),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "Snapshot without a version in the metadata")),
+    }
+}
+
+fn stream_version(number: i32) -> io::Result<StreamVersion> {
+    StreamVersion::try_from(number).map_err(|e| e.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use adapted::WriteEventsFailure;
+    use raw::RawMessage;
+    use {EventRecord, ExpectedVersion};
+    use super::{Aggregate, EventData, Loaded, write_package, is_conflict, crosses, snapshot_package, snapshot_version};
+
+    #[derive(Debug, Default)]
+    struct Counter(usize);
+
+    impl Aggregate for Counter {
+        type Event = usize;
+
+        fn apply(&mut self, event: &usize) {
+            self.0 += *event;
+        }
+
+        fn decode(record: &EventRecord) -> io::Result<usize> {
+            Ok(record.data.len())
+        }
+
+        fn encode(event: &usize) -> io::Result<EventData> {
+            Ok(EventData::bytes("added", vec![0u8; *event]))
+        }
+    }
+
+    #[test]
+    fn recorded_events_are_applied_and_written() {
+        let mut loaded = Loaded::<Counter>::new("counter-1".into());
+        loaded.record(2);
+        loaded.record(3);
+
+        assert_eq!(loaded.state().0, 5);
+        assert_eq!(loaded.uncommitted(), &[2, 3]);
+
+        match write_package(&loaded).unwrap().message {
+            RawMessage::WriteEvents(we) => {
+                assert_eq!(we.event_stream_id, "counter-1");
+                assert_eq!(we.expected_version, i32::from(ExpectedVersion::NoStream));
+                assert_eq!(we.events.len(), 2);
+                assert_eq!(we.events[1].data.len(), 3);
+            },
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn conflicts_are_detected() {
+        let conflict = io::Error::new(io::ErrorKind::Other, WriteEventsFailure::WrongExpectedVersion);
+        let denied = io::Error::new(io::ErrorKind::Other, WriteEventsFailure::AccessDenied);
+
+        assert!(is_conflict(&conflict));
+        assert!(!is_conflict(&denied));
+        assert!(!is_conflict(&io::Error::new(io::ErrorKind::Other, "other")));
+    }
+
+    #[test]
+    fn snapshots_are_taken_every_n_events() {
+        use std::convert::TryFrom;
+        use StreamVersion;
+
+        let exact = |n: u32| ExpectedVersion::Exact(StreamVersion::try_from(n).unwrap());
+
+        assert!(!crosses(ExpectedVersion::NoStream, exact(8), 10));
+        assert!(crosses(ExpectedVersion::NoStream, exact(9), 10));
+        assert!(crosses(exact(8), exact(12), 10));
+        assert!(!crosses(exact(9), exact(12), 10));
+    }
+
+    #[test]
+    fn snapshot_carries_version() {
+        use std::convert::TryFrom;
+        use StreamVersion;
+
+        let mut loaded = Loaded::<Counter>::new("counter-1".into());
+        loaded.version = ExpectedVersion::Exact(StreamVersion::try_from(41u32).unwrap());
+
+        let encode = |state: &Counter| -> io::Result<EventData> { Ok(EventData::bytes("snapshot", vec![0u8; state.0])) };
+
+        let event = match snapshot_package(&loaded, encode).unwrap().message {
+            RawMessage::WriteEvents(mut we) => {
+                assert_eq!(we.event_stream_id, "counter-1-snapshot");
+                we.events.remove(0)
+            },
+            other => panic!("unexpected message: {:?}", other),
+        };
+
+        let record = EventRecord {
+            metadata: event.metadata,
+            ..EventRecord::default()
+        };
+
+        assert_eq!(snapshot_version(&record).unwrap(), StreamVersion::try_from(41u32).unwrap());
+        assert!(snapshot_version(&EventRecord::default()).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! Serializing the payloads of events.
+
+use std::io;
+
+use ContentType;
+use super::{EventData, EventType, EventTypes};
+
+/// Serialization format of the data or metadata of events of type `T`. `Json` implements this
+/// for all serde types with the `json` feature; other formats such as CBOR, MessagePack or
+/// Protobuf can be plugged in by implementing this trait.
+pub trait PayloadCodec<T> {
+    /// The content type written along the serialized payloads. Formats other than JSON should
+    /// use `ContentType::Bytes`, as only JSON is understood by the server.
+    fn content_type(&self) -> ContentType;
+
+    /// Serializes the payload.
+    fn encode(&self, value: &T) -> io::Result<Vec<u8>>;
+
+    /// Deserializes the payload.
+    fn decode(&self, bytes: &[u8]) -> io::Result<T>;
+}
+
+/// JSON through `serde_json`, the default format of the payloads, available with the `json`
+/// feature.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl<T: ::serde::Serialize + ::serde::de::DeserializeOwned> PayloadCodec<T> for Json {
+    fn content_type(&self) -> ContentType {
+        ContentType::Json
+    }
+
+    fn encode(&self, value: &T) -> io::Result<Vec<u8>> {
+        ::serde_json::to_vec(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> io::Result<T> {
+        ::serde_json::from_slice(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl EventData {
+    /// Creates an event with the event type of `E` and the data serialized with `codec`.
+    pub fn encode<E: EventType, C: PayloadCodec<E>>(codec: &C, data: &E) -> io::Result<EventData> {
+        Ok(EventData {
+            data_content_type: codec.content_type(),
+            ..EventData::bytes(E::EVENT_TYPE, codec.encode(data)?)
+        })
+    }
+}
+
+impl<T: 'static> EventTypes<T> {
+    /// Registers the event type of `E`, decoding the data of the events with `codec` and
+    /// converting the payload into `T` with `wrap`, usually a variant of an enumeration:
+    ///
+    /// ```rust,ignore
+    /// let types = EventTypes::new()
+    ///     .register_codec(Json, Account::Opened)
+    ///     .register_codec(Json, Account::Closed);
+    /// ```
+    pub fn register_codec<E, C, F>(self, codec: C, wrap: F) -> Self
+        where E: EventType + 'static,
+              C: PayloadCodec<E> + 'static,
+              F: Fn(E) -> T + 'static
+    {
+        self.register::<E, _>(move |record| codec.decode(&record.data).map(&wrap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::str;
+    use ContentType;
+    use EventRecord;
+    use eventsourcing::{EventData, EventType, EventTypes};
+    use super::PayloadCodec;
+
+    #[derive(Debug, PartialEq)]
+    struct Renamed(String);
+
+    impl EventType for Renamed {
+        const EVENT_TYPE: &'static str = "renamed";
+    }
+
+    /// Plain UTF-8 text, standing in for a binary format
+    struct Text;
+
+    impl PayloadCodec<Renamed> for Text {
+        fn content_type(&self) -> ContentType {
+            ContentType::Bytes
+        }
+
+        fn encode(&self, value: &Renamed) -> io::Result<Vec<u8>> {
+            Ok(value.0.clone().into_bytes())
+        }
+
+        fn decode(&self, bytes: &[u8]) -> io::Result<Renamed> {
+            str::from_utf8(bytes)
+                .map(|s| Renamed(s.to_owned()))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+    }
+
+    #[test]
+    fn custom_codec_roundtrip() {
+        let data = EventData::encode(&Text, &Renamed("bob".into())).unwrap();
+        assert_eq!(data.event_type, "renamed");
+        assert_eq!(data.data_content_type, ContentType::Bytes);
+
+        let types = EventTypes::new().register_codec(Text, Some::<Renamed>);
+        let record = EventRecord {
+            event_type: data.event_type.clone(),
+            data: data.data.into(),
+            ..EventRecord::default()
+        };
+
+        assert_eq!(types.decode(&record).unwrap(), Some(Renamed("bob".into())));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_codec() {
+        use super::Json;
+
+        let bytes = PayloadCodec::<Vec<u32>>::encode(&Json, &vec![1, 2]).unwrap();
+        assert_eq!(bytes, b"[1,2]");
+        assert_eq!(PayloadCodec::<Vec<u32>>::decode(&Json, &bytes).unwrap(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,267 @@
+//! Folding the events of a feed into a state which is checkpointed to a stream, for read models.
+
+use std::io;
+use std::str;
+use std::rc::Rc;
+
+use futures::{future, Future, Stream};
+use tokio_service::Service;
+use uuid::Uuid;
+
+use feed::{StreamEvent, ResumeToken, ResumePosition, subscribe_from, watch_stream};
+use package::Package;
+use {Builder, ContentType, EventNumber, EventRecord, EventStoreClient, ExpectedVersion, LogPosition, ReadDirection};
+use response::{read_page, written};
+use super::PayloadCodec;
+
+/// The events folded by `project`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProjectionSource {
+    /// The events of the stream, with links resolved
+    Stream(String),
+    /// The events of all streams
+    All,
+}
+
+/// A checkpoint written by `project`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectionCheckpoint<T> {
+    /// The state after folding the event of the token
+    pub state: T,
+    /// Where the projection continues from after a restart
+    pub token: ResumeToken,
+    /// Events folded since the projection was started
+    pub events: u64,
+}
+
+/// Folds the events of the source into a state, starting from `initial`, and writes the state
+/// with the position of the last folded event to `checkpoint_stream` every `checkpoint_every`
+/// events. The feed yields each checkpoint once it has been written.
+///
+/// When started again, the projection continues from the latest checkpoint with the state of it
+/// instead of `initial`, through `feed::subscribe_from`. The events folded after the latest
+/// checkpoint are folded again, so `fold` should tolerate seeing an event twice, or the state
+/// should remember the last event it has applied. The state is serialized with `codec` as the
+/// data of the checkpoint events, and the `ResumeToken` is their metadata; `read_projection`
+/// reads the state back elsewhere, for example to serve queries.
+///
+/// Fails with the first failed fold, read or write; a checkpoint of another source fails the
+/// projection with `InvalidData`. Panics if `checkpoint_every` is zero.
+pub fn project<T, C, F>(client: &EventStoreClient, source: ProjectionSource, checkpoint_stream: &str, initial: T, codec: C, fold: F, checkpoint_every: u32) -> Box<Stream<Item = ProjectionCheckpoint<T>, Error = io::Error>>
+    where T: Clone + 'static,
+          C: PayloadCodec<T> + 'static,
+          F: FnMut(T, &StreamEvent) -> io::Result<T> + 'static
+{
+    assert!(checkpoint_every > 0);
+
+    let client = client.clone();
+    let checkpoint_stream = checkpoint_stream.to_owned();
+    let codec = Rc::new(codec);
+
+    Box::new(read_checkpoint(&client, &checkpoint_stream).and_then(move |checkpoint| {
+        let (state, events) = match checkpoint {
+            Some((record, token)) => {
+                check_source(&source, &token)?;
+                (codec.decode(&record.data)?, subscribe_from(&client, &token))
+            },
+            None => {
+                let events = match source {
+                    ProjectionSource::Stream(ref stream_id) => watch_stream(&client, stream_id.clone(), EventNumber::First),
+                    ProjectionSource::All => subscribe_from(&client, &ResumeToken::all(LogPosition::First, LogPosition::First)),
+                };
+                (initial, events)
+            },
+        };
+
+        let token_of = match source {
+            ProjectionSource::Stream(_) => ResumeToken::after as fn(&StreamEvent) -> Option<ResumeToken>,
+            ProjectionSource::All => ResumeToken::after_in_all,
+        };
+
+        Ok(fold_feed(Rc::new(client), checkpoint_stream, codec, state, events, fold, checkpoint_every, token_of))
+    }).flatten_stream())
+}
+
+/// Reads the state and position of the latest checkpoint written by `project` to
+/// `checkpoint_stream`, `None` if there is none yet.
+pub fn read_projection<S, T, C>(service: &S, checkpoint_stream: &str, codec: C) -> Box<Future<Item = Option<(T, ResumeToken)>, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error>,
+          S::Future: 'static,
+          T: 'static,
+          C: PayloadCodec<T> + 'static
+{
+    Box::new(read_checkpoint(service, checkpoint_stream).and_then(move |checkpoint| match checkpoint {
+        Some((record, token)) => Ok(Some((codec.decode(&record.data)?, token))),
+        None => Ok(None),
+    }))
+}
+
+/// Folds the events, writing a checkpoint after every `every` events before folding the next.
+fn fold_feed<S, T, C, F>(service: Rc<S>, checkpoint_stream: String, codec: Rc<C>, initial: T, events: Box<Stream<Item = StreamEvent, Error = io::Error>>, mut fold: F, every: u32, token_of: fn(&StreamEvent) -> Option<ResumeToken>) -> Box<Stream<Item = ProjectionCheckpoint<T>, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static,
+          T: Clone + 'static,
+          C: PayloadCodec<T> + 'static,
+          F: FnMut(T, &StreamEvent) -> io::Result<T> + 'static
+{
+    // the state is moved into the fold, and is gone if the fold fails
+    let mut state = Some(initial);
+    let mut folded = 0u64;
+
+    Box::new(events.and_then(move |e| -> Box<Future<Item = Option<ProjectionCheckpoint<T>>, Error = io::Error>> {
+        let token = match token_of(&e) {
+            Some(token) => token,
+            None => return Box::new(future::err(io::Error::new(io::ErrorKind::InvalidData, "Event without a position to continue from"))),
+        };
+
+        let previous = match state.take() {
+            Some(previous) => previous,
+            None => return Box::new(future::err(io::Error::new(io::ErrorKind::Other, "The projection has failed"))),
+        };
+
+        let next = match fold(previous, &e) {
+            Ok(next) => next,
+            Err(e) => return Box::new(future::err(e)),
+        };
+
+        folded += 1;
+
+        if folded % u64::from(every) != 0 {
+            state = Some(next);
+            return Box::new(future::ok(None));
+        }
+
+        let package = match checkpoint_package(&checkpoint_stream, &*codec, &next, &token) {
+            Ok(package) => package,
+            Err(e) => return Box::new(future::err(e)),
+        };
+
+        let checkpoint = ProjectionCheckpoint { state: next.clone(), token: token, events: folded };
+        state = Some(next);
+
+        Box::new(service.call(package).and_then(written).map(move |_| Some(checkpoint)))
+    }).filter_map(|checkpoint| checkpoint))
+}
+
+fn check_source(source: &ProjectionSource, token: &ResumeToken) -> io::Result<()> {
+    match (source, &token.position) {
+        (&ProjectionSource::Stream(ref stream_id), &ResumePosition::Stream(ref checkpointed, _)) if stream_id == checkpointed => Ok(()),
+        (&ProjectionSource::All, &ResumePosition::All(..)) => Ok(()),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("Checkpoint {} is not of the projected source", token))),
+    }
+}
+
+/// Reads the latest checkpoint event with the token in its metadata.
+fn read_checkpoint<S>(service: &S, checkpoint_stream: &str) -> Box<Future<Item = Option<(EventRecord<'static>, ResumeToken)>, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error>,
+          S::Future: 'static
+{
+    let package = Builder::read_stream_events()
+        .direction(ReadDirection::Backward)
+        .stream_id(checkpoint_stream.to_owned())
+        .from_event_number(EventNumber::Last)
+        .max_count(1)
+        .resolve_link_tos(false)
+        .build_package(None, None);
+
+    Box::new(service.call(package).and_then(|response| {
+        let page = match read_page(response)? {
+            Some(page) => page,
+            None => return Ok(None),
+        };
+
+        match page.events.into_iter().next() {
+            Some(event) => {
+                let token = checkpoint_token(&event.event)?;
+                Ok(Some((event.event, token)))
+            },
+            None => Ok(None),
+        }
+    }))
+}
+
+fn checkpoint_token(record: &EventRecord) -> io::Result<ResumeToken> {
+    match record.metadata.as_ref().and_then(|metadata| str::from_utf8(metadata).ok()) {
+        Some(token) => token.parse(),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "Checkpoint without a resume token in the metadata")),
+    }
+}
+
+fn checkpoint_package<T, C: PayloadCodec<T>>(checkpoint_stream: &str, codec: &C, state: &T, token: &ResumeToken) -> io::Result<Package> {
+    Ok(Builder::write_events()
+        .stream_id(checkpoint_stream.to_owned())
+        .expected_version(ExpectedVersion::Any)
+        .new_event()
+            .event_id(Uuid::new_v4())
+            .event_type("projectionCheckpoint")
+            .data(codec.encode(state)?)
+            .data_content_type(codec.content_type())
+            .metadata(token.to_string())
+            .metadata_content_type(ContentType::Bytes)
+        .done()
+        .build_package(None, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::rc::Rc;
+    use std::convert::TryFrom;
+    use futures::{stream, Future, Stream};
+    use server::{MemoryStore, StoreService};
+    use feed::{StreamEvent, ResumeToken};
+    use eventsourcing::PayloadCodec;
+    use {ContentType, EventRecord, StreamVersion};
+    use super::{ProjectionSource, fold_feed, check_source, read_projection};
+
+    /// Counts as decimal text.
+    #[derive(Clone, Copy)]
+    struct Decimal;
+
+    impl PayloadCodec<u64> for Decimal {
+        fn content_type(&self) -> ContentType {
+            ContentType::Bytes
+        }
+
+        fn encode(&self, value: &u64) -> io::Result<Vec<u8>> {
+            Ok(value.to_string().into_bytes())
+        }
+
+        fn decode(&self, bytes: &[u8]) -> io::Result<u64> {
+            String::from_utf8_lossy(bytes).parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not a count"))
+        }
+    }
+
+    fn event(number: i32) -> StreamEvent {
+        StreamEvent {
+            event: EventRecord { event_stream_id: "orders-1".into(), event_number: number, event_type: "created".into(), ..Default::default() },
+            link: None,
+            commit_position: None,
+            prepare_position: None,
+        }
+    }
+
+    #[test]
+    fn checkpoints_every_n_events() {
+        let service = StoreService::new(MemoryStore::new());
+        let events = Box::new(stream::iter_ok::<_, io::Error>((0..5).map(event)));
+
+        let checkpoints = fold_feed(Rc::new(service.clone()), "orders-count".to_owned(), Rc::new(Decimal), 0u64, events,
+                                    |count, _| Ok(count + 1), 2, ResumeToken::after)
+            .collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(checkpoints.iter().map(|c| (c.state, c.events)).collect::<Vec<_>>(), vec![(2, 2), (4, 4)]);
+
+        let (state, token) = read_projection(&service, "orders-count", Decimal).wait().unwrap().unwrap();
+        assert_eq!(state, 4);
+        assert_eq!(token, ResumeToken::stream("orders-1", StreamVersion::try_from(3u32).unwrap()));
+
+        assert!(check_source(&ProjectionSource::Stream("orders-1".into()), &token).is_ok());
+        assert!(check_source(&ProjectionSource::Stream("orders-2".into()), &token).is_err());
+        assert!(check_source(&ProjectionSource::All, &token).is_err());
+
+        assert_eq!(read_projection(&service, "other-count", Decimal).wait().unwrap(), None);
+    }
+}
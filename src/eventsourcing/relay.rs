@@ -0,0 +1,393 @@
+//! Forwarding the events of a stream with at-least-once delivery.
+
+use std::io;
+use std::rc::Rc;
+use std::vec;
+
+use futures::future::{self, Future, Loop};
+use tokio_service::Service;
+use uuid::Uuid;
+
+use package::Package;
+use raw::client_messages::ResolvedIndexedEvent;
+use feed::watch_stream;
+use {Builder, ContentType, EventNumber, EventRecord, EventStoreClient, ExpectedVersion, ReadDirection, StreamVersion};
+use response::{read_page, written};
+use super::stream_version;
+use json::json_u32;
+
+/// Forwards the events of a source stream to a sink, for example a message queue, a webhook or
+/// another store.
+///
+/// The position of the last forwarded event is checkpointed to a stream of its own, but only
+/// after the sink has acknowledged every event up to it. After a failure or a restart the relay
+/// continues from the checkpoint, so events are forwarded at least once and the sink may see some
+/// of them again. Events the sink keeps failing on are either moved to a poison stream or fail the
+/// relay, see `Relay::poison_stream`.
+///
+/// `Relay::follow` forwards the events as they are written through a catch-up subscription, while
+/// `Relay::run` only reads the source until its end and is run again to forward the events
+/// written since.
+pub struct Relay<S> {
+    service: Rc<S>,
+    source: String,
+    checkpoint_stream: String,
+    page_size: u8,
+    max_attempts: usize,
+    poison_stream: Option<String>,
+}
+
+/// State of `Relay::run` between the pages.
+struct Progress {
+    from: EventNumber,
+    checkpoint: Option<StreamVersion>,
+}
+
+impl<S> Relay<S>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    /// Creates a relay forwarding the events of `source`, checkpointing to `checkpoint_stream`.
+    /// Each event is offered to the sink up to three times.
+    pub fn new<A: Into<String>, B: Into<String>>(service: S, source: A, checkpoint_stream: B) -> Self {
+        Relay {
+            service: Rc::new(service),
+            source: source.into(),
+            checkpoint_stream: checkpoint_stream.into(),
+            page_size: 100,
+            max_attempts: 3,
+            poison_stream: None,
+        }
+    }
+
+    /// Sets the number of events read, and forwarded between checkpoints, at a time. Panics if
+    /// the size is zero.
+    ///
+    /// Defaults to 100.
+    pub fn page_size(mut self, size: u8) -> Self {
+        assert!(size > 0);
+        self.page_size = size;
+        self
+    }
+
+    /// Sets how many times an event is offered to the sink before it is considered poison.
+    /// Panics if `attempts` is zero.
+    ///
+    /// Defaults to 3.
+    pub fn max_attempts(mut self, attempts: usize) -> Self {
+        assert!(attempts > 0);
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// Writes the events the sink keeps failing on to `stream_id` and carries on with the next
+    /// event. The copies have the type, data and metadata of the original event.
+    ///
+    /// Defaults to failing the relay with the error of the sink.
+    pub fn poison_stream<I: Into<String>>(mut self, stream_id: I) -> Self {
+        self.poison_stream = Some(stream_id.into());
+        self
+    }
+
+    /// Forwards the events written to the source after the checkpoint, one at a time, until the
+    /// end of the stream. Completes with the version of the source checkpointed last, or `None` if
+    /// there has been nothing to forward ever.
+    ///
+    /// The relay is run again to forward the events written since, for example periodically.
+    pub fn run<F, R>(&self, sink: F) -> Box<Future<Item = Option<StreamVersion>, Error = io::Error>>
+        where F: Fn(&EventRecord<'static>) -> R + 'static,
+              R: Future<Item = (), Error = io::Error> + 'static
+    {
+        let service = self.service.clone();
+        let source = self.source.clone();
+        let checkpoint_stream = self.checkpoint_stream.clone();
+        let page_size = self.page_size;
+        let max_attempts = self.max_attempts;
+        let poison_stream = self.poison_stream.clone();
+        let sink = Rc::new(sink);
+
+        let start = read_checkpoint(&*self.service, &self.checkpoint_stream).and_then(|checkpoint| {
+            Ok(Progress { from: resume_from(checkpoint)?, checkpoint: checkpoint })
+        });
+
+        Box::new(start.and_then(move |start| future::loop_fn(start, move |progress| {
+            let package = Builder::read_stream_events()
+                .direction(ReadDirection::Forward)
+                .stream_id(source.clone())
+                .from_event_number(progress.from)
+                .max_count(page_size)
+                .resolve_link_tos(true)
+                .build_package(None, None);
+
+            let service = service.clone();
+            let checkpoint_stream = checkpoint_stream.clone();
+            let poison_stream = poison_stream.clone();
+            let sink = sink.clone();
+
+            service.clone().call(package).and_then(move |response| -> Box<Future<Item = Loop<Option<StreamVersion>, Progress>, Error = io::Error>> {
+                let page = match read_page(response) {
+                    Ok(Some(page)) => page,
+                    Ok(None) => return Box::new(future::ok(Loop::Break(progress.checkpoint))),
+                    Err(e) => return Box::new(future::err(e)),
+                };
+
+                let last = match page.events.last().map(original_version) {
+                    Some(Ok(version)) => version,
+                    Some(Err(e)) => return Box::new(future::err(e)),
+                    None => return Box::new(future::ok(Loop::Break(progress.checkpoint))),
+                };
+
+                let next = match page.next_page {
+                    Some(next) if !page.end_of_stream => Some(next),
+                    _ => None,
+                };
+
+                let checkpointer = service.clone();
+                let events = page.events.into_iter().map(|event| event.event).collect::<Vec<_>>();
+
+                Box::new(forward(service, sink, events, max_attempts, poison_stream)
+                    .and_then(move |_| checkpointer.call(checkpoint_package(&checkpoint_stream, last)))
+                    .and_then(written)
+                    .map(move |_| match next {
+                        Some(next) => Loop::Continue(Progress { from: next, checkpoint: Some(last) }),
+                        None => Loop::Break(Some(last)),
+                    }))
+            })
+        })))
+    }
+
+    /// Forwards the events written to the source after the checkpoint, and then the events
+    /// appended to it from now on, through a catch-up subscription like `feed::watch_stream`. The
+    /// checkpoint is written after every event the sink has acknowledged.
+    ///
+    /// Fails when the subscription is dropped or the connection is lost; following the relay again
+    /// continues from the checkpoint.
+    pub fn follow<F, R>(&self, client: &EventStoreClient, sink: F) -> Box<Future<Item = (), Error = io::Error>>
+        where F: Fn(&EventRecord<'static>) -> R + 'static,
+              R: Future<Item = (), Error = io::Error> + 'static
+    {
+        let client = client.clone();
+        let service = self.service.clone();
+        let source = self.source.clone();
+        let checkpoint_stream = self.checkpoint_stream.clone();
+        let max_attempts = self.max_attempts;
+        let poison_stream = self.poison_stream.clone();
+        let sink = Rc::new(sink);
+
+        let start = read_checkpoint(&*self.service, &self.checkpoint_stream).and_then(resume_from);
+
+        Box::new(start.and_then(move |from| watch_stream(&client, source, from).for_each(move |e| -> Box<Future<Item = (), Error = io::Error>> {
+            // the checkpoint is the position in the source, which is the link's when resolved
+            let last = match stream_version(e.original_event_number()) {
+                Ok(version) => version,
+                Err(e) => return Box::new(future::err(e)),
+            };
+
+            let checkpointer = service.clone();
+            let checkpoint_stream = checkpoint_stream.clone();
+
+            Box::new(forward(service.clone(), sink.clone(), vec![e.event], max_attempts, poison_stream.clone())
+                .and_then(move |_| checkpointer.call(checkpoint_package(&checkpoint_stream, last)))
+                .and_then(written)
+                .map(|_| ()))
+        })))
+    }
+}
+
+/// The event following the checkpoint.
+fn resume_from(checkpoint: Option<StreamVersion>) -> io::Result<EventNumber> {
+    match checkpoint {
+        Some(version) => Ok(EventNumber::Exact(stream_version(i32::from(version) + 1)?)),
+        None => Ok(EventNumber::First),
+    }
+}
+
+/// Version of the event in the source: the link's when the event was reached through one, for
+/// example in a `$ce-` or `$et-` stream.
+fn original_version(event: &ResolvedIndexedEvent) -> io::Result<StreamVersion> {
+    stream_version(event.link.as_ref().unwrap_or(&event.event).event_number)
+}
+
+/// Offers the events to the sink in order.
+fn forward<S, F, R>(service: Rc<S>, sink: Rc<F>, events: Vec<EventRecord<'static>>, max_attempts: usize, poison_stream: Option<String>) -> Box<Future<Item = (), Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static,
+          F: Fn(&EventRecord<'static>) -> R + 'static,
+          R: Future<Item = (), Error = io::Error> + 'static
+{
+    Box::new(future::loop_fn(events.into_iter(), move |mut events: vec::IntoIter<EventRecord<'static>>| -> Box<Future<Item = Loop<(), vec::IntoIter<EventRecord<'static>>>, Error = io::Error>> {
+        let event = match events.next() {
+            Some(event) => Rc::new(event),
+            None => return Box::new(future::ok(Loop::Break(()))),
+        };
+
+        let service = service.clone();
+        let poison_stream = poison_stream.clone();
+
+        Box::new(deliver(sink.clone(), event.clone(), max_attempts).or_else(move |e| -> Box<Future<Item = (), Error = io::Error>> {
+            let poison_stream = match poison_stream {
+                Some(poison_stream) => poison_stream,
+                None => return Box::new(future::err(e)),
+            };
+
+            warn!("moving event {}@{} to {} after failing to forward it: {}", event.event_number, event.event_stream_id, poison_stream, e);
+            Box::new(service.call(poison_package(&poison_stream, &event)).and_then(written).map(|_| ()))
+        }).map(move |_| Loop::Continue(events)))
+    }))
+}
+
+/// Offers the event to the sink until it acknowledges the event or the attempts run out.
+fn deliver<F, R>(sink: Rc<F>, event: Rc<EventRecord<'static>>, max_attempts: usize) -> Box<Future<Item = (), Error = io::Error>>
+    where F: Fn(&EventRecord<'static>) -> R + 'static,
+          R: Future<Item = (), Error = io::Error> + 'static
+{
+    Box::new(future::loop_fn(1, move |attempt| {
+        let event = event.clone();
+
+        (*sink)(&event).then(move |res| match res {
+            Ok(()) => Ok(Loop::Break(())),
+            Err(ref e) if attempt < max_attempts => {
+                debug!("forwarding event {}@{} failed, attempt {}: {}", event.event_number, event.event_stream_id, attempt, e);
+                Ok(Loop::Continue(attempt + 1))
+            },
+            Err(e) => Err(e),
+        })
+    }))
+}
+
+fn read_checkpoint<S>(service: &S, checkpoint_stream: &str) -> Box<Future<Item = Option<StreamVersion>, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error>,
+          S::Future: 'static
+{
+    let package = Builder::read_stream_events()
+        .direction(ReadDirection::Backward)
+        .stream_id(checkpoint_stream.to_owned())
+        .from_event_number(EventNumber::Last)
+        .max_count(1)
+        .resolve_link_tos(false)
+        .build_package(None, None);
+
+    Box::new(service.call(package).and_then(|response| {
+        let page = match read_page(response)? {
+            Some(page) => page,
+            None => return Ok(None),
+        };
+
+        match page.events.first() {
+            Some(event) => checkpoint_position(&event.event).map(Some),
+            None => Ok(None),
+        }
+    }))
+}
+
+fn checkpoint_position(record: &EventRecord) -> io::Result<StreamVersion> {
+    use std::convert::TryFrom;
+
+    match json_u32(&record.data, "position") {
+        Some(position) => StreamVersion::try_from(position).map_err(|e| e.into()),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "Checkpoint without a position")),
+    }
+}
+
+fn checkpoint_package(checkpoint_stream: &str, position: StreamVersion) -> Package {
+    Builder::write_events()
+        .stream_id(checkpoint_stream.to_owned())
+        .expected_version(ExpectedVersion::Any)
+        .new_event()
+            .event_id(Uuid::new_v4())
+            .event_type("checkpoint")
+            .data(format!("{{\"position\":{}}}", u32::from(position)))
+            .data_content_type(ContentType::Json)
+        .done()
+        .build_package(None, None)
+}
+
+fn poison_package(poison_stream: &str, event: &EventRecord) -> Package {
+    let mut builder = Builder::write_events();
+    builder.stream_id(poison_stream.to_owned())
+        .expected_version(ExpectedVersion::Any);
+
+    let new_event = builder.new_event()
+        .event_id(Uuid::new_v4())
+        .event_type(event.event_type.to_string())
+        .data(event.data.to_vec())
+        .data_content_type(content_type(event.data_content_type));
+
+    let new_event = match event.metadata {
+        Some(ref metadata) => new_event.metadata(metadata.to_vec()).metadata_content_type(content_type(event.metadata_content_type)),
+        None => new_event,
+    };
+
+    new_event.done();
+    builder.build_package(None, None)
+}
+
+fn content_type(value: i32) -> ContentType {
+    if value == i32::from(ContentType::Json) {
+        ContentType::Json
+    } else {
+        ContentType::Bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::convert::TryFrom;
+    use std::io;
+    use std::rc::Rc;
+    use futures::{future, Future};
+    use raw::RawMessage;
+    use raw::client_messages::ResolvedIndexedEvent;
+    use {EventRecord, StreamVersion};
+    use super::{deliver, checkpoint_package, checkpoint_position, original_version};
+
+    #[test]
+    fn delivery_is_retried() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let sink = Rc::new(move |_: &EventRecord<'static>| {
+            counter.set(counter.get() + 1);
+            if counter.get() < 3 {
+                future::err(io::Error::new(io::ErrorKind::Other, "unavailable"))
+            } else {
+                future::ok(())
+            }
+        });
+
+        let event = Rc::new(EventRecord::default());
+
+        assert!(deliver(sink.clone(), event.clone(), 2).wait().is_err());
+        assert_eq!(calls.get(), 2);
+
+        calls.set(0);
+        assert!(deliver(sink, event, 3).wait().is_ok());
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn checkpoint_roundtrip() {
+        let position = StreamVersion::try_from(41u32).unwrap();
+
+        let data = match checkpoint_package("relay-checkpoint", position).message {
+            RawMessage::WriteEvents(we) => we.events[0].data.to_vec(),
+            other => panic!("unexpected message: {:?}", other),
+        };
+
+        let record = EventRecord { data: data.into(), ..EventRecord::default() };
+        assert_eq!(checkpoint_position(&record).unwrap(), position);
+    }
+
+    #[test]
+    fn links_are_checkpointed_at_their_own_number() {
+        let event = ResolvedIndexedEvent {
+            event: EventRecord { event_stream_id: "orders-1".into(), event_number: 2, ..EventRecord::default() },
+            link: Some(EventRecord { event_stream_id: "$ce-orders".into(), event_number: 7, ..EventRecord::default() }),
+        };
+
+        assert_eq!(original_version(&event).unwrap(), StreamVersion::try_from(7u32).unwrap());
+
+        let event = ResolvedIndexedEvent { link: None, ..event };
+        assert_eq!(original_version(&event).unwrap(), StreamVersion::try_from(2u32).unwrap());
+    }
+}
@@ -0,0 +1,266 @@
+//! Copying the events of a stream to another through a transformation, for migrations rewriting
+//! streams.
+
+use std::io;
+use std::rc::Rc;
+
+use futures::{future, stream, Future, Stream};
+use tokio_service::Service;
+use uuid::Uuid;
+
+use package::Package;
+use {Builder, EventNumber, EventRecord, ExpectedVersion, ReadDirection, StreamVersion};
+use response::{read_page, written};
+use super::{EventData, stream_version};
+
+/// Progress of a replay after a batch of events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayProgress {
+    /// Number of the last source event of the batch, continue after it with `Replay::from`
+    pub last: StreamVersion,
+    /// Events read from the source so far
+    pub read: u64,
+    /// Events written to the target so far; the rest were dropped by the transformation
+    pub written: u64,
+}
+
+/// Copies the events of a source stream to a target stream, passing every event through a
+/// transformation which can rename its type, change its data or metadata, or drop it.
+///
+/// The events are read and written in batches, and the ids of the copies are derived from the ids
+/// of the source events and the name of the target stream. A batch written again after a failure
+/// therefore has the same ids, which the server recognizes as a duplicate of the earlier write
+/// while it remembers it.
+pub struct Replay<S> {
+    service: Rc<S>,
+    source: String,
+    target: String,
+    batch_size: u8,
+    from: EventNumber,
+}
+
+/// State of `Replay::run` between the batches.
+#[derive(Clone, Copy)]
+struct Batches {
+    /// `None` after the last batch
+    from: Option<EventNumber>,
+    read: u64,
+    written: u64,
+}
+
+impl<S> Replay<S>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    /// Creates a replay copying the events of `source` to `target`.
+    pub fn new<A: Into<String>, B: Into<String>>(service: S, source: A, target: B) -> Self {
+        Replay {
+            service: Rc::new(service),
+            source: source.into(),
+            target: target.into(),
+            batch_size: 100,
+            from: EventNumber::First,
+        }
+    }
+
+    /// Sets the number of events read, and written, at a time. Panics if the size is zero.
+    ///
+    /// Defaults to 100.
+    pub fn batch_size(mut self, size: u8) -> Self {
+        assert!(size > 0);
+        self.batch_size = size;
+        self
+    }
+
+    /// Sets the first source event to copy, for example the one after the `last` event of the
+    /// progress reported before an interruption.
+    ///
+    /// Defaults to `EventNumber::First`.
+    pub fn from(mut self, from: EventNumber) -> Self {
+        self.from = from;
+        self
+    }
+
+    /// Copies the events until the end of the source stream, yielding the progress after every
+    /// batch. The stream ends without progress if the source stream does not exist or has no
+    /// events from `Replay::from` on, and fails with the first failed read or write.
+    ///
+    /// Links are passed to the transformation as they are and not resolved.
+    pub fn run<F>(&self, transform: F) -> Box<Stream<Item = ReplayProgress, Error = io::Error>>
+        where F: Fn(&EventRecord<'static>) -> Option<EventData> + 'static
+    {
+        let service = self.service.clone();
+        let source = self.source.clone();
+        let target = self.target.clone();
+        let batch_size = self.batch_size;
+        let transform = Rc::new(transform);
+        let start = Batches { from: Some(self.from), read: 0, written: 0 };
+
+        Box::new(stream::unfold(start, move |batches| {
+            let from = match batches.from {
+                Some(from) => from,
+                None => return None,
+            };
+
+            let package = Builder::read_stream_events()
+                .direction(ReadDirection::Forward)
+                .stream_id(source.clone())
+                .from_event_number(from)
+                .max_count(batch_size)
+                .resolve_link_tos(false)
+                .build_package(None, None);
+
+            let service = service.clone();
+            let target = target.clone();
+            let transform = transform.clone();
+            let done = Batches { from: None, ..batches };
+
+            Some(service.clone().call(package).and_then(move |response| -> Box<Future<Item = (Option<ReplayProgress>, Batches), Error = io::Error>> {
+                let page = match read_page(response) {
+                    Ok(Some(page)) => page,
+                    Ok(None) => return Box::new(future::ok((None, done))),
+                    Err(e) => return Box::new(future::err(e)),
+                };
+
+                let last = match page.events.last().map(|event| stream_version(event.event.event_number)) {
+                    Some(Ok(version)) => version,
+                    Some(Err(e)) => return Box::new(future::err(e)),
+                    None => return Box::new(future::ok((None, done))),
+                };
+
+                let next = match page.next_page {
+                    Some(next) if !page.end_of_stream => Some(next),
+                    _ => None,
+                };
+
+                let copies = page.events.iter()
+                    .filter_map(|event| copy(&target, &event.event, &*transform))
+                    .collect::<Vec<_>>();
+
+                let progress = ReplayProgress {
+                    last: last,
+                    read: batches.read + page.events.len() as u64,
+                    written: batches.written + copies.len() as u64,
+                };
+
+                let batches = Batches { from: next, read: progress.read, written: progress.written };
+
+                if copies.is_empty() {
+                    return Box::new(future::ok((Some(progress), batches)));
+                }
+
+                Box::new(service.call(batch_package(&target, copies))
+                    .and_then(written)
+                    .map(move |_| (Some(progress), batches)))
+            }))
+        }).filter_map(|progress| progress))
+    }
+}
+
+/// Copies the events of `source` to `target` through `transform` with the defaults of `Replay`.
+pub fn replay_stream<S, A, B, F>(service: S, source: A, target: B, transform: F) -> Box<Stream<Item = ReplayProgress, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static,
+          A: Into<String>,
+          B: Into<String>,
+          F: Fn(&EventRecord<'static>) -> Option<EventData> + 'static
+{
+    Replay::new(service, source, target).run(transform)
+}
+
+fn copy<F>(target: &str, event: &EventRecord<'static>, transform: &F) -> Option<EventData>
+    where F: Fn(&EventRecord<'static>) -> Option<EventData>
+{
+    transform(event).map(|data| EventData { event_id: copy_id(target, &event.event_id), ..data })
+}
+
+/// Id of the copy in `target` of the event with the id, the same on every run. Two 64-bit FNV-1a
+/// hashes of the two make up the id, marked as a name based uuid.
+fn copy_id(target: &str, source_id: &[u8]) -> Uuid {
+    let hash = |seed: u64| target.bytes().chain(Some(0)).chain(source_id.iter().cloned())
+        .fold(seed, |hash, b| (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3));
+
+    let (high, low) = (hash(0xcbf2_9ce4_8422_2325), hash(0x8422_2325_cbf2_9ce4));
+
+    let mut bytes = [0u8; 16];
+    for i in 0..8 {
+        bytes[i] = (high >> (56 - 8 * i)) as u8;
+        bytes[8 + i] = (low >> (56 - 8 * i)) as u8;
+    }
+
+    // version 5 and the variant of RFC 4122
+    bytes[6] = (bytes[6] & 0x0f) | 0x50;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    Uuid::from_bytes(&bytes).expect("Uuid of 16 bytes")
+}
+
+fn batch_package(target: &str, events: Vec<EventData>) -> Package {
+    let mut builder = Builder::write_events();
+    builder.stream_id(target.to_owned())
+        .expected_version(ExpectedVersion::Any);
+
+    for data in events {
+        let new_event = builder.new_event()
+            .event_id(data.event_id)
+            .event_type(data.event_type)
+            .data(data.data)
+            .data_content_type(data.data_content_type);
+
+        let new_event = match data.metadata {
+            Some(metadata) => new_event.metadata(metadata).metadata_content_type(data.metadata_content_type),
+            None => new_event,
+        };
+
+        new_event.done();
+    }
+
+    builder.build_package(None, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+    use raw::RawMessage;
+    use eventsourcing::EventData;
+    use EventRecord;
+    use super::{copy, copy_id, batch_package};
+
+    fn rename(event: &EventRecord<'static>) -> Option<EventData> {
+        match &*event.event_type {
+            "obsolete" => None,
+            _ => Some(EventData::json(format!("{}_v2", event.event_type), event.data.to_vec())),
+        }
+    }
+
+    #[test]
+    fn copies_have_stable_ids() {
+        let source_id = Uuid::new_v4();
+        let event = EventRecord { event_id: source_id.as_bytes().to_vec().into(), event_type: "created".into(), ..EventRecord::default() };
+
+        let first = copy("orders-v2", &event, &rename).unwrap();
+        let second = copy("orders-v2", &event, &rename).unwrap();
+        assert_eq!(first.event_id, second.event_id);
+        assert_eq!(first.event_type, "created_v2");
+
+        assert!(copy_id("orders-v3", source_id.as_bytes()) != first.event_id);
+        assert!(copy_id("orders-v2", Uuid::new_v4().as_bytes()) != first.event_id);
+
+        let obsolete = EventRecord { event_type: "obsolete".into(), ..event };
+        assert_eq!(copy("orders-v2", &obsolete, &rename), None);
+    }
+
+    #[test]
+    fn batches_are_written_at_once() {
+        let events = vec![EventData::json("a", "{}"), EventData::bytes("b", vec![1, 2])];
+        let ids = events.iter().map(|e| e.event_id.as_bytes().to_vec()).collect::<Vec<_>>();
+
+        match batch_package("orders-v2", events).message {
+            RawMessage::WriteEvents(we) => {
+                assert_eq!(we.event_stream_id, "orders-v2");
+                assert_eq!(we.events.iter().map(|e| e.event_id.to_vec()).collect::<Vec<_>>(), ids);
+            },
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+}
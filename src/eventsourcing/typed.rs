@@ -0,0 +1,188 @@
+//! Events with deserialized data and metadata.
+
+use std::io;
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+use adapted::ResolvedEvent;
+use {EventRecord, LogPosition, ResolvedIndexedEvent, StreamVersion};
+use super::PayloadCodec;
+
+/// Deserialized data or metadata of an event, or the raw bytes if deserializing failed. Failing
+/// to deserialize a single payload does not fail reading the whole page of events, so that
+/// consumers can decide to skip or park the event.
+#[derive(Debug)]
+pub enum Payload<T> {
+    /// Successfully deserialized payload
+    Decoded(T),
+    /// Payload which failed to deserialize, with the error
+    Raw(Vec<u8>, io::Error),
+}
+
+impl<T> Payload<T> {
+    fn decode<C: PayloadCodec<T>>(codec: &C, bytes: &[u8]) -> Self {
+        match codec.decode(bytes) {
+            Ok(value) => Payload::Decoded(value),
+            Err(e) => Payload::Raw(bytes.to_vec(), e),
+        }
+    }
+
+    /// Returns the deserialized payload, if deserializing succeeded.
+    pub fn decoded(&self) -> Option<&T> {
+        match *self {
+            Payload::Decoded(ref value) => Some(value),
+            Payload::Raw(..) => None,
+        }
+    }
+
+    /// Returns the deserialized payload or the error deserializing it.
+    pub fn into_result(self) -> io::Result<T> {
+        match self {
+            Payload::Decoded(value) => Ok(value),
+            Payload::Raw(_, e) => Err(e),
+        }
+    }
+}
+
+/// Event read from a stream with the data deserialized as `T` and the metadata as `M`.
+#[derive(Debug)]
+pub struct TypedEvent<T, M> {
+    /// The stream the event was written to
+    pub stream_id: String,
+    /// Number of the event in the stream
+    pub event_number: StreamVersion,
+    /// Identifier of the event
+    pub event_id: Uuid,
+    /// Type of the event
+    pub event_type: String,
+    /// Position of the commit in the transaction log, only known for events read from `$all`
+    pub commit_position: Option<LogPosition>,
+    /// Position of the event in the transaction log, only known for events read from `$all`
+    pub prepare_position: Option<LogPosition>,
+    /// When the event was written, if known
+    pub created: Option<SystemTime>,
+    /// The data of the event
+    pub data: Payload<T>,
+    /// The metadata of the event, if there was any
+    pub metadata: Option<Payload<M>>,
+}
+
+impl<T, M> TypedEvent<T, M> {
+    /// Deserializes the data of the event with `data` and the metadata with `metadata`. Fails only
+    /// if the event number or the event id of the event are invalid; deserialization failures are
+    /// returned as `Payload::Raw`.
+    pub fn from_record<D, MC>(record: &EventRecord, data: &D, metadata: &MC) -> io::Result<Self>
+        where D: PayloadCodec<T>,
+              MC: PayloadCodec<M>
+    {
+        let event_number = StreamVersion::try_from(record.event_number).map_err(|e| -> io::Error { e.into() })?;
+        let event_id = Uuid::from_bytes(&record.event_id)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Event id is not an UUID"))?;
+
+        Ok(TypedEvent {
+            stream_id: record.event_stream_id.to_string(),
+            event_number: event_number,
+            event_id: event_id,
+            event_type: record.event_type.to_string(),
+            commit_position: None,
+            prepare_position: None,
+            created: record.created_epoch.and_then(epoch_millis),
+            data: Payload::decode(data, &record.data),
+            metadata: record.metadata.as_ref().map(|bytes| Payload::decode(metadata, bytes)),
+        })
+    }
+
+    /// Deserializes an event read from a stream like `from_record`. For resolved links this is
+    /// the event linked to.
+    pub fn from_indexed<D, MC>(event: &ResolvedIndexedEvent, data: &D, metadata: &MC) -> io::Result<Self>
+        where D: PayloadCodec<T>,
+              MC: PayloadCodec<M>
+    {
+        Self::from_record(&event.event, data, metadata)
+    }
+
+    /// Deserializes an event read from `$all` like `from_record`, including the positions.
+    pub fn from_resolved<D, MC>(event: &ResolvedEvent, data: &D, metadata: &MC) -> io::Result<Self>
+        where D: PayloadCodec<T>,
+              MC: PayloadCodec<M>
+    {
+        let mut typed = Self::from_record(&event.event, data, metadata)?;
+        typed.commit_position = Some(event.commit_position);
+        typed.prepare_position = Some(event.prepare_position);
+        Ok(typed)
+    }
+}
+
+/// Converts milliseconds since the Unix epoch, `None` for times before the epoch.
+fn epoch_millis(millis: i64) -> Option<SystemTime> {
+    if millis < 0 {
+        return None;
+    }
+
+    let millis = millis as u64;
+    Some(UNIX_EPOCH + Duration::new(millis / 1000, (millis % 1000) as u32 * 1_000_000))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::str;
+    use std::borrow::Cow;
+    use std::time::{Duration, UNIX_EPOCH};
+    use uuid::Uuid;
+    use {ContentType, EventRecord};
+    use eventsourcing::PayloadCodec;
+    use super::{TypedEvent, Payload};
+
+    struct Number;
+
+    impl PayloadCodec<u32> for Number {
+        fn content_type(&self) -> ContentType {
+            ContentType::Bytes
+        }
+
+        fn encode(&self, value: &u32) -> io::Result<Vec<u8>> {
+            Ok(value.to_string().into_bytes())
+        }
+
+        fn decode(&self, bytes: &[u8]) -> io::Result<u32> {
+            str::from_utf8(bytes).ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a number"))
+        }
+    }
+
+    #[test]
+    fn falls_back_to_raw_bytes() {
+        let record = EventRecord {
+            event_stream_id: "counter-1".into(),
+            event_number: 7,
+            event_id: Cow::Owned(Uuid::new_v4().as_bytes().to_vec()),
+            event_type: "counted".into(),
+            data: (&b"42"[..]).into(),
+            metadata: Some((&b"x"[..]).into()),
+            created_epoch: Some(1_500),
+            ..EventRecord::default()
+        };
+
+        let typed = TypedEvent::<u32, u32>::from_record(&record, &Number, &Number).unwrap();
+
+        assert_eq!(typed.stream_id, "counter-1");
+        assert_eq!(u32::from(typed.event_number), 7);
+        assert_eq!(typed.created, Some(UNIX_EPOCH + Duration::from_millis(1_500)));
+        assert_eq!(typed.data.decoded(), Some(&42));
+
+        match typed.metadata {
+            Some(Payload::Raw(ref bytes, _)) => assert_eq!(bytes, b"x"),
+            ref other => panic!("unexpected metadata: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_event_id_fails() {
+        let record = EventRecord { event_id: (&b"short"[..]).into(), ..EventRecord::default() };
+        assert!(TypedEvent::<u32, u32>::from_record(&record, &Number, &Number).is_err());
+    }
+}
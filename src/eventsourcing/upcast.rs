@@ -0,0 +1,145 @@
+//! Migrating old versions of events on read.
+
+use std::io;
+use std::fmt;
+use std::collections::HashMap;
+
+use EventRecord;
+use json::json_u32;
+
+/// Transformation from one schema version of an event to the next.
+type Upcast = Box<Fn(EventRecord<'static>) -> io::Result<EventRecord<'static>>>;
+
+/// Registry of transformations applied to events read from a stream before they are decoded, so
+/// that old versions of events can be read as the current version without rewriting the streams.
+///
+/// The schema version of an event is read from its JSON metadata, see `schema_version`. Each
+/// registered transformation takes an event of a single type and version to the next version,
+/// and the transformations are applied one after another until the event has no registered
+/// transformation left.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::EventRecord;
+/// use eventstore_tcp::eventsourcing::Upcasters;
+///
+/// let upcasters = Upcasters::new()
+///     .register("order_placed", 1, |mut record: EventRecord<'static>| {
+///         record.data = br#"{"currency":"EUR"}"#.to_vec().into();
+///         Ok(record)
+///     });
+///
+/// let upcasted = upcasters.upcast(EventRecord {
+///     event_type: "order_placed".into(),
+///     ..EventRecord::default()
+/// }).unwrap();
+///
+/// assert_eq!(&upcasted.data[..], &br#"{"currency":"EUR"}"#[..]);
+/// ```
+#[derive(Default)]
+pub struct Upcasters {
+    steps: HashMap<(String, u32), Upcast>,
+}
+
+impl fmt::Debug for Upcasters {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Upcasters {{ steps: {:?} }}", self.steps.keys().collect::<Vec<_>>())
+    }
+}
+
+impl Upcasters {
+    /// Creates a registry without any transformations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the transformation of events of `event_type` at schema `version` to the next
+    /// version. The transformation may also change the event type. Replaces any transformation
+    /// registered earlier for the same type and version.
+    pub fn register<S, F>(mut self, event_type: S, version: u32, upcast: F) -> Self
+        where S: Into<String>,
+              F: Fn(EventRecord<'static>) -> io::Result<EventRecord<'static>> + 'static
+    {
+        self.steps.insert((event_type.into(), version), Box::new(upcast));
+        self
+    }
+
+    /// Applies the registered transformations to the event until none is left for its type and
+    /// version. Events without any registered transformation are returned as is.
+    pub fn upcast(&self, record: EventRecord<'static>) -> io::Result<EventRecord<'static>> {
+        let mut version = schema_version(&record);
+        let mut record = record;
+
+        // every step moves to a higher version, so each is applied at most once
+        for _ in 0..self.steps.len() {
+            let next = match self.steps.get(&(record.event_type.to_string(), version)) {
+                Some(upcast) => (**upcast)(record)?,
+                None => break,
+            };
+
+            record = next;
+            version += 1;
+        }
+
+        Ok(record)
+    }
+}
+
+/// Returns the schema version of the event from the number in the `"schemaVersion"` field of its
+/// JSON metadata, or 1 if there is no such field.
+pub fn schema_version(record: &EventRecord) -> u32 {
+    record.metadata.as_ref()
+        .and_then(|metadata| json_u32(metadata, "schemaVersion"))
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use EventRecord;
+    use super::{Upcasters, schema_version};
+
+    fn record(event_type: &'static str, metadata: Option<&'static [u8]>) -> EventRecord<'static> {
+        EventRecord {
+            event_type: event_type.into(),
+            metadata: metadata.map(|m| m.into()),
+            ..EventRecord::default()
+        }
+    }
+
+    #[test]
+    fn versions_from_metadata() {
+        assert_eq!(schema_version(&record("a", None)), 1);
+        assert_eq!(schema_version(&record("a", Some(br#"{"schemaVersion": 3}"#))), 3);
+        assert_eq!(schema_version(&record("a", Some(br#"{"other":2}"#))), 1);
+    }
+
+    fn rename(mut record: EventRecord<'static>) -> io::Result<EventRecord<'static>> {
+        record.event_type = "renamed".into();
+        Ok(record)
+    }
+
+    fn append(mut record: EventRecord<'static>) -> io::Result<EventRecord<'static>> {
+        let mut data = record.data.into_owned();
+        data.push(b'!');
+        record.data = data.into();
+        Ok(record)
+    }
+
+    #[test]
+    fn steps_are_chained() {
+        let upcasters = Upcasters::new()
+            .register("original", 1, rename)
+            .register("renamed", 2, append)
+            .register("renamed", 3, append);
+
+        let upcasted = upcasters.upcast(record("original", None)).unwrap();
+        assert_eq!(upcasted.event_type, "renamed");
+        assert_eq!(&upcasted.data[..], b"!!");
+
+        // already at the latest version
+        let current = record("renamed", Some(br#"{"schemaVersion":4}"#));
+        assert_eq!(upcasters.upcast(current.clone()).unwrap(), current);
+    }
+}
@@ -2,7 +2,8 @@ use StreamVersion;
 
 /// `ExpectedVersion` represents the different modes of optimistic locking when writing to a stream
 /// using `WriteEventsBuilder`.
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum ExpectedVersion {
     /// No optimistic locking
     Any,
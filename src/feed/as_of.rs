@@ -0,0 +1,121 @@
+use std::io;
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::Stream;
+use tokio_service::Service;
+
+use package::Package;
+use {EventNumber, LogPosition, StreamVersion};
+use super::StreamEvent;
+use super::poll::{read_forward, read_all_forward};
+
+/// The last point of a stream included by `read_stream_as_of`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AsOf {
+    /// Up to and including the event with the number
+    Event(StreamVersion),
+    /// Up to and including the event at the commit and prepare positions in `$all`
+    Position(LogPosition, LogPosition),
+    /// Up to and including the events created at the time, according to the `created_epoch` of
+    /// the events
+    Time(SystemTime),
+}
+
+/// Reads the events of the stream from its first event up to the point `as_of`, for rebuilding a
+/// state as it was at that point, for example when auditing or debugging. Links are resolved, and
+/// the bounds apply to the events of the stream itself, the links for a stream of links.
+///
+/// The stream is read forwards and the feed ends with the last event within the bound, so a
+/// `Time` bound assumes the creation times grow with the event numbers; they may not if the clocks
+/// of the nodes of a cluster differ. The events of streams have no positions in `$all`, so for
+/// `Position` the whole of `$all` is read from its start instead, keeping the events of the
+/// stream. Events without the creation time or position needed for the bound fail the feed with
+/// `InvalidData`.
+pub fn read_stream_as_of<S, I>(service: S, stream_id: I, as_of: AsOf) -> Box<Stream<Item = StreamEvent, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static,
+          I: Into<Cow<'static, str>>
+{
+    let stream_id = stream_id.into();
+
+    match as_of {
+        AsOf::Position(..) => {
+            let all = read_all_forward(service, LogPosition::First, LogPosition::First);
+            Box::new(bounded(all, as_of).filter(move |e| e.original().event_stream_id == stream_id))
+        },
+        _ => bounded(read_forward(service, stream_id, EventNumber::First), as_of),
+    }
+}
+
+/// Ends the events at the first one outside the bound.
+fn bounded(events: Box<Stream<Item = StreamEvent, Error = io::Error>>, as_of: AsOf) -> Box<Stream<Item = StreamEvent, Error = io::Error>> {
+    Box::new(events
+        .and_then(move |e| within(&e, as_of).map(|within| (e, within)))
+        .take_while(|&(_, within)| Ok(within))
+        .map(|(e, _)| e))
+}
+
+fn within(e: &StreamEvent, as_of: AsOf) -> io::Result<bool> {
+    match as_of {
+        AsOf::Event(last) => Ok(e.original_event_number() <= i32::from(last)),
+        AsOf::Position(commit, prepare) => match (e.commit_position, e.prepare_position) {
+            (Some(c), Some(p)) => Ok((c, p) <= (commit, prepare)),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Event without a position in $all")),
+        },
+        AsOf::Time(time) => {
+            let original = e.original();
+
+            match original.created_epoch {
+                Some(millis) if millis >= 0 => Ok(UNIX_EPOCH + Duration::from_millis(millis as u64) <= time),
+                _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("Event {}@{} without a creation time", original.event_number, original.event_stream_id))),
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use futures::{Future, Stream};
+    use raw::client_messages::EventRecord;
+    use server::{MemoryStore, StoreService, StreamStore};
+    use feed::StreamEvent;
+    use {ExpectedVersion, LogPosition, StreamVersion};
+    use fixtures::json_event;
+    use super::{AsOf, read_stream_as_of, within};
+
+    #[test]
+    fn events_up_to_the_bound() {
+        let mut store = MemoryStore::new();
+        store.append("orders-1", ExpectedVersion::Any, vec![json_event("created", "{}"), json_event("paid", "{}"), json_event("shipped", "{}")]).unwrap();
+
+        let service = StoreService::new(store);
+        let read = |as_of| read_stream_as_of(service.clone(), "orders-1", as_of)
+            .map(|e| e.event.event_type.into_owned())
+            .collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(read(AsOf::Event(StreamVersion::try_from(1u32).unwrap())), vec!["created", "paid"]);
+        assert_eq!(read(AsOf::Time(SystemTime::now() + Duration::from_secs(60))), vec!["created", "paid", "shipped"]);
+        assert_eq!(read(AsOf::Time(UNIX_EPOCH)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn positions_and_missing_times() {
+        let at = |position: u64| StreamEvent {
+            event: EventRecord::default(),
+            link: None,
+            commit_position: Some(LogPosition::Exact(position)),
+            prepare_position: Some(LogPosition::Exact(position)),
+        };
+
+        let bound = AsOf::Position(LogPosition::Exact(100), LogPosition::Exact(100));
+        assert!(within(&at(100), bound).unwrap());
+        assert!(!within(&at(101), bound).unwrap());
+
+        assert!(within(&at(100), AsOf::Time(UNIX_EPOCH)).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::io;
+use std::time::{Duration, Instant};
+use std::collections::{HashSet, VecDeque};
+
+use futures::Stream;
+
+use super::StreamEvent;
+
+/// Window of the ids of the recent events of a feed, dropping the events seen again within it.
+/// Events are delivered again for example when a subscription is started again from an earlier
+/// position, or by persistent subscriptions retrying events which were not acknowledged in time.
+///
+/// The ids are those of the original events, see `StreamEvent::original`.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate futures;
+/// extern crate eventstore_tcp;
+///
+/// use std::time::Duration;
+/// use futures::{stream, Future, Stream};
+/// use eventstore_tcp::feed::{DedupWindow, StreamEvent};
+///
+/// # fn main() {
+/// let events: Vec<StreamEvent> = vec![];
+///
+/// let unique = DedupWindow::new(1000)
+///     .max_age(Duration::from_secs(60))
+///     .apply(stream::iter_ok(events));
+///
+/// assert!(unique.collect().wait().unwrap().is_empty());
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DedupWindow {
+    size: usize,
+    max_age: Option<Duration>,
+}
+
+impl DedupWindow {
+    /// Creates a window of the ids of the last `size` events. Panics if the size is zero.
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0);
+        DedupWindow {
+            size: size,
+            max_age: None,
+        }
+    }
+
+    /// Forgets the ids seen longer than `max_age` ago, even if the window is not full.
+    ///
+    /// Defaults to keeping the ids until newer ones push them out of the window.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Drops the events of the feed with an id in the window.
+    pub fn apply<S>(self, events: S) -> Box<Stream<Item = StreamEvent, Error = io::Error>>
+        where S: Stream<Item = StreamEvent, Error = io::Error> + 'static
+    {
+        let mut recent = Recent::new(self);
+        Box::new(events.filter(move |e| !recent.seen(&e.original().event_id, Instant::now())))
+    }
+}
+
+/// The ids in a `DedupWindow`, oldest first.
+struct Recent {
+    window: DedupWindow,
+    order: VecDeque<(Vec<u8>, Instant)>,
+    ids: HashSet<Vec<u8>>,
+}
+
+impl Recent {
+    fn new(window: DedupWindow) -> Self {
+        Recent {
+            window: window,
+            order: VecDeque::new(),
+            ids: HashSet::new(),
+        }
+    }
+
+    /// Was the id seen within the window, remembering it if not.
+    fn seen(&mut self, id: &[u8], now: Instant) -> bool {
+        if let Some(max_age) = self.window.max_age {
+            while self.order.front().map(|&(_, at)| now.duration_since(at) > max_age).unwrap_or(false) {
+                let (id, _) = self.order.pop_front().expect("Front was just checked");
+                self.ids.remove(&id);
+            }
+        }
+
+        if self.ids.contains(id) {
+            return true;
+        }
+
+        if self.order.len() == self.window.size {
+            let (oldest, _) = self.order.pop_front().expect("Window is full");
+            self.ids.remove(&oldest);
+        }
+
+        self.order.push_back((id.to_vec(), now));
+        self.ids.insert(id.to_vec());
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::{DedupWindow, Recent};
+
+    #[test]
+    fn ids_leave_the_window() {
+        let start = Instant::now();
+        let mut recent = Recent::new(DedupWindow::new(2).max_age(Duration::from_secs(10)));
+
+        assert!(!recent.seen(b"a", start));
+        assert!(!recent.seen(b"b", start));
+        assert!(recent.seen(b"a", start));
+
+        // pushes out the oldest
+        assert!(!recent.seen(b"c", start));
+        assert!(!recent.seen(b"a", start));
+
+        assert!(recent.seen(b"c", start + Duration::from_secs(5)));
+        assert!(!recent.seen(b"c", start + Duration::from_secs(11)));
+    }
+}
@@ -0,0 +1,92 @@
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::{Future, Stream};
+use tokio_service::Service;
+
+use package::Package;
+use adapted::{AdaptedMessage, ReadEventError};
+use json::json_u32;
+use response::{adapt, unexpected, read_event_failed};
+use {Builder, EventNumber};
+use super::StreamEvent;
+
+/// Drops the events created longer than `max_age` ago, using the `created_epoch` of the events.
+/// Events without a creation time are kept.
+///
+/// The server stops returning the events of a stream with `$maxAge` metadata once they expire,
+/// but events read or pushed before that, and events in links to them, are not filtered; nor are
+/// the events read while the clocks of the client and the server differ. Use with
+/// `stream_max_age` to apply the metadata of the stream to a feed.
+pub fn without_expired<S>(events: S, max_age: Duration) -> Box<Stream<Item = StreamEvent, Error = io::Error>>
+    where S: Stream<Item = StreamEvent, Error = io::Error> + 'static
+{
+    Box::new(events.filter(move |e| !is_expired(e, max_age, SystemTime::now())))
+}
+
+fn is_expired(e: &StreamEvent, max_age: Duration, now: SystemTime) -> bool {
+    let created = match e.event.created_epoch {
+        Some(millis) if millis >= 0 => UNIX_EPOCH + Duration::from_millis(millis as u64),
+        _ => return false,
+    };
+
+    match now.duration_since(created) {
+        Ok(age) => age > max_age,
+        // created in the future of this clock
+        Err(_) => false,
+    }
+}
+
+/// Reads the `$maxAge` of the stream from its metadata stream, `None` if the stream has no
+/// metadata or the metadata has no `$maxAge`.
+pub fn stream_max_age<S>(service: &S, stream_id: &str) -> Box<Future<Item = Option<Duration>, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error>,
+          S::Future: 'static
+{
+    let package = Builder::read_event()
+        .stream_id(format!("$${}", stream_id))
+        .event_number(EventNumber::Last)
+        .resolve_link_tos(false)
+        .build_package(None, None);
+
+    Box::new(service.call(package).and_then(|response| {
+        match adapt(response)? {
+            AdaptedMessage::ReadEventCompleted(Ok(metadata)) => {
+                Ok(json_u32(&metadata.event.data, "$maxAge").map(|secs| Duration::from_secs(u64::from(secs))))
+            },
+            AdaptedMessage::ReadEventCompleted(Err(ReadEventError::NotFound)) |
+            AdaptedMessage::ReadEventCompleted(Err(ReadEventError::NoStream)) => Ok(None),
+            AdaptedMessage::ReadEventCompleted(Err(e)) => Err(read_event_failed(e)),
+            other => Err(unexpected(other)),
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+    use raw::client_messages::EventRecord;
+    use feed::StreamEvent;
+
+    fn created_at(secs: i64) -> StreamEvent {
+        StreamEvent {
+            event: EventRecord { created_epoch: Some(secs * 1000), ..Default::default() },
+            link: None,
+            commit_position: None,
+            prepare_position: None,
+        }
+    }
+
+    #[test]
+    fn events_older_than_max_age_are_expired() {
+        let now = UNIX_EPOCH + Duration::from_secs(1000);
+        let max_age = Duration::from_secs(60);
+
+        assert!(super::is_expired(&created_at(900), max_age, now));
+        assert!(!super::is_expired(&created_at(950), max_age, now));
+        assert!(!super::is_expired(&created_at(2000), max_age, now));
+
+        let unknown = StreamEvent { event: EventRecord::default(), ..created_at(0) };
+        assert!(!super::is_expired(&unknown, max_age, now));
+    }
+}
@@ -0,0 +1,164 @@
+use std::io;
+use std::borrow::Cow;
+
+use futures::{Async, Poll, Stream};
+
+use {EventStoreClient, EventNumber};
+use super::{StreamEvent, ResumeToken, watch_stream, subscribe_from};
+
+/// How `merge_streams` orders the events available from more than one stream at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeOrder {
+    /// Takes turns between the streams, so that a busy stream does not hold back the others
+    Interleaved,
+    /// Lowest commit position in `$all` first. The positions are known for the events pushed by
+    /// the server, and the events read while catching up without one come first in the order of
+    /// the streams.
+    CommitPosition,
+}
+
+/// Event of one of the streams merged with `merge_streams`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergedEvent {
+    /// Index of the stream in the merged streams
+    pub source: usize,
+    /// The event
+    pub event: StreamEvent,
+}
+
+impl MergedEvent {
+    /// The token continuing the stream of the event after it, for keeping a token per stream.
+    pub fn token(&self) -> Option<ResumeToken> {
+        ResumeToken::after(&self.event)
+    }
+}
+
+/// Watches every one of the streams from its event like `watch_stream`, as one feed. Within a
+/// stream the events keep their order; the events of different streams are ordered by `order`
+/// among the events available at the same moment, so an event appended to a quiet stream may
+/// follow events appended later to the others.
+///
+/// The feed fails when any of the streams fails, and ends when all of them have ended.
+pub fn merge_streams<S: Into<Cow<'static, str>>>(client: &EventStoreClient, streams: Vec<(S, EventNumber)>, order: MergeOrder) -> Box<Stream<Item = MergedEvent, Error = io::Error>> {
+    let sources = streams.into_iter()
+        .map(|(stream_id, from)| watch_stream(client, stream_id, from))
+        .collect();
+
+    Box::new(Merge::new(sources, order))
+}
+
+/// Continues the merged streams from a token per stream, see `merge_streams` and
+/// `subscribe_from`. The `source` of the events is the index of its token.
+pub fn merge_from(client: &EventStoreClient, tokens: &[ResumeToken], order: MergeOrder) -> Box<Stream<Item = MergedEvent, Error = io::Error>> {
+    let sources = tokens.iter()
+        .map(|token| subscribe_from(client, token))
+        .collect();
+
+    Box::new(Merge::new(sources, order))
+}
+
+struct Source {
+    events: Box<Stream<Item = StreamEvent, Error = io::Error>>,
+    /// The next event of the source, taken from the stream but not yet yielded
+    ready: Option<StreamEvent>,
+    done: bool,
+}
+
+struct Merge {
+    sources: Vec<Source>,
+    order: MergeOrder,
+    /// The source to take from first in `MergeOrder::Interleaved`
+    next: usize,
+}
+
+impl Merge {
+    fn new(sources: Vec<Box<Stream<Item = StreamEvent, Error = io::Error>>>, order: MergeOrder) -> Self {
+        Merge {
+            sources: sources.into_iter().map(|events| Source { events: events, ready: None, done: false }).collect(),
+            order: order,
+            next: 0,
+        }
+    }
+}
+
+impl Stream for Merge {
+    type Item = MergedEvent;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<MergedEvent>, io::Error> {
+        for source in &mut self.sources {
+            if source.ready.is_none() && !source.done {
+                match source.events.poll()? {
+                    Async::Ready(Some(e)) => source.ready = Some(e),
+                    Async::Ready(None) => source.done = true,
+                    Async::NotReady => {},
+                }
+            }
+        }
+
+        let count = self.sources.len();
+        let chosen = match self.order {
+            MergeOrder::Interleaved => {
+                let next = self.next;
+                let sources = &self.sources;
+                (0..count).map(|i| (next + i) % count).find(|&i| sources[i].ready.is_some())
+            },
+            MergeOrder::CommitPosition => {
+                self.sources.iter().enumerate()
+                    .filter_map(|(i, source)| source.ready.as_ref().map(|e| (e.commit_position, i)))
+                    .min()
+                    .map(|(_, i)| i)
+            },
+        };
+
+        match chosen {
+            Some(i) => {
+                self.next = (i + 1) % count;
+                let event = self.sources[i].ready.take().expect("Source was chosen for its event");
+                Ok(Async::Ready(Some(MergedEvent { source: i, event: event })))
+            },
+            None if self.sources.iter().all(|source| source.done) => Ok(Async::Ready(None)),
+            // the sources without an event have all been polled
+            None => Ok(Async::NotReady),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use futures::{stream, Future, Stream};
+    use raw::client_messages::EventRecord;
+    use feed::StreamEvent;
+    use LogPosition;
+    use super::{Merge, MergeOrder};
+
+    fn events(stream_id: &'static str, positions: Vec<Option<u64>>) -> Box<Stream<Item = StreamEvent, Error = io::Error>> {
+        let events = positions.into_iter().enumerate().map(|(n, position)| StreamEvent {
+            event: EventRecord { event_stream_id: stream_id.into(), event_number: n as i32, ..Default::default() },
+            link: None,
+            commit_position: position.map(LogPosition::Exact),
+            prepare_position: position.map(LogPosition::Exact),
+        }).collect::<Vec<_>>();
+
+        Box::new(stream::iter_ok(events))
+    }
+
+    fn merged(order: MergeOrder, sources: Vec<Box<Stream<Item = StreamEvent, Error = io::Error>>>) -> Vec<(usize, i32)> {
+        Merge::new(sources, order)
+            .map(|e| (e.source, e.event.event_number))
+            .collect().wait().unwrap()
+    }
+
+    #[test]
+    fn interleaved_takes_turns() {
+        let sources = vec![events("a", vec![None, None, None]), events("b", vec![None])];
+        assert_eq!(merged(MergeOrder::Interleaved, sources), vec![(0, 0), (1, 0), (0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn lowest_commit_position_first() {
+        let sources = vec![events("a", vec![Some(10), Some(30)]), events("b", vec![None, Some(20)])];
+        assert_eq!(merged(MergeOrder::CommitPosition, sources), vec![(1, 0), (0, 0), (1, 1), (0, 1)]);
+    }
+}
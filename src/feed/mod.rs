@@ -0,0 +1,149 @@
+//! Change feeds of the events appearing in streams, as `futures::Stream`s of `StreamEvent`.
+//!
+//! The feeds reading streams are built on the `Service` of the client so that they work over
+//! `EventStoreClient`, `ClusterClient` and the wrappers of them alike. Subscriptions need the
+//! streaming requests of `EventStoreClient::call_streaming`.
+
+use std::io;
+use std::fmt;
+use std::error::Error;
+use std::borrow::Cow;
+
+use futures::Stream;
+
+use adapted;
+use raw::client_messages::{EventRecord, ResolvedIndexedEvent, ResolvedEvent};
+use raw::client_messages::mod_SubscriptionDropped::SubscriptionDropReason;
+use {EventStoreClient, LogPosition};
+use operation;
+
+mod poll;
+pub use self::poll::{poll_stream, read_forward, read_all_forward, read_all_backward};
+
+mod watch;
+pub use self::watch::watch_stream;
+
+mod max_age;
+pub use self::max_age::{without_expired, stream_max_age};
+
+mod dedup;
+pub use self::dedup::DedupWindow;
+
+mod resume;
+pub use self::resume::{ResumeToken, ResumePosition, subscribe_from};
+
+mod merge;
+pub use self::merge::{merge_streams, merge_from, MergeOrder, MergedEvent};
+
+mod stats;
+pub use self::stats::{FeedExt, WithStats, FeedStats, FeedSnapshot};
+
+mod as_of;
+pub use self::as_of::{AsOf, read_stream_as_of};
+
+/// Event delivered by a change feed, the same for the events read from a stream and the events
+/// pushed by the server to a subscription.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamEvent {
+    /// The event, resolved from the link if there is one
+    pub event: EventRecord<'static>,
+    /// The link event through which the event was reached, for example when reading a `$ce-`
+    /// stream with link resolution
+    pub link: Option<EventRecord<'static>>,
+    /// Position of the commit of the event in `$all`, known for the events pushed by the server
+    pub commit_position: Option<LogPosition>,
+    /// Position of the event in `$all`, known for the events pushed by the server
+    pub prepare_position: Option<LogPosition>,
+}
+
+impl StreamEvent {
+    /// The event as it is in the stream of the feed: the link if there is one, otherwise the
+    /// event.
+    pub fn original(&self) -> &EventRecord<'static> {
+        self.link.as_ref().unwrap_or(&self.event)
+    }
+
+    /// The event number of the original event within the stream of the feed.
+    pub fn original_event_number(&self) -> i32 {
+        self.original().event_number
+    }
+}
+
+impl From<ResolvedIndexedEvent<'static>> for StreamEvent {
+    fn from(e: ResolvedIndexedEvent<'static>) -> StreamEvent {
+        StreamEvent {
+            event: e.event,
+            link: e.link,
+            commit_position: None,
+            prepare_position: None,
+        }
+    }
+}
+
+impl From<ResolvedEvent<'static>> for StreamEvent {
+    fn from(e: ResolvedEvent<'static>) -> StreamEvent {
+        StreamEvent {
+            event: e.event,
+            link: e.link,
+            commit_position: LogPosition::from_i64_opt(e.commit_position),
+            prepare_position: LogPosition::from_i64_opt(e.prepare_position),
+        }
+    }
+}
+
+impl From<adapted::ResolvedEvent<'static>> for StreamEvent {
+    fn from(e: adapted::ResolvedEvent<'static>) -> StreamEvent {
+        StreamEvent {
+            event: e.event,
+            link: e.link,
+            commit_position: Some(e.commit_position),
+            prepare_position: Some(e.prepare_position),
+        }
+    }
+}
+
+/// Volatile subscription to the events appended to the stream from now on, or to every stream
+/// if `stream_id` is empty. Links are resolved. The feed fails with `Dropped` when the server ends
+/// the subscription, and with the error of the connection when it is lost; the subscription is
+/// not renewed.
+pub fn subscribe<I: Into<Cow<'static, str>>>(client: &EventStoreClient, stream_id: I) -> Box<Stream<Item = StreamEvent, Error = io::Error>> {
+    Box::new(client.subscribe_to_stream(stream_id, true).map(StreamEvent::from))
+}
+
+/// The server ended the subscription of a feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dropped {
+    /// The reason given by the server
+    pub reason: SubscriptionDropReason,
+}
+
+impl Dropped {
+    /// Returns the `Dropped` wrapped by the given error, if any.
+    pub fn find(e: &io::Error) -> Option<&Dropped> {
+        operation::find::<Dropped>(e)
+    }
+}
+
+impl fmt::Display for Dropped {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: {:?}", self.description(), self.reason)
+    }
+}
+
+impl Error for Dropped {
+    fn description(&self) -> &str {
+        "Subscription dropped"
+    }
+}
+
+impl From<Dropped> for io::Error {
+    fn from(dropped: Dropped) -> io::Error {
+        let kind = match dropped.reason {
+            SubscriptionDropReason::AccessDenied => io::ErrorKind::PermissionDenied,
+            SubscriptionDropReason::NotFound => io::ErrorKind::NotFound,
+            _ => io::ErrorKind::ConnectionAborted,
+        };
+
+        io::Error::new(kind, dropped)
+    }
+}
@@ -0,0 +1,323 @@
+use std::io;
+use std::rc::Rc;
+use std::borrow::Cow;
+use std::time::Duration;
+
+use futures::{future, stream, Future, Stream};
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_service::Service;
+
+use package::Package;
+use {Builder, EventNumber, LogPosition, ReadDirection};
+use response::{read_page, read_all_page};
+use super::StreamEvent;
+
+/// The number of events read at a time.
+const PAGE_SIZE: u8 = 100;
+
+/// Feed of the events of a stream, starting from the event `from`, found by reading the stream
+/// forwards. Once the end of the stream has been reached the stream is read again every
+/// `interval`, and only the events appended since are yielded. A stream which does not exist yet
+/// is polled until it does.
+///
+/// For servers or environments where long lived subscriptions are not available; the events are
+/// the same `StreamEvent`s as pushed to subscriptions. Links are resolved. The feed ends only on
+/// errors, and is stopped by dropping it.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// extern crate futures;
+/// extern crate tokio_core;
+/// extern crate eventstore_tcp;
+///
+/// use std::time::Duration;
+/// use futures::{Future, Stream};
+/// use tokio_core::reactor::Core;
+/// use eventstore_tcp::{EventStoreClient, EventNumber};
+/// use eventstore_tcp::feed::poll_stream;
+///
+/// fn main() {
+///     let mut core = Core::new().unwrap();
+///     let handle = core.handle();
+///
+///     let client = core.run(EventStoreClient::connect(&"127.0.0.1:1113".parse().unwrap(), &handle)).unwrap();
+///     let feed = poll_stream(client, "orders-1", EventNumber::First, Duration::from_secs(1), &handle);
+///
+///     core.run(feed.for_each(|e| {
+///         println!("{} {}", e.original_event_number(), e.event.event_type);
+///         Ok(())
+///     })).unwrap();
+/// }
+/// ```
+pub fn poll_stream<S, I>(service: S, stream_id: I, from: EventNumber, interval: Duration, handle: &Handle) -> Box<Stream<Item = StreamEvent, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static,
+          I: Into<Cow<'static, str>>
+{
+    read(service, stream_id.into(), from, Some((interval, handle.clone())))
+}
+
+/// Reads the events of the stream starting from the event `from`, ending once the end of the
+/// stream has been reached.
+pub fn read_forward<S>(service: S, stream_id: Cow<'static, str>, from: EventNumber) -> Box<Stream<Item = StreamEvent, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    read(service, stream_id, from, None)
+}
+
+/// Reads all events forwards starting from the positions, ending once the end of `$all` has been
+/// reached.
+pub fn read_all_forward<S>(service: S, commit: LogPosition, prepare: LogPosition) -> Box<Stream<Item = StreamEvent, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    read_all(service, ReadDirection::Forward, commit, prepare)
+}
+
+/// Reads all events backwards starting from the positions, the most recent first, ending once the
+/// start of `$all` has been reached. `LogPosition::Last` for both positions starts from the end of
+/// the log, for example to look at the latest events of every stream with `Stream::take`.
+pub fn read_all_backward<S>(service: S, commit: LogPosition, prepare: LogPosition) -> Box<Stream<Item = StreamEvent, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    read_all(service, ReadDirection::Backward, commit, prepare)
+}
+
+fn read_all<S>(service: S, direction: ReadDirection, commit: LogPosition, prepare: LogPosition) -> Box<Stream<Item = StreamEvent, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    let pages = stream::unfold(Some((commit, prepare)), move |position| {
+        let (commit, prepare) = match position {
+            Some(position) => position,
+            None => return None,
+        };
+
+        let package = Builder::read_all_events()
+            .direction(direction)
+            .positions(commit, prepare)
+            .max_count(PAGE_SIZE)
+            .resolve_link_tos(true)
+            .require_master(false)
+            .build_package(None, None);
+
+        Some(service.call(package).and_then(|response| {
+            let page = read_all_page(response)?;
+
+            // a page shorter than asked for ends at the end, or the start, of $all
+            let next = match page.next_positions() {
+                Some(next) if page.events.len() == PAGE_SIZE as usize => Some(next),
+                _ => None,
+            };
+
+            Ok((page.events.into_iter().map(StreamEvent::from).collect::<Vec<_>>(), next))
+        }))
+    });
+
+    Box::new(pages.map(stream::iter_ok::<_, io::Error>).flatten())
+}
+
+fn read<S>(service: S, stream_id: Cow<'static, str>, from: EventNumber, polling: Option<(Duration, Handle)>) -> Box<Stream<Item = StreamEvent, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    let polling = Polling {
+        service: Rc::new(service),
+        stream_id: stream_id,
+        next: from,
+        polling: polling,
+        caught_up: false,
+    };
+
+    let pages = stream::unfold(polling, |polling| {
+        if polling.caught_up && polling.polling.is_none() {
+            None
+        } else {
+            Some(polling.next_page())
+        }
+    });
+
+    Box::new(pages.map(stream::iter_ok::<_, io::Error>).flatten())
+}
+
+/// State of `poll_stream` and `read_forward` between the reads.
+struct Polling<S> {
+    service: Rc<S>,
+    stream_id: Cow<'static, str>,
+    next: EventNumber,
+    /// The interval to poll at once caught up, `None` when reading only until the end
+    polling: Option<(Duration, Handle)>,
+    /// Was the end of the stream reached by the previous read
+    caught_up: bool,
+}
+
+impl<S> Polling<S>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    /// Reads the next page, after waiting for the interval if the previous read reached the end.
+    fn next_page(self) -> Box<Future<Item = (Vec<StreamEvent>, Self), Error = io::Error>> {
+        let wait: Box<Future<Item = (), Error = io::Error>> = match self.polling {
+            Some((interval, ref handle)) if self.caught_up => match Timeout::new(interval, handle) {
+                Ok(timeout) => Box::new(timeout),
+                Err(e) => return Box::new(future::err(e)),
+            },
+            _ => Box::new(future::ok(())),
+        };
+
+        Box::new(wait.and_then(move |()| {
+            let package = Builder::read_stream_events()
+                .direction(ReadDirection::Forward)
+                .stream_id(self.stream_id.clone())
+                .from_event_number(self.next)
+                .max_count(PAGE_SIZE)
+                .resolve_link_tos(true)
+                .build_package(None, None);
+
+            let mut polling = self;
+
+            polling.service.call(package).and_then(move |response| {
+                let page = match read_page(response)? {
+                    Some(page) => page,
+                    None => {
+                        polling.caught_up = true;
+                        return Ok((Vec::new(), polling));
+                    },
+                };
+
+                polling.caught_up = page.end_of_stream;
+                if let Some(next) = page.next_page {
+                    polling.next = next;
+                }
+
+                Ok((page.events.into_iter().map(StreamEvent::from).collect(), polling))
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::cell::RefCell;
+    use std::convert::TryFrom;
+    use std::time::Duration;
+    use futures::{future, Future, Stream};
+    use tokio_core::reactor::Core;
+    use tokio_service::Service;
+    use adapted::{ReadStreamCompleted, ReadStreamError, ReadAllCompleted, ResolvedEvent, AdaptedMessage, ToMessage};
+    use package::Package;
+    use raw::RawMessage;
+    use raw::client_messages::{EventRecord, ResolvedIndexedEvent};
+    use {EventNumber, LogPosition, ReadDirection, StreamVersion};
+    use super::poll_stream;
+
+    /// Answers the reads from the pages in order.
+    struct Pages(RefCell<Vec<Result<ReadStreamCompleted<'static>, ReadStreamError<'static>>>>);
+
+    impl Service for Pages {
+        type Request = Package;
+        type Response = Package;
+        type Error = io::Error;
+        type Future = future::FutureResult<Package, io::Error>;
+
+        fn call(&self, req: Package) -> Self::Future {
+            let page = self.0.borrow_mut().remove(0);
+            future::ok(AdaptedMessage::ReadStreamEventsCompleted(ReadDirection::Forward, page).to_package(req.correlation_id))
+        }
+    }
+
+    fn page(numbers: Vec<i32>, next: u32, end_of_stream: bool) -> Result<ReadStreamCompleted<'static>, ReadStreamError<'static>> {
+        Ok(ReadStreamCompleted {
+            events: numbers.into_iter().map(|n| ResolvedIndexedEvent {
+                event: EventRecord { event_stream_id: "orders-1".into(), event_number: n, event_type: "created".into(), ..Default::default() },
+                link: None,
+            }).collect(),
+            next_page: Some(EventNumber::Exact(StreamVersion::try_from(next).unwrap())),
+            last_event_number: StreamVersion::try_from(next).unwrap(),
+            end_of_stream: end_of_stream,
+            last_commit_position: 0,
+        })
+    }
+
+    #[test]
+    fn reading_forward_ends_with_the_stream() {
+        let service = Pages(RefCell::new(vec![page(vec![0, 1], 2, false), page(vec![2], 3, true)]));
+        let numbers = super::read_forward(service, "orders-1".into(), EventNumber::First)
+            .map(|e| e.original_event_number()).collect().wait().unwrap();
+
+        assert_eq!(numbers, vec![0, 1, 2]);
+    }
+
+    /// Answers the reads of `$all` backwards with the pages in order, checking the positions.
+    struct AllPages(RefCell<Vec<(i64, ReadAllCompleted<'static>)>>);
+
+    impl Service for AllPages {
+        type Request = Package;
+        type Response = Package;
+        type Error = io::Error;
+        type Future = future::FutureResult<Package, io::Error>;
+
+        fn call(&self, req: Package) -> Self::Future {
+            let (from, page) = self.0.borrow_mut().remove(0);
+
+            match req.message {
+                RawMessage::ReadAllEvents(ReadDirection::Backward, ref read) => assert_eq!((read.commit_position, read.prepare_position), (from, from)),
+                ref other => panic!("unexpected request: {:?}", other),
+            }
+
+            future::ok(AdaptedMessage::ReadAllEventsCompleted(ReadDirection::Backward, Ok(page)).to_package(req.correlation_id))
+        }
+    }
+
+    fn all_page(positions: Vec<u64>, next: u64) -> ReadAllCompleted<'static> {
+        ReadAllCompleted {
+            commit_position: LogPosition::Last,
+            prepare_position: LogPosition::Last,
+            events: positions.into_iter().map(|p| ResolvedEvent {
+                event: EventRecord { event_stream_id: "orders-1".into(), event_type: "created".into(), ..Default::default() },
+                link: None,
+                commit_position: LogPosition::Exact(p),
+                prepare_position: LogPosition::Exact(p),
+            }).collect(),
+            next_commit_position: Some(LogPosition::Exact(next)),
+            next_prepare_position: Some(LogPosition::Exact(next)),
+        }
+    }
+
+    #[test]
+    fn reading_all_backward_from_the_end() {
+        let full = (0..super::PAGE_SIZE as u64).map(|i| 1000 - i * 10).collect::<Vec<_>>();
+        let service = AllPages(RefCell::new(vec![(-1, all_page(full, 10)), (10, all_page(vec![10, 5], 1))]));
+
+        let positions = super::read_all_backward(service, LogPosition::Last, LogPosition::Last)
+            .map(|e| e.commit_position.unwrap())
+            .collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(positions.len(), super::PAGE_SIZE as usize + 2);
+        assert_eq!(positions[0], LogPosition::Exact(1000));
+        assert_eq!(positions[positions.len() - 1], LogPosition::Exact(5));
+    }
+
+    #[test]
+    fn yields_only_new_events() {
+        let mut core = Core::new().unwrap();
+        let service = Pages(RefCell::new(vec![
+            Err(ReadStreamError::NoStream),
+            page(vec![0, 1], 2, false),
+            page(vec![2], 3, true),
+            page(vec![], 3, true),
+            page(vec![3], 4, true),
+        ]));
+
+        let feed = poll_stream(service, "orders-1", EventNumber::First, Duration::from_millis(1), &core.handle());
+        let numbers = core.run(feed.take(4).map(|e| e.original_event_number()).collect()).unwrap();
+
+        assert_eq!(numbers, vec![0, 1, 2, 3]);
+    }
+}
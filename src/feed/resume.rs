@@ -0,0 +1,216 @@
+use std::io;
+use std::fmt;
+use std::str::FromStr;
+use std::convert::TryFrom;
+
+use futures::{stream, Stream};
+
+use {EventStoreClient, EventNumber, LogPosition, StreamVersion};
+use super::{StreamEvent, subscribe, watch_stream};
+use super::poll::read_all_forward;
+
+/// Where a feed continues from, after the event received last.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum ResumePosition {
+    /// After the event of the stream with the number
+    Stream(String, StreamVersion),
+    /// After the event at the commit and prepare positions in `$all`
+    All(LogPosition, LogPosition),
+}
+
+/// The position of a feed in one value, for storing along with the state built from the events
+/// so that the feed can be continued exactly where it was left with `subscribe_from`.
+///
+/// A token can also carry the fingerprint of the filter applied to the events, so that a consumer
+/// can notice when the filter has changed since the token was stored and build its state again.
+/// The token is stored either with serde, with the `serialize` feature, or as its string form
+/// which `FromStr` parses back.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::LogPosition;
+/// use eventstore_tcp::feed::ResumeToken;
+///
+/// let token = ResumeToken::all(LogPosition::Exact(1200), LogPosition::Exact(1100)).with_filter("type=created");
+/// let stored = token.to_string();
+///
+/// let resumed = stored.parse::<ResumeToken>().unwrap();
+/// assert_eq!(resumed, token);
+/// assert!(resumed.matches_filter(Some("type=created")));
+/// assert!(!resumed.matches_filter(None));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ResumeToken {
+    /// Where to continue from
+    pub position: ResumePosition,
+    /// Fingerprint of the filter, see `ResumeToken::with_filter`
+    pub filter: Option<u64>,
+}
+
+impl ResumeToken {
+    /// Continues the stream after the event with the number.
+    pub fn stream<S: Into<String>>(stream_id: S, last: StreamVersion) -> Self {
+        ResumeToken {
+            position: ResumePosition::Stream(stream_id.into(), last),
+            filter: None,
+        }
+    }
+
+    /// Continues `$all` after the event at the positions.
+    pub fn all(commit: LogPosition, prepare: LogPosition) -> Self {
+        ResumeToken {
+            position: ResumePosition::All(commit, prepare),
+            filter: None,
+        }
+    }
+
+    /// Continues the stream of the feed after the event, see `StreamEvent::original`. `None` if
+    /// the event number is not valid.
+    pub fn after(e: &StreamEvent) -> Option<Self> {
+        let original = e.original();
+
+        StreamVersion::try_from(original.event_number).ok()
+            .map(|last| ResumeToken::stream(original.event_stream_id.to_string(), last))
+    }
+
+    /// Continues `$all` after the event, `None` if the position of the event is not known.
+    pub fn after_in_all(e: &StreamEvent) -> Option<Self> {
+        match (e.commit_position, e.prepare_position) {
+            (Some(commit), Some(prepare)) => Some(ResumeToken::all(commit, prepare)),
+            _ => None,
+        }
+    }
+
+    /// Records the fingerprint of a description of the filter applied to the events, such as the
+    /// event types or the query. The fingerprint is stable across processes and versions.
+    pub fn with_filter(mut self, filter: &str) -> Self {
+        self.filter = Some(fingerprint(filter));
+        self
+    }
+
+    /// Was the token created with the filter, or without a filter for `None`.
+    pub fn matches_filter(&self, filter: Option<&str>) -> bool {
+        self.filter == filter.map(fingerprint)
+    }
+}
+
+/// 64-bit FNV-1a, unlike the hashers of the standard library the same everywhere.
+fn fingerprint(filter: &str) -> u64 {
+    filter.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3))
+}
+
+impl fmt::Display for ResumeToken {
+    /// `stream:<number>:<filter>:<stream id>` or `all:<commit>:<prepare>:<filter>`, with the
+    /// filter in hex or `-`.
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let filter = match self.filter {
+            Some(filter) => format!("{:016x}", filter),
+            None => "-".to_owned(),
+        };
+
+        match self.position {
+            ResumePosition::Stream(ref stream_id, last) => write!(fmt, "stream:{}:{}:{}", u32::from(last), filter, stream_id),
+            ResumePosition::All(commit, prepare) => {
+                let (commit, prepare): (i64, i64) = (commit.into(), prepare.into());
+                write!(fmt, "all:{}:{}:{}", commit, prepare, filter)
+            },
+        }
+    }
+}
+
+impl FromStr for ResumeToken {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid resume token: {:?}", s));
+        let filter = |part: &str| match part {
+            "-" => Ok(None),
+            hex => u64::from_str_radix(hex, 16).map(Some).map_err(|_| invalid()),
+        };
+
+        let parts = s.splitn(4, ':').collect::<Vec<_>>();
+        if parts.len() != 4 {
+            return Err(invalid());
+        }
+
+        match parts[0] {
+            "stream" => {
+                let last = parts[1].parse::<u32>().ok().and_then(|last| StreamVersion::try_from(last).ok()).ok_or_else(&invalid)?;
+                Ok(ResumeToken { position: ResumePosition::Stream(parts[3].to_owned(), last), filter: filter(parts[2])? })
+            },
+            "all" => {
+                let position = |part: &str| part.parse::<i64>().ok().and_then(LogPosition::from_i64_opt).ok_or_else(&invalid);
+                Ok(ResumeToken { position: ResumePosition::All(position(parts[1])?, position(parts[2])?), filter: filter(parts[3])? })
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Continues the feed of the token: watches the stream from the event following the one in the
+/// token like `watch_stream`, or reads `$all` from the positions and then follows a subscription
+/// to all streams, skipping the events before the positions and the ones already read.
+///
+/// The filter of the token is not applied; check it with `ResumeToken::matches_filter`.
+pub fn subscribe_from(client: &EventStoreClient, token: &ResumeToken) -> Box<Stream<Item = StreamEvent, Error = io::Error>> {
+    match token.position {
+        ResumePosition::Stream(ref stream_id, last) => {
+            match StreamVersion::try_from(u32::from(last) + 1) {
+                Ok(next) => watch_stream(client, stream_id.clone(), EventNumber::Exact(next)),
+                Err(e) => Box::new(stream::once::<StreamEvent, io::Error>(Err(e.into()))),
+            }
+        },
+        ResumePosition::All(commit, prepare) => {
+            let live = subscribe(client, "");
+            let catch_up = read_all_forward(client.clone(), commit, prepare);
+            let mut last = (commit, prepare);
+
+            Box::new(catch_up.chain(live).filter(move |e| match (e.commit_position, e.prepare_position) {
+                (Some(commit), Some(prepare)) if (commit, prepare) > last => {
+                    last = (commit, prepare);
+                    true
+                },
+                _ => false,
+            }))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use raw::client_messages::EventRecord;
+    use feed::StreamEvent;
+    use {LogPosition, StreamVersion};
+    use super::{ResumeToken, ResumePosition};
+
+    #[test]
+    fn tokens_round_trip_as_strings() {
+        let stream = ResumeToken::stream("orders-1:eu", StreamVersion::try_from(41u32).unwrap()).with_filter("created");
+        assert_eq!(stream.to_string().parse::<ResumeToken>().unwrap(), stream);
+
+        let all = ResumeToken::all(LogPosition::Exact(5), LogPosition::First);
+        assert_eq!(all.to_string(), "all:5:0:-");
+        assert_eq!("all:5:0:-".parse::<ResumeToken>().unwrap(), all);
+
+        assert!("all:5:-".parse::<ResumeToken>().is_err());
+        assert!("stream:-1:-:orders".parse::<ResumeToken>().is_err());
+    }
+
+    #[test]
+    fn tokens_after_events() {
+        let link = EventRecord { event_stream_id: "$ce-orders".into(), event_number: 7, ..Default::default() };
+        let e = StreamEvent {
+            event: EventRecord { event_stream_id: "orders-1".into(), event_number: 2, ..Default::default() },
+            link: Some(link),
+            commit_position: None,
+            prepare_position: None,
+        };
+
+        assert_eq!(ResumeToken::after(&e).unwrap().position, ResumePosition::Stream("$ce-orders".into(), StreamVersion::try_from(7u32).unwrap()));
+        assert_eq!(ResumeToken::after_in_all(&e), None);
+    }
+}
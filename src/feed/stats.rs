@@ -0,0 +1,208 @@
+use std::io;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::{Async, Poll, Stream};
+
+use super::StreamEvent;
+
+/// Adds `with_stats` to the feeds of `StreamEvent`s.
+pub trait FeedExt: Stream<Item = StreamEvent, Error = io::Error> + Sized {
+    /// Counts the events passing through the feed, see `FeedStats`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate futures;
+    /// extern crate eventstore_tcp;
+    ///
+    /// use futures::{stream, Future, Stream};
+    /// use eventstore_tcp::feed::{FeedExt, StreamEvent};
+    ///
+    /// # fn main() {
+    /// let events: Vec<StreamEvent> = vec![];
+    ///
+    /// let events = stream::iter_ok(events).with_stats();
+    /// let stats = events.stats();
+    ///
+    /// events.collect().wait().unwrap();
+    /// assert_eq!(stats.snapshot().events, 0);
+    /// # }
+    /// ```
+    fn with_stats(self) -> WithStats<Self> {
+        WithStats {
+            events: self,
+            stats: FeedStats(Rc::new(RefCell::new(FeedSnapshot::new(Instant::now())))),
+        }
+    }
+}
+
+impl<S: Stream<Item = StreamEvent, Error = io::Error>> FeedExt for S {}
+
+/// Feed counting its events, created with `FeedExt::with_stats`.
+pub struct WithStats<S> {
+    events: S,
+    stats: FeedStats,
+}
+
+impl<S> WithStats<S> {
+    /// The handle for querying the statistics while the feed is consumed.
+    pub fn stats(&self) -> FeedStats {
+        self.stats.clone()
+    }
+}
+
+impl<S: Stream<Item = StreamEvent, Error = io::Error>> Stream for WithStats<S> {
+    type Item = StreamEvent;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<StreamEvent>, io::Error> {
+        let polled = self.events.poll()?;
+
+        if let Async::Ready(Some(ref e)) = polled {
+            self.stats.0.borrow_mut().record(e, SystemTime::now());
+        }
+
+        Ok(polled)
+    }
+}
+
+/// Handle to the statistics of a feed, cheap to clone and to keep after the feed has ended.
+#[derive(Debug, Clone)]
+pub struct FeedStats(Rc<RefCell<FeedSnapshot>>);
+
+impl FeedStats {
+    /// The statistics at the moment.
+    pub fn snapshot(&self) -> FeedSnapshot {
+        let mut snapshot = self.0.borrow().clone();
+        snapshot.elapsed = snapshot.started.elapsed();
+        snapshot
+    }
+}
+
+/// Statistics of a feed since `with_stats` was called on it.
+///
+/// The gaps and the ordering are measured between the consecutive events of the same stream, by
+/// the numbers of the original events, see `StreamEvent::original`. Gaps appear for example when
+/// events have been deleted, have expired or were filtered out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedSnapshot {
+    /// Events yielded by the feed
+    pub events: u64,
+    /// Bytes of data and metadata of the events
+    pub bytes: u64,
+    /// Time since the statistics were started
+    pub elapsed: Duration,
+    /// The most event numbers skipped between two consecutive events of a stream
+    pub max_gap: u32,
+    /// Events with a number not higher than the previous event of the stream
+    pub out_of_order: u64,
+    /// How long ago the last event was created when it was received, `None` until an event with
+    /// a creation time has been received. Near zero for a feed following live events, and
+    /// includes the difference of the clocks of the client and the server.
+    pub lag: Option<Duration>,
+    started: Instant,
+    previous: Option<(String, i32)>,
+}
+
+impl FeedSnapshot {
+    fn new(started: Instant) -> Self {
+        FeedSnapshot {
+            events: 0,
+            bytes: 0,
+            elapsed: Duration::from_secs(0),
+            max_gap: 0,
+            out_of_order: 0,
+            lag: None,
+            started: started,
+            previous: None,
+        }
+    }
+
+    /// Average events per second over `elapsed`.
+    pub fn events_per_sec(&self) -> f64 {
+        per_sec(self.events, self.elapsed)
+    }
+
+    /// Average bytes per second over `elapsed`.
+    pub fn bytes_per_sec(&self) -> f64 {
+        per_sec(self.bytes, self.elapsed)
+    }
+
+    fn record(&mut self, e: &StreamEvent, now: SystemTime) {
+        self.events += 1;
+        self.bytes += (e.event.data.len() + e.event.metadata.as_ref().map(|m| m.len()).unwrap_or(0)) as u64;
+
+        let original = e.original();
+        let number = original.event_number;
+
+        if let Some((ref stream_id, previous)) = self.previous {
+            if *stream_id == *original.event_stream_id {
+                if number <= previous {
+                    self.out_of_order += 1;
+                } else {
+                    self.max_gap = self.max_gap.max((number - previous - 1) as u32);
+                }
+            }
+        }
+
+        self.previous = Some((original.event_stream_id.to_string(), number));
+
+        if let Some(millis) = e.event.created_epoch {
+            if millis >= 0 {
+                let created = UNIX_EPOCH + Duration::from_millis(millis as u64);
+                self.lag = Some(now.duration_since(created).unwrap_or_else(|_| Duration::from_secs(0)));
+            }
+        }
+    }
+}
+
+fn per_sec(count: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+
+    if secs > 0.0 {
+        count as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+    use raw::client_messages::EventRecord;
+    use feed::StreamEvent;
+    use super::FeedSnapshot;
+
+    fn event(stream_id: &'static str, number: i32, created_secs: i64) -> StreamEvent {
+        StreamEvent {
+            event: EventRecord {
+                event_stream_id: stream_id.into(),
+                event_number: number,
+                data: vec![0; 10].into(),
+                created_epoch: Some(created_secs * 1000),
+                ..Default::default()
+            },
+            link: None,
+            commit_position: None,
+            prepare_position: None,
+        }
+    }
+
+    #[test]
+    fn gaps_order_and_lag() {
+        let now = UNIX_EPOCH + Duration::from_secs(1000);
+        let mut snapshot = FeedSnapshot::new(Instant::now());
+
+        for e in &[event("a", 0, 990), event("a", 4, 995), event("b", 0, 996), event("b", 0, 997), event("a", 5, 998)] {
+            snapshot.record(e, now);
+        }
+
+        assert_eq!(snapshot.events, 5);
+        assert_eq!(snapshot.bytes, 50);
+        assert_eq!(snapshot.max_gap, 3);
+        assert_eq!(snapshot.out_of_order, 1);
+        assert_eq!(snapshot.lag, Some(Duration::from_secs(2)));
+    }
+}
@@ -0,0 +1,205 @@
+use std::io;
+use std::borrow::Cow;
+
+use futures::{stream, Async, Poll, Stream};
+
+use {EventStoreClient, EventNumber};
+use super::{StreamEvent, subscribe};
+use super::poll::read_forward;
+
+/// Feed of the events of the stream starting from the event `from`: the events already in the
+/// stream followed by the events appended to it, in order and each only once.
+///
+/// The stream is subscribed to first and then read until its end, after which the events pushed
+/// to the subscription meanwhile are yielded, skipping the ones already read. `EventNumber::Last`
+/// skips the reading and yields only the events appended from now on. Links are resolved and the
+/// events are ordered by the event numbers of the watched stream.
+///
+/// Fails like `subscribe`; the subscription is not renewed, but watching can be started again from
+/// the event following the last one received.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// extern crate futures;
+/// extern crate tokio_core;
+/// extern crate eventstore_tcp;
+///
+/// use futures::{Future, Stream};
+/// use tokio_core::reactor::Core;
+/// use eventstore_tcp::{EventStoreClient, EventNumber};
+/// use eventstore_tcp::feed::watch_stream;
+///
+/// fn main() {
+///     let mut core = Core::new().unwrap();
+///     let client = core.run(EventStoreClient::connect(&"127.0.0.1:1113".parse().unwrap(), &core.handle())).unwrap();
+///
+///     core.run(watch_stream(&client, "orders-1", EventNumber::First).for_each(|e| {
+///         println!("{} {}", e.original_event_number(), e.event.event_type);
+///         Ok(())
+///     })).unwrap();
+/// }
+/// ```
+pub fn watch_stream<I: Into<Cow<'static, str>>>(client: &EventStoreClient, stream_id: I, from: EventNumber) -> Box<Stream<Item = StreamEvent, Error = io::Error>> {
+    let stream_id = stream_id.into();
+    let live = subscribe(client, stream_id.clone());
+
+    let (catch_up, last) = match from {
+        EventNumber::Last => (Box::new(stream::empty::<StreamEvent, io::Error>()) as Box<Stream<Item = StreamEvent, Error = io::Error>>, None),
+        EventNumber::First => (read_forward(client.clone(), stream_id, from), None),
+        EventNumber::Exact(number) => {
+            let before = i32::from(number) - 1;
+            (read_forward(client.clone(), stream_id, from), if before >= 0 { Some(before) } else { None })
+        },
+    };
+
+    Box::new(Watch {
+        catch_up: catch_up,
+        reading: true,
+        live: live,
+        first_live: None,
+        live_ended: false,
+        last: last,
+    })
+}
+
+/// The events read from the stream followed by the ones pushed to the subscription.
+///
+/// The subscription is polled together with the reading until its first event, so that it is
+/// sent before the reading even when the credentials of the subscription are fetched first. The
+/// later events wait in the connection until the reading is done.
+struct Watch {
+    catch_up: Box<Stream<Item = StreamEvent, Error = io::Error>>,
+    reading: bool,
+    live: Box<Stream<Item = StreamEvent, Error = io::Error>>,
+    first_live: Option<StreamEvent>,
+    live_ended: bool,
+    last: Option<i32>,
+}
+
+impl Watch {
+    fn next(&mut self) -> Poll<Option<StreamEvent>, io::Error> {
+        if self.reading {
+            if self.first_live.is_none() && !self.live_ended {
+                match self.live.poll()? {
+                    Async::Ready(Some(e)) => self.first_live = Some(e),
+                    Async::Ready(None) => self.live_ended = true,
+                    Async::NotReady => {},
+                }
+            }
+
+            match self.catch_up.poll()? {
+                Async::Ready(None) => self.reading = false,
+                other => return Ok(other),
+            }
+        }
+
+        if let Some(e) = self.first_live.take() {
+            return Ok(Async::Ready(Some(e)));
+        }
+
+        if self.live_ended {
+            return Ok(Async::Ready(None));
+        }
+
+        self.live.poll()
+    }
+}
+
+impl Stream for Watch {
+    type Item = StreamEvent;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<StreamEvent>, io::Error> {
+        loop {
+            let e = match self.next()? {
+                Async::Ready(Some(e)) => e,
+                other => return Ok(other),
+            };
+
+            // the events appended during the reading are both read and pushed to the subscription
+            let number = e.original_event_number();
+
+            match self.last {
+                Some(last) if number <= last => continue,
+                _ => {},
+            }
+
+            self.last = Some(number);
+            return Ok(Async::Ready(Some(e)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use futures::Stream;
+    use adapted::{AdaptedMessage, ReadStreamCompleted, ToMessage};
+    use raw::RawMessage;
+    use raw::client_messages::{EventRecord, ResolvedEvent, ResolvedIndexedEvent, StreamEventAppeared, SubscriptionConfirmation};
+    use server::ScriptedServer;
+    use transport::{scripted_client, scripted_client_with};
+    use {CredentialsMap, EventNumber, ReadDirection, StreamVersion, UsernamePassword};
+    use super::watch_stream;
+
+    fn record(number: i32) -> EventRecord<'static> {
+        EventRecord { event_stream_id: "orders-1".into(), event_number: number, event_type: "created".into(), ..Default::default() }
+    }
+
+    fn appeared(number: i32) -> RawMessage<'static> {
+        RawMessage::StreamEventAppeared(StreamEventAppeared {
+            event: ResolvedEvent { event: record(number), link: None, commit_position: 10, prepare_position: 10 },
+        })
+    }
+
+    fn page(numbers: &[i32]) -> AdaptedMessage<'static> {
+        let last = *numbers.last().unwrap() as u32;
+
+        AdaptedMessage::ReadStreamEventsCompleted(ReadDirection::Forward, Ok(ReadStreamCompleted {
+            events: numbers.iter().map(|&number| ResolvedIndexedEvent { event: record(number), link: None }).collect(),
+            next_page: Some(EventNumber::Exact(StreamVersion::try_from(last + 1).unwrap())),
+            last_event_number: StreamVersion::try_from(last).unwrap(),
+            end_of_stream: true,
+            last_commit_position: 0,
+        }))
+    }
+
+    #[test]
+    fn boundary_events_are_yielded_once() {
+        let server = ScriptedServer::new()
+            .expect(0xC0)
+                .respond(RawMessage::SubscriptionConfirmation(SubscriptionConfirmation { last_commit_position: 10, last_event_number: Some(1) }))
+                .respond(appeared(2))
+                .respond(appeared(3))
+            .expect(0xB2)
+                .respond(page(&[1, 2]).to_message().into_owned());
+
+        let (mut core, client) = scripted_client(server);
+
+        let from = EventNumber::Exact(StreamVersion::try_from(1u32).unwrap());
+        let numbers = core.run(watch_stream(&client, "orders-1", from).take(3).map(|e| e.original_event_number()).collect()).unwrap();
+
+        assert_eq!(numbers, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn subscribes_before_reading_with_a_credentials_provider() {
+        let server = ScriptedServer::new()
+            .expect(0xC0)
+                .respond(RawMessage::SubscriptionConfirmation(SubscriptionConfirmation { last_commit_position: 10, last_event_number: Some(2) }))
+                .respond(appeared(3))
+            .expect(0xB2)
+                .respond(page(&[1, 2]).to_message().into_owned());
+        let script = server.script();
+
+        let (mut core, client) = scripted_client_with(server, |settings| {
+            settings.credentials_provider(CredentialsMap::new(UsernamePassword::new("app", "1")));
+        });
+
+        let numbers = core.run(watch_stream(&client, "orders-1", EventNumber::First).take(3).map(|e| e.original_event_number()).collect()).unwrap();
+
+        assert_eq!(numbers, vec![1, 2, 3]);
+        script.assert_complete();
+    }
+}
@@ -0,0 +1,195 @@
+//! C ABI of the codec, available with the `ffi` feature, for tools written in other languages
+//! such as Wireshark dissectors. The functions are declared for C in `include/eventstore_tcp.h`;
+//! build a linkable library with for example
+//! `cargo rustc --release --no-default-features --features ffi -- --crate-type cdylib`.
+//!
+//! Every package and buffer returned by the functions is owned by the caller, and must be given
+//! back to the matching `eventstore_free_*` function exactly once. The credentials of decoded
+//! frames are not exposed.
+
+use std::ptr;
+use std::slice;
+use std::ffi::CString;
+use std::os::raw::c_char;
+
+use bytes::BytesMut;
+use uuid::Uuid;
+use tokio_io::codec::Encoder;
+
+use codec::PackageCodec;
+use package::Package;
+use raw::RawMessage;
+
+/// A frame decoded by `eventstore_decode_package`.
+#[repr(C)]
+pub struct EventStorePackage {
+    /// Discriminator of the message
+    pub command: u8,
+    /// 1 if the frame carried credentials, otherwise 0
+    pub authenticated: u8,
+    /// Correlation id of the frame
+    pub correlation_id: [u8; 16],
+    /// The protobuf encoded message, `payload_len` bytes
+    pub payload: *mut u8,
+    /// Length of `payload`
+    pub payload_len: usize,
+    /// Description of the decoded message as a NUL terminated string, or NULL
+    pub description: *mut c_char,
+}
+
+/// Decodes the first frame of the `len` bytes at `buf`, starting from the length prefix.
+///
+/// Returns 1 and stores the package in `*out` and the length of the frame in `*consumed` when a
+/// whole frame was decoded; 0 when `buf` does not yet contain a whole frame; and -1 when the frame
+/// is invalid, in which case the stream of frames cannot be decoded further.
+#[no_mangle]
+pub unsafe extern "C" fn eventstore_decode_package(buf: *const u8, len: usize, out: *mut *mut EventStorePackage, consumed: *mut usize) -> i32 {
+    if buf.is_null() || out.is_null() || consumed.is_null() {
+        return -1;
+    }
+
+    let input = slice::from_raw_parts(buf, len);
+
+    let (package, frame_len) = match PackageCodec.decode_borrowed(input) {
+        Ok(Some(decoded)) => decoded,
+        Ok(None) => return 0,
+        Err(_) => return -1,
+    };
+
+    let mut payload = Vec::with_capacity(package.message.encoded_len());
+    if package.message.encode(&mut payload).is_err() {
+        return -1;
+    }
+
+    let description = CString::new(format!("{:?}", package.message))
+        .map(CString::into_raw)
+        .unwrap_or(ptr::null_mut());
+
+    let mut correlation_id = [0u8; 16];
+    correlation_id.copy_from_slice(package.correlation_id.as_bytes());
+
+    let (payload, payload_len) = into_raw(payload);
+
+    *out = Box::into_raw(Box::new(EventStorePackage {
+        command: package.message.discriminator(),
+        authenticated: if package.authentication.is_some() { 1 } else { 0 },
+        correlation_id: correlation_id,
+        payload: payload,
+        payload_len: payload_len,
+        description: description,
+    }));
+    *consumed = frame_len;
+    1
+}
+
+/// Encodes a frame without credentials for the message of `command` with the protobuf encoded
+/// `payload` of `payload_len` bytes and the 16 bytes of `correlation_id`.
+///
+/// Returns 0 and stores the frame, including the length prefix, in `*out` and its length in
+/// `*out_len`; or -1 if the payload is not a valid message for the command.
+#[no_mangle]
+pub unsafe extern "C" fn eventstore_encode_package(command: u8, correlation_id: *const u8, payload: *const u8, payload_len: usize, out: *mut *mut u8, out_len: *mut usize) -> i32 {
+    if correlation_id.is_null() || (payload.is_null() && payload_len > 0) || out.is_null() || out_len.is_null() {
+        return -1;
+    }
+
+    let payload: &[u8] = if payload_len > 0 { slice::from_raw_parts(payload, payload_len) } else { &[] };
+
+    let correlation_id = match Uuid::from_bytes(slice::from_raw_parts(correlation_id, 16)) {
+        Ok(id) => id,
+        Err(_) => return -1,
+    };
+
+    let message = match RawMessage::decode(command, payload) {
+        Ok(message) => message.into_owned(),
+        Err(_) => return -1,
+    };
+
+    let mut buf = BytesMut::new();
+    let package = Package { correlation_id: correlation_id, authentication: None, message: message };
+
+    if PackageCodec.encode(package, &mut buf).is_err() {
+        return -1;
+    }
+
+    let (frame, frame_len) = into_raw(buf.to_vec());
+    *out = frame;
+    *out_len = frame_len;
+    0
+}
+
+/// Frees a package returned by `eventstore_decode_package`. Does nothing for NULL.
+#[no_mangle]
+pub unsafe extern "C" fn eventstore_free_package(package: *mut EventStorePackage) {
+    if package.is_null() {
+        return;
+    }
+
+    let package = Box::from_raw(package);
+    eventstore_free_buffer(package.payload, package.payload_len);
+
+    if !package.description.is_null() {
+        drop(CString::from_raw(package.description));
+    }
+}
+
+/// Frees a frame returned by `eventstore_encode_package`, given with its length. Does nothing
+/// for NULL.
+#[no_mangle]
+pub unsafe extern "C" fn eventstore_free_buffer(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(Box::from_raw(slice::from_raw_parts_mut(buf, len) as *mut [u8]));
+    }
+}
+
+/// Leaks the bytes as a pointer and a length for `eventstore_free_buffer`; NULL for no bytes.
+fn into_raw(bytes: Vec<u8>) -> (*mut u8, usize) {
+    if bytes.is_empty() {
+        return (ptr::null_mut(), 0);
+    }
+
+    let len = bytes.len();
+    (Box::into_raw(bytes.into_boxed_slice()) as *mut u8, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use std::slice;
+    use std::ffi::CStr;
+    use builder::Builder;
+    use EventNumber;
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        let package = Builder::read_event().stream_id("orders-1").event_number(EventNumber::First).build_package(None, None);
+        let mut payload = Vec::new();
+        package.message.encode(&mut payload).unwrap();
+
+        unsafe {
+            let (mut frame, mut frame_len) = (ptr::null_mut(), 0);
+            assert_eq!(eventstore_encode_package(0xB0, package.correlation_id.as_bytes().as_ptr(), payload.as_ptr(), payload.len(), &mut frame, &mut frame_len), 0);
+
+            let (mut decoded, mut consumed) = (ptr::null_mut(), 0);
+            assert_eq!(eventstore_decode_package(frame, frame_len - 1, &mut decoded, &mut consumed), 0);
+            assert_eq!(eventstore_decode_package(frame, frame_len, &mut decoded, &mut consumed), 1);
+            assert_eq!(consumed, frame_len);
+
+            {
+                let decoded = &*decoded;
+                assert_eq!(decoded.command, 0xB0);
+                assert_eq!(decoded.authenticated, 0);
+                assert_eq!(&decoded.correlation_id[..], package.correlation_id.as_bytes());
+                assert_eq!(slice::from_raw_parts(decoded.payload, decoded.payload_len), &payload[..]);
+                assert!(CStr::from_ptr(decoded.description).to_str().unwrap().contains("orders-1"));
+            }
+
+            eventstore_free_package(decoded);
+            eventstore_free_buffer(frame, frame_len);
+
+            // not a ReadEvent
+            assert_eq!(eventstore_encode_package(0xB0, package.correlation_id.as_bytes().as_ptr(), [0xffu8].as_ptr(), 1, &mut frame, &mut frame_len), -1);
+        }
+    }
+}
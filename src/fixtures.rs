@@ -0,0 +1,24 @@
+//! Values shared by the tests of the modules.
+
+use std::borrow::Cow;
+
+use uuid::Uuid;
+
+use {ContentType, NewEvent};
+
+/// An event with a random id and `data` of the content type.
+pub fn new_event(event_type: &'static str, content_type: ContentType, data: &'static str) -> NewEvent<'static> {
+    NewEvent {
+        event_id: Cow::Owned(Uuid::new_v4().as_bytes().to_vec()),
+        event_type: Cow::Borrowed(event_type),
+        data_content_type: content_type.into(),
+        metadata_content_type: ContentType::Bytes.into(),
+        data: Cow::Borrowed(data.as_bytes()),
+        metadata: None,
+    }
+}
+
+/// An event with a random id and JSON `data`.
+pub fn json_event(event_type: &'static str, data: &'static str) -> NewEvent<'static> {
+    new_event(event_type, ContentType::Json, data)
+}
@@ -0,0 +1,287 @@
+//! Discovering the members of a cluster from the gossip the nodes serve over HTTP, available with
+//! the `gossip` feature.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use futures::{future, Future, Stream};
+use hyper::{self, Client, Uri};
+use hyper::client::HttpConnector;
+use serde_json::{self, Value};
+use tokio_core::reactor::{Handle, Timeout};
+
+use cluster::{ClusterMember, Discovery, NodeRole};
+
+/// The default port of the HTTP interface, serving the gossip.
+const GOSSIP_PORT: u16 = 2113;
+
+/// `Discovery` asking the gossip seeds for the members of the cluster, like the `discover://`
+/// connection strings of the official clients.
+///
+/// With a DNS name the name is resolved to the seeds again on every discovery, so that the name
+/// of for example a headless service of Kubernetes follows the nodes as they come and go. The
+/// name is resolved with the resolver of the operating system, blocking the reactor while it
+/// does. The seeds are asked in turn until one of them answers, each within the `timeout`; only
+/// the members which are alive and are the leader, a follower or a read-only replica are
+/// returned.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// extern crate tokio_core;
+/// extern crate eventstore_tcp;
+///
+/// use tokio_core::reactor::Core;
+/// use eventstore_tcp::{ClusterClient, ConnectionSettings, GossipDiscovery};
+///
+/// fn main() {
+///     let mut core = Core::new().unwrap();
+///     let handle = core.handle();
+///
+///     let discovery = GossipDiscovery::parse("discover://eventstore.default.svc.cluster.local:2113", &handle).unwrap();
+///     let client = core.run(ClusterClient::connect(discovery, &handle, ConnectionSettings::default())).unwrap();
+/// #   drop(client);
+/// }
+/// ```
+pub struct GossipDiscovery {
+    seeds: Seeds,
+    client: Client<HttpConnector>,
+    handle: Handle,
+    timeout: Duration,
+}
+
+enum Seeds {
+    Dns(String, u16),
+    Fixed(Vec<SocketAddr>),
+}
+
+impl GossipDiscovery {
+    /// Discovers through the seeds the DNS name resolves to, asking them at the HTTP `port`.
+    pub fn dns<S: Into<String>>(name: S, port: u16, handle: &Handle) -> Self {
+        Self::new(Seeds::Dns(name.into(), port), handle)
+    }
+
+    /// Discovers through the HTTP interfaces of the given nodes.
+    pub fn seeds(seeds: Vec<SocketAddr>, handle: &Handle) -> Self {
+        Self::new(Seeds::Fixed(seeds), handle)
+    }
+
+    fn new(seeds: Seeds, handle: &Handle) -> Self {
+        GossipDiscovery {
+            seeds: seeds,
+            client: Client::new(handle),
+            handle: handle.clone(),
+            // the gossip timeout of the official clients
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// How long a seed is waited for before the next one is asked, failing the request with
+    /// `TimedOut`.
+    ///
+    /// Defaults to 1 second.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Parses a `discover://name:port` connection string. The port is the HTTP port of the nodes,
+    /// defaulting to 2113.
+    pub fn parse(uri: &str, handle: &Handle) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid discover:// connection string {:?}", uri));
+
+        if !uri.starts_with("discover://") {
+            return Err(invalid());
+        }
+
+        let authority = uri["discover://".len()..].trim_right_matches('/');
+
+        let (name, port) = match authority.rfind(':') {
+            Some(i) => (&authority[..i], authority[i + 1..].parse::<u16>().map_err(|_| invalid())?),
+            None => (authority, GOSSIP_PORT),
+        };
+
+        if name.is_empty() || name.contains('/') {
+            return Err(invalid());
+        }
+
+        Ok(Self::dns(name, port, handle))
+    }
+
+    fn resolve(&self) -> io::Result<Vec<SocketAddr>> {
+        match self.seeds {
+            Seeds::Dns(ref name, port) => Ok((name.as_str(), port).to_socket_addrs()?.collect()),
+            Seeds::Fixed(ref seeds) => Ok(seeds.clone()),
+        }
+    }
+}
+
+impl Discovery for GossipDiscovery {
+    fn discover(&self) -> Box<Future<Item = Vec<ClusterMember>, Error = io::Error>> {
+        let seeds = match self.resolve() {
+            Ok(seeds) => seeds,
+            Err(e) => return Box::new(future::err(e)),
+        };
+
+        let none: Box<Future<Item = Vec<ClusterMember>, Error = io::Error>> =
+            Box::new(future::err(io::Error::new(io::ErrorKind::NotFound, "No gossip seeds found")));
+
+        // the following seeds are asked only when the previous ones failed
+        seeds.into_iter().fold(none, |previous, seed| {
+            let client = self.client.clone();
+            let handle = self.handle.clone();
+            let timeout = self.timeout;
+            Box::new(previous.or_else(move |_| within(timeout, &handle, seed, gossip(&client, seed))))
+        })
+    }
+}
+
+/// Fails the gossip request with `TimedOut` unless it completes within `timeout`.
+fn within(timeout: Duration, handle: &Handle, seed: SocketAddr, request: Box<Future<Item = Vec<ClusterMember>, Error = io::Error>>) -> Box<Future<Item = Vec<ClusterMember>, Error = io::Error>> {
+    let timer = match Timeout::new(timeout, handle) {
+        Ok(timer) => timer,
+        Err(e) => return Box::new(future::err(e)),
+    };
+
+    let timed_out = timer.and_then(move |()| {
+        Err(io::Error::new(io::ErrorKind::TimedOut, format!("Gossip from {} timed out after {:?}", seed, timeout)))
+    });
+
+    Box::new(request.select(timed_out).map(|(members, _)| members).map_err(|(e, _)| e))
+}
+
+fn gossip(client: &Client<HttpConnector>, seed: SocketAddr) -> Box<Future<Item = Vec<ClusterMember>, Error = io::Error>> {
+    let uri = match format!("http://{}/gossip?format=json", seed).parse::<Uri>() {
+        Ok(uri) => uri,
+        Err(e) => return Box::new(future::err(io::Error::new(io::ErrorKind::InvalidInput, e))),
+    };
+
+    Box::new(client.get(uri).map_err(http_error).and_then(move |res| {
+        let status = res.status();
+
+        res.body().concat2().map_err(http_error).and_then(move |body| {
+            if !status.is_success() {
+                return Err(io::Error::new(io::ErrorKind::Other, format!("Gossip from {} failed with {}", seed, status)));
+            }
+
+            let gossip = serde_json::from_slice::<Value>(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            parse_members(&gossip)
+        })
+    }))
+}
+
+fn parse_members(gossip: &Value) -> io::Result<Vec<ClusterMember>> {
+    let members = match gossip.get("members").and_then(Value::as_array) {
+        Some(members) => members,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Gossip without members")),
+    };
+
+    Ok(members.iter().filter_map(|member| {
+        if member.get("isAlive").and_then(Value::as_bool) != Some(true) {
+            return None;
+        }
+
+        // servers before 20.6 call the roles master and slave
+        let role = match member.get("state").and_then(Value::as_str) {
+            Some("Leader") | Some("Master") => NodeRole::Leader,
+            Some("Follower") | Some("Slave") => NodeRole::Follower,
+            Some("ReadOnlyReplica") => NodeRole::ReadOnlyReplica,
+            _ => return None,
+        };
+
+        let ip = member.get("externalTcpIp").and_then(Value::as_str);
+        let port = member.get("externalTcpPort").and_then(Value::as_u64);
+
+        match (ip, port) {
+            (Some(ip), Some(port)) if port <= u64::from(u16::max_value()) => {
+                format!("{}:{}", ip, port).parse().ok().map(|addr| ClusterMember { role: role, tcp_addr: addr })
+            },
+            _ => None,
+        }
+    }).collect())
+}
+
+fn http_error(e: hyper::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use tokio_core::reactor::Core;
+    use cluster::{ClusterMember, Discovery, NodeRole};
+    use super::{GossipDiscovery, Seeds, parse_members};
+
+    #[test]
+    fn silent_seeds_are_timed_out() {
+        use std::io;
+        use std::time::Duration;
+        use futures::{Future, Stream};
+        use tokio_core::net::TcpListener;
+        use tokio_io::io::{read, write_all};
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        // accepted by the operating system, but never answered
+        let silent = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+
+        let answering = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let seeds = vec![silent.local_addr().unwrap(), answering.local_addr().unwrap()];
+
+        let inner = handle.clone();
+        handle.spawn(answering.incoming().for_each(move |(stream, _)| {
+            let body = r#"{"members": [{"state": "Leader", "isAlive": true, "externalTcpIp": "10.0.0.1", "externalTcpPort": 1113}]}"#;
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+
+            inner.spawn(read(stream, vec![0u8; 1024])
+                .and_then(move |(stream, _, _)| write_all(stream, response.into_bytes()))
+                .map(|_| ())
+                .map_err(|e| panic!("{}", e)));
+            Ok(())
+        }).map_err(|e: io::Error| panic!("{}", e)));
+
+        let discovery = GossipDiscovery::seeds(seeds, &handle).timeout(Duration::from_millis(50));
+        let members = core.run(discovery.discover()).unwrap();
+
+        assert_eq!(members, vec![ClusterMember { role: NodeRole::Leader, tcp_addr: "10.0.0.1:1113".parse().unwrap() }]);
+        drop(silent);
+    }
+
+    #[test]
+    fn connection_strings() {
+        let core = Core::new().unwrap();
+
+        match GossipDiscovery::parse("discover://eventstore.local:2114/", &core.handle()).unwrap().seeds {
+            Seeds::Dns(ref name, port) => assert_eq!((name.as_str(), port), ("eventstore.local", 2114)),
+            Seeds::Fixed(_) => panic!("expected a DNS name"),
+        }
+
+        match GossipDiscovery::parse("discover://eventstore.local", &core.handle()).unwrap().seeds {
+            Seeds::Dns(_, port) => assert_eq!(port, 2113),
+            Seeds::Fixed(_) => panic!("expected a DNS name"),
+        }
+
+        assert!(GossipDiscovery::parse("tcp://eventstore.local:1113", &core.handle()).is_err());
+        assert!(GossipDiscovery::parse("discover://:2113", &core.handle()).is_err());
+        assert!(GossipDiscovery::parse("discover://eventstore.local:http", &core.handle()).is_err());
+    }
+
+    #[test]
+    fn alive_members_are_parsed() {
+        let gossip = serde_json::from_str(r#"{"members": [
+            {"state": "Master", "isAlive": true, "externalTcpIp": "10.0.0.1", "externalTcpPort": 1113},
+            {"state": "Slave", "isAlive": false, "externalTcpIp": "10.0.0.2", "externalTcpPort": 1113},
+            {"state": "ReadOnlyReplica", "isAlive": true, "externalTcpIp": "10.0.0.3", "externalTcpPort": 1113},
+            {"state": "CatchingUp", "isAlive": true, "externalTcpIp": "10.0.0.4", "externalTcpPort": 1113}
+        ]}"#).unwrap();
+
+        assert_eq!(parse_members(&gossip).unwrap(), vec![
+            ClusterMember { role: NodeRole::Leader, tcp_addr: "10.0.0.1:1113".parse().unwrap() },
+            ClusterMember { role: NodeRole::ReadOnlyReplica, tcp_addr: "10.0.0.3:1113".parse().unwrap() },
+        ]);
+
+        assert!(parse_members(&serde_json::from_str("{}").unwrap()).is_err());
+    }
+}
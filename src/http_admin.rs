@@ -0,0 +1,352 @@
+//! Administration over the HTTP API of EventStore, for the operations not available over TCP
+//! such as managing projections and users. Available with the `http-admin` feature.
+//!
+//! The requests are authenticated with the credentials provider of the `ConnectionSettings`, so
+//! the TCP and HTTP clients of an application are configured once. Only plain HTTP is supported.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! extern crate futures;
+//! extern crate tokio_core;
+//! extern crate eventstore_tcp;
+//!
+//! use futures::Future;
+//! use tokio_core::reactor::Core;
+//! use eventstore_tcp::{ConnectionSettings, UsernamePassword};
+//! use eventstore_tcp::http_admin::HttpAdmin;
+//!
+//! fn main() {
+//!     let mut core = Core::new().unwrap();
+//!     let settings = ConnectionSettings::builder()
+//!         .credentials_provider(UsernamePassword::new("admin", "changeit"))
+//!         .build();
+//!
+//!     let admin = HttpAdmin::new("http://127.0.0.1:2113", &settings, &core.handle()).unwrap();
+//!
+//!     let created = admin.create_projection("order-totals", "fromCategory('orders').when({})", true)
+//!         .and_then(|_| admin.projections());
+//!
+//!     for projection in core.run(created).unwrap() {
+//!         println!("{}: {} {:.1}%", projection.name, projection.status, projection.progress);
+//!     }
+//! }
+//! ```
+
+use std::io;
+use std::fmt;
+use std::error::Error;
+
+use futures::{future, Future, Stream};
+use hyper::{self, Client, Method, Request, Uri};
+use hyper::client::HttpConnector;
+use hyper::header::{Authorization, Basic, ContentType};
+use serde_json::{self, Map, Value};
+use tokio_core::reactor::Handle;
+
+use operation;
+use {ConnectionSettings, UsernamePassword};
+
+/// A projection as listed by `HttpAdmin::projections`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectionInfo {
+    /// Name of the projection
+    pub name: String,
+    /// Status such as `Running` or `Stopped`
+    pub status: String,
+    /// Mode such as `Continuous` or `OneTime`
+    pub mode: String,
+    /// Progress in percent
+    pub progress: f64,
+}
+
+/// A user as listed by `HttpAdmin::users`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserInfo {
+    /// The name the user logs in with
+    pub login_name: String,
+    /// Full name of the user
+    pub full_name: String,
+    /// Groups of the user, such as `$admins`
+    pub groups: Vec<String>,
+    /// Is the user disabled
+    pub disabled: bool,
+}
+
+/// Client of the HTTP API of a node.
+#[derive(Clone)]
+pub struct HttpAdmin {
+    client: Client<HttpConnector>,
+    base: String,
+    settings: ConnectionSettings,
+}
+
+impl HttpAdmin {
+    /// Creates a client of the HTTP API at `base`, for example `http://127.0.0.1:2113`,
+    /// authenticating with the credentials provider of `settings` if it has one.
+    pub fn new(base: &str, settings: &ConnectionSettings, handle: &Handle) -> io::Result<Self> {
+        if let Err(e) = base.parse::<Uri>() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid base URL {:?}: {}", base, e)));
+        }
+
+        Ok(HttpAdmin {
+            client: Client::new(handle),
+            base: base.trim_right_matches('/').to_owned(),
+            settings: settings.clone(),
+        })
+    }
+
+    /// Lists all of the projections.
+    pub fn projections(&self) -> Box<Future<Item = Vec<ProjectionInfo>, Error = io::Error>> {
+        Box::new(self.send(Method::Get, "/projections/any".into(), None).and_then(|body| parse_projections(&body)))
+    }
+
+    /// Creates a continuous projection running `query`, enabled and checkpointed. `emit` allows
+    /// the projection to write to streams.
+    pub fn create_projection(&self, name: &str, query: &str, emit: bool) -> Box<Future<Item = (), Error = io::Error>> {
+        let path = format!("/projections/continuous?name={}&emit={}&checkpoints=true&enabled=true", encode(name), emit);
+        self.command(Method::Post, path, Some((ContentType::plaintext(), query.to_owned())))
+    }
+
+    /// The state of the projection as JSON.
+    pub fn projection_state(&self, name: &str) -> Box<Future<Item = Vec<u8>, Error = io::Error>> {
+        self.send(Method::Get, format!("/projection/{}/state", encode(name)), None)
+    }
+
+    /// Starts the projection.
+    pub fn enable_projection(&self, name: &str) -> Box<Future<Item = (), Error = io::Error>> {
+        self.command(Method::Post, format!("/projection/{}/command/enable", encode(name)), None)
+    }
+
+    /// Stops the projection.
+    pub fn disable_projection(&self, name: &str) -> Box<Future<Item = (), Error = io::Error>> {
+        self.command(Method::Post, format!("/projection/{}/command/disable", encode(name)), None)
+    }
+
+    /// Runs the projection again from the beginning.
+    pub fn reset_projection(&self, name: &str) -> Box<Future<Item = (), Error = io::Error>> {
+        self.command(Method::Post, format!("/projection/{}/command/reset", encode(name)), None)
+    }
+
+    /// Deletes the stopped projection.
+    pub fn delete_projection(&self, name: &str) -> Box<Future<Item = (), Error = io::Error>> {
+        self.command(Method::Delete, format!("/projection/{}", encode(name)), None)
+    }
+
+    /// Lists all of the users.
+    pub fn users(&self) -> Box<Future<Item = Vec<UserInfo>, Error = io::Error>> {
+        Box::new(self.send(Method::Get, "/users/".into(), None).and_then(|body| parse_users(&body)))
+    }
+
+    /// Creates a user with the login name and password of `credentials`.
+    pub fn create_user(&self, credentials: &UsernamePassword, full_name: &str, groups: &[&str]) -> Box<Future<Item = (), Error = io::Error>> {
+        let mut user = Map::new();
+        user.insert("loginName".into(), credentials.username().into());
+        user.insert("fullName".into(), full_name.into());
+        user.insert("groups".into(), Value::Array(groups.iter().map(|&group| group.into()).collect()));
+        user.insert("password".into(), credentials.password().into());
+
+        self.command(Method::Post, "/users/".into(), Some((ContentType::json(), Value::Object(user).to_string())))
+    }
+
+    /// Sets a new password for the user.
+    pub fn reset_password(&self, login_name: &str, password: &str) -> Box<Future<Item = (), Error = io::Error>> {
+        let mut body = Map::new();
+        body.insert("newPassword".into(), password.into());
+
+        self.command(Method::Post, format!("/users/{}/command/reset-password", encode(login_name)), Some((ContentType::json(), Value::Object(body).to_string())))
+    }
+
+    /// Allows the user to log in again.
+    pub fn enable_user(&self, login_name: &str) -> Box<Future<Item = (), Error = io::Error>> {
+        self.command(Method::Post, format!("/users/{}/command/enable", encode(login_name)), None)
+    }
+
+    /// Prevents the user from logging in.
+    pub fn disable_user(&self, login_name: &str) -> Box<Future<Item = (), Error = io::Error>> {
+        self.command(Method::Post, format!("/users/{}/command/disable", encode(login_name)), None)
+    }
+
+    /// Deletes the user.
+    pub fn delete_user(&self, login_name: &str) -> Box<Future<Item = (), Error = io::Error>> {
+        self.command(Method::Delete, format!("/users/{}", encode(login_name)), None)
+    }
+
+    fn command(&self, method: Method, path: String, body: Option<(ContentType, String)>) -> Box<Future<Item = (), Error = io::Error>> {
+        Box::new(self.send(method, path, body).map(|_| ()))
+    }
+
+    /// Sends the request, completing with the body of a successful response.
+    fn send(&self, method: Method, path: String, body: Option<(ContentType, String)>) -> Box<Future<Item = Vec<u8>, Error = io::Error>> {
+        let uri = match format!("{}{}", self.base, path).parse::<Uri>() {
+            Ok(uri) => uri,
+            Err(e) => return Box::new(future::err(io::Error::new(io::ErrorKind::InvalidInput, e))),
+        };
+
+        let credentials: Box<Future<Item = Option<UsernamePassword>, Error = io::Error>> = match self.settings.credentials_provider() {
+            Some(provider) => Box::new(provider.get().map(Some)),
+            None => Box::new(future::ok(None)),
+        };
+
+        let client = self.client.clone();
+
+        Box::new(credentials.and_then(move |credentials| {
+            let mut req = Request::new(method, uri);
+
+            if let Some(credentials) = credentials {
+                req.headers_mut().set(Authorization(Basic {
+                    username: credentials.username().to_owned(),
+                    password: Some(credentials.password().to_owned()),
+                }));
+            }
+
+            if let Some((content_type, body)) = body {
+                req.headers_mut().set(content_type);
+                req.set_body(body);
+            }
+
+            client.request(req).map_err(http_error).and_then(|res| {
+                let status = res.status();
+
+                res.body().concat2().map_err(http_error).and_then(move |body| {
+                    if status.is_success() {
+                        Ok(body.to_vec())
+                    } else {
+                        Err(AdminRequestFailed {
+                            status: status.as_u16(),
+                            body: String::from_utf8_lossy(&body).into_owned(),
+                        }.into())
+                    }
+                })
+            })
+        }))
+    }
+}
+
+/// The HTTP API answered a request with an error status. Operations failing because of this are
+/// completed with an `io::Error` wrapping this value, of kind `PermissionDenied` for 401 and 403,
+/// `NotFound` for 404 and `Other` for the rest, see `AdminRequestFailed::find`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminRequestFailed {
+    /// The status code of the response
+    pub status: u16,
+    /// The body of the response, usually explaining the error
+    pub body: String,
+}
+
+impl AdminRequestFailed {
+    /// Returns the `AdminRequestFailed` wrapped by the given error, if any.
+    pub fn find(e: &io::Error) -> Option<&AdminRequestFailed> {
+        operation::find::<AdminRequestFailed>(e)
+    }
+}
+
+impl fmt::Display for AdminRequestFailed {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: {} {}", self.description(), self.status, self.body)
+    }
+}
+
+impl Error for AdminRequestFailed {
+    fn description(&self) -> &str {
+        "Admin request failed"
+    }
+}
+
+impl From<AdminRequestFailed> for io::Error {
+    fn from(failed: AdminRequestFailed) -> io::Error {
+        let kind = match failed.status {
+            401 | 403 => io::ErrorKind::PermissionDenied,
+            404 => io::ErrorKind::NotFound,
+            _ => io::ErrorKind::Other,
+        };
+
+        io::Error::new(kind, failed)
+    }
+}
+
+fn http_error(e: hyper::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+/// Percent-encodes everything but the unreserved characters of RFC 3986.
+fn encode(segment: &str) -> String {
+    segment.bytes().map(|b| match b {
+        b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+fn parse_json(body: &[u8]) -> io::Result<Value> {
+    serde_json::from_slice(body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected {} in the response", what))
+}
+
+fn parse_projections(body: &[u8]) -> io::Result<Vec<ProjectionInfo>> {
+    let json = parse_json(body)?;
+    let projections = json.get("projections").and_then(Value::as_array).ok_or_else(|| invalid("projections"))?;
+
+    projections.iter().map(|projection| {
+        let text = |field: &str| projection.get(field).and_then(Value::as_str).map(|s| s.to_owned());
+
+        Ok(ProjectionInfo {
+            name: text("name").ok_or_else(|| invalid("projection without a name"))?,
+            status: text("status").unwrap_or_default(),
+            mode: text("mode").unwrap_or_default(),
+            progress: projection.get("progress").and_then(Value::as_f64).unwrap_or(0.0),
+        })
+    }).collect()
+}
+
+fn parse_users(body: &[u8]) -> io::Result<Vec<UserInfo>> {
+    let json = parse_json(body)?;
+    let users = json.get("data").and_then(Value::as_array).ok_or_else(|| invalid("users"))?;
+
+    users.iter().map(|user| {
+        let text = |field: &str| user.get(field).and_then(Value::as_str).map(|s| s.to_owned());
+
+        Ok(UserInfo {
+            login_name: text("loginName").ok_or_else(|| invalid("user without a login name"))?,
+            full_name: text("fullName").unwrap_or_default(),
+            groups: user.get("groups").and_then(Value::as_array)
+                .map(|groups| groups.iter().filter_map(Value::as_str).map(|group| group.to_owned()).collect())
+                .unwrap_or_default(),
+            disabled: user.get("disabled").and_then(Value::as_bool).unwrap_or(false),
+        })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, parse_projections, parse_users, ProjectionInfo, UserInfo};
+
+    #[test]
+    fn names_are_encoded() {
+        assert_eq!(encode("$by_category"), "%24by_category");
+        assert_eq!(encode("order totals/eu"), "order%20totals%2Feu");
+    }
+
+    #[test]
+    fn listings_are_parsed() {
+        let projections = br#"{"projections":[{"name":"$by_category","status":"Running","mode":"Continuous","progress":100.0,"eventsProcessedAfterRestart":5}]}"#;
+        assert_eq!(parse_projections(projections).unwrap(), vec![ProjectionInfo {
+            name: "$by_category".into(),
+            status: "Running".into(),
+            mode: "Continuous".into(),
+            progress: 100.0,
+        }]);
+
+        let users = br#"{"data":[{"loginName":"admin","fullName":"Event Store Administrator","groups":["$admins"],"disabled":false}]}"#;
+        assert_eq!(parse_users(users).unwrap(), vec![UserInfo {
+            login_name: "admin".into(),
+            full_name: "Event Store Administrator".into(),
+            groups: vec!["$admins".into()],
+            disabled: false,
+        }]);
+
+        assert!(parse_users(b"[]").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Reading single fields of JSON documents without a JSON parser.
+
+use std::str;
+
+/// Finds the value of a top level field holding a non-negative integer from a JSON object,
+/// without parsing the rest of the document. The other values of the object are only skipped
+/// over, so a key of a nested object or the text of a string value never matches the field.
+pub fn json_u32(json: &[u8], field: &str) -> Option<u32> {
+    let mut pos = whitespace(json, 0);
+
+    if json.get(pos) != Some(&b'{') {
+        return None;
+    }
+
+    pos = whitespace(json, pos + 1);
+
+    loop {
+        let key_end = match string_end(json, pos) {
+            Some(end) => end,
+            None => return None,
+        };
+
+        let key = &json[pos + 1..key_end - 1];
+        pos = whitespace(json, key_end);
+
+        if json.get(pos) != Some(&b':') {
+            return None;
+        }
+
+        pos = whitespace(json, pos + 1);
+
+        let value_end = match value_end(json, pos) {
+            Some(end) => end,
+            None => return None,
+        };
+
+        if key == field.as_bytes() {
+            let value = &json[pos..value_end];
+
+            return if value.iter().all(|&b| b'0' <= b && b <= b'9') {
+                str::from_utf8(value).ok().and_then(|value| value.parse().ok())
+            } else {
+                None
+            };
+        }
+
+        pos = whitespace(json, value_end);
+
+        match json.get(pos) {
+            Some(&b',') => pos = whitespace(json, pos + 1),
+            _ => return None,
+        }
+    }
+}
+
+fn whitespace(json: &[u8], mut pos: usize) -> usize {
+    while pos < json.len() && is_whitespace(json[pos]) {
+        pos += 1;
+    }
+    pos
+}
+
+fn is_whitespace(b: u8) -> bool {
+    b == b' ' || b == b'\t' || b == b'\n' || b == b'\r'
+}
+
+/// The position after the string starting at `pos`, skipping the escaped quotes.
+fn string_end(json: &[u8], pos: usize) -> Option<usize> {
+    if json.get(pos) != Some(&b'"') {
+        return None;
+    }
+
+    let mut i = pos + 1;
+
+    while i < json.len() {
+        match json[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+
+    None
+}
+
+/// The position after the value starting at `pos`: a string, an object or an array with
+/// everything nested in it, or a number, boolean or null.
+fn value_end(json: &[u8], pos: usize) -> Option<usize> {
+    match json.get(pos) {
+        Some(&b'"') => string_end(json, pos),
+        Some(&b'{') | Some(&b'[') => {
+            let mut depth = 0;
+            let mut i = pos;
+
+            loop {
+                match json.get(i) {
+                    Some(&b'"') => {
+                        i = match string_end(json, i) {
+                            Some(end) => end,
+                            None => return None,
+                        };
+                        continue;
+                    },
+                    Some(&b'{') | Some(&b'[') => depth += 1,
+                    Some(&b'}') | Some(&b']') => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(i + 1);
+                        }
+                    },
+                    Some(_) => {},
+                    None => return None,
+                }
+
+                i += 1;
+            }
+        },
+        Some(_) => {
+            let mut i = pos;
+
+            while i < json.len() && !is_whitespace(json[i]) && json[i] != b',' && json[i] != b'}' && json[i] != b']' {
+                i += 1;
+            }
+
+            if i > pos { Some(i) } else { None }
+        },
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::json_u32;
+
+    #[test]
+    fn top_level_integers() {
+        assert_eq!(json_u32(br#"{"a":"x","version":42}"#, "version"), Some(42));
+        assert_eq!(json_u32(br#"{ "a" : [1, {"b": null}] , "version" : 7 }"#, "version"), Some(7));
+        assert_eq!(json_u32(br#"{"version":"42"}"#, "version"), None);
+        assert_eq!(json_u32(br#"{"version":-1}"#, "version"), None);
+        assert_eq!(json_u32(br#"{"version":4.5}"#, "version"), None);
+        assert_eq!(json_u32(b"\xff", "version"), None);
+    }
+
+    #[test]
+    fn nested_keys_are_skipped() {
+        assert_eq!(json_u32(br#"{"data":{"schemaVersion":3}}"#, "schemaVersion"), None);
+        assert_eq!(json_u32(br#"{"data":[{"schemaVersion":3}],"schemaVersion":2}"#, "schemaVersion"), Some(2));
+    }
+
+    #[test]
+    fn keys_inside_strings_are_skipped() {
+        assert_eq!(json_u32(br#"{"note":"\"$maxAge\": 60"}"#, "$maxAge"), None);
+        assert_eq!(json_u32(br#"{"note":"\"$maxAge\": 60, }","$maxAge":3600}"#, "$maxAge"), Some(3600));
+    }
+}
@@ -7,10 +7,11 @@
 //! You can build values of `Package` using `builder::Builder` and it's functions. Actual payloads
 //! are described as `Message` enum.
 //!
-//! The protocol is multiplexed so you can have multiple (hard limit is 128 currently) calls going
-//! at any point in time. Current implementation is based on `tokio_proto::multiplex`, at the
-//! moment using a custom fork. It does not yet support `tokio_proto::streaming::multiplex` which is
-//! needed to support subscriptions.
+//! The protocol is multiplexed so you can have multiple calls going at any point in time. The
+//! connection is owned by a task spawned on the reactor, which correlates responses to requests,
+//! keeps the connection alive with heartbeats and reconnects when needed. Requests answered with
+//! more than one package, such as subscriptions, are sent with `EventStoreClient::call_streaming`;
+//! `EventStoreClient::subscribe_to_stream` streams the events pushed to a subscription.
 //!
 //! # Panics
 //!
@@ -25,17 +26,14 @@
 //!
 //! extern crate futures;
 //! extern crate tokio_core;
-//! extern crate tokio_proto;
-//! extern crate tokio_service;
 //! extern crate eventstore_tcp;
 //!
 //! use std::convert::TryFrom;
 //! use std::net::SocketAddr;
 //! use futures::Future;
 //! use tokio_core::reactor::Core;
-//! use tokio_service::Service;
 //!
-//! use eventstore_tcp::{EventStoreClient, Builder, AdaptedMessage, StreamVersion, ContentType};
+//! use eventstore_tcp::prelude::*;
 //!
 //! fn main() {
 //!     let addr = "127.0.0.1:1113".parse::<SocketAddr>().unwrap();
@@ -78,53 +76,212 @@
 //! ```
 //!
 //! More examples can be found in the aspiring command line tool under `testclient/`.
+//!
+//! # Protocol only
+//!
+//! Without the default `client` feature only the protocol is built: `codec`, `package`, `raw`,
+//! `adapted` and `builder` with the value types, and none of the sockets, clients or servers.
+//! This build also compiles for `wasm32-unknown-unknown`, for decoding captured frames in the
+//! browser, like the `decoder/` tool does on the command line.
 #![deny(missing_docs)]
 #![feature(try_from)]
+#![cfg_attr(test, feature(test))]
 
 #[macro_use]
 extern crate bitflags;
 extern crate quick_protobuf;
 extern crate uuid;
 extern crate byteorder;
+#[cfg(feature = "client")]
+extern crate rand;
 #[macro_use]
 extern crate error_chain;
 extern crate futures;
 extern crate tokio_io;
+#[cfg(feature = "client")]
 extern crate tokio_core;
-extern crate tokio_proto;
+#[cfg(feature = "client")]
 extern crate tokio_service;
 extern crate bytes;
 #[macro_use]
 extern crate derive_more;
 #[macro_use]
 extern crate derive_into_owned;
+#[macro_use]
+extern crate log;
+#[cfg(feature = "tls")]
+extern crate native_tls;
+#[cfg(feature = "tls")]
+extern crate tokio_tls;
+#[cfg(feature = "tls")]
+extern crate sha2;
+#[cfg(any(feature = "json", feature = "serialize"))]
+extern crate serde;
+#[cfg(feature = "serialize")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(any(feature = "json", feature = "cloudevents", feature = "http-admin", feature = "gossip", feature = "system-streams"))]
+extern crate serde_json;
+#[cfg(feature = "cloudevents")]
+extern crate base64;
+#[cfg(feature = "cpupool")]
+extern crate futures_cpupool;
+#[cfg(all(unix, feature = "unix"))]
+extern crate tokio_uds;
+#[cfg(any(feature = "http-admin", feature = "gossip"))]
+extern crate hyper;
+#[cfg(feature = "tower")]
+extern crate tower_service;
 
 #[cfg(test)]
 extern crate hex;
+#[cfg(test)]
+extern crate test;
 
 use std::str;
 
 pub mod raw;
-pub use raw::RawMessage;
-pub use raw::client_messages::{WriteEvents, ResolvedIndexedEvent, EventRecord, ReadAllEvents};
-pub use raw::client_messages::mod_NotHandled::{NotHandledReason, MasterInfo};
+pub use raw::{RawMessage, TcpCommand};
+pub use raw::{WriteEvents, NewEvent, ResolvedIndexedEvent, EventRecord, ReadAllEvents};
+pub use raw::{NotHandledReason, MasterInfo};
 
 pub mod adapted;
-pub use adapted::AdaptedMessage;
+pub use adapted::{AdaptedMessage, ToMessage};
 
 pub mod package;
 pub use package::Package;
 
 pub mod codec;
 
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "client")]
+pub mod server;
+
+#[cfg(feature = "client")]
+pub mod feed;
+
+#[cfg(feature = "eventsourcing")]
+pub mod eventsourcing;
+
+#[cfg(feature = "cloudevents")]
+pub mod cloudevents;
+
+#[cfg(feature = "http-admin")]
+pub mod http_admin;
+
+#[cfg(feature = "system-streams")]
+pub mod system;
+
+#[cfg(feature = "client")]
 mod client;
-pub use client::EventStoreClient;
+#[cfg(feature = "client")]
+pub use client::{EventStoreClient, StreamingClient};
+
+#[cfg(feature = "client")]
+mod connection;
+#[cfg(feature = "client")]
+pub use connection::{ConnectionClosed, ConnectionEvent};
+
+#[cfg(feature = "client")]
+mod pending;
+
+#[cfg(feature = "client")]
+mod diagnostics;
+#[cfg(feature = "client")]
+pub use diagnostics::Diagnostics;
+
+#[cfg(feature = "client")]
+mod deadline;
+#[cfg(feature = "client")]
+pub use deadline::DeadlineExceeded;
+
+mod operation;
+pub use operation::OperationError;
+
+#[cfg(feature = "client")]
+mod backoff;
+#[cfg(feature = "client")]
+pub use backoff::{Backoff, NewBackoff, DecorrelatedJitter};
+
+mod broadcast;
+pub use broadcast::Broadcast;
+
+#[cfg(feature = "client")]
+mod budget;
+#[cfg(feature = "client")]
+pub use budget::{MemoryBudget, OverBudget, BudgetExceeded};
+
+#[cfg(feature = "client")]
+mod buffer;
+#[cfg(feature = "client")]
+pub use buffer::{WriteBuffer, WriteQueue, MemoryWriteQueue, FileWriteQueue, Flushed};
+
+#[cfg(feature = "client")]
+mod transaction;
+#[cfg(feature = "client")]
+pub use transaction::TransactionHandle;
+
+#[cfg(feature = "client")]
+mod cluster;
+#[cfg(feature = "client")]
+pub use cluster::{ClusterClient, ClusterMember, Discovery, NodePreference, NodeRole};
+
+#[cfg(feature = "gossip")]
+mod gossip;
+#[cfg(feature = "gossip")]
+pub use gossip::GossipDiscovery;
+
+#[cfg(feature = "client")]
+mod tls;
+#[cfg(feature = "client")]
+pub use tls::{TlsSettings, TlsSettingsBuilder, ClientIdentity, ServerIdentity};
+
+#[cfg(feature = "client")]
+mod bus;
+#[cfg(feature = "client")]
+pub use bus::{EventWriter, EventReader, EventSubscriber, EventBus};
+
+#[cfg(feature = "client")]
+mod settings;
+#[cfg(feature = "client")]
+pub use settings::{ConnectionSettings, ConnectionSettingsBuilder};
+
+#[cfg(feature = "client")]
+mod config;
+#[cfg(feature = "client")]
+pub use config::{ClientConfig, CredentialsConfig, TlsConfig, InvalidConfig};
+
+#[cfg(feature = "client")]
+mod transport;
+#[cfg(feature = "client")]
+pub use transport::{AsyncIo, Transport, TcpTransport, DuplexStream, duplex, MemoryTransport, MemoryIncoming};
+#[cfg(all(unix, feature = "unix"))]
+pub use transport::UnixTransport;
 
 pub mod builder;
 pub use builder::Builder;
 
+#[cfg(feature = "client")]
+pub mod prelude;
+
+#[cfg(feature = "client")]
+mod json;
+
+#[cfg(feature = "client")]
+mod response;
+
+mod unsupported;
+
+#[cfg(all(test, feature = "client"))]
+mod fixtures;
+
+mod validate;
+pub use validate::{InvalidWrite, Violation, MAX_EVENT_SIZE};
+
 mod auth;
-pub use auth::UsernamePassword;
+pub use auth::{UsernamePassword, CredentialsProvider, CredentialsMap, AuthenticationFailed};
 
 mod event_number;
 pub use event_number::EventNumber;
@@ -132,12 +289,21 @@ pub use event_number::EventNumber;
 mod stream_version;
 pub use stream_version::StreamVersion;
 
+mod stream_id;
+pub use stream_id::StreamId;
+
+mod transaction_id;
+pub use transaction_id::TransactionId;
+
 mod expected_version;
 pub use expected_version::ExpectedVersion;
 
 mod content_type;
 pub use content_type::ContentType;
 
+mod persistent_subscription;
+pub use persistent_subscription::PersistentSubscriptionSettings;
+
 mod errors {
     use std::str;
     use std::io;
@@ -152,6 +318,16 @@ mod errors {
         ReadEvent,
         /// Missing from ReadStreamEventsCompleted
         ReadStream,
+        /// Missing from DeleteStreamCompleted
+        DeleteStream,
+        /// Missing from TransactionStartCompleted
+        TransactionStart,
+        /// Missing from TransactionWriteCompleted
+        TransactionWrite,
+        /// Missing from TransactionCommitCompleted
+        TransactionCommit,
+        /// Missing from NotHandled
+        NotHandled,
     }
 
     impl fmt::Display for ResultStatusKind {
@@ -161,6 +337,11 @@ mod errors {
                 WriteEvents => "WriteEventsCompleted::result",
                 ReadEvent => "ReadEventCompleted::result",
                 ReadStream => "ReadStreamEventsCompleted::result",
+                DeleteStream => "DeleteStreamCompleted::result",
+                TransactionStart => "TransactionStartCompleted::result",
+                TransactionWrite => "TransactionWriteCompleted::result",
+                TransactionCommit => "TransactionCommitCompleted::result",
+                NotHandled => "NotHandled::reason",
             })
         }
     }
@@ -192,9 +373,15 @@ mod errors {
             UnimplementedConversion {
                 display("Unimplemented conversion")
             }
+            InvalidMasterInfo {
+                display("Invalid master info of NotHandled")
+            }
             WriteEventsInvalidTransaction {
                 display("Unexpected write events result: invalid transaction")
             }
+            DeleteStreamInvalidTransaction {
+                display("Unexpected delete stream result: invalid transaction")
+            }
         }
     }
 
@@ -214,7 +401,8 @@ mod errors {
 use self::errors::{Error, ErrorKind};
 
 /// The direction in which events are read.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum ReadDirection {
     /// Read from first (event 0) to the latest
     Forward,
@@ -224,7 +412,8 @@ pub enum ReadDirection {
 
 /// Global unique position in the EventStore, used when reading all events.
 /// Range -1..i64::max_value()
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum LogPosition {
     /// The first event ever
     First,
@@ -0,0 +1,146 @@
+//! Context of the operations failed by the connection task.
+
+use std::io;
+use std::fmt;
+use std::error::Error;
+use std::net::SocketAddr;
+
+use uuid::Uuid;
+
+#[cfg(feature = "client")]
+use package::Package;
+use raw::TcpCommand;
+
+/// The request of an operation failed by the connection, and the node it was sent to. The
+/// connection wraps the cause of the failure into this, in an `io::Error` of the same kind as the
+/// cause, see `OperationError::find`.
+///
+/// The errors wrapped by the connection such as `ConnectionClosed` can be found through this
+/// with their own `find`.
+#[derive(Debug)]
+pub struct OperationError {
+    /// Discriminator of the request
+    pub command: u8,
+    /// Stream targeted by the request, see `RawMessage::stream_id`
+    pub stream_id: Option<String>,
+    /// Correlation id the request was last sent with
+    pub correlation_id: Uuid,
+    /// Address of the node the request was sent or would have been sent to
+    pub endpoint: SocketAddr,
+    cause: io::Error,
+}
+
+impl OperationError {
+    /// The command of the request, if it is in the protocol.
+    pub fn command(&self) -> Option<TcpCommand> {
+        use std::convert::TryFrom;
+        TcpCommand::try_from(self.command).ok()
+    }
+
+    /// The error which failed the operation.
+    pub fn inner(&self) -> &io::Error {
+        &self.cause
+    }
+
+    /// Returns the `OperationError` wrapped by the given error, if any.
+    pub fn find(e: &io::Error) -> Option<&OperationError> {
+        e.get_ref().and_then(|inner| inner.downcast_ref::<OperationError>())
+    }
+}
+
+impl fmt::Display for OperationError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.command() {
+            Some(command) => write!(fmt, "{:?}", command)?,
+            None => write!(fmt, "0x{:02x}", self.command)?,
+        }
+
+        if let Some(ref stream_id) = self.stream_id {
+            write!(fmt, " on {}", stream_id)?;
+        }
+
+        write!(fmt, " ({}) to {} failed: {}", self.correlation_id, self.endpoint, self.cause)
+    }
+}
+
+impl Error for OperationError {
+    fn description(&self) -> &str {
+        "Operation failed"
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        Some(&self.cause)
+    }
+}
+
+/// The context of a request kept by the connection while the request is in flight.
+#[cfg(feature = "client")]
+#[derive(Debug, Clone)]
+pub struct Context {
+    command: u8,
+    stream_id: Option<String>,
+    correlation_id: Uuid,
+}
+
+#[cfg(feature = "client")]
+impl Context {
+    /// Captures the context of the request, before it is sent.
+    pub fn of(package: &Package) -> Self {
+        Context {
+            command: package.message.discriminator(),
+            stream_id: package.message.stream_id().map(|id| id.to_owned()),
+            correlation_id: package.correlation_id,
+        }
+    }
+
+    /// The discriminator of the request.
+    pub fn command(&self) -> u8 {
+        self.command
+    }
+
+    /// Wraps the error failing the request sent to `endpoint` into an `OperationError`.
+    pub fn error(&self, endpoint: SocketAddr, cause: io::Error) -> io::Error {
+        let kind = cause.kind();
+
+        io::Error::new(kind, OperationError {
+            command: self.command,
+            stream_id: self.stream_id.clone(),
+            correlation_id: self.correlation_id,
+            endpoint: endpoint,
+            cause: cause,
+        })
+    }
+}
+
+/// Returns the error of type `T` wrapped by the given error, directly or within an
+/// `OperationError`.
+pub fn find<T: Error + 'static>(e: &io::Error) -> Option<&T> {
+    match OperationError::find(e) {
+        Some(operation) => find(&operation.cause),
+        None => e.get_ref().and_then(|inner| inner.downcast_ref::<T>()),
+    }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use std::io;
+    use builder::Builder;
+    use raw::TcpCommand;
+    use {ConnectionClosed, OperationError};
+    use super::Context;
+
+    #[test]
+    fn context_is_found_with_the_cause() {
+        let package = Builder::read_event().stream_id("orders").event_number(::EventNumber::First).build_package(None, None);
+        let e = Context::of(&package).error("127.0.0.1:1113".parse().unwrap(), ConnectionClosed::new("gone").into());
+
+        assert_eq!(e.kind(), io::ErrorKind::NotConnected);
+        assert_eq!(ConnectionClosed::find(&e), Some(&ConnectionClosed::new("gone")));
+
+        let operation = OperationError::find(&e).unwrap();
+        assert_eq!(operation.command(), Some(TcpCommand::ReadEvent));
+        assert_eq!(operation.stream_id, Some("orders".to_owned()));
+        assert_eq!(operation.correlation_id, package.correlation_id);
+        assert_eq!(e.to_string(), format!("ReadEvent on orders ({}) to 127.0.0.1:1113 failed: Connection closed: gone", package.correlation_id));
+    }
+}
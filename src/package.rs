@@ -1,5 +1,6 @@
 //! Frame and MessageContainer
 
+use std::fmt;
 use uuid::Uuid;
 use UsernamePassword;
 use raw;
@@ -18,6 +19,62 @@ pub struct Package {
     pub message: raw::RawMessage<'static>,
 }
 
+/// `Package` decoded with `PackageCodec::decode_borrowed`, with the payloads borrowed from the
+/// decoded buffer.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BorrowedPackage<'a> {
+    /// Possible authentication data included in the packet.
+    pub authentication: Option<UsernamePassword>,
+    /// Correlation id of the packet.
+    pub correlation_id: Uuid,
+    /// The message borrowing from the decoded buffer.
+    pub message: raw::RawMessage<'a>,
+}
+
+impl Package {
+    /// Creates a package of the message without authentication, correlated with a new random
+    /// id.
+    pub fn new<M: Into<raw::RawMessage<'static>>>(message: M) -> Self {
+        Package {
+            authentication: None,
+            correlation_id: Uuid::new_v4(),
+            message: message.into(),
+        }
+    }
+
+    /// Creates a package of the message authenticated with the credentials, correlated with a new
+    /// random id. The codec sets the authenticated flag of the frame for these.
+    pub fn with_auth<M: Into<raw::RawMessage<'static>>>(message: M, credentials: UsernamePassword) -> Self {
+        Package {
+            authentication: Some(credentials),
+            ..Package::new(message)
+        }
+    }
+}
+
+impl<'a> BorrowedPackage<'a> {
+    /// Copies the borrowed payloads into a `Package` which can outlive the decoded buffer.
+    pub fn into_owned(self) -> Package {
+        Package {
+            authentication: self.authentication,
+            correlation_id: self.correlation_id,
+            message: self.message.into_owned(),
+        }
+    }
+}
+
+/// One line summary for logging: the discriminator, correlation id and whether the package is
+/// authenticated, but never the credentials or the payload.
+impl fmt::Display for Package {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "0x{:02x} {}", self.message.discriminator(), self.correlation_id)?;
+        if self.authentication.is_some() {
+            write!(fmt, " authenticated")?;
+        }
+        Ok(())
+    }
+}
+
 trait SendReq: Send {}
 
 impl SendReq for Package {}
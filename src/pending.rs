@@ -0,0 +1,243 @@
+//! Table of the requests in flight on a connection, keyed by correlation id.
+//!
+//! The values are kept in a slab, and the index only maps the correlation ids to the slots.
+//! Removed slots are chained into a free list and reused by the following inserts, so the table
+//! does not grow with the number of requests made over the lifetime of the connection but only
+//! with the number of concurrent requests.
+
+use std::mem;
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+enum Slot<T> {
+    Occupied(Uuid, T),
+    /// Free slot with the index of the next free slot
+    Vacant(usize),
+}
+
+pub struct Pending<T> {
+    slots: Vec<Slot<T>>,
+    index: HashMap<Uuid, usize>,
+    /// First free slot, `slots.len()` if there are none
+    next_free: usize,
+}
+
+impl<T> Pending<T> {
+    pub fn new() -> Self {
+        Pending {
+            slots: Vec::new(),
+            index: HashMap::new(),
+            next_free: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<&T> {
+        match self.index.get(id).map(|&key| &self.slots[key]) {
+            Some(&Slot::Occupied(_, ref value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, id: &Uuid) -> Option<&mut T> {
+        match self.index.get(id) {
+            Some(&key) => match self.slots[key] {
+                Slot::Occupied(_, ref mut value) => Some(value),
+                Slot::Vacant(_) => None,
+            },
+            None => None,
+        }
+    }
+
+    pub fn values<'a>(&'a self) -> Box<Iterator<Item = &'a T> + 'a> {
+        Box::new(self.slots.iter().filter_map(|slot| match *slot {
+            Slot::Occupied(_, ref value) => Some(value),
+            Slot::Vacant(_) => None,
+        }))
+    }
+
+    pub fn iter<'a>(&'a self) -> Box<Iterator<Item = (&'a Uuid, &'a T)> + 'a> {
+        Box::new(self.slots.iter().filter_map(|slot| match *slot {
+            Slot::Occupied(ref id, ref value) => Some((id, value)),
+            Slot::Vacant(_) => None,
+        }))
+    }
+
+    /// Inserts the value, replacing and returning the value with the same id if any.
+    pub fn insert(&mut self, id: Uuid, value: T) -> Option<T> {
+        if let Some(&key) = self.index.get(&id) {
+            return match mem::replace(&mut self.slots[key], Slot::Occupied(id, value)) {
+                Slot::Occupied(_, old) => Some(old),
+                Slot::Vacant(_) => unreachable!("indexed slot was vacant"),
+            };
+        }
+
+        let key = self.next_free;
+
+        if key == self.slots.len() {
+            self.slots.push(Slot::Occupied(id, value));
+            self.next_free = self.slots.len();
+        } else {
+            self.next_free = match mem::replace(&mut self.slots[key], Slot::Occupied(id, value)) {
+                Slot::Vacant(next) => next,
+                Slot::Occupied(..) => unreachable!("free slot was occupied"),
+            };
+        }
+
+        self.index.insert(id, key);
+        None
+    }
+
+    pub fn remove(&mut self, id: &Uuid) -> Option<T> {
+        let key = match self.index.remove(id) {
+            Some(key) => key,
+            None => return None,
+        };
+
+        match mem::replace(&mut self.slots[key], Slot::Vacant(self.next_free)) {
+            Slot::Occupied(_, value) => {
+                self.next_free = key;
+                Some(value)
+            },
+            Slot::Vacant(_) => unreachable!("indexed slot was vacant"),
+        }
+    }
+
+    /// Removes all of the values.
+    pub fn drain(&mut self) -> Vec<(Uuid, T)> {
+        self.index.clear();
+        self.next_free = 0;
+
+        mem::replace(&mut self.slots, Vec::new())
+            .into_iter()
+            .filter_map(|slot| match slot {
+                Slot::Occupied(id, value) => Some((id, value)),
+                Slot::Vacant(_) => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use test::{Bencher, black_box};
+    use uuid::Uuid;
+    use super::Pending;
+
+    #[test]
+    fn lookups() {
+        let mut pending = Pending::new();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        pending.insert(a, 1);
+        pending.insert(b, 2);
+        pending.remove(&a);
+
+        assert_eq!(pending.get(&a), None);
+        assert_eq!(pending.get(&b), Some(&2));
+        assert_eq!(pending.values().collect::<Vec<_>>(), vec![&2]);
+    }
+
+    #[test]
+    fn slots_are_reused() {
+        let mut pending = Pending::new();
+        let ids = (0..3).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+
+        for (i, id) in ids.iter().enumerate() {
+            assert!(pending.insert(*id, i).is_none());
+        }
+
+        assert_eq!(pending.remove(&ids[1]), Some(1));
+        assert_eq!(pending.remove(&ids[1]), None);
+        assert_eq!(pending.remove(&ids[0]), Some(0));
+
+        pending.insert(Uuid::new_v4(), 3);
+        pending.insert(Uuid::new_v4(), 4);
+        assert_eq!(pending.slots.len(), 3);
+        assert_eq!(pending.len(), 3);
+
+        pending.insert(Uuid::new_v4(), 5);
+        assert_eq!(pending.slots.len(), 4);
+
+        assert_eq!(pending.insert(ids[2], 6), Some(2));
+        assert_eq!(pending.remove(&ids[2]), Some(6));
+
+        let mut drained = pending.drain().into_iter().map(|(_, value)| value).collect::<Vec<_>>();
+        drained.sort();
+        assert_eq!(drained, vec![3, 4, 5]);
+        assert!(pending.is_empty());
+    }
+
+    fn ids(concurrent: usize) -> Vec<Uuid> {
+        (0..concurrent).map(|_| Uuid::new_v4()).collect()
+    }
+
+    // both keep 10k requests in flight, completing the oldest one and sending it again every iteration
+
+    #[bench]
+    fn slab_10k_in_flight(b: &mut Bencher) {
+        let ids = ids(10_000);
+        let mut pending = Pending::new();
+
+        for id in &ids {
+            pending.insert(*id, ());
+        }
+
+        let mut i = 0;
+        b.iter(|| {
+            let id = ids[i % ids.len()];
+            black_box(pending.remove(&id));
+            pending.insert(id, ());
+            i += 1;
+        });
+    }
+
+    /// Routing through a table shared behind a lock, which the connection task avoids by owning
+    /// the table.
+    #[bench]
+    fn mutex_hashmap_10k_in_flight(b: &mut Bencher) {
+        use std::sync::{Arc, Mutex};
+
+        let ids = ids(10_000);
+        let pending = Arc::new(Mutex::new(HashMap::new()));
+
+        for id in &ids {
+            pending.lock().unwrap().insert(*id, ());
+        }
+
+        let mut i = 0;
+        b.iter(|| {
+            let id = ids[i % ids.len()];
+            black_box(pending.lock().unwrap().remove(&id));
+            pending.lock().unwrap().insert(id, ());
+            i += 1;
+        });
+    }
+
+    #[bench]
+    fn hashmap_10k_in_flight(b: &mut Bencher) {
+        let ids = ids(10_000);
+        let mut pending = HashMap::new();
+
+        for id in &ids {
+            pending.insert(*id, ());
+        }
+
+        let mut i = 0;
+        b.iter(|| {
+            let id = ids[i % ids.len()];
+            black_box(pending.remove(&id));
+            pending.insert(id, ());
+            i += 1;
+        });
+    }
+}
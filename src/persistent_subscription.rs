@@ -0,0 +1,81 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use EventNumber;
+
+/// Settings of a persistent subscription group, given to `PersistentSubscriptionBuilder::settings`
+/// when creating or updating the group. Each field maps onto the field of the same meaning in
+/// `CreatePersistentSubscription` and `UpdatePersistentSubscription`; the defaults are those of
+/// the .NET client.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use eventstore_tcp::{Builder, EventNumber, PersistentSubscriptionSettings};
+///
+/// let settings = PersistentSubscriptionSettings {
+///     start_from: EventNumber::First,
+///     message_timeout: Duration::from_secs(10),
+///     max_retry_count: 3,
+///     ..PersistentSubscriptionSettings::default()
+/// };
+///
+/// let package = Builder::create_persistent_subscription()
+///     .stream_id("my_stream-1")
+///     .group_name("billing")
+///     .settings(settings)
+///     .build_package(None, None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PersistentSubscriptionSettings {
+    /// Whether or not the server should resolve links found in the stream to events of other
+    /// streams. Defaults to `false`.
+    pub resolve_link_tos: bool,
+    /// The event the group starts from. Defaults to `EventNumber::Last`, only the events written
+    /// after the group was created.
+    pub start_from: EventNumber,
+    /// How long the server waits for an ack before retrying the event. Defaults to 30 seconds.
+    pub message_timeout: Duration,
+    /// Should the server keep statistics of the group. Defaults to `false`.
+    pub record_statistics: bool,
+    /// The number of events buffered while the group is live. Defaults to 500.
+    pub live_buffer_size: u16,
+    /// The number of events read at a time while catching up. Defaults to 20.
+    pub read_batch_size: u16,
+    /// The number of events buffered while catching up. Defaults to 500.
+    pub history_buffer_size: u16,
+    /// How many times an event is retried before it is parked. Defaults to 10.
+    pub max_retry_count: u16,
+    /// How often the position of the group is checkpointed. Defaults to 2 seconds.
+    pub checkpoint_after: Duration,
+    /// The minimum number of acked events between the checkpoints. Defaults to 10.
+    pub min_checkpoint_count: u16,
+    /// The maximum number of acked events between the checkpoints. Defaults to 1000.
+    pub max_checkpoint_count: u16,
+    /// Limits the number of consumers of the group. Defaults to 0, unlimited.
+    pub max_subscriber_count: u16,
+    /// The strategy of distributing the events between the consumers, a name such as
+    /// `"RoundRobin"`, `"DispatchToSingle"` or `"Pinned"`. Defaults to `"RoundRobin"`.
+    pub consumer_strategy: Cow<'static, str>,
+}
+
+impl Default for PersistentSubscriptionSettings {
+    fn default() -> Self {
+        PersistentSubscriptionSettings {
+            resolve_link_tos: false,
+            start_from: EventNumber::Last,
+            message_timeout: Duration::from_secs(30),
+            record_statistics: false,
+            live_buffer_size: 500,
+            read_batch_size: 20,
+            history_buffer_size: 500,
+            max_retry_count: 10,
+            checkpoint_after: Duration::from_secs(2),
+            min_checkpoint_count: 10,
+            max_checkpoint_count: 1000,
+            max_subscriber_count: 0,
+            consumer_strategy: Cow::Borrowed("RoundRobin"),
+        }
+    }
+}
@@ -0,0 +1,21 @@
+//! The types needed for typical use of the client, for importing with a glob:
+//!
+//! ```rust
+//! use eventstore_tcp::prelude::*;
+//! ```
+//!
+//! Includes `tokio_service::Service` for calling `EventStoreClient::call`, and the successful and
+//! failed responses of `adapted`.
+
+pub use tokio_service::Service;
+
+pub use {EventStoreClient, ClusterClient, ConnectionSettings, UsernamePassword};
+pub use {Builder, Package, RawMessage, AdaptedMessage, ToMessage};
+pub use {StreamId, ExpectedVersion, StreamVersion, EventNumber, LogPosition, ReadDirection, ContentType, TransactionId};
+pub use PersistentSubscriptionSettings;
+pub use {NewEvent, EventRecord, ResolvedIndexedEvent};
+pub use {ConnectionClosed, ConnectionEvent, AuthenticationFailed};
+pub use adapted::{WriteEventsCompleted, WriteEventsFailure, DeleteStreamCompleted, DeleteStreamFailure, TransactionFailure,
+                  ReadEventError, ReadStreamCompleted, ReadStreamError, ReadAllCompleted, ReadAllError, ResolvedEvent,
+                  SubscriptionConfirmation, SubscriptionDropReason, PersistentSubscriptionConfirmation,
+                  CreatePersistentSubscriptionFailure, UpdatePersistentSubscriptionFailure, NotHandledInfo};
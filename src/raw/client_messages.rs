@@ -2087,3 +2087,50 @@ impl From<i32> for ScavengeResult {
 }
 
 }
+
+#[derive(IntoOwned, Borrowed, Debug, Default, PartialEq, Clone)]
+pub struct IdentifyClient<'a> {
+    pub version: i32,
+    pub connection_name: Option<Cow<'a, str>>,
+}
+
+impl<'a> IdentifyClient<'a> {
+    pub fn from_reader(r: &mut BytesReader, bytes: &'a [u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        while !r.is_eof() {
+            match r.next_tag(bytes) {
+                Ok(8) => msg.version = r.read_int32(bytes)?,
+                Ok(18) => msg.connection_name = Some(r.read_string(bytes).map(Cow::Borrowed)?),
+                Ok(t) => { r.read_unknown(bytes, t)?; }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl<'a> MessageWrite for IdentifyClient<'a> {
+    fn get_size(&self) -> usize {
+        0
+        + 1 + sizeof_varint(*(&self.version) as u64)
+        + self.connection_name.as_ref().map_or(0, |m| 1 + sizeof_len((m).len()))
+    }
+
+    fn write_message<W: Write>(&self, w: &mut Writer<W>) -> Result<()> {
+        w.write_with_tag(8, |w| w.write_int32(*&self.version))?;
+        if let Some(ref s) = self.connection_name { w.write_with_tag(18, |w| w.write_string(&**s))?; }
+        Ok(())
+    }
+}
+
+#[derive(IntoOwned, Borrowed, Debug, Default, PartialEq, Clone)]
+pub struct ClientIdentified { }
+
+impl ClientIdentified {
+    pub fn from_reader(r: &mut BytesReader, _: &[u8]) -> Result<Self> {
+        r.read_to_end();
+        Ok(Self::default())
+    }
+}
+
+impl MessageWrite for ClientIdentified { }
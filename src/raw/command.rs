@@ -0,0 +1,194 @@
+use std::convert::TryFrom;
+
+use errors::{Error, ErrorKind};
+
+macro_rules! commands {
+    ($($(#[$doc:meta])* $name:ident = $value:tt,)*) => {
+        /// The commands of the protocol by their discriminators, the first byte of the frame
+        /// after the length. Allows classifying frames without decoding the payloads, for example
+        /// when routing packages in proxies and servers.
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        pub enum TcpCommand {
+            $($(#[$doc])* $name,)*
+        }
+
+        impl TcpCommand {
+            /// All commands in the order of their discriminators.
+            pub fn all() -> &'static [TcpCommand] {
+                static ALL: &'static [TcpCommand] = &[$(TcpCommand::$name,)*];
+                ALL
+            }
+        }
+
+        impl From<TcpCommand> for u8 {
+            fn from(command: TcpCommand) -> u8 {
+                match command {
+                    $(TcpCommand::$name => $value,)*
+                }
+            }
+        }
+
+        impl TryFrom<u8> for TcpCommand {
+            type Error = Error;
+
+            /// Fails with `ErrorKind::UnsupportedDiscriminator` for discriminators not in the
+            /// protocol.
+            fn try_from(discriminator: u8) -> Result<Self, Self::Error> {
+                match discriminator {
+                    $($value => Ok(TcpCommand::$name),)*
+                    d => Err(ErrorKind::UnsupportedDiscriminator(d).into()),
+                }
+            }
+        }
+    }
+}
+
+commands! {
+    /// Heartbeat request from either side
+    HeartbeatRequest = 0x01,
+    /// Response to a heartbeat request
+    HeartbeatResponse = 0x02,
+    /// Ping request
+    Ping = 0x03,
+    /// Ping response
+    Pong = 0x04,
+
+    /// Append to a stream
+    WriteEvents = 0x82,
+    /// Response to `WriteEvents`
+    WriteEventsCompleted = 0x83,
+    /// Start of a transaction on a stream
+    TransactionStart = 0x84,
+    /// Response to `TransactionStart`
+    TransactionStartCompleted = 0x85,
+    /// Appending events within a transaction
+    TransactionWrite = 0x86,
+    /// Response to `TransactionWrite`
+    TransactionWriteCompleted = 0x87,
+    /// Commit of a transaction
+    TransactionCommit = 0x88,
+    /// Response to `TransactionCommit`
+    TransactionCommitCompleted = 0x89,
+    /// Deleting a stream
+    DeleteStream = 0x8A,
+    /// Response to `DeleteStream`
+    DeleteStreamCompleted = 0x8B,
+
+    /// Reading a single event
+    ReadEvent = 0xB0,
+    /// Response to `ReadEvent`
+    ReadEventCompleted = 0xB1,
+    /// Reading a stream forwards
+    ReadStreamEventsForward = 0xB2,
+    /// Response to `ReadStreamEventsForward`
+    ReadStreamEventsForwardCompleted = 0xB3,
+    /// Reading a stream backwards
+    ReadStreamEventsBackward = 0xB4,
+    /// Response to `ReadStreamEventsBackward`
+    ReadStreamEventsBackwardCompleted = 0xB5,
+    /// Reading all events forwards
+    ReadAllEventsForward = 0xB6,
+    /// Response to `ReadAllEventsForward`
+    ReadAllEventsForwardCompleted = 0xB7,
+    /// Reading all events backwards
+    ReadAllEventsBackward = 0xB8,
+    /// Response to `ReadAllEventsBackward`
+    ReadAllEventsBackwardCompleted = 0xB9,
+
+    /// Volatile subscription to a stream or all streams
+    SubscribeToStream = 0xC0,
+    /// Response to `SubscribeToStream`
+    SubscriptionConfirmation = 0xC1,
+    /// Event pushed to a volatile subscription
+    StreamEventAppeared = 0xC2,
+    /// Ending a volatile subscription
+    UnsubscribeFromStream = 0xC3,
+    /// Server ended a subscription
+    SubscriptionDropped = 0xC4,
+    /// Joining a persistent subscription group
+    ConnectToPersistentSubscription = 0xC5,
+    /// Response to `ConnectToPersistentSubscription`
+    PersistentSubscriptionConfirmation = 0xC6,
+    /// Event pushed to a persistent subscription
+    PersistentSubscriptionStreamEventAppeared = 0xC7,
+    /// Creating a persistent subscription group
+    CreatePersistentSubscription = 0xC8,
+    /// Response to `CreatePersistentSubscription`
+    CreatePersistentSubscriptionCompleted = 0xC9,
+    /// Deleting a persistent subscription group
+    DeletePersistentSubscription = 0xCA,
+    /// Response to `DeletePersistentSubscription`
+    DeletePersistentSubscriptionCompleted = 0xCB,
+    /// Acknowledging events of a persistent subscription
+    PersistentSubscriptionAckEvents = 0xCC,
+    /// Negatively acknowledging events of a persistent subscription
+    PersistentSubscriptionNakEvents = 0xCD,
+    /// Updating the settings of a persistent subscription group
+    UpdatePersistentSubscription = 0xCE,
+    /// Response to `UpdatePersistentSubscription`
+    UpdatePersistentSubscriptionCompleted = 0xCF,
+
+    /// Starting a scavenge of the database
+    ScavengeDatabase = 0xD0,
+    /// Response to `ScavengeDatabase`
+    ScavengeDatabaseCompleted = 0xD1,
+
+    /// Request was not understood
+    BadRequest = 0xF0,
+    /// Request was not handled, for example a write to a slave node
+    NotHandled = 0xF1,
+    /// Authenticating the credentials of the package
+    Authenticate = 0xF2,
+    /// Positive response to `Authenticate`
+    Authenticated = 0xF3,
+    /// Negative response to `Authenticate` or any request with rejected credentials
+    NotAuthenticated = 0xF4,
+    /// Naming the connection
+    IdentifyClient = 0xF5,
+    /// Response to `IdentifyClient`
+    ClientIdentified = 0xF6,
+}
+
+impl TcpCommand {
+    /// Returns the discriminator of the command.
+    pub fn discriminator(&self) -> u8 {
+        u8::from(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::collections::HashSet;
+    use super::TcpCommand;
+
+    #[test]
+    fn every_discriminator_maps_back() {
+        let known = TcpCommand::all().iter().map(|c| c.discriminator()).collect::<HashSet<u8>>();
+        assert_eq!(known.len(), TcpCommand::all().len());
+
+        for d in 0..256u32 {
+            let d = d as u8;
+            match TcpCommand::try_from(d) {
+                Ok(command) => assert_eq!(command.discriminator(), d),
+                Err(_) => assert!(!known.contains(&d), "0x{:02x}", d),
+            }
+        }
+
+        assert_eq!(TcpCommand::try_from(0x82).unwrap(), TcpCommand::WriteEvents);
+        assert_eq!(TcpCommand::try_from(0xCF).unwrap(), TcpCommand::UpdatePersistentSubscriptionCompleted);
+        assert!(TcpCommand::try_from(0x00).is_err());
+    }
+
+    #[test]
+    fn raw_messages_have_their_command() {
+        use raw::RawMessage;
+
+        for command in TcpCommand::all() {
+            // messages without a variant of their own are kept as `Unsupported`
+            if let Ok(message) = RawMessage::decode(command.discriminator(), &[]) {
+                assert_eq!(message.command(), Some(*command));
+            }
+        }
+    }
+}
@@ -1,6 +1,15 @@
 //! Raw module contains the enumeration `RawMessage` and raw decoding and encoding functionality.
 //! There should not be need to handle `RawMessage` values directly but if there is ever a bug,
 //! using the raw messages should still work.
+//!
+//! # Stability
+//!
+//! The messages of the protocol are generated from `ClientMessageDtos.proto` into
+//! `client_messages`. Their names and fields follow the `.proto` of EventStore and change only
+//! with the protocol, in a breaking release. How the generated code is organized, such as the
+//! `mod_*` modules of the nested enums or the `from_reader` functions, depends on the version of
+//! the generator and may change in any release: use the messages and enums re-exported here
+//! instead.
 
 use std::io;
 use std::str;
@@ -8,9 +17,37 @@ use std::borrow::Cow;
 use quick_protobuf;
 
 pub mod client_messages;
-pub use self::client_messages::{EventRecord, WriteEvents, WriteEventsCompleted, ReadEvent, ReadEventCompleted, ReadStreamEvents, ReadStreamEventsCompleted, ReadAllEvents, ReadAllEventsCompleted, NotHandled, DeleteStream, DeleteStreamCompleted, OperationResult};
+pub use self::client_messages::{NewEvent, EventRecord, ResolvedIndexedEvent, ResolvedEvent, OperationResult};
+pub use self::client_messages::{WriteEvents, WriteEventsCompleted, DeleteStream, DeleteStreamCompleted};
+pub use self::client_messages::{TransactionStart, TransactionStartCompleted, TransactionWrite, TransactionWriteCompleted,
+                                TransactionCommit, TransactionCommitCompleted};
+pub use self::client_messages::{ReadEvent, ReadEventCompleted, ReadStreamEvents, ReadStreamEventsCompleted, ReadAllEvents,
+                                ReadAllEventsCompleted};
+pub use self::client_messages::{SubscribeToStream, SubscriptionConfirmation, StreamEventAppeared, UnsubscribeFromStream,
+                                SubscriptionDropped};
+pub use self::client_messages::{CreatePersistentSubscription, CreatePersistentSubscriptionCompleted,
+                                UpdatePersistentSubscription, UpdatePersistentSubscriptionCompleted,
+                                DeletePersistentSubscription, DeletePersistentSubscriptionCompleted,
+                                ConnectToPersistentSubscription, PersistentSubscriptionConfirmation,
+                                PersistentSubscriptionStreamEventAppeared, PersistentSubscriptionAckEvents,
+                                PersistentSubscriptionNakEvents};
+pub use self::client_messages::{NotHandled, ScavengeDatabase, ScavengeDatabaseCompleted, IdentifyClient, ClientIdentified};
+pub use self::client_messages::mod_ReadEventCompleted::ReadEventResult;
+pub use self::client_messages::mod_ReadStreamEventsCompleted::ReadStreamResult;
+pub use self::client_messages::mod_ReadAllEventsCompleted::ReadAllResult;
+pub use self::client_messages::mod_CreatePersistentSubscriptionCompleted::CreatePersistentSubscriptionResult;
+pub use self::client_messages::mod_UpdatePersistentSubscriptionCompleted::UpdatePersistentSubscriptionResult;
+pub use self::client_messages::mod_DeletePersistentSubscriptionCompleted::DeletePersistentSubscriptionResult;
+pub use self::client_messages::mod_PersistentSubscriptionNakEvents::NakAction;
+pub use self::client_messages::mod_SubscriptionDropped::SubscriptionDropReason;
+pub use self::client_messages::mod_NotHandled::{NotHandledReason, MasterInfo};
+pub use self::client_messages::mod_ScavengeDatabaseCompleted::ScavengeResult;
+
+mod command;
+pub use self::command::TcpCommand;
 
 use adapted;
+use package::Package;
 
 use errors::Error;
 use ReadDirection;
@@ -40,6 +77,21 @@ pub enum RawMessage<'a> {
     /// Response to previous stream deletion request
     DeleteStreamCompleted(DeleteStreamCompleted<'a>),
 
+    /// Request to start a transaction on a stream
+    TransactionStart(TransactionStart<'a>),
+    /// Response to a transaction start, with the id of the transaction
+    TransactionStartCompleted(TransactionStartCompleted<'a>),
+
+    /// Request to write events within a transaction
+    TransactionWrite(TransactionWrite<'a>),
+    /// Response to a write within a transaction
+    TransactionWriteCompleted(TransactionWriteCompleted<'a>),
+
+    /// Request to commit the events written within a transaction
+    TransactionCommit(TransactionCommit),
+    /// Response to a transaction commit, which can fail like appends
+    TransactionCommitCompleted(TransactionCommitCompleted<'a>),
+
     /// Request to read a single event from a stream
     ReadEvent(ReadEvent<'a>),
     /// Response to a single event read
@@ -55,6 +107,31 @@ pub enum RawMessage<'a> {
     /// Response to a read all in given direction
     ReadAllEventsCompleted(ReadDirection, ReadAllEventsCompleted<'a>),
 
+    /// Request for a volatile subscription to a stream, or to all streams
+    SubscribeToStream(SubscribeToStream<'a>),
+    /// Response to a subscription request, after which the events are pushed to the subscriber
+    SubscriptionConfirmation(SubscriptionConfirmation),
+    /// Event appended to the subscribed stream, pushed with the correlation id of the subscription
+    StreamEventAppeared(StreamEventAppeared<'a>),
+    /// Request to end the subscription started with the same correlation id
+    UnsubscribeFromStream,
+    /// The subscription has ended, after an unsubscription or for the given reason
+    SubscriptionDropped(SubscriptionDropped),
+
+    /// Request to connect as a consumer of a persistent subscription group
+    ConnectToPersistentSubscription(ConnectToPersistentSubscription<'a>),
+    /// Response to a connection to a persistent subscription, with the id for acknowledging the
+    /// pushed events
+    PersistentSubscriptionConfirmation(PersistentSubscriptionConfirmation<'a>),
+    /// Request to create a persistent subscription group on a stream
+    CreatePersistentSubscription(CreatePersistentSubscription<'a>),
+    /// Response to the creation of a persistent subscription group
+    CreatePersistentSubscriptionCompleted(CreatePersistentSubscriptionCompleted<'a>),
+    /// Request to replace the settings of an existing persistent subscription group
+    UpdatePersistentSubscription(UpdatePersistentSubscription<'a>),
+    /// Response to the update of a persistent subscription group
+    UpdatePersistentSubscriptionCompleted(UpdatePersistentSubscriptionCompleted<'a>),
+
     /// Request was not understood. Please open an issue!
     BadRequest(BadRequestPayload<'a>),
 
@@ -73,6 +150,13 @@ pub enum RawMessage<'a> {
     /// authentication was not accepted. May contain a reason.
     NotAuthenticated(NotAuthenticatedPayload<'a>),
 
+    /// Request to identify the connection with a name and client version. Supported by servers
+    /// from version 4.0 onwards.
+    IdentifyClient(IdentifyClient<'a>),
+
+    /// Positive response to `IdentifyClient`.
+    ClientIdentified,
+
     /// Placeholder for a discriminator and the undecoded bytes
     Unsupported(u8, Cow<'a, [u8]>),
 }
@@ -134,6 +218,12 @@ impl<'a> ByteWrapper<'a> for BadRequestPayload<'a> {
 
 impl<'a> RawMessage<'a> {
 
+    /// Creates a package of the message without authentication, see `Package::new`. Borrowed
+    /// payloads are copied.
+    pub fn into_package(self) -> Package {
+        Package::new(self.into_owned())
+    }
+
     /// Attempt to convert a raw message into an adapted one
     pub fn try_adapt(self) -> Result<adapted::AdaptedMessage<'a>, (Self, Error)> {
         use CustomTryInto;
@@ -188,6 +278,13 @@ impl<'a> RawMessage<'a> {
             0x82 => decoded!(WriteEvents, buf, RawMessage::WriteEvents),
             0x83 => decoded!(WriteEventsCompleted, buf, RawMessage::WriteEventsCompleted),
 
+            0x84 => decoded!(TransactionStart, buf, RawMessage::TransactionStart),
+            0x85 => decoded!(TransactionStartCompleted, buf, RawMessage::TransactionStartCompleted),
+            0x86 => decoded!(TransactionWrite, buf, RawMessage::TransactionWrite),
+            0x87 => decoded!(TransactionWriteCompleted, buf, RawMessage::TransactionWriteCompleted),
+            0x88 => decoded!(TransactionCommit, buf, RawMessage::TransactionCommit),
+            0x89 => decoded!(TransactionCommitCompleted, buf, RawMessage::TransactionCommitCompleted),
+
             0x8A => decoded!(DeleteStream, buf, RawMessage::DeleteStream),
             0x8B => decoded!(DeleteStreamCompleted, buf, RawMessage::DeleteStreamCompleted),
 
@@ -204,11 +301,25 @@ impl<'a> RawMessage<'a> {
             0xB8 => decoded!(ReadAllEvents, buf, RawMessage::ReadAllEvents, Backward),
             0xB9 => decoded!(ReadAllEventsCompleted, buf, RawMessage::ReadAllEventsCompleted, Backward),
 
+            0xC0 => decoded!(SubscribeToStream, buf, RawMessage::SubscribeToStream),
+            0xC1 => decoded!(SubscriptionConfirmation, buf, RawMessage::SubscriptionConfirmation),
+            0xC2 => decoded!(StreamEventAppeared, buf, RawMessage::StreamEventAppeared),
+            0xC3 => without_data!(RawMessage::UnsubscribeFromStream, buf),
+            0xC4 => decoded!(SubscriptionDropped, buf, RawMessage::SubscriptionDropped),
+            0xC5 => decoded!(ConnectToPersistentSubscription, buf, RawMessage::ConnectToPersistentSubscription),
+            0xC6 => decoded!(PersistentSubscriptionConfirmation, buf, RawMessage::PersistentSubscriptionConfirmation),
+            0xC8 => decoded!(CreatePersistentSubscription, buf, RawMessage::CreatePersistentSubscription),
+            0xC9 => decoded!(CreatePersistentSubscriptionCompleted, buf, RawMessage::CreatePersistentSubscriptionCompleted),
+            0xCE => decoded!(UpdatePersistentSubscription, buf, RawMessage::UpdatePersistentSubscription),
+            0xCF => decoded!(UpdatePersistentSubscriptionCompleted, buf, RawMessage::UpdatePersistentSubscriptionCompleted),
+
             0xF0 => Ok(RawMessage::BadRequest(Cow::Borrowed(buf).into())),
             0xF1 => decoded!(NotHandled, buf, RawMessage::NotHandled),
             0xF2 => without_data!(RawMessage::Authenticate, buf),
             0xF3 => without_data!(RawMessage::Authenticated, buf),
             0xF4 => Ok(RawMessage::NotAuthenticated(Cow::Borrowed(buf).into())),
+            0xF5 => decoded!(IdentifyClient, buf, RawMessage::IdentifyClient),
+            0xF6 => without_data!(RawMessage::ClientIdentified, buf),
             x => Ok((x, Cow::Borrowed(buf)).into()),
         }
     }
@@ -235,7 +346,9 @@ impl<'a> RawMessage<'a> {
             Ping |
             Pong |
             Authenticate |
-            Authenticated => Ok(()),
+            Authenticated |
+            UnsubscribeFromStream |
+            ClientIdentified => Ok(()),
 
             WriteEvents(ref x) => encode!(x, w),
             WriteEventsCompleted(ref x) => encode!(x, w),
@@ -243,6 +356,13 @@ impl<'a> RawMessage<'a> {
             DeleteStream(ref x) => encode!(x, w),
             DeleteStreamCompleted(ref x) => encode!(x, w),
 
+            TransactionStart(ref x) => encode!(x, w),
+            TransactionStartCompleted(ref x) => encode!(x, w),
+            TransactionWrite(ref x) => encode!(x, w),
+            TransactionWriteCompleted(ref x) => encode!(x, w),
+            TransactionCommit(ref x) => encode!(x, w),
+            TransactionCommitCompleted(ref x) => encode!(x, w),
+
             ReadEvent(ref x) => encode!(x, w),
             ReadEventCompleted(ref x) => encode!(x, w),
 
@@ -252,16 +372,111 @@ impl<'a> RawMessage<'a> {
             ReadAllEvents(_, ref x) => encode!(x, w),
             ReadAllEventsCompleted(_, ref x) => encode!(x, w),
 
+            SubscribeToStream(ref x) => encode!(x, w),
+            SubscriptionConfirmation(ref x) => encode!(x, w),
+            StreamEventAppeared(ref x) => encode!(x, w),
+            SubscriptionDropped(ref x) => encode!(x, w),
+            ConnectToPersistentSubscription(ref x) => encode!(x, w),
+            PersistentSubscriptionConfirmation(ref x) => encode!(x, w),
+            CreatePersistentSubscription(ref x) => encode!(x, w),
+            CreatePersistentSubscriptionCompleted(ref x) => encode!(x, w),
+            UpdatePersistentSubscription(ref x) => encode!(x, w),
+            UpdatePersistentSubscriptionCompleted(ref x) => encode!(x, w),
+
             BadRequest(ref x) => w.write_all(x.as_ref()),
             NotHandled(ref x) => encode!(x, w),
             NotAuthenticated(ref x) => w.write_all(x.as_ref()),
+            IdentifyClient(ref x) => encode!(x, w),
             Unsupported(_, ref x) => w.write_all(x),
         }
     }
 
-    /// Returns the protocol discriminator value for the variant
+    /// Returns the length of the encoded message, without the header of the frame.
+    pub fn encoded_len(&self) -> usize {
+        use self::RawMessage::*;
+        use quick_protobuf::MessageWrite;
+
+        match *self {
+            HeartbeatRequest |
+            HeartbeatResponse |
+            Ping |
+            Pong |
+            Authenticate |
+            Authenticated |
+            UnsubscribeFromStream |
+            ClientIdentified => 0,
+
+            WriteEvents(ref x) => x.get_size(),
+            WriteEventsCompleted(ref x) => x.get_size(),
+
+            DeleteStream(ref x) => x.get_size(),
+            DeleteStreamCompleted(ref x) => x.get_size(),
+
+            TransactionStart(ref x) => x.get_size(),
+            TransactionStartCompleted(ref x) => x.get_size(),
+            TransactionWrite(ref x) => x.get_size(),
+            TransactionWriteCompleted(ref x) => x.get_size(),
+            TransactionCommit(ref x) => x.get_size(),
+            TransactionCommitCompleted(ref x) => x.get_size(),
+
+            ReadEvent(ref x) => x.get_size(),
+            ReadEventCompleted(ref x) => x.get_size(),
+
+            ReadStreamEvents(_, ref x) => x.get_size(),
+            ReadStreamEventsCompleted(_, ref x) => x.get_size(),
+
+            ReadAllEvents(_, ref x) => x.get_size(),
+            ReadAllEventsCompleted(_, ref x) => x.get_size(),
+
+            SubscribeToStream(ref x) => x.get_size(),
+            SubscriptionConfirmation(ref x) => x.get_size(),
+            StreamEventAppeared(ref x) => x.get_size(),
+            SubscriptionDropped(ref x) => x.get_size(),
+            ConnectToPersistentSubscription(ref x) => x.get_size(),
+            PersistentSubscriptionConfirmation(ref x) => x.get_size(),
+            CreatePersistentSubscription(ref x) => x.get_size(),
+            CreatePersistentSubscriptionCompleted(ref x) => x.get_size(),
+            UpdatePersistentSubscription(ref x) => x.get_size(),
+            UpdatePersistentSubscriptionCompleted(ref x) => x.get_size(),
+
+            BadRequest(ref x) => x.as_ref().len(),
+            NotHandled(ref x) => x.get_size(),
+            NotAuthenticated(ref x) => x.as_ref().len(),
+            IdentifyClient(ref x) => x.get_size(),
+            Unsupported(_, ref x) => x.len(),
+        }
+    }
+
+    /// Returns the stream targeted by a request: `"$all"` for reads of and subscriptions to all
+    /// events, and `None` for responses and requests not targeting a stream.
+    pub fn stream_id(&self) -> Option<&str> {
+        use self::RawMessage::*;
+
+        match *self {
+            WriteEvents(ref x) => Some(&*x.event_stream_id),
+            DeleteStream(ref x) => Some(&*x.event_stream_id),
+            TransactionStart(ref x) => Some(&*x.event_stream_id),
+            ReadEvent(ref x) => Some(&*x.event_stream_id),
+            ReadStreamEvents(_, ref x) => Some(&*x.event_stream_id),
+            ReadAllEvents(..) => Some("$all"),
+            SubscribeToStream(ref x) if x.event_stream_id.is_empty() => Some("$all"),
+            SubscribeToStream(ref x) => Some(&*x.event_stream_id),
+            ConnectToPersistentSubscription(ref x) => Some(&*x.event_stream_id),
+            CreatePersistentSubscription(ref x) => Some(&*x.event_stream_id),
+            UpdatePersistentSubscription(ref x) => Some(&*x.event_stream_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the command of the message, or `None` for an `Unsupported` discriminator outside
+    /// of the protocol.
+    pub fn command(&self) -> Option<TcpCommand> {
+        use std::convert::TryFrom;
+        TcpCommand::try_from(self.discriminator()).ok()
+    }
+
+    /// Returns the protocol discriminator value for the variant, see `TcpCommand`
     pub fn discriminator(&self) -> u8 {
-        // FIXME: copied from ::Message
         use self::RawMessage::*;
         match *self {
             HeartbeatRequest => 0x01,
@@ -275,6 +490,13 @@ impl<'a> RawMessage<'a> {
             DeleteStream(_) => 0x8A,
             DeleteStreamCompleted(_) => 0x8B,
 
+            TransactionStart(_) => 0x84,
+            TransactionStartCompleted(_) => 0x85,
+            TransactionWrite(_) => 0x86,
+            TransactionWriteCompleted(_) => 0x87,
+            TransactionCommit(_) => 0x88,
+            TransactionCommitCompleted(_) => 0x89,
+
             ReadEvent(_) => 0xB0,
             ReadEventCompleted(_) => 0xB1,
 
@@ -290,11 +512,25 @@ impl<'a> RawMessage<'a> {
             ReadAllEvents(ReadDirection::Backward, _) => 0xB8,
             ReadAllEventsCompleted(ReadDirection::Backward, _) => 0xB9,
 
+            SubscribeToStream(_) => 0xC0,
+            SubscriptionConfirmation(_) => 0xC1,
+            StreamEventAppeared(_) => 0xC2,
+            UnsubscribeFromStream => 0xC3,
+            SubscriptionDropped(_) => 0xC4,
+            ConnectToPersistentSubscription(_) => 0xC5,
+            PersistentSubscriptionConfirmation(_) => 0xC6,
+            CreatePersistentSubscription(_) => 0xC8,
+            CreatePersistentSubscriptionCompleted(_) => 0xC9,
+            UpdatePersistentSubscription(_) => 0xCE,
+            UpdatePersistentSubscriptionCompleted(_) => 0xCF,
+
             BadRequest(_) => 0xf0,
             NotHandled(_) => 0xf1,
             Authenticate => 0xf2,
             Authenticated => 0xf3,
             NotAuthenticated(_) => 0xf4,
+            IdentifyClient(_) => 0xf5,
+            ClientIdentified => 0xf6,
             Unsupported(d, _) => d,
         }
     }
@@ -0,0 +1,119 @@
+//! Adapting the responses to the requests the helpers of the crate make. The failures reported by
+//! the server are wrapped in `io::Error` as the typed errors of the `adapted` module, so that the
+//! callers can `downcast_ref` them, with denied access as `PermissionDenied`.
+
+use std::io;
+
+use adapted::{AdaptedMessage, ReadStreamCompleted, ReadStreamError, ReadAllCompleted, ReadAllError};
+use adapted::{ReadEventError, WriteEventsCompleted};
+use package::Package;
+
+pub fn adapt(response: Package) -> io::Result<AdaptedMessage<'static>> {
+    response.message.try_adapt().map_err(|(_, e)| -> io::Error { e.into() })
+}
+
+pub fn unexpected(response: AdaptedMessage) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected response: {:?}", response))
+}
+
+/// Returns `None` if the stream does not exist.
+pub fn read_page(response: Package) -> io::Result<Option<ReadStreamCompleted<'static>>> {
+    match adapt(response)? {
+        AdaptedMessage::ReadStreamEventsCompleted(_, Ok(page)) => Ok(Some(page)),
+        AdaptedMessage::ReadStreamEventsCompleted(_, Err(ReadStreamError::NoStream)) => Ok(None),
+        AdaptedMessage::ReadStreamEventsCompleted(_, Err(e)) => Err(read_stream_failed(e)),
+        other => Err(unexpected(other)),
+    }
+}
+
+pub fn read_all_page(response: Package) -> io::Result<ReadAllCompleted<'static>> {
+    match adapt(response)? {
+        AdaptedMessage::ReadAllEventsCompleted(_, Ok(page)) => Ok(page),
+        AdaptedMessage::ReadAllEventsCompleted(_, Err(e)) => Err(read_all_failed(e)),
+        other => Err(unexpected(other)),
+    }
+}
+
+pub fn written(response: Package) -> io::Result<WriteEventsCompleted> {
+    match adapt(response)? {
+        AdaptedMessage::WriteEventsCompleted(Ok(completed)) => Ok(completed),
+        AdaptedMessage::WriteEventsCompleted(Err(failure)) => Err(io::Error::new(io::ErrorKind::Other, failure)),
+        other => Err(unexpected(other)),
+    }
+}
+
+pub fn read_stream_failed(e: ReadStreamError<'static>) -> io::Error {
+    let kind = match e {
+        ReadStreamError::AccessDenied => io::ErrorKind::PermissionDenied,
+        _ => io::ErrorKind::Other,
+    };
+
+    io::Error::new(kind, e)
+}
+
+pub fn read_all_failed(e: ReadAllError<'static>) -> io::Error {
+    let kind = match e {
+        ReadAllError::AccessDenied => io::ErrorKind::PermissionDenied,
+        _ => io::ErrorKind::Other,
+    };
+
+    io::Error::new(kind, e)
+}
+
+pub fn read_event_failed(e: ReadEventError<'static>) -> io::Error {
+    let kind = match e {
+        ReadEventError::AccessDenied => io::ErrorKind::PermissionDenied,
+        _ => io::ErrorKind::Other,
+    };
+
+    io::Error::new(kind, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use uuid::Uuid;
+    use adapted::{ReadStreamError, ReadAllError};
+    use package::Package;
+    use raw::RawMessage;
+    use raw::client_messages::{ReadStreamEventsCompleted, ReadAllEventsCompleted};
+    use raw::client_messages::mod_ReadStreamEventsCompleted::ReadStreamResult;
+    use raw::client_messages::mod_ReadAllEventsCompleted::ReadAllResult;
+    use super::{read_page, read_all_page};
+
+    fn package(message: RawMessage<'static>) -> Package {
+        Package {
+            authentication: None,
+            correlation_id: Uuid::new_v4(),
+            message: message,
+        }
+    }
+
+    #[test]
+    fn read_failures_are_typed() {
+        let denied = ReadStreamEventsCompleted {
+            result: Some(ReadStreamResult::AccessDenied),
+            ..ReadStreamEventsCompleted::default()
+        };
+
+        let e = read_page(package(RawMessage::ReadStreamEventsCompleted(denied))).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(e.get_ref().and_then(|e| e.downcast_ref::<ReadStreamError<'static>>()), Some(&ReadStreamError::AccessDenied));
+
+        let failed = ReadAllEventsCompleted {
+            result: ReadAllResult::Error,
+            error: Some("disk".into()),
+            ..ReadAllEventsCompleted::default()
+        };
+
+        let e = read_all_page(package(RawMessage::ReadAllEventsCompleted(failed))).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Other);
+        assert_eq!(e.get_ref().and_then(|e| e.downcast_ref::<ReadAllError<'static>>()), Some(&ReadAllError::Error(Some("disk".into()))));
+    }
+
+    #[test]
+    fn other_responses_are_unexpected() {
+        let e = read_page(package(RawMessage::Ping)).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
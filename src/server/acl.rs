@@ -0,0 +1,232 @@
+//! Access control of the streams served by `StoreService`.
+
+use std::ops::Range;
+use std::collections::HashMap;
+
+use raw::client_messages::NewEvent;
+use {EventNumber, ExpectedVersion, ReadDirection, StreamVersion};
+use super::store::{StreamStore, StreamSlice, StoreError};
+
+/// The operations on a stream allowed by an `Acl`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// Reading the events
+    Read,
+    /// Appending events
+    Write,
+    /// Deleting the stream
+    Delete,
+    /// Reading the metastream
+    MetadataRead,
+    /// Appending to the metastream
+    MetadataWrite,
+}
+
+/// Access control list of a stream, like the `$acl` of the stream metadata in EventStore: the
+/// users and roles allowed each operation. The role `$all` allows everyone, including requests
+/// without credentials, while the members of `$admins` are allowed every operation regardless of
+/// the list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Acl {
+    allowed: HashMap<Operation, Vec<String>>,
+}
+
+impl Acl {
+    /// Creates a list allowing only the members of `$admins`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a list allowing every operation to everyone, the default of EventStore for the
+    /// streams not starting with `$`.
+    pub fn everyone() -> Self {
+        [Operation::Read, Operation::Write, Operation::Delete, Operation::MetadataRead, Operation::MetadataWrite]
+            .iter()
+            .fold(Acl::new(), |acl, operation| acl.allow(*operation, "$all"))
+    }
+
+    /// Allows the operation to the user or the members of the role.
+    pub fn allow<S: Into<String>>(mut self, operation: Operation, user_or_role: S) -> Self {
+        self.allowed.entry(operation).or_insert_with(Vec::new).push(user_or_role.into());
+        self
+    }
+
+    /// Is the operation allowed to the user, who is a member of the given roles. `None` is the
+    /// user of requests without credentials.
+    pub fn allows(&self, operation: Operation, user: Option<&str>, roles: &[String]) -> bool {
+        if roles.iter().any(|role| role == "$admins") {
+            return true;
+        }
+
+        match self.allowed.get(&operation) {
+            Some(allowed) => allowed.iter().any(|allowed| {
+                allowed == "$all" || Some(&allowed[..]) == user || roles.contains(allowed)
+            }),
+            None => false,
+        }
+    }
+}
+
+/// The `Acl`s of the streams and the roles of the users, checked by `StoreService::acls`.
+///
+/// Streams without an `Acl` of their own get the defaults of EventStore: streams starting with
+/// `$` are accessible only to `$admins`, others to everyone. Operations on a metastream `$$stream`
+/// are checked as the metadata operations of `stream`. The user `admin` is a member of `$admins`.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::server::{Acl, Acls, Operation};
+///
+/// let acls = Acls::new()
+///     .stream("orders", Acl::new()
+///         .allow(Operation::Read, "$all")
+///         .allow(Operation::Write, "$writers"))
+///     .role("ann", "$writers");
+///
+/// assert!(acls.allows(Some("ann"), "orders", Operation::Write));
+/// assert!(!acls.allows(None, "orders", Operation::Write));
+/// assert!(!acls.allows(Some("ann"), "$stats", Operation::Read));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Acls {
+    streams: HashMap<String, Acl>,
+    user_streams: Acl,
+    system_streams: Acl,
+    roles: HashMap<String, Vec<String>>,
+}
+
+impl Acls {
+    /// Creates the defaults of EventStore, see above.
+    pub fn new() -> Self {
+        Acls {
+            streams: HashMap::new(),
+            user_streams: Acl::everyone(),
+            system_streams: Acl::new(),
+            roles: HashMap::new(),
+        }.role("admin", "$admins")
+    }
+
+    /// Sets the `Acl` of the stream.
+    pub fn stream<S: Into<String>>(mut self, stream: S, acl: Acl) -> Self {
+        self.streams.insert(stream.into(), acl);
+        self
+    }
+
+    /// Sets the `Acl` of the streams not starting with `$` without an `Acl` of their own.
+    ///
+    /// Defaults to `Acl::everyone()`.
+    pub fn user_streams(mut self, acl: Acl) -> Self {
+        self.user_streams = acl;
+        self
+    }
+
+    /// Sets the `Acl` of the streams starting with `$` without an `Acl` of their own.
+    ///
+    /// Defaults to `Acl::new()`, allowing only `$admins`.
+    pub fn system_streams(mut self, acl: Acl) -> Self {
+        self.system_streams = acl;
+        self
+    }
+
+    /// Makes the user a member of the role.
+    pub fn role<U: Into<String>, R: Into<String>>(mut self, user: U, role: R) -> Self {
+        self.roles.entry(user.into()).or_insert_with(Vec::new).push(role.into());
+        self
+    }
+
+    /// Is the operation allowed to the user on the stream. `None` is the user of requests without
+    /// credentials.
+    pub fn allows(&self, user: Option<&str>, stream: &str, operation: Operation) -> bool {
+        let (stream, operation) = match (stream.starts_with("$$"), operation) {
+            (true, Operation::Read) => (&stream[2..], Operation::MetadataRead),
+            (true, Operation::Write) => (&stream[2..], Operation::MetadataWrite),
+            _ => (stream, operation),
+        };
+
+        let acl = match self.streams.get(stream) {
+            Some(acl) => acl,
+            None if stream.starts_with('$') => &self.system_streams,
+            None => &self.user_streams,
+        };
+
+        let roles = user.and_then(|user| self.roles.get(user)).map(|roles| &roles[..]).unwrap_or(&[]);
+
+        acl.allows(operation, user, roles)
+    }
+}
+
+impl Default for Acls {
+    fn default() -> Self {
+        Acls::new()
+    }
+}
+
+/// `StreamStore` failing the operations not allowed to `user` with `AccessDenied`.
+pub struct Authorized<'a, S: ?Sized + 'a> {
+    pub store: &'a mut S,
+    pub acls: &'a Acls,
+    pub user: Option<&'a str>,
+}
+
+impl<'a, S: StreamStore + ?Sized + 'a> Authorized<'a, S> {
+    fn check(&self, stream: &str, operation: Operation) -> Result<(), StoreError> {
+        if self.acls.allows(self.user, stream, operation) {
+            Ok(())
+        } else {
+            Err(StoreError::AccessDenied)
+        }
+    }
+}
+
+impl<'a, S: StreamStore + ?Sized + 'a> StreamStore for Authorized<'a, S> {
+    fn append(&mut self, stream: &str, expected: ExpectedVersion, events: Vec<NewEvent<'static>>) -> Result<Range<StreamVersion>, StoreError> {
+        self.check(stream, Operation::Write)?;
+        self.store.append(stream, expected, events)
+    }
+
+    fn read(&self, stream: &str, from: EventNumber, max_count: usize, direction: ReadDirection) -> Result<StreamSlice, StoreError> {
+        self.check(stream, Operation::Read)?;
+        self.store.read(stream, from, max_count, direction)
+    }
+
+    fn delete(&mut self, stream: &str, expected: ExpectedVersion, hard: bool) -> Result<(), StoreError> {
+        self.check(stream, Operation::Delete)?;
+        self.store.delete(stream, expected, hard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Acl, Acls, Operation};
+
+    #[test]
+    fn defaults_of_eventstore() {
+        let acls = Acls::new();
+
+        assert!(acls.allows(None, "orders", Operation::Write));
+        assert!(acls.allows(None, "$$orders", Operation::Write));
+        assert!(!acls.allows(None, "$stats", Operation::Read));
+        assert!(!acls.allows(Some("ann"), "$$$stats", Operation::Read));
+        assert!(acls.allows(Some("admin"), "$stats", Operation::Delete));
+    }
+
+    #[test]
+    fn users_and_roles() {
+        let acls = Acls::new()
+            .stream("orders", Acl::new()
+                .allow(Operation::Read, "$readers")
+                .allow(Operation::Write, "bob")
+                .allow(Operation::MetadataRead, "$all"))
+            .role("ann", "$readers");
+
+        assert!(acls.allows(Some("ann"), "orders", Operation::Read));
+        assert!(!acls.allows(Some("ann"), "orders", Operation::Write));
+        assert!(acls.allows(Some("bob"), "orders", Operation::Write));
+        assert!(!acls.allows(Some("bob"), "orders", Operation::Read));
+        assert!(!acls.allows(Some("bob"), "orders", Operation::Delete));
+        assert!(acls.allows(None, "$$orders", Operation::Read));
+        assert!(!acls.allows(None, "$$orders", Operation::Write));
+        assert!(acls.allows(Some("admin"), "orders", Operation::Delete));
+    }
+}
@@ -0,0 +1,179 @@
+//! Latency and faults injected into the responses of `Server`.
+
+use std::io;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures::{future, Future};
+use rand::{self, Rng};
+use tokio_core::reactor::{Handle, Timeout};
+
+use package::Package;
+
+/// Distribution of the latency of responses, sampled for every request with millisecond
+/// precision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Latency {
+    /// Always the given latency
+    Fixed(Duration),
+    /// Uniformly distributed between the bounds, inclusive
+    Uniform(Duration, Duration),
+    /// Exponentially distributed with the given mean, mostly quick but with a long tail
+    Exponential(Duration),
+}
+
+impl Latency {
+    fn sample<R: Rng>(&self, rng: &mut R) -> Duration {
+        match *self {
+            Latency::Fixed(latency) => latency,
+            Latency::Uniform(min, max) => {
+                let (min, max) = (millis(min), millis(max));
+                if min < max {
+                    Duration::from_millis(rng.gen_range(min, max + 1))
+                } else {
+                    Duration::from_millis(min)
+                }
+            },
+            Latency::Exponential(mean) => {
+                let u: f64 = rng.gen();
+                Duration::from_millis((-(1.0 - u).ln() * millis(mean) as f64) as u64)
+            },
+        }
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs().saturating_mul(1000) + u64::from(duration.subsec_nanos() / 1_000_000)
+}
+
+/// Adverse conditions applied to the responses by `Server::chaos`, for testing how clients cope
+/// with slow and unreliable servers.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use eventstore_tcp::server::{Chaos, Latency};
+///
+/// // writes are slow, every tenth request loses the connection
+/// let chaos = Chaos::new()
+///     .latency(Latency::Uniform(Duration::from_millis(1), Duration::from_millis(20)))
+///     .command_latency(0x82, Latency::Exponential(Duration::from_millis(200)))
+///     .disconnect_probability(0.1)
+///     .reorder_responses(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Chaos {
+    latency: Option<Latency>,
+    command_latencies: HashMap<u8, Latency>,
+    disconnect_probability: f64,
+    reorder: bool,
+}
+
+impl Default for Chaos {
+    fn default() -> Self {
+        Chaos::new()
+    }
+}
+
+impl Chaos {
+    /// Creates the conditions of a well behaving server: no added latency, no disconnects and the
+    /// responses in the order of the requests.
+    pub fn new() -> Self {
+        Chaos {
+            latency: None,
+            command_latencies: HashMap::new(),
+            disconnect_probability: 0.0,
+            reorder: false,
+        }
+    }
+
+    /// Delays the responses to the commands without a latency of their own.
+    ///
+    /// Defaults to no added latency.
+    pub fn latency(mut self, latency: Latency) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    /// Delays the responses to the command with the given discriminator.
+    pub fn command_latency(mut self, discriminator: u8, latency: Latency) -> Self {
+        self.command_latencies.insert(discriminator, latency);
+        self
+    }
+
+    /// Closes the connection instead of writing a response with the given probability, for each
+    /// response. The request has been handled by the service, so the client cannot know whether
+    /// for example a write succeeded.
+    ///
+    /// Defaults to 0.0, never.
+    pub fn disconnect_probability(mut self, probability: f64) -> Self {
+        assert!(probability >= 0.0 && probability <= 1.0, "Probability is not between 0 and 1");
+        self.disconnect_probability = probability;
+        self
+    }
+
+    /// Writes each response as soon as its latency has passed when `true`, so that quick
+    /// responses overtake the slow ones. Otherwise the responses wait for the responses to the
+    /// earlier requests.
+    ///
+    /// Defaults to `false`.
+    pub fn reorder_responses(mut self, reorder: bool) -> Self {
+        self.reorder = reorder;
+        self
+    }
+
+    /// Are the responses written as they complete.
+    pub fn reorders(&self) -> bool {
+        self.reorder
+    }
+}
+
+/// Delays the response to a request with the given discriminator by the sampled latency, and
+/// completes with `None` if the connection should be closed instead.
+pub fn disrupt(chaos: &Chaos, discriminator: u8, response: Box<Future<Item = Package, Error = io::Error>>, handle: &Handle) -> Box<Future<Item = Option<Package>, Error = io::Error>> {
+    let mut rng = rand::thread_rng();
+
+    let disconnect = chaos.disconnect_probability > 0.0 && rng.gen::<f64>() < chaos.disconnect_probability;
+    let response = response.map(move |res| if disconnect { None } else { Some(res) });
+
+    let latency = chaos.command_latencies.get(&discriminator).or(chaos.latency.as_ref());
+
+    match latency.map(|latency| latency.sample(&mut rng)) {
+        Some(delay) => match Timeout::new(delay, handle) {
+            Ok(timeout) => Box::new(response.join(timeout).map(|(res, ())| res)),
+            Err(e) => Box::new(future::err(e)),
+        },
+        None => Box::new(response),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use rand;
+    use super::{Chaos, Latency};
+
+    #[test]
+    fn latencies_are_within_bounds() {
+        let mut rng = rand::thread_rng();
+        let (min, max) = (Duration::from_millis(5), Duration::from_millis(10));
+
+        for _ in 0..100 {
+            let latency = Latency::Uniform(min, max).sample(&mut rng);
+            assert!(latency >= min && latency <= max, "{:?}", latency);
+        }
+
+        assert_eq!(Latency::Uniform(max, min).sample(&mut rng), max);
+        assert_eq!(Latency::Fixed(min).sample(&mut rng), min);
+
+        let total = (0..1000).map(|_| super::millis(Latency::Exponential(Duration::from_millis(100)).sample(&mut rng))).sum::<u64>();
+        assert!(total > 50_000 && total < 200_000, "{}", total);
+    }
+
+    #[test]
+    #[should_panic]
+    fn probability_is_checked() {
+        Chaos::new().disconnect_probability(1.5);
+    }
+}
@@ -0,0 +1,862 @@
+//! Scaffolding for serving the protocol, mostly useful for mock servers in tests. Every request
+//! `Package` read from a connection is handed to a `tokio_service::Service`, and the responses are
+//! written back in the order of the requests. Heartbeats are handled by the server: the clients
+//! are sent heartbeat requests when idle, and their heartbeat requests are answered without
+//! calling the service.
+//!
+//! `MultiplexedServer` lifts the one response per request restriction for serving subscriptions:
+//! the requests are handed to a `Dispatch`, which answers them through a `Responder`.
+//!
+//! `StoreService` is a mock server answering writes, reads and deletes from a `StreamStore`, by
+//! default the streams kept in memory by `MemoryStore`, optionally checking the `Acls` of the
+//! streams. `PersistentSubscriptions` extends it with consumer groups of persistent subscriptions,
+//! served with `MultiplexedServer`.
+//!
+//! `ScriptedServer` instead expects exactly the requests of a script, for testing clients against
+//! the protocol.
+//!
+//! `Server::chaos` makes any of these slow and unreliable, for testing the timeouts, retries and
+//! reconnects of clients.
+//!
+//! Requests the handlers fail with `Unimplemented` are answered with `BadRequest`, or the message
+//! configured with `Server::unimplemented`, so that clients never wait for a response in vain.
+
+use std::io;
+use std::fmt;
+use std::rc::Rc;
+use std::error::Error;
+use std::borrow::Cow;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::{future, Future, Stream, Sink};
+use futures::sync::mpsc;
+use tokio_core::reactor::Handle;
+use tokio_core::net::TcpListener;
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_service::{Service, NewService};
+use uuid::Uuid;
+
+use package::Package;
+use codec::PackageCodec;
+use connection::Heartbeats;
+use transport::AsyncIo;
+use raw::RawMessage;
+use tls::Acceptor;
+use {ServerIdentity, UsernamePassword};
+
+mod store;
+pub use self::store::{StreamStore, StreamSlice, StoreError, MemoryStore, StoreService};
+mod acl;
+pub use self::acl::{Acl, Acls, Operation};
+mod scripted;
+pub use self::scripted::{ScriptedServer, Script, Fault};
+mod chaos;
+pub use self::chaos::{Chaos, Latency};
+mod persistent;
+pub use self::persistent::PersistentSubscriptions;
+
+/// Checks the credentials of requests before they are dispatched to the service. Requests with
+/// credentials failing the check are answered with `NotAuthenticated` without calling the service,
+/// while requests without credentials are always dispatched.
+pub trait Authenticator {
+    /// Returns `true` if the credentials are valid.
+    fn authenticate(&self, credentials: &UsernamePassword) -> bool;
+}
+
+/// `Authenticator` accepting a fixed set of users. The passwords are compared in constant time.
+#[derive(Debug, Clone, Default)]
+pub struct StaticUsers {
+    users: Vec<UsernamePassword>,
+}
+
+impl StaticUsers {
+    /// Creates an authenticator without any users.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an user.
+    pub fn user(mut self, credentials: UsernamePassword) -> Self {
+        self.users.push(credentials);
+        self
+    }
+}
+
+impl Authenticator for StaticUsers {
+    fn authenticate(&self, credentials: &UsernamePassword) -> bool {
+        self.users.iter()
+            .filter(|user| user.username() == credentials.username())
+            .any(|user| constant_time_eq(user.password().as_bytes(), credentials.password().as_bytes()))
+    }
+}
+
+/// Compares the slices so that the time taken does not depend on the position of the first
+/// difference. Only the length is leaked.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Serves the connections accepted by `listener` with a new service created for each connection
+/// by `new_service`, with the defaults of `Server`. The returned future completes only on errors
+/// accepting connections.
+pub fn bind_server<N>(listener: TcpListener, new_service: N, handle: &Handle) -> Box<Future<Item = (), Error = io::Error>>
+    where N: NewService<Request = Package, Response = Package, Error = io::Error> + 'static,
+          N::Instance: 'static,
+          <N::Instance as Service>::Future: 'static
+{
+    Server::new(new_service).serve_listener(listener, handle)
+}
+
+/// Serves connections by creating a new service for each connection with `N`.
+pub struct Server<N> {
+    new_service: N,
+    authenticator: Option<Rc<Authenticator>>,
+    heartbeat_interval: Duration,
+    heartbeat_timeout: Duration,
+    unimplemented: Rc<Fn(u8) -> RawMessage<'static>>,
+    chaos: Option<Rc<Chaos>>,
+    tls: Option<ServerIdentity>,
+}
+
+impl<N> Server<N>
+    where N: NewService<Request = Package, Response = Package, Error = io::Error> + 'static,
+          N::Instance: 'static,
+          <N::Instance as Service>::Future: 'static
+{
+    /// Creates a server without authentication.
+    pub fn new(new_service: N) -> Self {
+        Server {
+            new_service: new_service,
+            authenticator: None,
+            heartbeat_interval: Duration::from_millis(750),
+            heartbeat_timeout: Duration::from_millis(1500),
+            unimplemented: Rc::new(bad_request),
+            chaos: None,
+            tls: None,
+        }
+    }
+
+    /// Checks the credentials of all requests with the given `Authenticator`. `Authenticate`
+    /// requests are answered by the server with `Authenticated` or `NotAuthenticated`, like the
+    /// requests without credentials other than heartbeats and `IdentifyClient`.
+    pub fn authenticator<A: Authenticator + 'static>(mut self, authenticator: A) -> Self {
+        self.authenticator = Some(Rc::new(authenticator));
+        self
+    }
+
+    /// Sends the clients a heartbeat request after `interval` without receiving anything from
+    /// them, closing the connection if there is no response within `timeout`.
+    ///
+    /// Defaults to the heartbeat settings of the client, 750 ms and 1500 ms.
+    pub fn heartbeats(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Answers the requests failed by the service with `Unimplemented` with the message returned
+    /// by `f` for the discriminator of the command, for example `NotHandled` for commands the
+    /// node being emulated would forward to the leader.
+    ///
+    /// Defaults to `BadRequest`, like EventStore answers the commands it does not know.
+    pub fn unimplemented<F: Fn(u8) -> RawMessage<'static> + 'static>(mut self, f: F) -> Self {
+        self.unimplemented = Rc::new(f);
+        self
+    }
+
+    /// Delays the responses, closes connections and reorders the responses as configured with
+    /// `chaos`. Up to 64 requests of a connection are handled at the same time so that the delays
+    /// overlap.
+    ///
+    /// Defaults to answering the requests one at a time without any of these.
+    pub fn chaos(mut self, chaos: Chaos) -> Self {
+        self.chaos = Some(Rc::new(chaos));
+        self
+    }
+
+    /// Encrypts the accepted connections with TLS, presenting the given certificate to the
+    /// clients. Requires the `tls` feature of this crate, like the client; otherwise serving
+    /// fails. Connections given to `serve_connection` are served as they are.
+    ///
+    /// Defaults to unencrypted connections.
+    pub fn tls(mut self, identity: ServerIdentity) -> Self {
+        self.tls = Some(identity);
+        self
+    }
+
+    /// Binds to `addr` and serves every accepted connection on the given reactor. The returned
+    /// future completes only on errors accepting connections.
+    pub fn serve(self, addr: &SocketAddr, handle: &Handle) -> io::Result<Box<Future<Item = (), Error = io::Error>>> {
+        let listener = TcpListener::bind(addr, handle)?;
+        Ok(self.serve_listener(listener, handle))
+    }
+
+    /// Serves every connection accepted by the already bound `listener`, like `serve`.
+    pub fn serve_listener(self, listener: TcpListener, handle: &Handle) -> Box<Future<Item = (), Error = io::Error>> {
+        let identity = self.tls.clone();
+        let server = Rc::new(self);
+
+        accept(listener, identity.as_ref(), handle, move |io, handle| server.serve_connection(io, handle))
+    }
+
+    /// Serves a single already accepted connection until the client disconnects, or stops
+    /// responding to heartbeats.
+    pub fn serve_connection<T: AsyncRead + AsyncWrite + 'static>(&self, io: T, handle: &Handle) -> io::Result<Box<Future<Item = (), Error = io::Error>>> {
+        let service = self.new_service.new_service()?;
+        let authenticator = self.authenticator.clone();
+        let transport = Heartbeats::new(io.framed(PackageCodec), handle, self.heartbeat_interval, self.heartbeat_timeout, "server".into())?;
+        let (sink, stream) = transport.split();
+
+        let unimplemented = self.unimplemented.clone();
+        let responses = stream.map(move |req| {
+            let discriminator = req.message.discriminator();
+            (discriminator, dispatch(&service, authenticator.as_ref().map(|a| &**a), &unimplemented, req))
+        });
+
+        let chaos = match self.chaos {
+            Some(ref chaos) => chaos.clone(),
+            None => return Ok(Box::new(sink.send_all(responses.and_then(|(_, response)| response)).map(|_| ()))),
+        };
+
+        let reorder = chaos.reorders();
+        let handle = handle.clone();
+        let disrupted = responses.map(move |(discriminator, response)| self::chaos::disrupt(&chaos, discriminator, response, &handle));
+
+        let disrupted: Box<Stream<Item = Option<Package>, Error = io::Error>> = if reorder {
+            Box::new(disrupted.buffer_unordered(64))
+        } else {
+            Box::new(disrupted.buffered(64))
+        };
+
+        // `None` closes the connection after the earlier responses have been written
+        let responses = disrupted
+            .take_while(|res| Ok(res.is_some()))
+            .filter_map(|res| res);
+
+        Ok(Box::new(sink.send_all(responses).map(|_| ())))
+    }
+}
+
+/// Handles the requests of a multiplexed connection, answering each with any number of packages
+/// sent through the `Responder`, in any order and at any time. Needed for serving subscriptions,
+/// where the events are pushed to the client as they are written.
+pub trait Dispatch {
+    /// Called once for an accepted connection before any of the requests. The responder can be
+    /// kept for pushing packages to the client. Does nothing by default.
+    fn connected(&self, _responder: &Responder) {}
+
+    /// Handles a request. The returned future is run on the reactor concurrently with the other
+    /// requests; failing it does not close the connection.
+    fn dispatch(&self, req: Package, responder: Responder) -> Box<Future<Item = (), Error = io::Error>>;
+}
+
+/// Creates a `Dispatch` for each connection, like `tokio_service::NewService`.
+pub trait NewDispatch {
+    /// The handler of a connection.
+    type Instance: Dispatch;
+
+    /// Creates the handler for a new connection.
+    fn new_dispatch(&self) -> io::Result<Self::Instance>;
+}
+
+impl<F, D> NewDispatch for F
+    where F: Fn() -> io::Result<D>,
+          D: Dispatch
+{
+    type Instance = D;
+
+    fn new_dispatch(&self) -> io::Result<D> {
+        self()
+    }
+}
+
+/// Sends packages to the client of a multiplexed connection. Cheap to clone; the clones send on
+/// the same connection.
+#[derive(Clone)]
+pub struct Responder {
+    tx: mpsc::UnboundedSender<Package>,
+}
+
+impl Responder {
+    /// Queues the package to be written to the client. Fails with `BrokenPipe` once the
+    /// connection has been closed.
+    pub fn send(&self, package: Package) -> io::Result<()> {
+        self.tx.unbounded_send(package)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Connection has been closed"))
+    }
+
+    /// Sends the `message` as a response correlated to `correlation_id`.
+    pub fn respond(&self, correlation_id: Uuid, message: RawMessage<'static>) -> io::Result<()> {
+        self.send(Package {
+            authentication: None,
+            correlation_id: correlation_id,
+            message: message,
+        })
+    }
+}
+
+/// Serves connections with multiplexed handlers created by `N`, see `Dispatch`. Authentication
+/// and heartbeats are handled like by `Server`.
+pub struct MultiplexedServer<N> {
+    new_dispatch: N,
+    authenticator: Option<Rc<Authenticator>>,
+    heartbeat_interval: Duration,
+    heartbeat_timeout: Duration,
+    unimplemented: Rc<Fn(u8) -> RawMessage<'static>>,
+    tls: Option<ServerIdentity>,
+}
+
+impl<N> MultiplexedServer<N>
+    where N: NewDispatch + 'static,
+          N::Instance: 'static
+{
+    /// Creates a server without authentication.
+    pub fn new(new_dispatch: N) -> Self {
+        MultiplexedServer {
+            new_dispatch: new_dispatch,
+            authenticator: None,
+            heartbeat_interval: Duration::from_millis(750),
+            heartbeat_timeout: Duration::from_millis(1500),
+            unimplemented: Rc::new(bad_request),
+            tls: None,
+        }
+    }
+
+    /// Checks the credentials of all requests with the given `Authenticator`, see
+    /// `Server::authenticator`.
+    pub fn authenticator<A: Authenticator + 'static>(mut self, authenticator: A) -> Self {
+        self.authenticator = Some(Rc::new(authenticator));
+        self
+    }
+
+    /// Sends heartbeat requests to idle clients, see `Server::heartbeats`.
+    ///
+    /// Defaults to 750 ms and 1500 ms.
+    pub fn heartbeats(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Answers the requests failed by the handler with `Unimplemented`, see
+    /// `Server::unimplemented`.
+    ///
+    /// Defaults to `BadRequest`.
+    pub fn unimplemented<F: Fn(u8) -> RawMessage<'static> + 'static>(mut self, f: F) -> Self {
+        self.unimplemented = Rc::new(f);
+        self
+    }
+
+    /// Encrypts the accepted connections with TLS, see `Server::tls`.
+    ///
+    /// Defaults to unencrypted connections.
+    pub fn tls(mut self, identity: ServerIdentity) -> Self {
+        self.tls = Some(identity);
+        self
+    }
+
+    /// Binds to `addr` and serves every accepted connection on the given reactor. The returned
+    /// future completes only on errors accepting connections.
+    pub fn serve(self, addr: &SocketAddr, handle: &Handle) -> io::Result<Box<Future<Item = (), Error = io::Error>>> {
+        let listener = TcpListener::bind(addr, handle)?;
+        Ok(self.serve_listener(listener, handle))
+    }
+
+    /// Serves every connection accepted by the already bound `listener`, like `serve`.
+    pub fn serve_listener(self, listener: TcpListener, handle: &Handle) -> Box<Future<Item = (), Error = io::Error>> {
+        let identity = self.tls.clone();
+        let server = Rc::new(self);
+
+        accept(listener, identity.as_ref(), handle, move |io, handle| server.serve_connection(io, handle))
+    }
+
+    /// Serves a single already accepted connection until the client disconnects, or stops
+    /// responding to heartbeats. The responders of the connection fail from then on.
+    pub fn serve_connection<T: AsyncRead + AsyncWrite + 'static>(&self, io: T, handle: &Handle) -> io::Result<Box<Future<Item = (), Error = io::Error>>> {
+        let dispatcher = self.new_dispatch.new_dispatch()?;
+        let authenticator = self.authenticator.clone();
+        let unimplemented = self.unimplemented.clone();
+        let transport = Heartbeats::new(io.framed(PackageCodec), handle, self.heartbeat_interval, self.heartbeat_timeout, "server".into())?;
+        let (sink, stream) = transport.split();
+
+        let (tx, rx) = mpsc::unbounded();
+        let responder = Responder { tx: tx };
+        dispatcher.connected(&responder);
+
+        let handle = handle.clone();
+        let reading = stream.for_each(move |req| {
+            match check_credentials(authenticator.as_ref().map(|a| &**a), &req) {
+                Some(response) => responder.send(response)?,
+                None => {
+                    let correlation_id = req.correlation_id;
+                    let unimplemented = unimplemented.clone();
+                    let responder = responder.clone();
+
+                    handle.spawn(dispatcher.dispatch(req, responder.clone())
+                        .or_else(move |e| answer_unimplemented(&*unimplemented, correlation_id, e).and_then(|res| responder.send(res)))
+                        .map_err(move |e| warn!("handling request {} failed: {}", correlation_id, e)));
+                },
+            }
+            Ok(())
+        });
+
+        // the responders keep the receiver open, so writing completes only when reading does
+        let writing = sink.send_all(rx.map_err(|()| io::Error::new(io::ErrorKind::Other, "Responses ended")))
+            .map(|_| ());
+
+        Ok(Box::new(reading.select(writing).map(|_| ()).map_err(|(e, _)| e)))
+    }
+}
+
+/// Accepts the connections of `listener`, completing the TLS handshake first if `identity` is
+/// given, and spawns the future returned by `serve` for each of them.
+fn accept<F>(listener: TcpListener, identity: Option<&ServerIdentity>, handle: &Handle, serve: F) -> Box<Future<Item = (), Error = io::Error>>
+    where F: Fn(Box<AsyncIo>, &Handle) -> io::Result<Box<Future<Item = (), Error = io::Error>>> + 'static
+{
+    let acceptor = match identity.map(Acceptor::new) {
+        Some(Ok(acceptor)) => Some(acceptor),
+        Some(Err(e)) => return Box::new(future::err(e)),
+        None => None,
+    };
+
+    let handle = handle.clone();
+    let serve = Rc::new(serve);
+
+    Box::new(listener.incoming().for_each(move |(socket, peer)| {
+        // handshakes run on their own so that a slow client does not hold up accepting the others
+        let stream = match acceptor {
+            Some(ref acceptor) => acceptor.accept(socket),
+            None => Box::new(future::ok(Box::new(socket) as Box<AsyncIo>)),
+        };
+
+        let serve = serve.clone();
+        let inner = handle.clone();
+
+        handle.spawn(stream
+            .and_then(move |stream| future::result(serve(stream, &inner)).flatten())
+            .map_err(move |e| failed(&peer, &e)));
+        Ok(())
+    }))
+}
+
+/// Logs the error a connection was closed with; clients missing the heartbeat timeout are
+/// dropped like by EventStore.
+fn failed(peer: &SocketAddr, e: &io::Error) {
+    match e.kind() {
+        io::ErrorKind::TimedOut => info!("dropped {}: {}", peer, e),
+        _ => warn!("serving {} failed: {}", peer, e),
+    }
+}
+
+fn dispatch<S>(service: &S, authenticator: Option<&Authenticator>, unimplemented: &Rc<Fn(u8) -> RawMessage<'static>>, req: Package) -> Box<Future<Item = Package, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error>,
+          S::Future: 'static
+{
+    if let Some(response) = check_credentials(authenticator, &req) {
+        return Box::new(future::ok(response));
+    }
+
+    let correlation_id = req.correlation_id;
+    let unimplemented = unimplemented.clone();
+
+    Box::new(service.call(req).or_else(move |e| answer_unimplemented(&*unimplemented, correlation_id, e)))
+}
+
+/// Returns the response to a request failed with `Unimplemented`, or the error as is.
+fn answer_unimplemented(unimplemented: &Fn(u8) -> RawMessage<'static>, correlation_id: Uuid, e: io::Error) -> io::Result<Package> {
+    let discriminator = match Unimplemented::find(&e) {
+        Some(failed) => failed.discriminator,
+        None => return Err(e),
+    };
+
+    Ok(Package {
+        authentication: None,
+        correlation_id: correlation_id,
+        message: unimplemented(discriminator),
+    })
+}
+
+fn bad_request(discriminator: u8) -> RawMessage<'static> {
+    let reason = format!("Unimplemented command 0x{:02x}", discriminator);
+    RawMessage::BadRequest(Cow::Owned(reason.into_bytes()).into())
+}
+
+/// Error for failing the requests a service or a `Dispatch` does not implement, answered by the
+/// server as configured with `Server::unimplemented`, instead of closing the connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unimplemented {
+    /// The discriminator of the command
+    pub discriminator: u8,
+}
+
+impl Unimplemented {
+    /// Create a new value for the command with the given discriminator.
+    pub fn new(discriminator: u8) -> Self {
+        Unimplemented { discriminator: discriminator }
+    }
+
+    /// Returns the `Unimplemented` wrapped by the given error, if any.
+    pub fn find(e: &io::Error) -> Option<&Unimplemented> {
+        e.get_ref().and_then(|inner| inner.downcast_ref::<Unimplemented>())
+    }
+}
+
+impl fmt::Display for Unimplemented {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: 0x{:02x}", self.description(), self.discriminator)
+    }
+}
+
+impl Error for Unimplemented {
+    fn description(&self) -> &str {
+        "Unimplemented command"
+    }
+}
+
+impl From<Unimplemented> for io::Error {
+    fn from(unimplemented: Unimplemented) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, unimplemented)
+    }
+}
+
+/// Returns the response to requests answered by the server itself: `Authenticate` requests and
+/// requests failing the credentials check, including the requests without credentials once an
+/// `Authenticator` is set. Heartbeats and `IdentifyClient` need no credentials. Other requests
+/// are dispatched.
+fn check_credentials(authenticator: Option<&Authenticator>, req: &Package) -> Option<Package> {
+    let authenticator = match authenticator {
+        Some(authenticator) => authenticator,
+        None => return None,
+    };
+
+    let (is_authenticate, needs_credentials) = match req.message {
+        RawMessage::Authenticate => (true, true),
+        RawMessage::HeartbeatRequest |
+        RawMessage::HeartbeatResponse |
+        RawMessage::IdentifyClient(_) => (false, false),
+        _ => (false, true),
+    };
+
+    if !needs_credentials {
+        return None;
+    }
+
+    let authenticated = req.authentication.as_ref().map(|credentials| authenticator.authenticate(credentials));
+
+    let response = match (authenticated, is_authenticate) {
+        (Some(true), true) => RawMessage::Authenticated,
+        (Some(true), false) => return None,
+        (Some(false), _) | (None, _) => RawMessage::NotAuthenticated(Cow::Borrowed(&b"Not Authenticated"[..]).into()),
+    };
+
+    Some(Package {
+        authentication: None,
+        correlation_id: req.correlation_id,
+        message: response,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::rc::Rc;
+    use std::borrow::Cow;
+    use futures::future;
+    use tokio_service::Service;
+    use package::Package;
+    use super::{Authenticator, StaticUsers, Unimplemented, constant_time_eq};
+    use UsernamePassword;
+
+    /// Answers every request with the request itself.
+    struct Echo;
+
+    impl Service for Echo {
+        type Request = Package;
+        type Response = Package;
+        type Error = io::Error;
+        type Future = future::FutureResult<Package, io::Error>;
+
+        fn call(&self, req: Package) -> Self::Future {
+            future::ok(req)
+        }
+    }
+
+    /// Fails every request as unimplemented.
+    struct Nothing;
+
+    impl Service for Nothing {
+        type Request = Package;
+        type Response = Package;
+        type Error = io::Error;
+        type Future = future::FutureResult<Package, io::Error>;
+
+        fn call(&self, req: Package) -> Self::Future {
+            future::err(Unimplemented::new(req.message.discriminator()).into())
+        }
+    }
+
+    #[test]
+    fn heartbeats_are_sent_and_answered() {
+        use std::time::Duration;
+        use futures::{Future, Stream, Sink};
+        use tokio_core::reactor::Core;
+        use tokio_core::net::{TcpListener, TcpStream};
+        use tokio_io::AsyncRead;
+        use codec::PackageCodec;
+        use raw::RawMessage;
+        use super::Server;
+
+        struct Pong;
+
+        impl Service for Pong {
+            type Request = Package;
+            type Response = Package;
+            type Error = io::Error;
+            type Future = future::FutureResult<Package, io::Error>;
+
+            fn call(&self, req: Package) -> Self::Future {
+                assert_eq!(req.message, RawMessage::Ping);
+                future::ok(Package { message: RawMessage::Pong, ..req })
+            }
+        }
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new(|| Ok(Pong))
+            .heartbeats(Duration::from_millis(50), Duration::from_secs(5))
+            .serve_listener(listener, &handle);
+        handle.spawn(server.map_err(|e| panic!("{}", e)));
+
+        let heartbeat = Package::new(RawMessage::HeartbeatRequest);
+        let ping = Package { message: RawMessage::Ping, ..heartbeat.clone() };
+
+        let client = TcpStream::connect(&addr, &handle)
+            .and_then(|stream| stream.framed(PackageCodec).send(heartbeat))
+            .and_then(|framed| framed.send(ping))
+            .and_then(|framed| framed.take(3).collect());
+
+        let mut messages = core.run(client).unwrap().into_iter().map(|pkg| pkg.message).collect::<Vec<_>>();
+
+        // the server sends its own heartbeat request only after the responses
+        assert_eq!(messages.pop(), Some(RawMessage::HeartbeatRequest));
+        assert_eq!(messages, vec![RawMessage::HeartbeatResponse, RawMessage::Pong]);
+    }
+
+    #[test]
+    fn clients_missing_heartbeats_are_dropped() {
+        use std::time::Duration;
+        use futures::{Future, Stream};
+        use tokio_core::reactor::Core;
+        use tokio_core::net::{TcpListener, TcpStream};
+        use tokio_io::AsyncRead;
+        use codec::PackageCodec;
+        use raw::RawMessage;
+        use super::Server;
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new(|| Ok(Echo))
+            .heartbeats(Duration::from_millis(20), Duration::from_millis(50))
+            .serve_listener(listener, &handle);
+        handle.spawn(server.map_err(|e| panic!("{}", e)));
+
+        // the client only reads, so the connection ends once the server gives up on it
+        let client = TcpStream::connect(&addr, &handle)
+            .and_then(|stream| stream.framed(PackageCodec).collect());
+
+        let messages = core.run(client).unwrap().into_iter().map(|pkg| pkg.message).collect::<Vec<_>>();
+        assert_eq!(messages, vec![RawMessage::HeartbeatRequest]);
+    }
+
+    #[test]
+    fn chaos_reorders_and_disconnects() {
+        use std::time::Duration;
+        use futures::{Future, Stream, Sink};
+        use tokio_core::reactor::Core;
+        use tokio_core::net::{TcpListener, TcpStream};
+        use tokio_io::AsyncRead;
+        use codec::PackageCodec;
+        use raw::RawMessage;
+        use super::{Server, Chaos, Latency};
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let serve = |chaos| {
+            let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = Server::new(|| Ok(Echo))
+                .heartbeats(Duration::from_secs(5), Duration::from_secs(5))
+                .chaos(chaos)
+                .serve_listener(listener, &handle);
+            handle.spawn(server.map_err(|e| panic!("{}", e)));
+            addr
+        };
+
+        let slow_pings = serve(Chaos::new()
+            .command_latency(0x03, Latency::Fixed(Duration::from_millis(100)))
+            .reorder_responses(true));
+
+        let client = TcpStream::connect(&slow_pings, &handle)
+            .and_then(|stream| stream.framed(PackageCodec).send(Package::new(RawMessage::Ping)))
+            .and_then(|framed| framed.send(Package::new(RawMessage::Pong)))
+            .and_then(|framed| framed.take(2).collect());
+
+        let messages = core.run(client).unwrap().into_iter().map(|pkg| pkg.message).collect::<Vec<_>>();
+        assert_eq!(messages, vec![RawMessage::Pong, RawMessage::Ping]);
+
+        let disconnecting = serve(Chaos::new().disconnect_probability(1.0));
+
+        let client = TcpStream::connect(&disconnecting, &handle)
+            .and_then(|stream| stream.framed(PackageCodec).send(Package::new(RawMessage::Ping)))
+            .and_then(|framed| framed.collect());
+
+        assert!(core.run(client).unwrap().is_empty());
+    }
+
+    #[test]
+    fn multiplexed_responses_and_pushes() {
+        use futures::{Future, Stream, Sink};
+        use tokio_core::reactor::Core;
+        use tokio_core::net::{TcpListener, TcpStream};
+        use tokio_io::AsyncRead;
+        use uuid::Uuid;
+        use codec::PackageCodec;
+        use raw::RawMessage;
+        use super::{MultiplexedServer, Dispatch, Responder};
+
+        struct TwoPongs;
+
+        impl Dispatch for TwoPongs {
+            fn connected(&self, responder: &Responder) {
+                responder.respond(Uuid::nil(), RawMessage::Pong).unwrap();
+            }
+
+            fn dispatch(&self, req: Package, responder: Responder) -> Box<Future<Item = (), Error = io::Error>> {
+                let res = responder.respond(req.correlation_id, RawMessage::Pong)
+                    .and_then(|_| responder.respond(req.correlation_id, RawMessage::Pong));
+                Box::new(future::result(res))
+            }
+        }
+
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let addr = listener.local_addr().unwrap();
+        handle.spawn(MultiplexedServer::new(|| Ok(TwoPongs)).serve_listener(listener, &handle).map_err(|e| panic!("{}", e)));
+
+        let ping = Package::new(RawMessage::Ping);
+        let id = ping.correlation_id;
+
+        let client = TcpStream::connect(&addr, &handle)
+            .and_then(|stream| stream.framed(PackageCodec).send(ping))
+            .and_then(|framed| framed.take(3).collect());
+
+        let received = core.run(client).unwrap().into_iter()
+            .map(|pkg| (pkg.correlation_id, pkg.message))
+            .collect::<Vec<_>>();
+
+        assert_eq!(received, vec![(Uuid::nil(), RawMessage::Pong), (id, RawMessage::Pong), (id, RawMessage::Pong)]);
+    }
+
+    #[test]
+    fn unimplemented_commands() {
+        use futures::Future;
+        use raw::RawMessage;
+        use raw::client_messages::NotHandled;
+        use raw::client_messages::mod_NotHandled::NotHandledReason;
+        use builder::Builder;
+        use super::{dispatch, bad_request};
+
+        let ping = || Builder::ping().build_package(None, None);
+
+        let default = Rc::new(bad_request) as Rc<Fn(u8) -> RawMessage<'static>>;
+        let res = dispatch(&Nothing, None, &default, ping()).wait().unwrap();
+        assert_eq!(res.message, RawMessage::BadRequest(Cow::Borrowed(&b"Unimplemented command 0x03"[..]).into()));
+
+        let not_ready = Rc::new(|_: u8| RawMessage::NotHandled(NotHandled {
+            reason: Some(NotHandledReason::NotReady),
+            additional_info: None,
+        })) as Rc<Fn(u8) -> RawMessage<'static>>;
+        let res = dispatch(&Nothing, None, &not_ready, ping()).wait().unwrap();
+        match res.message {
+            RawMessage::NotHandled(ref not_handled) => assert_eq!(not_handled.reason, Some(NotHandledReason::NotReady)),
+            ref other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "tls"))]
+    fn tls_requires_the_feature() {
+        use tokio_core::reactor::Core;
+        use tokio_core::net::TcpListener;
+        use ServerIdentity;
+        use super::Server;
+
+        let mut core = Core::new().unwrap();
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &core.handle()).unwrap();
+        let server = Server::new(|| Ok(Echo))
+            .tls(ServerIdentity::from_pkcs12(Vec::new(), ""))
+            .serve_listener(listener, &core.handle());
+
+        let e = core.run(server).unwrap_err();
+        assert!(e.to_string().contains("tls feature"), "{}", e);
+    }
+
+    #[test]
+    fn requests_without_credentials_are_not_authenticated() {
+        use uuid::Uuid;
+        use raw::RawMessage;
+        use raw::client_messages::IdentifyClient;
+        use super::check_credentials;
+
+        let users = StaticUsers::new().user(UsernamePassword::new("admin", "changeit"));
+        let package = |message, authentication| Package {
+            authentication: authentication,
+            correlation_id: Uuid::new_v4(),
+            message: message,
+        };
+
+        let not_authenticated = RawMessage::NotAuthenticated(Cow::Borrowed(&b"Not Authenticated"[..]).into());
+        let answer = |req: Package| check_credentials(Some(&users), &req).map(|res| res.message);
+
+        assert_eq!(answer(package(RawMessage::Authenticate, None)), Some(not_authenticated.clone()));
+        assert_eq!(answer(package(RawMessage::Ping, None)), Some(not_authenticated));
+        assert_eq!(answer(package(RawMessage::Authenticate, Some(UsernamePassword::new("admin", "changeit")))), Some(RawMessage::Authenticated));
+        assert_eq!(answer(package(RawMessage::Ping, Some(UsernamePassword::new("admin", "changeit")))), None);
+
+        assert_eq!(answer(package(RawMessage::HeartbeatRequest, None)), None);
+        assert_eq!(answer(package(RawMessage::IdentifyClient(IdentifyClient { version: 1, connection_name: None }), None)), None);
+
+        assert_eq!(check_credentials(None, &package(RawMessage::Ping, None)), None);
+    }
+
+    #[test]
+    fn static_users() {
+        let users = StaticUsers::new().user(UsernamePassword::new("admin", "changeit"));
+
+        assert!(users.authenticate(&UsernamePassword::new("admin", "changeit")));
+        assert!(!users.authenticate(&UsernamePassword::new("admin", "changeis")));
+        assert!(!users.authenticate(&UsernamePassword::new("ops", "changeit")));
+    }
+
+    #[test]
+    fn constant_time_comparison() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+        assert!(constant_time_eq(b"", b""));
+    }
+}
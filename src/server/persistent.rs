@@ -0,0 +1,690 @@
+//! Emulation of persistent subscriptions: consumer groups of a stream, competing for the events.
+
+use std::io;
+use std::cmp;
+use std::mem;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use futures::{future, Future, Stream};
+use quick_protobuf::BytesReader;
+use tokio_core::reactor::{Handle, Interval};
+use uuid::Uuid;
+
+use package::Package;
+use raw::RawMessage;
+use raw::client_messages::{EventRecord, NewEvent, ResolvedIndexedEvent, CreatePersistentSubscription,
+                           CreatePersistentSubscriptionCompleted, DeletePersistentSubscription,
+                           DeletePersistentSubscriptionCompleted, ConnectToPersistentSubscription,
+                           PersistentSubscriptionConfirmation, PersistentSubscriptionStreamEventAppeared,
+                           PersistentSubscriptionAckEvents, PersistentSubscriptionNakEvents, SubscriptionDropped};
+use raw::client_messages::mod_CreatePersistentSubscriptionCompleted::CreatePersistentSubscriptionResult;
+use raw::client_messages::mod_DeletePersistentSubscriptionCompleted::DeletePersistentSubscriptionResult;
+use raw::client_messages::mod_PersistentSubscriptionNakEvents::NakAction;
+use raw::client_messages::mod_SubscriptionDropped::SubscriptionDropReason;
+use unsupported::encoded;
+use {EventNumber, ExpectedVersion, ReadDirection, StreamVersion};
+use super::{Dispatch, NewDispatch, Responder};
+use super::store::{StreamStore, respond};
+
+/// Mock server of persistent subscriptions, serving also the requests of `StoreService` from the
+/// same `StreamStore`. Serve it with `MultiplexedServer`, as the events are pushed to the
+/// consumers.
+///
+/// The consumers connected to a group get the events in turns, up to the number of events each
+/// allows in flight. Events not acknowledged within the message timeout of the group, or
+/// negatively acknowledged with the retry action, are delivered again before the newer events.
+/// Once the maximum retry count of the group is exceeded, and when negatively acknowledged with the
+/// park action, events are parked: links to them are written to the
+/// `$persistentsubscription-<stream>::<group>-parked` stream. The events in flight to a consumer
+/// which disconnects are delivered to the others.
+///
+/// The timeouts are checked every 100 ms on the reactor given to `new`. Named consumer strategies
+/// other than round robin and checkpoints are not emulated.
+pub struct PersistentSubscriptions<S> {
+    store: Rc<RefCell<S>>,
+    state: Rc<RefCell<State>>,
+}
+
+impl<S> Clone for PersistentSubscriptions<S> {
+    fn clone(&self) -> Self {
+        PersistentSubscriptions {
+            store: self.store.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Groups by the stream and the name of the group
+    groups: HashMap<(String, String), Group>,
+    /// Consumers by the correlation id of `ConnectToPersistentSubscription`
+    consumers: HashMap<Uuid, ((String, String), Responder)>,
+}
+
+impl<S: StreamStore + 'static> PersistentSubscriptions<S> {
+    /// Creates a server without any groups, redelivering the events timed out in flight on the
+    /// reactor of `handle`.
+    pub fn new(store: S, handle: &Handle) -> io::Result<Self> {
+        let subscriptions = PersistentSubscriptions {
+            store: Rc::new(RefCell::new(store)),
+            state: Rc::new(RefCell::new(State::default())),
+        };
+
+        let timeouts = subscriptions.clone_weak();
+
+        // stops once the server has been dropped
+        handle.spawn(Interval::new(Duration::from_millis(100), handle)?
+            .map(move |_| timeouts.upgrade())
+            .take_while(|subscriptions| Ok(subscriptions.is_some()))
+            .for_each(|subscriptions| {
+                if let Some(subscriptions) = subscriptions {
+                    subscriptions.deliver(Instant::now());
+                }
+                Ok(())
+            })
+            .map_err(|e| warn!("checking persistent subscription timeouts failed: {}", e)));
+
+        Ok(subscriptions)
+    }
+
+    /// The store, for setting up fixtures and inspecting the written events in tests.
+    pub fn store(&self) -> &Rc<RefCell<S>> {
+        &self.store
+    }
+
+    fn clone_weak(&self) -> WeakSubscriptions<S> {
+        WeakSubscriptions {
+            store: Rc::downgrade(&self.store),
+            state: Rc::downgrade(&self.state),
+        }
+    }
+
+    fn handle(&self, req: Package, responder: &Responder) -> io::Result<()> {
+        let id = req.correlation_id;
+
+        let response = match req.message {
+            RawMessage::CreatePersistentSubscription(create) => self.create(create),
+            RawMessage::Unsupported(0xCA, ref payload) => self.delete(decode(payload, DeletePersistentSubscription::from_reader)?),
+            RawMessage::ConnectToPersistentSubscription(connect) => return self.connect(id, connect, responder),
+            RawMessage::Unsupported(0xCC, ref payload) => {
+                let ack = decode(payload, PersistentSubscriptionAckEvents::from_reader)?;
+                self.with_group_of(id, |group| group.ack(id, &ack.processed_event_ids));
+                None
+            },
+            RawMessage::Unsupported(0xCD, ref payload) => {
+                let nak = decode(payload, PersistentSubscriptionNakEvents::from_reader)?;
+                self.with_group_of(id, |group| group.nak(id, &nak.processed_event_ids, nak.action));
+                None
+            },
+            RawMessage::UnsubscribeFromStream => {
+                self.with_group_of(id, |group| group.unsubscribe(id));
+
+                match self.state.borrow_mut().consumers.remove(&id) {
+                    Some(_) => Some(dropped(SubscriptionDropReason::Unsubscribed)),
+                    None => None,
+                }
+            },
+            other => Some(respond(&mut *self.store.borrow_mut(), other)?),
+        };
+
+        if let Some(response) = response {
+            responder.respond(id, response)?;
+        }
+
+        // writes, acknowledgements and disconnects can all make events deliverable
+        self.deliver(Instant::now());
+        Ok(())
+    }
+
+    fn create(&self, req: CreatePersistentSubscription) -> Option<RawMessage<'static>> {
+        let key = (req.event_stream_id.to_string(), req.subscription_group_name.to_string());
+        let mut state = self.state.borrow_mut();
+
+        let result = if state.groups.contains_key(&key) {
+            CreatePersistentSubscriptionResult::AlreadyExists
+        } else {
+            let group = Group::new(&key.0, GroupSettings::from(&req), &*self.store.borrow());
+            state.groups.insert(key, group);
+            CreatePersistentSubscriptionResult::Success
+        };
+
+        Some(RawMessage::CreatePersistentSubscriptionCompleted(CreatePersistentSubscriptionCompleted {
+            result: result,
+            reason: None,
+        }))
+    }
+
+    fn delete(&self, req: DeletePersistentSubscription) -> Option<RawMessage<'static>> {
+        let key = (req.event_stream_id.to_string(), req.subscription_group_name.to_string());
+        let mut state = self.state.borrow_mut();
+
+        let result = match state.groups.remove(&key) {
+            Some(_) => DeletePersistentSubscriptionResult::Success,
+            None => DeletePersistentSubscriptionResult::DoesNotExist,
+        };
+
+        let consumers = state.consumers.iter()
+            .filter(|&(_, &(ref group, _))| *group == key)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        for id in consumers {
+            if let Some((_, responder)) = state.consumers.remove(&id) {
+                let _ = responder.respond(id, dropped(SubscriptionDropReason::PersistentSubscriptionDeleted));
+            }
+        }
+
+        Some(encoded(0xCB, &DeletePersistentSubscriptionCompleted {
+            result: result,
+            reason: None,
+        }))
+    }
+
+    fn connect(&self, id: Uuid, req: ConnectToPersistentSubscription, responder: &Responder) -> io::Result<()> {
+        let key = (req.event_stream_id.to_string(), req.subscription_id.to_string());
+
+        {
+            let mut state = self.state.borrow_mut();
+            let state = &mut *state;
+
+            match state.groups.get_mut(&key) {
+                Some(group) => group.subscribe(id, cmp::max(req.allowed_in_flight_messages, 1) as usize),
+                None => return responder.respond(id, dropped(SubscriptionDropReason::NotFound)),
+            }
+
+            state.consumers.insert(id, (key.clone(), responder.clone()));
+        }
+
+        let last_event_number = self.store.borrow()
+            .read(&key.0, EventNumber::Last, 1, ReadDirection::Backward)
+            .ok()
+            .map(|slice| i32::from(slice.last_event_number));
+
+        responder.respond(id, RawMessage::PersistentSubscriptionConfirmation(PersistentSubscriptionConfirmation {
+            last_commit_position: -1,
+            subscription_id: Cow::Owned(format!("{}::{}", key.0, key.1)),
+            last_event_number: last_event_number,
+        }))?;
+
+        self.deliver(Instant::now());
+        Ok(())
+    }
+
+    fn with_group_of<F: FnOnce(&mut Group)>(&self, consumer: Uuid, f: F) {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+
+        if let Some(&(ref key, _)) = state.consumers.get(&consumer) {
+            if let Some(group) = state.groups.get_mut(key) {
+                f(group);
+            }
+        }
+    }
+
+    /// Pushes the deliverable events to the consumers, and writes the links to the parked events.
+    fn deliver(&self, now: Instant) {
+        let mut parked = Vec::new();
+
+        {
+            let store = self.store.borrow();
+            let mut state = self.state.borrow_mut();
+            let state = &mut *state;
+
+            loop {
+                let mut disconnected = Vec::new();
+
+                for (key, group) in &mut state.groups {
+                    for (consumer, event) in group.deliver(&*store, now) {
+                        let sent = match state.consumers.get(&consumer) {
+                            Some(&(_, ref responder)) => responder.respond(consumer, appeared(event)).is_ok(),
+                            None => false,
+                        };
+
+                        if !sent {
+                            disconnected.push((key.clone(), consumer));
+                        }
+                    }
+                }
+
+                if disconnected.is_empty() {
+                    break;
+                }
+
+                // the events in flight to these go to the other consumers on the next round
+                for (key, consumer) in disconnected {
+                    state.consumers.remove(&consumer);
+                    if let Some(group) = state.groups.get_mut(&key) {
+                        group.unsubscribe(consumer);
+                    }
+                }
+            }
+
+            for (key, group) in &mut state.groups {
+                let events = group.take_parked();
+                if !events.is_empty() {
+                    parked.push((format!("$persistentsubscription-{}::{}-parked", key.0, key.1), events));
+                }
+            }
+        }
+
+        let mut store = self.store.borrow_mut();
+
+        for (stream, events) in parked {
+            let links = events.into_iter().map(link).collect();
+            if store.append(&stream, ExpectedVersion::Any, links).is_err() {
+                warn!("parking events to {} failed", stream);
+            }
+        }
+    }
+}
+
+impl<S: StreamStore + 'static> Dispatch for PersistentSubscriptions<S> {
+    fn dispatch(&self, req: Package, responder: Responder) -> Box<Future<Item = (), Error = io::Error>> {
+        Box::new(future::result(self.handle(req, &responder)))
+    }
+}
+
+impl<S: StreamStore + 'static> NewDispatch for PersistentSubscriptions<S> {
+    type Instance = Self;
+
+    fn new_dispatch(&self) -> io::Result<Self> {
+        Ok(self.clone())
+    }
+}
+
+/// Keeps the timer from keeping the server alive.
+struct WeakSubscriptions<S> {
+    store: ::std::rc::Weak<RefCell<S>>,
+    state: ::std::rc::Weak<RefCell<State>>,
+}
+
+impl<S> WeakSubscriptions<S> {
+    fn upgrade(&self) -> Option<PersistentSubscriptions<S>> {
+        match (self.store.upgrade(), self.state.upgrade()) {
+            (Some(store), Some(state)) => Some(PersistentSubscriptions { store: store, state: state }),
+            _ => None,
+        }
+    }
+}
+
+/// The settings of a group emulated from `CreatePersistentSubscription`.
+#[derive(Debug, Clone)]
+struct GroupSettings {
+    /// Event number of the first event, or -1 for the end of the stream
+    start_from: i32,
+    message_timeout: Duration,
+    max_retry_count: u32,
+    read_batch_size: usize,
+}
+
+impl<'a, 'b> From<&'a CreatePersistentSubscription<'b>> for GroupSettings {
+    fn from(req: &'a CreatePersistentSubscription<'b>) -> Self {
+        // the defaults of EventStore for unset values
+        let or = |value: i32, default: i32| if value > 0 { value } else { default };
+
+        GroupSettings {
+            start_from: req.start_from,
+            message_timeout: Duration::from_millis(or(req.message_timeout_milliseconds, 30_000) as u64),
+            max_retry_count: cmp::max(req.max_retry_count, 0) as u32,
+            read_batch_size: or(req.read_batch_size, 20) as usize,
+        }
+    }
+}
+
+struct InFlight {
+    event: EventRecord<'static>,
+    consumer: Uuid,
+    retries: u32,
+    deadline: Instant,
+}
+
+struct Consumer {
+    id: Uuid,
+    allowed_in_flight: usize,
+}
+
+/// Delivers the events of a stream to the consumers connected to a group.
+struct Group {
+    stream: String,
+    settings: GroupSettings,
+    /// Event number of the next event to read from the stream
+    next_event: u32,
+    buffer: VecDeque<EventRecord<'static>>,
+    consumers: Vec<Consumer>,
+    /// Index of the consumer taking the next turn
+    next_consumer: usize,
+    in_flight: Vec<InFlight>,
+    /// Events to deliver again with the number of retries so far, before the newer events
+    retry: VecDeque<(EventRecord<'static>, u32)>,
+    parked: Vec<EventRecord<'static>>,
+}
+
+impl Group {
+    fn new<S: StreamStore + ?Sized>(stream: &str, settings: GroupSettings, store: &S) -> Self {
+        let next_event = if settings.start_from >= 0 {
+            settings.start_from as u32
+        } else {
+            match store.read(stream, EventNumber::Last, 1, ReadDirection::Backward) {
+                Ok(slice) => u32::from(slice.last_event_number) + 1,
+                Err(_) => 0,
+            }
+        };
+
+        Group {
+            stream: stream.to_owned(),
+            settings: settings,
+            next_event: next_event,
+            buffer: VecDeque::new(),
+            consumers: Vec::new(),
+            next_consumer: 0,
+            in_flight: Vec::new(),
+            retry: VecDeque::new(),
+            parked: Vec::new(),
+        }
+    }
+
+    fn subscribe(&mut self, id: Uuid, allowed_in_flight: usize) {
+        self.consumers.push(Consumer {
+            id: id,
+            allowed_in_flight: allowed_in_flight,
+        });
+    }
+
+    /// Removes the consumer; the events in flight to it are delivered again first.
+    fn unsubscribe(&mut self, id: Uuid) {
+        self.consumers.retain(|consumer| consumer.id != id);
+
+        let (returned, kept): (Vec<_>, Vec<_>) = self.in_flight.drain(..).partition(|f| f.consumer == id);
+        self.in_flight = kept;
+
+        for f in returned.into_iter().rev() {
+            self.retry.push_front((f.event, f.retries));
+        }
+    }
+
+    fn ack(&mut self, consumer: Uuid, event_ids: &[Cow<[u8]>]) {
+        for event_id in event_ids {
+            self.take_in_flight(consumer, event_id);
+        }
+    }
+
+    fn nak(&mut self, consumer: Uuid, event_ids: &[Cow<[u8]>], action: NakAction) {
+        for event_id in event_ids {
+            if let Some(f) = self.take_in_flight(consumer, event_id) {
+                match action {
+                    NakAction::Park => self.parked.push(f.event),
+                    NakAction::Skip => (),
+                    NakAction::Unknown | NakAction::Retry | NakAction::Stop => self.retry_later(f.event, f.retries + 1),
+                }
+            }
+        }
+    }
+
+    fn take_in_flight(&mut self, consumer: Uuid, event_id: &[u8]) -> Option<InFlight> {
+        match self.in_flight.iter().position(|f| f.consumer == consumer && &*f.event.event_id == event_id) {
+            Some(index) => Some(self.in_flight.remove(index)),
+            None => None,
+        }
+    }
+
+    fn retry_later(&mut self, event: EventRecord<'static>, retries: u32) {
+        if retries > self.settings.max_retry_count {
+            self.parked.push(event);
+        } else {
+            self.retry.push_back((event, retries));
+        }
+    }
+
+    /// Returns the events to deliver now, and to which consumers, after retrying the events which
+    /// have timed out in flight.
+    fn deliver<S: StreamStore + ?Sized>(&mut self, store: &S, now: Instant) -> Vec<(Uuid, EventRecord<'static>)> {
+        let (expired, kept): (Vec<_>, Vec<_>) = self.in_flight.drain(..).partition(|f| f.deadline <= now);
+        self.in_flight = kept;
+
+        for f in expired {
+            self.retry_later(f.event, f.retries + 1);
+        }
+
+        let mut deliveries = Vec::new();
+
+        loop {
+            let consumer = match self.next_free_consumer() {
+                Some(consumer) => consumer,
+                None => break,
+            };
+
+            let (event, retries) = match self.retry.pop_front() {
+                Some(retry) => retry,
+                None => match self.read_next(store) {
+                    Some(event) => (event, 0),
+                    None => break,
+                },
+            };
+
+            deliveries.push((consumer, event.clone()));
+            self.in_flight.push(InFlight {
+                event: event,
+                consumer: consumer,
+                retries: retries,
+                deadline: now + self.settings.message_timeout,
+            });
+        }
+
+        deliveries
+    }
+
+    /// Picks the next consumer in turn with room for more events in flight.
+    fn next_free_consumer(&mut self) -> Option<Uuid> {
+        for _ in 0..self.consumers.len() {
+            let index = self.next_consumer % self.consumers.len();
+            self.next_consumer = index + 1;
+
+            let consumer = &self.consumers[index];
+            let in_flight = self.in_flight.iter().filter(|f| f.consumer == consumer.id).count();
+
+            if in_flight < consumer.allowed_in_flight {
+                return Some(consumer.id);
+            }
+        }
+
+        None
+    }
+
+    fn read_next<S: StreamStore + ?Sized>(&mut self, store: &S) -> Option<EventRecord<'static>> {
+        if self.buffer.is_empty() {
+            let from = match StreamVersion::try_from(self.next_event) {
+                Ok(from) => from,
+                Err(_) => return None,
+            };
+
+            if let Ok(slice) = store.read(&self.stream, EventNumber::Exact(from), self.settings.read_batch_size, ReadDirection::Forward) {
+                if let Some(last) = slice.events.last() {
+                    self.next_event = last.event_number as u32 + 1;
+                }
+                self.buffer.extend(slice.events);
+            }
+        }
+
+        self.buffer.pop_front()
+    }
+
+    fn take_parked(&mut self) -> Vec<EventRecord<'static>> {
+        mem::replace(&mut self.parked, Vec::new())
+    }
+}
+
+fn decode<'a, M, F>(payload: &'a [u8], from_reader: F) -> io::Result<M>
+    where F: FnOnce(&mut BytesReader, &'a [u8]) -> ::quick_protobuf::Result<M>
+{
+    let mut reader = BytesReader::from_bytes(payload);
+    from_reader(&mut reader, payload).map_err(|e| e.into())
+}
+
+fn appeared(event: EventRecord<'static>) -> RawMessage<'static> {
+    encoded(0xC7, &PersistentSubscriptionStreamEventAppeared {
+        event: ResolvedIndexedEvent {
+            event: event,
+            link: None,
+        },
+    })
+}
+
+fn dropped(reason: SubscriptionDropReason) -> RawMessage<'static> {
+    RawMessage::SubscriptionDropped(SubscriptionDropped { reason: reason })
+}
+
+/// A link event to `event`, like EventStore writes to the parked streams.
+fn link(event: EventRecord<'static>) -> NewEvent<'static> {
+    NewEvent {
+        event_id: Cow::Owned(Uuid::new_v4().as_bytes().to_vec()),
+        event_type: Cow::Borrowed("$>"),
+        data_content_type: 0,
+        metadata_content_type: 0,
+        data: Cow::Owned(format!("{}@{}", event.event_number, event.event_stream_id).into_bytes()),
+        metadata: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::time::{Duration, Instant};
+    use uuid::Uuid;
+    use raw::client_messages::NewEvent;
+    use raw::client_messages::mod_PersistentSubscriptionNakEvents::NakAction;
+    use server::{StreamStore, MemoryStore};
+    use ExpectedVersion;
+    use super::{Group, GroupSettings};
+
+    fn store(count: usize) -> MemoryStore {
+        let mut store = MemoryStore::new();
+        let events = (0..count).map(|_| NewEvent {
+            event_id: Cow::Owned(Uuid::new_v4().as_bytes().to_vec()),
+            ..NewEvent::default()
+        }).collect();
+        store.append("orders", ExpectedVersion::Any, events).unwrap();
+        store
+    }
+
+    fn group(store: &MemoryStore, max_retry_count: u32) -> Group {
+        Group::new("orders", GroupSettings {
+            start_from: 0,
+            message_timeout: Duration::from_secs(10),
+            max_retry_count: max_retry_count,
+            read_batch_size: 2,
+        }, store)
+    }
+
+    fn numbers(deliveries: &[(Uuid, ::raw::client_messages::EventRecord<'static>)]) -> Vec<(Uuid, i32)> {
+        deliveries.iter().map(|&(consumer, ref event)| (consumer, event.event_number)).collect()
+    }
+
+    #[test]
+    fn round_robin_within_in_flight_limits() {
+        let store = store(5);
+        let mut group = group(&store, 10);
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let now = Instant::now();
+
+        group.subscribe(a, 2);
+        group.subscribe(b, 1);
+
+        let deliveries = group.deliver(&store, now);
+        assert_eq!(numbers(&deliveries), vec![(a, 0), (b, 1), (a, 2)]);
+        assert!(group.deliver(&store, now).is_empty());
+
+        group.ack(b, &[deliveries[1].1.event_id.clone()]);
+        assert_eq!(numbers(&group.deliver(&store, now)), vec![(b, 3)]);
+    }
+
+    #[test]
+    fn timed_out_events_are_retried_then_parked() {
+        let store = store(1);
+        let mut group = group(&store, 1);
+        let a = Uuid::new_v4();
+        let now = Instant::now();
+
+        group.subscribe(a, 10);
+        assert_eq!(numbers(&group.deliver(&store, now)), vec![(a, 0)]);
+
+        let later = now + Duration::from_secs(11);
+        assert_eq!(numbers(&group.deliver(&store, later)), vec![(a, 0)]);
+        assert!(group.take_parked().is_empty());
+
+        assert!(group.deliver(&store, later + Duration::from_secs(11)).is_empty());
+        assert_eq!(group.take_parked().len(), 1);
+    }
+
+    #[test]
+    fn naks_and_disconnects_redeliver_first() {
+        let store = store(4);
+        let mut group = group(&store, 10);
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let now = Instant::now();
+
+        group.subscribe(a, 2);
+        let deliveries = group.deliver(&store, now);
+        assert_eq!(numbers(&deliveries), vec![(a, 0), (a, 1)]);
+
+        group.nak(a, &[deliveries[0].1.event_id.clone()], NakAction::Retry);
+        group.nak(a, &[deliveries[1].1.event_id.clone()], NakAction::Park);
+        assert_eq!(group.take_parked().len(), 1);
+        assert_eq!(numbers(&group.deliver(&store, now)), vec![(a, 0), (a, 2)]);
+
+        group.subscribe(b, 2);
+        group.unsubscribe(a);
+        assert_eq!(numbers(&group.deliver(&store, now)), vec![(b, 0), (b, 2)]);
+    }
+
+    #[test]
+    fn served_over_the_wire() {
+        use futures::{Future, Stream};
+        use futures::sync::mpsc;
+        use tokio_core::reactor::Core;
+        use package::Package;
+        use raw::RawMessage;
+        use raw::client_messages::{CreatePersistentSubscription, ConnectToPersistentSubscription};
+        use server::{Dispatch, Responder};
+        use super::PersistentSubscriptions;
+
+        fn request(id: Uuid, message: RawMessage<'static>) -> Package {
+            Package {
+                authentication: None,
+                correlation_id: id,
+                message: message,
+            }
+        }
+
+        let core = Core::new().unwrap();
+        let subscriptions = PersistentSubscriptions::new(store(2), &core.handle()).unwrap();
+        let (tx, rx) = mpsc::unbounded();
+        let responder = Responder { tx: tx };
+
+        let create = CreatePersistentSubscription {
+            subscription_group_name: "workers".into(),
+            event_stream_id: "orders".into(),
+            ..CreatePersistentSubscription::default()
+        };
+        subscriptions.handle(request(Uuid::new_v4(), RawMessage::CreatePersistentSubscription(create)), &responder).unwrap();
+
+        let connect = ConnectToPersistentSubscription {
+            subscription_id: "workers".into(),
+            event_stream_id: "orders".into(),
+            allowed_in_flight_messages: 10,
+        };
+        let id = Uuid::new_v4();
+        subscriptions.dispatch(request(id, RawMessage::ConnectToPersistentSubscription(connect)), responder.clone()).wait().unwrap();
+
+        let discriminators = rx.take(4).wait()
+            .map(|res| res.unwrap().message.discriminator())
+            .collect::<Vec<_>>();
+
+        assert_eq!(discriminators, vec![0xC9, 0xC6, 0xC7, 0xC7]);
+    }
+}
@@ -0,0 +1,357 @@
+//! Server following a script of the expected requests, for testing clients at the protocol level.
+
+use std::io;
+use std::fmt;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::collections::VecDeque;
+
+use futures::{stream, Future, Stream, Sink};
+use tokio_core::reactor::Handle;
+use tokio_core::net::TcpListener;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use package::Package;
+use codec::PackageCodec;
+use connection::Heartbeats;
+use raw::RawMessage;
+use ServerIdentity;
+use super::accept;
+
+/// Faults a `ScriptedServer` can answer a request with instead of responding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Closes the connection without responding
+    Disconnect,
+    /// Leaves the request unanswered, for testing timeouts
+    NoResponse,
+}
+
+enum Action {
+    Respond(RawMessage<'static>),
+    Fault(Fault),
+}
+
+struct Step {
+    description: String,
+    matches: Box<Fn(&Package) -> bool>,
+    actions: Vec<Action>,
+}
+
+#[derive(Default)]
+struct State {
+    steps: VecDeque<Step>,
+    deviations: Vec<String>,
+}
+
+/// Server expecting exactly the requests of a script, in order, and answering each with the
+/// responses or the fault scripted for it. Requests deviating from the script are recorded and the
+/// connection is closed; `Script::assert_complete` fails the test on any deviations, or if some of
+/// the script was never reached.
+///
+/// The script continues over the connections, so reconnecting clients can be tested by scripting
+/// a `Fault::Disconnect`. Heartbeats are handled like by `Server` and are not part of the script.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// extern crate futures;
+/// extern crate tokio_core;
+/// extern crate eventstore_tcp;
+///
+/// use futures::Future;
+/// use tokio_core::reactor::Core;
+/// use eventstore_tcp::RawMessage;
+/// use eventstore_tcp::server::{ScriptedServer, Fault};
+///
+/// fn main() {
+///     let mut core = Core::new().unwrap();
+///
+///     let server = ScriptedServer::new()
+///         .expect(0x03).fault(Fault::Disconnect)
+///         .expect(0x03).respond(RawMessage::Pong);
+///     let script = server.script();
+///
+///     let serving = server.serve(&"127.0.0.1:1113".parse().unwrap(), &core.handle()).unwrap();
+///     core.handle().spawn(serving.map_err(|e| panic!("{}", e)));
+///
+///     // run the client pinging twice on the core
+///
+///     script.assert_complete();
+/// }
+/// ```
+pub struct ScriptedServer {
+    state: Rc<RefCell<State>>,
+    heartbeat_interval: Duration,
+    heartbeat_timeout: Duration,
+    tls: Option<ServerIdentity>,
+}
+
+impl ScriptedServer {
+    /// Creates a server with an empty script.
+    pub fn new() -> Self {
+        ScriptedServer {
+            state: Rc::new(RefCell::new(State::default())),
+            heartbeat_interval: Duration::from_millis(750),
+            heartbeat_timeout: Duration::from_millis(1500),
+            tls: None,
+        }
+    }
+
+    /// Expects the next request to be a message with the given discriminator.
+    pub fn expect(self, discriminator: u8) -> Self {
+        self.expect_matching(format!("request 0x{:02x}", discriminator), move |req| req.message.discriminator() == discriminator)
+    }
+
+    /// Expects the next request to be a message equal to `message`.
+    pub fn expect_message(self, message: RawMessage<'static>) -> Self {
+        self.expect_matching(format!("{:?}", message), move |req| req.message == message)
+    }
+
+    /// Expects the next request to be accepted by `f`, described as `description` when it is
+    /// not.
+    pub fn expect_matching<S, F>(self, description: S, f: F) -> Self
+        where S: Into<String>,
+              F: Fn(&Package) -> bool + 'static
+    {
+        self.state.borrow_mut().steps.push_back(Step {
+            description: description.into(),
+            matches: Box::new(f),
+            actions: Vec::new(),
+        });
+        self
+    }
+
+    /// Answers the last expected request with `message`, correlated to the request. Can be called
+    /// multiple times for requests answered with more than one package.
+    pub fn respond(self, message: RawMessage<'static>) -> Self {
+        self.then(Action::Respond(message))
+    }
+
+    /// Answers the last expected request with the fault, after the responses scripted before it.
+    pub fn fault(self, fault: Fault) -> Self {
+        self.then(Action::Fault(fault))
+    }
+
+    fn then(self, action: Action) -> Self {
+        self.state.borrow_mut().steps.back_mut()
+            .expect("Responses are scripted after the expected request")
+            .actions.push(action);
+        self
+    }
+
+    /// Sends heartbeat requests to idle clients, see `Server::heartbeats`.
+    ///
+    /// Defaults to 750 ms and 1500 ms.
+    pub fn heartbeats(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self.heartbeat_timeout = timeout;
+        self
+    }
+
+    /// Encrypts the accepted connections with TLS, see `Server::tls`.
+    ///
+    /// Defaults to unencrypted connections.
+    pub fn tls(mut self, identity: ServerIdentity) -> Self {
+        self.tls = Some(identity);
+        self
+    }
+
+    /// The progress of the script, for verifying it once the client is done.
+    pub fn script(&self) -> Script {
+        Script { state: self.state.clone() }
+    }
+
+    /// Binds to `addr` and serves every accepted connection on the given reactor. The returned
+    /// future completes only on errors accepting connections.
+    pub fn serve(self, addr: &SocketAddr, handle: &Handle) -> io::Result<Box<Future<Item = (), Error = io::Error>>> {
+        let listener = TcpListener::bind(addr, handle)?;
+        Ok(self.serve_listener(listener, handle))
+    }
+
+    /// Serves every connection accepted by the already bound `listener`, like `serve`.
+    pub fn serve_listener(self, listener: TcpListener, handle: &Handle) -> Box<Future<Item = (), Error = io::Error>> {
+        let identity = self.tls.clone();
+        let server = Rc::new(self);
+
+        accept(listener, identity.as_ref(), handle, move |io, handle| server.serve_connection(io, handle))
+    }
+
+    /// Serves a single already accepted connection until the client disconnects, a request
+    /// deviates from the script or a `Fault::Disconnect` is reached.
+    pub fn serve_connection<T: AsyncRead + AsyncWrite + 'static>(&self, io: T, handle: &Handle) -> io::Result<Box<Future<Item = (), Error = io::Error>>> {
+        let transport = Heartbeats::new(io.framed(PackageCodec), handle, self.heartbeat_interval, self.heartbeat_timeout, "server".into())?;
+        let (sink, requests) = transport.split();
+        let script = self.script();
+
+        // `None` in the responses disconnects after the packages before it have been written
+        let responses = requests
+            .and_then(move |req| script.next(req))
+            .map(stream::iter_ok::<_, io::Error>)
+            .flatten()
+            .take_while(|res| Ok(res.is_some()))
+            .filter_map(|res| res);
+
+        Ok(Box::new(sink.send_all(responses).map(|_| ())))
+    }
+}
+
+impl Default for ScriptedServer {
+    fn default() -> Self {
+        ScriptedServer::new()
+    }
+}
+
+/// Handle to the progress of the script of a `ScriptedServer`.
+#[derive(Clone)]
+pub struct Script {
+    state: Rc<RefCell<State>>,
+}
+
+impl Script {
+    /// The number of expected requests not yet received.
+    pub fn remaining(&self) -> usize {
+        self.state.borrow().steps.len()
+    }
+
+    /// Descriptions of the requests which deviated from the script.
+    pub fn deviations(&self) -> Vec<String> {
+        self.state.borrow().deviations.clone()
+    }
+
+    /// Panics if any request deviated from the script, or if some of the expected requests were
+    /// never received.
+    pub fn assert_complete(&self) {
+        let state = self.state.borrow();
+
+        if !state.deviations.is_empty() {
+            panic!("requests deviated from the script:\n  {}", state.deviations.join("\n  "));
+        }
+
+        if !state.steps.is_empty() {
+            let remaining = state.steps.iter().map(|step| &step.description[..]).collect::<Vec<_>>();
+            panic!("expected requests were not received:\n  {}", remaining.join("\n  "));
+        }
+    }
+
+    /// Takes the next step for the request, returning the responses followed by `None` for
+    /// disconnecting.
+    fn next(&self, req: Package) -> io::Result<Vec<Option<Package>>> {
+        let mut state = self.state.borrow_mut();
+
+        let step = match state.steps.front() {
+            Some(step) if (step.matches)(&req) => None,
+            Some(step) => Some(format!("expected {}, got {:?}", step.description, req.message)),
+            None => Some(format!("expected nothing, got {:?}", req.message)),
+        };
+
+        if let Some(deviation) = step {
+            state.deviations.push(deviation.clone());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, Deviation(deviation)));
+        }
+
+        let step = state.steps.pop_front().expect("Step was just matched");
+        let mut responses = Vec::new();
+
+        for action in step.actions {
+            match action {
+                Action::Respond(message) => responses.push(Some(Package {
+                    authentication: None,
+                    correlation_id: req.correlation_id,
+                    message: message,
+                })),
+                Action::Fault(Fault::Disconnect) => {
+                    responses.push(None);
+                    break;
+                },
+                Action::Fault(Fault::NoResponse) => break,
+            }
+        }
+
+        Ok(responses)
+    }
+}
+
+#[derive(Debug)]
+struct Deviation(String);
+
+impl fmt::Display for Deviation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Request deviated from the script: {}", self.0)
+    }
+}
+
+impl ::std::error::Error for Deviation {
+    fn description(&self) -> &str {
+        "Request deviated from the script"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use futures::{Future, Stream, Sink};
+    use tokio_core::reactor::Core;
+    use tokio_core::net::{TcpListener, TcpStream};
+    use tokio_io::AsyncRead;
+    use codec::PackageCodec;
+    use package::Package;
+    use raw::RawMessage;
+    use super::{ScriptedServer, Fault};
+
+    #[test]
+    fn follows_the_script_over_connections() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = ScriptedServer::new()
+            .heartbeats(Duration::from_secs(5), Duration::from_secs(5))
+            .expect(0x03).fault(Fault::Disconnect)
+            .expect_message(RawMessage::Ping).respond(RawMessage::Pong).respond(RawMessage::Pong);
+        let script = server.script();
+        handle.spawn(server.serve_listener(listener, &handle).map_err(|e| panic!("{}", e)));
+
+        let disconnected = TcpStream::connect(&addr, &handle)
+            .and_then(|stream| stream.framed(PackageCodec).send(Package::new(RawMessage::Ping)))
+            .and_then(|framed| framed.collect());
+
+        assert!(core.run(disconnected).unwrap().is_empty());
+        assert_eq!(script.remaining(), 1);
+
+        let answered = TcpStream::connect(&addr, &handle)
+            .and_then(|stream| stream.framed(PackageCodec).send(Package::new(RawMessage::Ping)))
+            .and_then(|framed| framed.take(2).collect());
+
+        let messages = core.run(answered).unwrap().into_iter().map(|pkg| pkg.message).collect::<Vec<_>>();
+        assert_eq!(messages, vec![RawMessage::Pong, RawMessage::Pong]);
+
+        script.assert_complete();
+    }
+
+    #[test]
+    fn deviations_are_recorded() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = ScriptedServer::new()
+            .expect(0x82).respond(RawMessage::Pong);
+        let script = server.script();
+        handle.spawn(server.serve_listener(listener, &handle).map_err(|e| panic!("{}", e)));
+
+        let client = TcpStream::connect(&addr, &handle)
+            .and_then(|stream| stream.framed(PackageCodec).send(Package::new(RawMessage::Ping)))
+            .and_then(|framed| framed.collect());
+
+        // the server closes the connection, with or without a reset
+        let _ = core.run(client);
+
+        assert_eq!(script.deviations(), vec!["expected request 0x82, got Ping".to_owned()]);
+        assert_eq!(script.remaining(), 1);
+    }
+}
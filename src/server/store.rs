@@ -0,0 +1,668 @@
+//! Storage of the events served by `StoreService`.
+
+use std::io;
+use std::fmt;
+use std::cmp;
+use std::error::Error;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::convert::TryFrom;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::future;
+use tokio_service::{Service, NewService};
+use uuid::Uuid;
+
+use package::Package;
+use server::Unimplemented;
+use super::acl::{Acls, Authorized};
+use raw::RawMessage;
+use raw::client_messages::{NewEvent, EventRecord, ResolvedIndexedEvent, WriteEvents, WriteEventsCompleted,
+                           ReadEvent, ReadEventCompleted, ReadStreamEvents, ReadStreamEventsCompleted,
+                           DeleteStream, DeleteStreamCompleted, OperationResult};
+use raw::client_messages::mod_ReadEventCompleted::ReadEventResult;
+use raw::client_messages::mod_ReadStreamEventsCompleted::ReadStreamResult;
+use {ContentType, EventNumber, ExpectedVersion, ReadDirection, StreamVersion};
+
+/// Failures of the `StreamStore` operations, answered with the corresponding results on the wire.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    /// Optimistic locking failure; stream version was not the expected
+    WrongExpectedVersion,
+    /// Stream was not found
+    NoStream,
+    /// Stream has been deleted for good
+    StreamDeleted,
+    /// Insufficient permissions to the stream
+    AccessDenied,
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.description())
+    }
+}
+
+impl Error for StoreError {
+    fn description(&self) -> &str {
+        use self::StoreError::*;
+        match *self {
+            WrongExpectedVersion => "Stream version was not expected, optimistic locking failure",
+            NoStream => "Stream was not found",
+            StreamDeleted => "Stream had been deleted",
+            AccessDenied => "Access to stream was denied",
+        }
+    }
+}
+
+/// Events read from a stream with `StreamStore::read`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamSlice {
+    /// The events in the order of the read, without resolving links
+    pub events: Vec<EventRecord<'static>>,
+    /// Event number of the last event in the stream
+    pub last_event_number: StreamVersion,
+}
+
+/// Storage of the streams behind `StoreService`. `MemoryStore` keeps the streams in memory; other
+/// implementations can serve fixtures or even a persistent store.
+///
+/// Stream metadata is kept like by EventStore, as `$metadata` events in the metastream `$$stream`,
+/// which the default implementations of `metadata` and `set_metadata` read and append to.
+pub trait StreamStore {
+    /// Appends the events to the stream if its current version matches `expected`, and returns
+    /// the event numbers of the appended events.
+    fn append(&mut self, stream: &str, expected: ExpectedVersion, events: Vec<NewEvent<'static>>) -> Result<Range<StreamVersion>, StoreError>;
+
+    /// Reads at most `max_count` events starting from the event number `from`. Fails with
+    /// `NoStream` for streams without events.
+    fn read(&self, stream: &str, from: EventNumber, max_count: usize, direction: ReadDirection) -> Result<StreamSlice, StoreError>;
+
+    /// Deletes the stream if its current version matches `expected`. Soft deleted streams can be
+    /// written to again, continuing the event numbers, while hard deleted streams cannot.
+    fn delete(&mut self, stream: &str, expected: ExpectedVersion, hard: bool) -> Result<(), StoreError>;
+
+    /// The data of the latest `$metadata` event of the stream, if any.
+    fn metadata(&self, stream: &str) -> Result<Option<Vec<u8>>, StoreError> {
+        match self.read(&metastream(stream), EventNumber::Last, 1, ReadDirection::Backward) {
+            Ok(slice) => Ok(slice.events.into_iter().next().map(|event| event.data.into_owned())),
+            Err(StoreError::NoStream) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Appends a `$metadata` event with the JSON `metadata` to the metastream of the stream, and
+    /// returns the event number of it. `expected` is the version of the metastream.
+    fn set_metadata(&mut self, stream: &str, expected: ExpectedVersion, metadata: Vec<u8>) -> Result<StreamVersion, StoreError> {
+        let event = NewEvent {
+            event_id: Cow::Owned(Uuid::new_v4().as_bytes().to_vec()),
+            event_type: Cow::Borrowed("$metadata"),
+            data_content_type: ContentType::Json.into(),
+            metadata_content_type: ContentType::Bytes.into(),
+            data: Cow::Owned(metadata),
+            metadata: None,
+        };
+
+        self.append(&metastream(stream), expected, vec![event]).map(|written| written.start)
+    }
+}
+
+fn metastream(stream: &str) -> String {
+    format!("$${}", stream)
+}
+
+/// `StreamStore` keeping the streams in memory.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    streams: HashMap<String, MemoryStream>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryStream {
+    events: Vec<EventRecord<'static>>,
+    /// Events before this one have been soft deleted
+    first_visible: usize,
+    hard_deleted: bool,
+}
+
+impl MemoryStream {
+    fn last_event_number(&self) -> Option<StreamVersion> {
+        if self.events.len() > self.first_visible {
+            Some(version(self.events.len() - 1))
+        } else {
+            None
+        }
+    }
+
+    fn check(&self, expected: ExpectedVersion) -> Result<(), StoreError> {
+        if self.hard_deleted {
+            return Err(StoreError::StreamDeleted);
+        }
+
+        match (expected, self.last_event_number()) {
+            (ExpectedVersion::Any, _) |
+            (ExpectedVersion::NoStream, None) => Ok(()),
+            (ExpectedVersion::Exact(expected), Some(last)) if expected == last => Ok(()),
+            _ => Err(StoreError::WrongExpectedVersion),
+        }
+    }
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StreamStore for MemoryStore {
+    fn append(&mut self, stream: &str, expected: ExpectedVersion, events: Vec<NewEvent<'static>>) -> Result<Range<StreamVersion>, StoreError> {
+        let entry = self.streams.entry(stream.to_owned()).or_insert_with(MemoryStream::default);
+        entry.check(expected)?;
+
+        let first = entry.events.len();
+        let created = epoch_millis();
+
+        for (i, event) in events.into_iter().enumerate() {
+            entry.events.push(EventRecord {
+                event_stream_id: Cow::Owned(stream.to_owned()),
+                event_number: (first + i) as i32,
+                event_id: event.event_id,
+                event_type: event.event_type,
+                data_content_type: event.data_content_type,
+                metadata_content_type: event.metadata_content_type,
+                data: event.data,
+                metadata: event.metadata,
+                created: None,
+                created_epoch: Some(created),
+            });
+        }
+
+        Ok(version(first)..version(entry.events.len()))
+    }
+
+    fn read(&self, stream: &str, from: EventNumber, max_count: usize, direction: ReadDirection) -> Result<StreamSlice, StoreError> {
+        let entry = match self.streams.get(stream) {
+            Some(entry) => entry,
+            None => return Err(StoreError::NoStream),
+        };
+
+        if entry.hard_deleted {
+            return Err(StoreError::StreamDeleted);
+        }
+
+        let last = match entry.last_event_number() {
+            Some(last) => last,
+            None => return Err(StoreError::NoStream),
+        };
+
+        let len = entry.events.len();
+
+        let events = match direction {
+            ReadDirection::Forward => {
+                let start = match from {
+                    EventNumber::First => 0,
+                    EventNumber::Exact(n) => u32::from(n) as usize,
+                    EventNumber::Last => len,
+                };
+                let start = cmp::max(start, entry.first_visible);
+                let end = cmp::min(start.saturating_add(max_count), len);
+
+                entry.events.get(start..end).unwrap_or(&[]).to_vec()
+            },
+            ReadDirection::Backward => {
+                let from = match from {
+                    EventNumber::First => 0,
+                    EventNumber::Exact(n) => u32::from(n) as usize,
+                    EventNumber::Last => len - 1,
+                };
+                let end = cmp::min(from + 1, len);
+                let start = cmp::max(end.saturating_sub(max_count), entry.first_visible);
+
+                entry.events.get(start..end).unwrap_or(&[]).iter().rev().cloned().collect()
+            },
+        };
+
+        Ok(StreamSlice {
+            events: events,
+            last_event_number: last,
+        })
+    }
+
+    fn delete(&mut self, stream: &str, expected: ExpectedVersion, hard: bool) -> Result<(), StoreError> {
+        let entry = self.streams.entry(stream.to_owned()).or_insert_with(MemoryStream::default);
+        entry.check(expected)?;
+
+        entry.first_visible = entry.events.len();
+        entry.hard_deleted = hard;
+        Ok(())
+    }
+}
+
+fn version(event_number: usize) -> StreamVersion {
+    StreamVersion::try_from(event_number as u32).expect("Too many events in a stream")
+}
+
+fn epoch_millis() -> i64 {
+    let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    elapsed.as_secs() as i64 * 1000 + i64::from(elapsed.subsec_nanos() / 1_000_000)
+}
+
+/// `tokio_service::Service` answering the requests to write, read and delete streams from a
+/// `StreamStore`. Serving the service with `Server` shares the store between the connections:
+///
+/// ```rust,no_run
+/// extern crate tokio_core;
+/// extern crate eventstore_tcp;
+///
+/// use tokio_core::reactor::Core;
+/// use eventstore_tcp::server::{Server, StoreService, MemoryStore};
+///
+/// fn main() {
+///     let mut core = Core::new().unwrap();
+///     let server = Server::new(StoreService::new(MemoryStore::new()))
+///         .serve(&"127.0.0.1:1113".parse().unwrap(), &core.handle())
+///         .unwrap();
+///
+///     core.run(server).unwrap();
+/// }
+/// ```
+///
+/// Links are not resolved, and other requests fail with `Unimplemented`.
+pub struct StoreService<S> {
+    store: Rc<RefCell<S>>,
+    acls: Option<Rc<Acls>>,
+}
+
+impl<S> Clone for StoreService<S> {
+    fn clone(&self) -> Self {
+        StoreService {
+            store: self.store.clone(),
+            acls: self.acls.clone(),
+        }
+    }
+}
+
+impl<S: StreamStore> StoreService<S> {
+    /// Creates a service serving the given store.
+    pub fn new(store: S) -> Self {
+        StoreService {
+            store: Rc::new(RefCell::new(store)),
+            acls: None,
+        }
+    }
+
+    /// Checks the operations of the requests against the `Acls`, as the user of the credentials of
+    /// the request. Operations which are not allowed are answered with the `AccessDenied` result
+    /// of the response. Check the credentials with `Server::authenticator`; the given username is
+    /// trusted here.
+    ///
+    /// Defaults to allowing everything.
+    pub fn acls(mut self, acls: Acls) -> Self {
+        self.acls = Some(Rc::new(acls));
+        self
+    }
+
+    /// The store, for setting up fixtures and inspecting the written events in tests.
+    pub fn store(&self) -> &Rc<RefCell<S>> {
+        &self.store
+    }
+}
+
+impl<S: StreamStore> Service for StoreService<S> {
+    type Request = Package;
+    type Response = Package;
+    type Error = io::Error;
+    type Future = future::FutureResult<Package, io::Error>;
+
+    fn call(&self, req: Package) -> Self::Future {
+        let Package { authentication, correlation_id, message } = req;
+        let mut store = self.store.borrow_mut();
+
+        let response = match self.acls {
+            Some(ref acls) => respond(&mut Authorized {
+                store: &mut *store,
+                acls: acls,
+                user: authentication.as_ref().map(|credentials| credentials.username()),
+            }, message),
+            None => respond(&mut *store, message),
+        };
+
+        future::result(response
+            .map(|message| Package {
+                authentication: None,
+                correlation_id: correlation_id,
+                message: message,
+            })
+            .map_err(|e| e.into()))
+    }
+}
+
+impl<S: StreamStore> NewService for StoreService<S> {
+    type Request = Package;
+    type Response = Package;
+    type Error = io::Error;
+    type Instance = Self;
+
+    fn new_service(&self) -> io::Result<Self> {
+        Ok(self.clone())
+    }
+}
+
+/// Answers a request from the store, for `PersistentSubscriptions` as well.
+pub fn respond<S: StreamStore + ?Sized>(store: &mut S, message: RawMessage<'static>) -> Result<RawMessage<'static>, Unimplemented> {
+    Ok(match message {
+        RawMessage::WriteEvents(req) => write_events(store, req),
+        RawMessage::ReadEvent(req) => read_event(store, req),
+        RawMessage::ReadStreamEvents(direction, req) => read_stream_events(store, direction, req),
+        RawMessage::DeleteStream(req) => delete_stream(store, req),
+        RawMessage::Ping => RawMessage::Pong,
+        RawMessage::Authenticate => RawMessage::Authenticated,
+        RawMessage::IdentifyClient(_) => RawMessage::ClientIdentified,
+        other => return Err(Unimplemented::new(other.discriminator())),
+    })
+}
+
+fn write_events<S: StreamStore + ?Sized>(store: &mut S, req: WriteEvents<'static>) -> RawMessage<'static> {
+    let expected = match expected_version(req.expected_version) {
+        Some(expected) => expected,
+        None => return bad_request("Unsupported expected version"),
+    };
+
+    let (result, first, last) = match store.append(&req.event_stream_id, expected, req.events) {
+        Ok(written) => (OperationResult::Success, i32::from(written.start), i32::from(written.end) - 1),
+        Err(e) => (operation_result(e), -1, -1),
+    };
+
+    RawMessage::WriteEventsCompleted(WriteEventsCompleted {
+        result: Some(result),
+        message: None,
+        first_event_number: first,
+        last_event_number: last,
+        prepare_position: None,
+        commit_position: None,
+    })
+}
+
+fn read_event<S: StreamStore + ?Sized>(store: &S, req: ReadEvent<'static>) -> RawMessage<'static> {
+    let (from, direction) = match StreamVersion::try_from(req.event_number) {
+        Ok(number) => (EventNumber::Exact(number), ReadDirection::Forward),
+        Err(_) => (EventNumber::Last, ReadDirection::Backward),
+    };
+
+    let (result, event) = match store.read(&req.event_stream_id, from, 1, direction) {
+        Ok(slice) => match slice.events.into_iter().next() {
+            // reads forward start from the first event not soft deleted
+            Some(ref event) if req.event_number >= 0 && event.event_number != req.event_number => (ReadEventResult::NotFound, None),
+            Some(event) => (ReadEventResult::Success, Some(event)),
+            None => (ReadEventResult::NotFound, None),
+        },
+        Err(StoreError::NoStream) => (ReadEventResult::NoStream, None),
+        Err(StoreError::StreamDeleted) => (ReadEventResult::StreamDeleted, None),
+        Err(StoreError::AccessDenied) => (ReadEventResult::AccessDenied, None),
+        Err(StoreError::WrongExpectedVersion) => (ReadEventResult::Error, None),
+    };
+
+    RawMessage::ReadEventCompleted(ReadEventCompleted {
+        result: Some(result),
+        event: ResolvedIndexedEvent {
+            event: event.unwrap_or_default(),
+            link: None,
+        },
+        error: None,
+    })
+}
+
+fn read_stream_events<S: StreamStore + ?Sized>(store: &S, direction: ReadDirection, req: ReadStreamEvents<'static>) -> RawMessage<'static> {
+    let from = match StreamVersion::try_from(req.from_event_number) {
+        Ok(number) => EventNumber::Exact(number),
+        Err(_) => EventNumber::Last,
+    };
+    let max_count = cmp::max(req.max_count, 0) as usize;
+
+    let completed = match store.read(&req.event_stream_id, from, max_count, direction) {
+        Ok(slice) => {
+            let last = i32::from(slice.last_event_number);
+            let (next, end) = match direction {
+                ReadDirection::Forward => {
+                    let next = slice.events.last().map(|event| event.event_number + 1).unwrap_or(last + 1);
+                    (next, next > last)
+                },
+                ReadDirection::Backward => {
+                    let next = slice.events.last().map(|event| event.event_number - 1).unwrap_or(-1);
+                    (next, next < 0 || slice.events.len() < max_count)
+                },
+            };
+
+            ReadStreamEventsCompleted {
+                events: slice.events.into_iter().map(|event| ResolvedIndexedEvent { event: event, link: None }).collect(),
+                result: Some(ReadStreamResult::Success),
+                next_event_number: next,
+                last_event_number: last,
+                is_end_of_stream: end,
+                last_commit_position: -1,
+                error: None,
+            }
+        },
+        Err(e) => ReadStreamEventsCompleted {
+            events: Vec::new(),
+            result: Some(match e {
+                StoreError::NoStream => ReadStreamResult::NoStream,
+                StoreError::StreamDeleted => ReadStreamResult::StreamDeleted,
+                StoreError::AccessDenied => ReadStreamResult::AccessDenied,
+                StoreError::WrongExpectedVersion => ReadStreamResult::Error,
+            }),
+            next_event_number: -1,
+            last_event_number: -1,
+            is_end_of_stream: true,
+            last_commit_position: -1,
+            error: None,
+        },
+    };
+
+    RawMessage::ReadStreamEventsCompleted(direction, completed)
+}
+
+fn delete_stream<S: StreamStore + ?Sized>(store: &mut S, req: DeleteStream<'static>) -> RawMessage<'static> {
+    let expected = match expected_version(req.expected_version) {
+        Some(expected) => expected,
+        None => return bad_request("Unsupported expected version"),
+    };
+
+    let result = match store.delete(&req.event_stream_id, expected, req.hard_delete.unwrap_or(false)) {
+        Ok(()) => OperationResult::Success,
+        Err(e) => operation_result(e),
+    };
+
+    RawMessage::DeleteStreamCompleted(DeleteStreamCompleted {
+        result: Some(result),
+        message: None,
+        prepare_position: None,
+        commit_position: None,
+    })
+}
+
+fn expected_version(version: i32) -> Option<ExpectedVersion> {
+    match version {
+        -2 => Some(ExpectedVersion::Any),
+        -1 => Some(ExpectedVersion::NoStream),
+        version => StreamVersion::try_from(version).ok().map(ExpectedVersion::Exact),
+    }
+}
+
+fn operation_result(e: StoreError) -> OperationResult {
+    match e {
+        StoreError::WrongExpectedVersion | StoreError::NoStream => OperationResult::WrongExpectedVersion,
+        StoreError::StreamDeleted => OperationResult::StreamDeleted,
+        StoreError::AccessDenied => OperationResult::AccessDenied,
+    }
+}
+
+fn bad_request(message: &'static str) -> RawMessage<'static> {
+    RawMessage::BadRequest(Cow::Borrowed(message.as_bytes()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::convert::TryFrom;
+    use futures::Future;
+    use tokio_service::Service;
+    use raw::RawMessage;
+    use raw::client_messages::NewEvent;
+    use {Builder, EventNumber, ExpectedVersion, ReadDirection, StreamVersion};
+    use super::{StreamStore, MemoryStore, StoreError, StoreService};
+
+    fn events(count: usize) -> Vec<NewEvent<'static>> {
+        (0..count).map(|i| NewEvent {
+            event_type: Cow::Owned(format!("event-{}", i)),
+            ..NewEvent::default()
+        }).collect()
+    }
+
+    fn version(n: u32) -> StreamVersion {
+        StreamVersion::try_from(n).unwrap()
+    }
+
+    #[test]
+    fn expected_versions_and_deletes() {
+        let mut store = MemoryStore::new();
+
+        assert_eq!(store.append("foo", ExpectedVersion::NoStream, events(2)), Ok(version(0)..version(2)));
+        assert_eq!(store.append("foo", ExpectedVersion::NoStream, events(1)), Err(StoreError::WrongExpectedVersion));
+        assert_eq!(store.append("foo", version(1).into(), events(1)), Ok(version(2)..version(3)));
+
+        assert_eq!(store.delete("foo", version(1).into(), false), Err(StoreError::WrongExpectedVersion));
+        assert_eq!(store.delete("foo", version(2).into(), false), Ok(()));
+        assert_eq!(store.read("foo", EventNumber::First, 10, ReadDirection::Forward), Err(StoreError::NoStream));
+
+        // soft deleted streams continue from the last event number
+        assert_eq!(store.append("foo", ExpectedVersion::NoStream, events(1)), Ok(version(3)..version(4)));
+
+        assert_eq!(store.delete("foo", ExpectedVersion::Any, true), Ok(()));
+        assert_eq!(store.append("foo", ExpectedVersion::Any, events(1)), Err(StoreError::StreamDeleted));
+        assert_eq!(store.read("foo", EventNumber::First, 10, ReadDirection::Forward), Err(StoreError::StreamDeleted));
+    }
+
+    #[test]
+    fn reads_in_both_directions() {
+        let mut store = MemoryStore::new();
+        store.append("foo", ExpectedVersion::Any, events(5)).unwrap();
+
+        let numbers = |from, count, direction| {
+            let slice = store.read("foo", from, count, direction).unwrap();
+            assert_eq!(slice.last_event_number, version(4));
+            slice.events.iter().map(|event| event.event_number).collect::<Vec<_>>()
+        };
+
+        assert_eq!(numbers(EventNumber::First, 2, ReadDirection::Forward), vec![0, 1]);
+        assert_eq!(numbers(version(3).into(), 10, ReadDirection::Forward), vec![3, 4]);
+        assert_eq!(numbers(version(7).into(), 10, ReadDirection::Forward), Vec::<i32>::new());
+        assert_eq!(numbers(EventNumber::Last, 2, ReadDirection::Backward), vec![4, 3]);
+        assert_eq!(numbers(version(1).into(), 10, ReadDirection::Backward), vec![1, 0]);
+    }
+
+    #[test]
+    fn metadata_is_kept_in_the_metastream() {
+        let mut store = MemoryStore::new();
+
+        assert_eq!(store.metadata("foo"), Ok(None));
+        assert_eq!(store.set_metadata("foo", ExpectedVersion::NoStream, b"{\"$maxCount\":1}".to_vec()), Ok(version(0)));
+        assert_eq!(store.set_metadata("foo", ExpectedVersion::Any, b"{\"$maxCount\":2}".to_vec()), Ok(version(1)));
+        assert_eq!(store.metadata("foo"), Ok(Some(b"{\"$maxCount\":2}".to_vec())));
+
+        let slice = store.read("$$foo", EventNumber::First, 10, ReadDirection::Forward).unwrap();
+        assert_eq!(slice.events[0].event_type, "$metadata");
+    }
+
+    #[test]
+    fn service_writes_reads_and_deletes() {
+        let service = StoreService::new(MemoryStore::new());
+        let call = |package| service.call(package).wait().unwrap().message;
+
+        let write = Builder::write_events()
+            .stream_id("foo")
+            .expected_version(ExpectedVersion::NoStream)
+            .new_event().event_type("first").data(&b"{}"[..]).done()
+            .new_event().event_type("second").data(&b"{}"[..]).done()
+            .build_package(None, None);
+
+        match call(write) {
+            RawMessage::WriteEventsCompleted(ref completed) => {
+                assert_eq!((completed.first_event_number, completed.last_event_number), (0, 1));
+            },
+            other => panic!("unexpected response: {:?}", other),
+        }
+
+        let read = Builder::read_stream_events()
+            .stream_id("foo")
+            .direction(ReadDirection::Backward)
+            .from_event_number(EventNumber::Last)
+            .max_count(10)
+            .build_package(None, None);
+
+        match call(read) {
+            RawMessage::ReadStreamEventsCompleted(ReadDirection::Backward, ref completed) => {
+                let types = completed.events.iter().map(|e| &*e.event.event_type).collect::<Vec<_>>();
+                assert_eq!(types, vec!["second", "first"]);
+                assert_eq!((completed.next_event_number, completed.last_event_number, completed.is_end_of_stream), (-1, 1, true));
+            },
+            other => panic!("unexpected response: {:?}", other),
+        }
+
+        let delete = Builder::delete_stream()
+            .stream_id("foo")
+            .expected_version(ExpectedVersion::Any)
+            .hard_delete(true)
+            .build_package(None, None);
+        call(delete);
+
+        let read = Builder::read_event().stream_id("foo").event_number(EventNumber::First).build_package(None, None);
+        match call(read) {
+            RawMessage::ReadEventCompleted(ref completed) => {
+                assert_eq!(completed.result, Some(::raw::client_messages::mod_ReadEventCompleted::ReadEventResult::StreamDeleted));
+            },
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn service_checks_acls() {
+        use auth::UsernamePassword;
+        use raw::client_messages::OperationResult;
+        use server::{Acl, Acls, Operation};
+
+        let acls = Acls::new()
+            .stream("orders", Acl::new().allow(Operation::Read, "$all").allow(Operation::Write, "ann"));
+        let service = StoreService::new(MemoryStore::new()).acls(acls);
+
+        let write = |user: Option<&'static str>| {
+            let credentials = user.map(|user| UsernamePassword::new(user, "changeit"));
+            let write = Builder::write_events()
+                .stream_id("orders")
+                .expected_version(ExpectedVersion::Any)
+                .new_event().event_type("placed").data(&b"{}"[..]).done()
+                .build_package(credentials, None);
+
+            match service.call(write).wait().unwrap().message {
+                RawMessage::WriteEventsCompleted(completed) => completed.result,
+                other => panic!("unexpected response: {:?}", other),
+            }
+        };
+
+        assert_eq!(write(None), Some(OperationResult::AccessDenied));
+        assert_eq!(write(Some("bob")), Some(OperationResult::AccessDenied));
+        assert_eq!(write(Some("ann")), Some(OperationResult::Success));
+
+        let read = Builder::read_event().stream_id("$$orders").event_number(EventNumber::Last).build_package(None, None);
+        match service.call(read).wait().unwrap().message {
+            RawMessage::ReadEventCompleted(ref completed) => {
+                assert_eq!(completed.result, Some(::raw::client_messages::mod_ReadEventCompleted::ReadEventResult::AccessDenied));
+            },
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,713 @@
+//! Connection level settings used by `EventStoreClient`.
+
+use std::fmt;
+use std::sync::Arc;
+use std::borrow::Cow;
+use std::time::Duration;
+
+use backoff::{Backoff, NewBackoff, DecorrelatedJitter};
+use cluster::NodePreference;
+use tls::TlsSettings;
+use auth::CredentialsProvider;
+use budget::MemoryBudget;
+use transport::{Transport, TcpTransport};
+#[cfg(feature = "cpupool")]
+use futures_cpupool::CpuPool;
+
+/// Settings for a single connection made by `EventStoreClient`. Use
+/// `ConnectionSettings::builder()` to create values other than the default.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::ConnectionSettings;
+///
+/// let settings = ConnectionSettings::builder()
+///     .connection_name("billing-projections")
+///     .build();
+///
+/// assert_eq!(settings.connection_name(), Some("billing-projections"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionSettings {
+    connection_name: Option<Cow<'static, str>>,
+    heartbeat_interval: Duration,
+    heartbeat_timeout: Duration,
+    max_reconnect_attempts: usize,
+    max_discovery_attempts: usize,
+    max_operation_retries: usize,
+    backoff: Option<CustomBackoff>,
+    node_preference: NodePreference,
+    topology_refresh: Option<Duration>,
+    tls: Option<TlsSettings>,
+    credentials: Option<CustomCredentials>,
+    trusted_writes: bool,
+    tcp_nodelay: bool,
+    send_buffer_size: Option<usize>,
+    recv_buffer_size: Option<usize>,
+    tcp_keepalive: Option<Duration>,
+    read_buffer_size: usize,
+    max_read_buffer_retained: usize,
+    #[cfg(feature = "cpupool")]
+    decode_pool: Option<(Custom<CpuPool>, usize)>,
+    stream_memory_budget: Option<Custom<MemoryBudget>>,
+    transport: Option<CustomTransport>,
+    diagnostics_interval: Option<Duration>,
+    #[cfg(feature = "tower")]
+    max_in_flight: usize,
+}
+
+/// User given trait implementation; compared by identity so that the settings remain comparable.
+struct Custom<T: ?Sized>(Arc<T>);
+
+impl<T: ?Sized> Clone for Custom<T> {
+    fn clone(&self) -> Self {
+        Custom(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> PartialEq for Custom<T> {
+    fn eq(&self, other: &Custom<T>) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T: ?Sized> Eq for Custom<T> {}
+
+impl<T: ?Sized> fmt::Debug for Custom<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Custom")
+    }
+}
+
+type CustomBackoff = Custom<NewBackoff + Send + Sync>;
+type CustomCredentials = Custom<CredentialsProvider + Send + Sync>;
+type CustomTransport = Custom<Transport + Send + Sync>;
+
+static TCP: TcpTransport = TcpTransport;
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        ConnectionSettings {
+            connection_name: None,
+            heartbeat_interval: Duration::from_millis(750),
+            heartbeat_timeout: Duration::from_millis(1500),
+            max_reconnect_attempts: 10,
+            max_discovery_attempts: 10,
+            max_operation_retries: 10,
+            backoff: None,
+            node_preference: NodePreference::Leader,
+            topology_refresh: None,
+            tls: None,
+            credentials: None,
+            trusted_writes: false,
+            tcp_nodelay: true,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            tcp_keepalive: None,
+            read_buffer_size: 8 * 1024,
+            max_read_buffer_retained: 1024 * 1024,
+            #[cfg(feature = "cpupool")]
+            decode_pool: None,
+            stream_memory_budget: None,
+            transport: None,
+            diagnostics_interval: None,
+            #[cfg(feature = "tower")]
+            max_in_flight: 1000,
+        }
+    }
+}
+
+impl ConnectionSettings {
+    /// Creates a builder for settings starting from the defaults.
+    pub fn builder() -> ConnectionSettingsBuilder {
+        ConnectionSettingsBuilder::new()
+    }
+
+    /// The human readable name of the connection, if one was given.
+    pub fn connection_name(&self) -> Option<&str> {
+        self.connection_name.as_ref().map(|x| x.as_ref())
+    }
+
+    /// How long the connection can stay silent before a heartbeat request is sent to the server.
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.heartbeat_interval
+    }
+
+    /// How long to wait for any package from the server after sending a heartbeat request before
+    /// considering the connection dead.
+    pub fn heartbeat_timeout(&self) -> Duration {
+        self.heartbeat_timeout
+    }
+
+    /// How many failed connection attempts are made in a row before the connection is closed
+    /// for good.
+    pub fn max_reconnect_attempts(&self) -> usize {
+        self.max_reconnect_attempts
+    }
+
+    /// How many times `ClusterClient` tries to discover the members of the cluster before giving
+    /// up.
+    pub fn max_discovery_attempts(&self) -> usize {
+        self.max_discovery_attempts
+    }
+
+    /// How many times a read or a ping answered with `NotHandled` by a node not ready or too busy
+    /// is sent again before the response is passed on.
+    pub fn max_operation_retries(&self) -> usize {
+        self.max_operation_retries
+    }
+
+    /// Creates the schedule of delays between retried attempts, see
+    /// `ConnectionSettingsBuilder::backoff`.
+    pub fn backoff(&self) -> Box<Backoff> {
+        match self.backoff {
+            Some(ref custom) => custom.0.new_backoff(),
+            None => DecorrelatedJitter::default().new_backoff(),
+        }
+    }
+
+    /// The kind of cluster member `ClusterClient` sends reads to.
+    pub fn node_preference(&self) -> NodePreference {
+        self.node_preference
+    }
+
+    /// How often `ClusterClient` discovers the cluster members again, if at all.
+    pub fn topology_refresh(&self) -> Option<Duration> {
+        self.topology_refresh
+    }
+
+    /// Settings for encrypting the connection, if it is encrypted.
+    pub fn tls(&self) -> Option<&TlsSettings> {
+        self.tls.as_ref()
+    }
+
+    /// The provider of credentials for the operations sent without any.
+    pub fn credentials_provider(&self) -> Option<&(CredentialsProvider + Send + Sync)> {
+        self.credentials.as_ref().map(|custom| &*custom.0)
+    }
+
+    /// Are writes sent with the TrustedWrite flag, bypassing the access checks of the server. Can
+    /// only be enabled with `ConnectionSettingsBuilder::dangerous_trusted_writes`.
+    pub fn trusted_writes(&self) -> bool {
+        self.trusted_writes
+    }
+
+    /// Is Nagle's algorithm disabled on the socket, sending small packages immediately.
+    pub fn tcp_nodelay(&self) -> bool {
+        self.tcp_nodelay
+    }
+
+    /// The size of the send buffer of the socket, if not left to the operating system.
+    pub fn send_buffer_size(&self) -> Option<usize> {
+        self.send_buffer_size
+    }
+
+    /// The size of the receive buffer of the socket, if not left to the operating system.
+    pub fn recv_buffer_size(&self) -> Option<usize> {
+        self.recv_buffer_size
+    }
+
+    /// The idle time after which TCP keepalive probes are sent, if enabled.
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        self.tcp_keepalive
+    }
+
+    /// The initial capacity of the buffer frames are read into, and the capacity it is shrunk back
+    /// to after large frames.
+    pub fn read_buffer_size(&self) -> usize {
+        self.read_buffer_size
+    }
+
+    /// The largest capacity of the read buffer kept after the frames in it have been decoded.
+    pub fn max_read_buffer_retained(&self) -> usize {
+        self.max_read_buffer_retained
+    }
+
+    /// The thread pool parsing the frames larger than the threshold, if any.
+    #[cfg(feature = "cpupool")]
+    pub fn decode_pool(&self) -> Option<(&CpuPool, usize)> {
+        self.decode_pool.as_ref().map(|&(ref pool, threshold)| (&*pool.0, threshold))
+    }
+
+    /// The limits on the packages buffered for streaming requests, if any.
+    pub fn stream_memory_budget(&self) -> Option<&Arc<MemoryBudget>> {
+        self.stream_memory_budget.as_ref().map(|custom| &custom.0)
+    }
+
+    /// The transport opening the streams of the connections.
+    pub fn transport(&self) -> &(Transport + Send + Sync) {
+        match self.transport {
+            Some(ref custom) => &*custom.0,
+            None => &TCP,
+        }
+    }
+
+    /// How often a `Diagnostics` line is logged for each connection, if at all.
+    pub fn diagnostics_interval(&self) -> Option<Duration> {
+        self.diagnostics_interval
+    }
+
+    /// The most requests sent through `tower_service::Service` on the connection waiting for their
+    /// responses at a time.
+    #[cfg(feature = "tower")]
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    /// The label used for this connection in the log output of this crate: either the connection
+    /// name or `"unnamed"`.
+    pub fn log_label(&self) -> &str {
+        self.connection_name().unwrap_or("unnamed")
+    }
+}
+
+/// Builder for `ConnectionSettings`.
+pub struct ConnectionSettingsBuilder {
+    connection_name: Option<Cow<'static, str>>,
+    heartbeat_interval: Option<Duration>,
+    heartbeat_timeout: Option<Duration>,
+    max_reconnect_attempts: Option<usize>,
+    max_discovery_attempts: Option<usize>,
+    max_operation_retries: Option<usize>,
+    backoff: Option<CustomBackoff>,
+    node_preference: Option<NodePreference>,
+    topology_refresh: Option<Duration>,
+    tls: Option<TlsSettings>,
+    credentials: Option<CustomCredentials>,
+    trusted_writes: bool,
+    tcp_nodelay: Option<bool>,
+    send_buffer_size: Option<usize>,
+    recv_buffer_size: Option<usize>,
+    tcp_keepalive: Option<Duration>,
+    read_buffer_size: Option<usize>,
+    max_read_buffer_retained: Option<usize>,
+    #[cfg(feature = "cpupool")]
+    decode_pool: Option<(Custom<CpuPool>, usize)>,
+    stream_memory_budget: Option<Custom<MemoryBudget>>,
+    transport: Option<CustomTransport>,
+    diagnostics_interval: Option<Duration>,
+    #[cfg(feature = "tower")]
+    max_in_flight: Option<usize>,
+}
+
+impl ConnectionSettingsBuilder {
+    fn new() -> Self {
+        ConnectionSettingsBuilder {
+            connection_name: None,
+            heartbeat_interval: None,
+            heartbeat_timeout: None,
+            max_reconnect_attempts: None,
+            max_discovery_attempts: None,
+            max_operation_retries: None,
+            backoff: None,
+            node_preference: None,
+            topology_refresh: None,
+            tls: None,
+            credentials: None,
+            trusted_writes: false,
+            tcp_nodelay: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            tcp_keepalive: None,
+            read_buffer_size: None,
+            max_read_buffer_retained: None,
+            #[cfg(feature = "cpupool")]
+            decode_pool: None,
+            stream_memory_budget: None,
+            transport: None,
+            diagnostics_interval: None,
+            #[cfg(feature = "tower")]
+            max_in_flight: None,
+        }
+    }
+
+    /// Sets a human readable name for the connection, so that the many connections opened by an
+    /// application can be told apart. The name is sent to the server with `IdentifyClient` right
+    /// after connecting, where it shows up in the admin UI, and is used to label the log output of
+    /// this crate.
+    ///
+    /// Identifying the connection requires server version 4.0 or later. When no name is set the
+    /// connection is not identified at all. Panics if the name is an empty string.
+    pub fn connection_name<S: Into<Cow<'static, str>>>(&mut self, name: S) -> &mut Self {
+        let name = name.into();
+        assert!(name.len() > 0);
+        self.connection_name = Some(name);
+        self
+    }
+
+    /// Sets the time the connection can stay silent before a heartbeat request is sent. This
+    /// should be a bit more than the round trip time to the server, which makes the default too
+    /// low for high latency links.
+    ///
+    /// Defaults to 750ms. Panics if the interval is zero.
+    pub fn heartbeat_interval(&mut self, interval: Duration) -> &mut Self {
+        assert!(interval > Duration::from_millis(0));
+        self.heartbeat_interval = Some(interval);
+        self
+    }
+
+    /// Sets the time to wait for any package after sending a heartbeat request, after which the
+    /// connection is closed.
+    ///
+    /// Defaults to 1500ms. Panics if the timeout is zero.
+    pub fn heartbeat_timeout(&mut self, timeout: Duration) -> &mut Self {
+        assert!(timeout > Duration::from_millis(0));
+        self.heartbeat_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how many failed connection attempts are made in a row before giving up. The counter is
+    /// reset whenever a connection is made. After giving up all pending and future operations fail
+    /// with `ConnectionClosed`. Zero disables reconnecting.
+    ///
+    /// Defaults to 10.
+    pub fn max_reconnect_attempts(&mut self, attempts: usize) -> &mut Self {
+        self.max_reconnect_attempts = Some(attempts);
+        self
+    }
+
+    /// Sets how many times `ClusterClient::connect` discovers the members of the cluster before
+    /// failing with `ConnectionClosed`. An attempt fails when the discovery fails or finds no
+    /// leader, for example during an election. Panics if `attempts` is zero.
+    ///
+    /// Defaults to 10.
+    pub fn max_discovery_attempts(&mut self, attempts: usize) -> &mut Self {
+        assert!(attempts > 0);
+        self.max_discovery_attempts = Some(attempts);
+        self
+    }
+
+    /// Sets how many times a read or a ping answered with `NotHandled` because the node is not
+    /// ready or too busy is sent again, waiting for the delays of `backoff` in between. Once the
+    /// retries run out the request is completed with the `NotHandled`. Zero disables retrying.
+    ///
+    /// Defaults to 10.
+    pub fn max_operation_retries(&mut self, retries: usize) -> &mut Self {
+        self.max_operation_retries = Some(retries);
+        self
+    }
+
+    /// Sets the schedule of delays between retried attempts: connecting again after a failed
+    /// connection attempt or a lost connection, discovering the cluster again in
+    /// `ClusterClient::connect`, and sending again the operations retried because of
+    /// `max_operation_retries`. A new `Backoff` is created for each of these, and reset whenever
+    /// an attempt succeeds.
+    ///
+    /// Defaults to `DecorrelatedJitter::default()`, which waits between 100ms and 10s.
+    pub fn backoff<B: NewBackoff + Send + Sync + 'static>(&mut self, backoff: B) -> &mut Self {
+        self.backoff = Some(Custom(Arc::new(backoff)));
+        self
+    }
+
+    /// Sets the kind of cluster member `ClusterClient` sends reads to, for example
+    /// `NodePreference::ReadOnlyReplica` to keep reads off the leader. Has no effect on
+    /// `EventStoreClient` which always uses a single connection.
+    ///
+    /// Defaults to `NodePreference::Leader`.
+    pub fn node_preference(&mut self, preference: NodePreference) -> &mut Self {
+        self.node_preference = Some(preference);
+        self
+    }
+
+    /// Makes `ClusterClient` discover the cluster members every `interval` and migrate its
+    /// connections when the leader or the preferred member for reads changes. Migrating waits for
+    /// the operations in flight to complete before connecting to the new member.
+    ///
+    /// Disabled by default. Panics if the interval is zero.
+    pub fn topology_refresh(&mut self, interval: Duration) -> &mut Self {
+        assert!(interval > Duration::from_millis(0));
+        self.topology_refresh = Some(interval);
+        self
+    }
+
+    /// Encrypts the connection with TLS, which requires the `tls` feature of this crate.
+    ///
+    /// Defaults to an unencrypted connection.
+    pub fn tls(&mut self, settings: TlsSettings) -> &mut Self {
+        self.tls = Some(settings);
+        self
+    }
+
+    /// Sets the provider asked for credentials whenever an operation without credentials is sent.
+    /// A `UsernamePassword` can be used as static default credentials, while other providers can
+    /// fetch rotated secrets from elsewhere without restarting the client.
+    ///
+    /// Defaults to sending the operations without credentials.
+    pub fn credentials_provider<P: CredentialsProvider + Send + Sync + 'static>(&mut self, provider: P) -> &mut Self {
+        self.credentials = Some(Custom(Arc::new(provider)));
+        self
+    }
+
+    /// Sends writes and stream deletions with the TrustedWrite flag, making the server skip the
+    /// access checks for them. The flag is meant for the components of the cluster itself, and
+    /// any client using it can write to every stream regardless of the credentials, so this is
+    /// available only with the `trusted-writes` feature of this crate.
+    ///
+    /// Defaults to regular writes.
+    #[cfg(feature = "trusted-writes")]
+    pub fn dangerous_trusted_writes(&mut self) -> &mut Self {
+        self.trusted_writes = true;
+        self
+    }
+
+    /// Enables or disables Nagle's algorithm on the socket. With the algorithm enabled, small
+    /// packages such as heartbeat responses and single event writes can be held back for up to
+    /// the delayed acknowledgement timeout of the server, which adds tens of milliseconds to the
+    /// latency.
+    ///
+    /// Defaults to `true`, disabling the algorithm.
+    pub fn tcp_nodelay(&mut self, nodelay: bool) -> &mut Self {
+        self.tcp_nodelay = Some(nodelay);
+        self
+    }
+
+    /// Sets the size of the send buffer of the socket (`SO_SNDBUF`). Panics if the size is zero.
+    ///
+    /// Defaults to the size chosen by the operating system.
+    pub fn send_buffer_size(&mut self, size: usize) -> &mut Self {
+        assert!(size > 0);
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets the size of the receive buffer of the socket (`SO_RCVBUF`). Larger buffers help
+    /// reading large pages of events over high latency links. Panics if the size is zero.
+    ///
+    /// Defaults to the size chosen by the operating system.
+    pub fn recv_buffer_size(&mut self, size: usize) -> &mut Self {
+        assert!(size > 0);
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Enables TCP keepalive probes after the connection has been idle for `idle`, which keeps
+    /// idle connections open through firewalls and NAT gateways dropping silent connections. The
+    /// heartbeats detect dead connections regardless.
+    ///
+    /// Defaults to no keepalive probes. Panics if the time is zero.
+    pub fn tcp_keepalive(&mut self, idle: Duration) -> &mut Self {
+        assert!(idle > Duration::from_millis(0));
+        self.tcp_keepalive = Some(idle);
+        self
+    }
+
+    /// Sets the initial capacity of the buffer frames are read into. The buffer grows as needed to
+    /// hold whole frames, reserving the full length of a frame at once as soon as its length is
+    /// known. Panics if the size is zero.
+    ///
+    /// Defaults to 8 KiB.
+    pub fn read_buffer_size(&mut self, size: usize) -> &mut Self {
+        assert!(size > 0);
+        self.read_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets the largest capacity of the read buffer kept once the frames in it have been decoded.
+    /// A buffer which has grown larger, for example for a large page of events, is shrunk back to
+    /// `read_buffer_size` so that long-lived connections do not hold on to the memory.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn max_read_buffer_retained(&mut self, size: usize) -> &mut Self {
+        self.max_read_buffer_retained = Some(size);
+        self
+    }
+
+    /// Parses the frames larger than `threshold` bytes on `pool` instead of the reactor, so that
+    /// parsing a multi-megabyte page of events does not hold up the heartbeats and the other
+    /// operations sharing the reactor. Responses parsed on the pool can complete out of order with
+    /// the other responses. Requires the `cpupool` feature of this crate.
+    ///
+    /// Defaults to parsing every frame on the reactor.
+    #[cfg(feature = "cpupool")]
+    pub fn decode_pool(&mut self, pool: CpuPool, threshold: usize) -> &mut Self {
+        self.decode_pool = Some((Custom(Arc::new(pool)), threshold));
+        self
+    }
+
+    /// Limits the memory held by the packages received for streaming requests but not yet taken
+    /// by the consumers. The budget is shared by the connections made with the settings.
+    ///
+    /// Defaults to no limits.
+    pub fn stream_memory_budget(&mut self, budget: MemoryBudget) -> &mut Self {
+        self.stream_memory_budget = Some(Custom(Arc::new(budget)));
+        self
+    }
+
+    /// Makes the connections over the streams opened by `transport` instead of plain TCP, for
+    /// example to run over another runtime or a tunnel. The socket and TLS settings are given to
+    /// the transport, which may ignore them.
+    ///
+    /// Defaults to `TcpTransport`.
+    pub fn transport<T: Transport + Send + Sync + 'static>(&mut self, transport: T) -> &mut Self {
+        self.transport = Some(Custom(Arc::new(transport)));
+        self
+    }
+
+    /// Logs the `Diagnostics` of each connection at the info level every `interval`, for keeping
+    /// an eye on the requests stuck waiting for their responses.
+    ///
+    /// Defaults to not logging them. Panics if the interval is zero.
+    pub fn diagnostics_interval(&mut self, interval: Duration) -> &mut Self {
+        assert!(interval > Duration::from_secs(0));
+        self.diagnostics_interval = Some(interval);
+        self
+    }
+
+    /// Sets the most requests sent through `tower_service::Service` by the handles of a connection
+    /// waiting for their responses at a time. `poll_ready` is not ready while that many are in
+    /// flight, holding back the callers instead of queueing the requests without bounds. Requires
+    /// the `tower` feature of this crate.
+    ///
+    /// Defaults to 1000. Panics if the limit is zero.
+    #[cfg(feature = "tower")]
+    pub fn max_in_flight(&mut self, max: usize) -> &mut Self {
+        assert!(max > 0);
+        self.max_in_flight = Some(max);
+        self
+    }
+
+    /// Builds the settings. Values of this builder will be moved into the settings.
+    pub fn build(&mut self) -> ConnectionSettings {
+        let defaults = ConnectionSettings::default();
+        ConnectionSettings {
+            connection_name: self.connection_name.take(),
+            heartbeat_interval: self.heartbeat_interval.take().unwrap_or(defaults.heartbeat_interval),
+            heartbeat_timeout: self.heartbeat_timeout.take().unwrap_or(defaults.heartbeat_timeout),
+            max_reconnect_attempts: self.max_reconnect_attempts.take().unwrap_or(defaults.max_reconnect_attempts),
+            max_discovery_attempts: self.max_discovery_attempts.take().unwrap_or(defaults.max_discovery_attempts),
+            max_operation_retries: self.max_operation_retries.take().unwrap_or(defaults.max_operation_retries),
+            backoff: self.backoff.take().or(defaults.backoff),
+            node_preference: self.node_preference.take().unwrap_or(defaults.node_preference),
+            topology_refresh: self.topology_refresh.take(),
+            tls: self.tls.take(),
+            credentials: self.credentials.take(),
+            trusted_writes: ::std::mem::replace(&mut self.trusted_writes, false),
+            tcp_nodelay: self.tcp_nodelay.take().unwrap_or(defaults.tcp_nodelay),
+            send_buffer_size: self.send_buffer_size.take(),
+            recv_buffer_size: self.recv_buffer_size.take(),
+            tcp_keepalive: self.tcp_keepalive.take(),
+            read_buffer_size: self.read_buffer_size.take().unwrap_or(defaults.read_buffer_size),
+            max_read_buffer_retained: self.max_read_buffer_retained.take().unwrap_or(defaults.max_read_buffer_retained),
+            #[cfg(feature = "cpupool")]
+            decode_pool: self.decode_pool.take(),
+            stream_memory_budget: self.stream_memory_budget.take(),
+            transport: self.transport.take(),
+            diagnostics_interval: self.diagnostics_interval.take(),
+            #[cfg(feature = "tower")]
+            max_in_flight: self.max_in_flight.take().unwrap_or(defaults.max_in_flight),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConnectionSettings;
+
+    #[test]
+    fn default_has_no_name() {
+        let settings = ConnectionSettings::default();
+        assert_eq!(settings.connection_name(), None);
+        assert_eq!(settings.log_label(), "unnamed");
+    }
+
+    #[test]
+    fn builder_moves_name() {
+        let mut builder = ConnectionSettings::builder();
+        let settings = builder.connection_name("foobar").build();
+        assert_eq!(settings.connection_name(), Some("foobar"));
+        assert_eq!(settings.log_label(), "foobar");
+
+        assert_eq!(builder.build(), ConnectionSettings::default());
+    }
+
+    #[test]
+    fn heartbeat_defaults() {
+        use std::time::Duration;
+
+        let settings = ConnectionSettings::default();
+        assert_eq!(settings.heartbeat_interval(), Duration::from_millis(750));
+        assert_eq!(settings.heartbeat_timeout(), Duration::from_millis(1500));
+
+        let settings = ConnectionSettings::builder()
+            .heartbeat_interval(Duration::from_secs(5))
+            .build();
+        assert_eq!(settings.heartbeat_interval(), Duration::from_secs(5));
+        assert_eq!(settings.heartbeat_timeout(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn reconnection_defaults() {
+        let settings = ConnectionSettings::default();
+        assert_eq!(settings.max_reconnect_attempts(), 10);
+        assert_eq!(settings.max_discovery_attempts(), 10);
+        assert_eq!(settings.max_operation_retries(), 10);
+
+        let settings = ConnectionSettings::builder()
+            .max_reconnect_attempts(0)
+            .max_discovery_attempts(1)
+            .build();
+        assert_eq!(settings.max_reconnect_attempts(), 0);
+        assert_eq!(settings.max_discovery_attempts(), 1);
+    }
+
+    #[test]
+    fn custom_backoff() {
+        use std::time::Duration;
+        use backoff::{Backoff, DecorrelatedJitter};
+
+        let fixed = DecorrelatedJitter::new(Duration::from_millis(5), Duration::from_millis(5));
+        let settings = ConnectionSettings::builder()
+            .backoff(fixed)
+            .build();
+
+        assert_eq!(settings.backoff().next_delay(), Duration::from_millis(5));
+        assert_eq!(settings.clone(), settings);
+        assert!(settings != ConnectionSettings::default());
+    }
+
+    #[test]
+    fn custom_transport() {
+        use transport::TcpTransport;
+
+        let settings = ConnectionSettings::builder()
+            .transport(TcpTransport)
+            .build();
+
+        assert_eq!(settings.clone(), settings);
+        assert!(settings != ConnectionSettings::default());
+    }
+
+    #[test]
+    fn trusted_writes_are_opt_in() {
+        assert!(!ConnectionSettings::default().trusted_writes());
+        assert!(!ConnectionSettings::builder().build().trusted_writes());
+    }
+
+    #[test]
+    fn socket_defaults() {
+        use std::time::Duration;
+
+        let settings = ConnectionSettings::default();
+        assert!(settings.tcp_nodelay());
+        assert_eq!(settings.send_buffer_size(), None);
+        assert_eq!(settings.tcp_keepalive(), None);
+
+        let settings = ConnectionSettings::builder()
+            .tcp_nodelay(false)
+            .recv_buffer_size(1 << 20)
+            .tcp_keepalive(Duration::from_secs(30))
+            .build();
+        assert!(!settings.tcp_nodelay());
+        assert_eq!(settings.recv_buffer_size(), Some(1 << 20));
+        assert_eq!(settings.tcp_keepalive(), Some(Duration::from_secs(30)));
+    }
+
+    #[cfg(feature = "trusted-writes")]
+    #[test]
+    fn dangerous_trusted_writes() {
+        let mut builder = ConnectionSettings::builder();
+        assert!(builder.dangerous_trusted_writes().build().trusted_writes());
+        assert!(!builder.build().trusted_writes());
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt;
+use std::borrow::Cow;
+
+/// Name of a stream, with helpers for the `category-id` naming convention. The `$by_category`
+/// system projection takes the part of the name before the first dash as the category of the
+/// stream and links its events to the `$ce-category` stream.
+///
+/// Converts into the stream ids taken by the builders and `Repository::load`.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::StreamId;
+///
+/// let stream = StreamId::from_category("order", 1234);
+///
+/// assert_eq!(stream.as_str(), "order-1234");
+/// assert_eq!(stream.category(), Some("order"));
+/// assert_eq!(stream.entity_id(), Some("1234"));
+/// assert_eq!(StreamId::category_stream("order").as_str(), "$ce-order");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StreamId(Cow<'static, str>);
+
+impl StreamId {
+    /// Wraps the stream name as is.
+    pub fn new<S: Into<Cow<'static, str>>>(name: S) -> Self {
+        StreamId(name.into())
+    }
+
+    /// The stream of the entity `id` in the category, `category-id`. The id can contain dashes,
+    /// such as the hyphenated form of an `Uuid`.
+    ///
+    /// Panics if the category is empty or contains a dash, as it would not be the category of the
+    /// stream.
+    pub fn from_category<C: AsRef<str>, I: fmt::Display>(category: C, id: I) -> Self {
+        let category = category.as_ref();
+        assert!(!category.is_empty() && !category.contains('-'), "Invalid category: {:?}", category);
+
+        StreamId(Cow::Owned(format!("{}-{}", category, id)))
+    }
+
+    /// The `$ce-category` stream of the events of every stream in the category, written by the
+    /// `$by_category` projection.
+    pub fn category_stream<C: AsRef<str>>(category: C) -> Self {
+        StreamId(Cow::Owned(format!("$ce-{}", category.as_ref())))
+    }
+
+    /// The name of the stream.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The part before the first dash, if the name has a dash and the part is not empty.
+    pub fn category(&self) -> Option<&str> {
+        self.split().map(|(category, _)| category)
+    }
+
+    /// The part after the first dash, if the name has a category.
+    pub fn entity_id(&self) -> Option<&str> {
+        self.split().map(|(_, id)| id)
+    }
+
+    /// Is this a system stream such as `$ce-order`, which are named starting with `$`.
+    pub fn is_system(&self) -> bool {
+        self.0.starts_with('$')
+    }
+
+    fn split(&self) -> Option<(&str, &str)> {
+        match self.0.find('-') {
+            Some(0) | None => None,
+            Some(i) => Some((&self.0[..i], &self.0[i + 1..])),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for StreamId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&'static str> for StreamId {
+    fn from(name: &'static str) -> Self {
+        StreamId(Cow::Borrowed(name))
+    }
+}
+
+impl From<String> for StreamId {
+    fn from(name: String) -> Self {
+        StreamId(Cow::Owned(name))
+    }
+}
+
+impl From<StreamId> for Cow<'static, str> {
+    fn from(stream_id: StreamId) -> Self {
+        stream_id.0
+    }
+}
+
+impl From<StreamId> for String {
+    fn from(stream_id: StreamId) -> Self {
+        stream_id.0.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+    use super::StreamId;
+
+    #[test]
+    fn names_are_split_on_the_first_dash() {
+        let id = Uuid::new_v4();
+        let stream = StreamId::from_category("order", id);
+
+        assert_eq!(stream.category(), Some("order"));
+        assert_eq!(stream.entity_id(), Some(&id.to_string()[..]));
+
+        assert_eq!(StreamId::new("order-").entity_id(), Some(""));
+        assert_eq!(StreamId::from("orders").category(), None);
+        assert_eq!(StreamId::from("-1").category(), None);
+
+        let system = StreamId::category_stream("order");
+        assert!(system.is_system());
+        assert_eq!(system.category(), Some("$ce"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn categories_cannot_contain_dashes() {
+        StreamId::from_category("order-line", 1);
+    }
+}
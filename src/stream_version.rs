@@ -5,7 +5,7 @@ use {Error, ErrorKind};
 /// event number of the latest event. As such, values are non-negative integers up to
 /// `i32::max_value`. Negative values of `i32` have special meaning in the protocol, and are
 /// restricted from being used with this type.
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct StreamVersion(u32);
 
 impl From<StreamVersion> for u32 {
@@ -43,3 +43,21 @@ impl TryFrom<i32> for StreamVersion {
         }
     }
 }
+
+/// Serialized as the number, and validated like `TryFrom<u32>` when deserialized.
+#[cfg(feature = "serialize")]
+impl ::serde::Serialize for StreamVersion {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.0)
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl<'de> ::serde::Deserialize<'de> for StreamVersion {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as DeError;
+
+        let ver = u32::deserialize(deserializer)?;
+        StreamVersion::try_from(ver).map_err(|e| D::Error::custom(e.to_string()))
+    }
+}
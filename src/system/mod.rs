@@ -0,0 +1,89 @@
+//! Reading the system streams of EventStore over TCP, such as the checkpoints of the persistent
+//! subscriptions, the statistics of the node, the progress of scavenges and the users, for
+//! monitoring a node from Rust tooling without the HTTP API. Available with the `system-streams`
+//! feature.
+//!
+//! The helpers work over every `Service` of `Package`s like the feeds. The system streams start
+//! with `$` and can be read only by the members of `$admins`, so the client should have the
+//! credentials of an administrator for them, for example through a `CredentialsMap`:
+//!
+//! ```rust,no_run
+//! extern crate futures;
+//! extern crate tokio_core;
+//! extern crate eventstore_tcp;
+//!
+//! use tokio_core::reactor::Core;
+//! use eventstore_tcp::{EventStoreClient, ConnectionSettings, CredentialsMap, UsernamePassword};
+//! use eventstore_tcp::system::persistent_subscription_info;
+//!
+//! fn main() {
+//!     let mut core = Core::new().unwrap();
+//!     let handle = core.handle();
+//!
+//!     let credentials = CredentialsMap::new(UsernamePassword::new("app", "secret"))
+//!         .prefix("$", UsernamePassword::new("admin", "changeit"));
+//!     let settings = ConnectionSettings::builder().credentials_provider(credentials).build();
+//!
+//!     let client = core.run(EventStoreClient::connect_with_settings(&"127.0.0.1:1113".parse().unwrap(), &handle, settings)).unwrap();
+//!     let info = core.run(persistent_subscription_info(&client, "orders", "billing")).unwrap();
+//!
+//!     println!("checkpoint at {:?}, about {} parked", info.last_checkpoint, info.parked);
+//! }
+//! ```
+
+use std::io;
+
+use futures::Future;
+use serde_json::{self, Value};
+use tokio_service::Service;
+
+use adapted::{AdaptedMessage, ReadStreamError};
+use package::Package;
+use raw::{EventRecord, ResolvedIndexedEvent};
+use response::{adapt, unexpected, read_stream_failed};
+use {Builder, EventNumber, ReadDirection};
+
+mod persistent;
+pub use self::persistent::{PersistentSubscriptionInfo, persistent_subscription_info, persistent_subscriptions,
+                           parked_events, checkpoint_stream, parked_stream};
+
+mod stats;
+pub use self::stats::{ServerStats, read_stats};
+
+mod scavenge;
+pub use self::scavenge::{ScavengeProgress, ScavengeCompleted, ScavengeOutcome, ChunksScavenged, follow_scavenge, wait_for_scavenge};
+
+mod users;
+pub use self::users::{UserDetails, users, user};
+
+/// Reads the last event of the stream, `None` if the stream does not exist, has been deleted or is
+/// empty.
+fn read_last<S>(service: &S, stream_id: String) -> Box<Future<Item = Option<ResolvedIndexedEvent<'static>>, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error>,
+          S::Future: 'static
+{
+    let package = Builder::read_stream_events()
+        .direction(ReadDirection::Backward)
+        .stream_id(stream_id)
+        .from_event_number(EventNumber::Last)
+        .max_count(1)
+        .resolve_link_tos(false)
+        .build_package(None, None);
+
+    Box::new(service.call(package).and_then(|response| {
+        match adapt(response)? {
+            AdaptedMessage::ReadStreamEventsCompleted(_, Ok(page)) => Ok(page.events.into_iter().next()),
+            AdaptedMessage::ReadStreamEventsCompleted(_, Err(ReadStreamError::NoStream)) |
+            AdaptedMessage::ReadStreamEventsCompleted(_, Err(ReadStreamError::StreamDeleted)) => Ok(None),
+            AdaptedMessage::ReadStreamEventsCompleted(_, Err(e)) => Err(read_stream_failed(e)),
+            other => Err(unexpected(other)),
+        }
+    }))
+}
+
+/// Parses the data of a system event, which is always JSON.
+fn json_data(record: &EventRecord) -> io::Result<Value> {
+    serde_json::from_slice(&record.data).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {} event: {}", record.event_type, e))
+    })
+}
@@ -0,0 +1,190 @@
+use std::io;
+use std::convert::TryFrom;
+
+use futures::{Future, Stream};
+use serde_json::Value;
+use tokio_service::Service;
+
+use feed::{self, StreamEvent};
+use package::Package;
+use raw::EventRecord;
+use {EventNumber, StreamVersion};
+use super::{read_last, json_data};
+
+/// The stream the server keeps the configuration of all of the persistent subscriptions in.
+const CONFIG_STREAM: &'static str = "$persistentSubscriptionConfig";
+
+/// State of a consumer group of a persistent subscription, see `persistent_subscription_info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PersistentSubscriptionInfo {
+    /// The stream subscribed to
+    pub stream_id: String,
+    /// Name of the consumer group
+    pub group_name: String,
+    /// The event number of the last event checkpointed by the group, `None` until the group has
+    /// checkpointed for the first time
+    pub last_checkpoint: Option<StreamVersion>,
+    /// Estimate of the parked events which have not been replayed: the events of the parked
+    /// stream after the point it has been truncated to when replaying
+    pub parked: u32,
+}
+
+/// The stream holding the checkpoints of the group `group_name` of the persistent subscription
+/// to `stream_id`.
+pub fn checkpoint_stream(stream_id: &str, group_name: &str) -> String {
+    format!("$persistentsubscription-{}::{}-checkpoint", stream_id, group_name)
+}
+
+/// The stream the events parked by the group `group_name` of the persistent subscription to
+/// `stream_id` are written to.
+pub fn parked_stream(stream_id: &str, group_name: &str) -> String {
+    format!("$persistentsubscription-{}::{}-parked", stream_id, group_name)
+}
+
+/// Reads the last checkpoint of the consumer group and estimates the number of events it has
+/// parked, from the checkpoint and parked streams of the group. A group which does not exist
+/// looks like a group which has neither checkpointed nor parked anything.
+pub fn persistent_subscription_info<S>(service: &S, stream_id: &str, group_name: &str) -> Box<Future<Item = PersistentSubscriptionInfo, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error>,
+          S::Future: 'static
+{
+    let parked = parked_stream(stream_id, group_name);
+
+    let checkpoint = read_last(service, checkpoint_stream(stream_id, group_name)).and_then(|last| match last {
+        Some(e) => checkpoint_version(&e.event).map(Some),
+        None => Ok(None),
+    });
+
+    let last_parked = read_last(service, parked.clone()).map(|last| last.map(|e| e.event.event_number));
+    let truncated = truncated_before(service, &parked);
+
+    let (stream_id, group_name) = (stream_id.to_owned(), group_name.to_owned());
+
+    Box::new(checkpoint.join3(last_parked, truncated).map(move |(last_checkpoint, last_parked, truncated)| {
+        let written = match last_parked {
+            Some(last) if last >= 0 => last as u32 + 1,
+            _ => 0,
+        };
+
+        PersistentSubscriptionInfo {
+            stream_id: stream_id,
+            group_name: group_name,
+            last_checkpoint: last_checkpoint,
+            parked: written.saturating_sub(truncated),
+        }
+    }))
+}
+
+/// Lists the persistent subscriptions of the node as pairs of the stream and the name of the
+/// group, from the configuration the server keeps of them.
+pub fn persistent_subscriptions<S>(service: &S) -> Box<Future<Item = Vec<(String, String)>, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error>,
+          S::Future: 'static
+{
+    Box::new(read_last(service, CONFIG_STREAM.to_owned()).and_then(|last| {
+        let config = match last {
+            Some(e) => json_data(&e.event)?,
+            None => return Ok(Vec::new()),
+        };
+
+        let entries = match config.get("entries").and_then(Value::as_array) {
+            Some(entries) => entries,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Persistent subscription config without entries")),
+        };
+
+        Ok(entries.iter().filter_map(|entry| {
+            let stream = entry.get("stream").and_then(Value::as_str);
+            let group = entry.get("group").and_then(Value::as_str);
+
+            match (stream, group) {
+                (Some(stream), Some(group)) => Some((stream.to_owned(), group.to_owned())),
+                _ => None,
+            }
+        }).collect())
+    }))
+}
+
+/// Reads the parked events of the consumer group which have not been replayed, with the links to
+/// the parked events resolved, ending at the end of the parked stream.
+pub fn parked_events<S>(service: S, stream_id: &str, group_name: &str) -> Box<Stream<Item = StreamEvent, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    let parked = parked_stream(stream_id, group_name);
+
+    let truncated = truncated_before(&service, &parked);
+
+    Box::new(truncated.and_then(move |truncated| {
+        let from = StreamVersion::try_from(truncated).map_err(|e| -> io::Error { e.into() })?;
+        Ok(feed::read_forward(service, parked.into(), EventNumber::Exact(from)))
+    }).flatten_stream())
+}
+
+/// The `$tb` of the metadata of the stream: the events before it have been truncated.
+fn truncated_before<S>(service: &S, stream_id: &str) -> Box<Future<Item = u32, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error>,
+          S::Future: 'static
+{
+    Box::new(read_last(service, format!("$${}", stream_id)).and_then(|last| {
+        let metadata = match last {
+            Some(e) => json_data(&e.event)?,
+            None => return Ok(0),
+        };
+
+        Ok(metadata.get("$tb").and_then(Value::as_u64).map_or(0, |tb| tb.min(u64::from(u32::max_value())) as u32))
+    }))
+}
+
+/// The checkpoint events hold the event number as a JSON number.
+fn checkpoint_version(record: &EventRecord) -> io::Result<StreamVersion> {
+    match json_data(record)?.as_u64() {
+        Some(number) if number <= u64::from(u32::max_value()) => StreamVersion::try_from(number as u32).map_err(|e| e.into()),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Checkpoint without an event number")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use futures::{Future, Stream};
+    use server::{MemoryStore, StoreService, StreamStore};
+    use {ExpectedVersion, StreamVersion};
+    use fixtures::json_event;
+    use super::{PersistentSubscriptionInfo, persistent_subscription_info, persistent_subscriptions, parked_events};
+
+    #[test]
+    fn consumer_group_state() {
+        let mut store = MemoryStore::new();
+        let parked = "$persistentsubscription-orders::billing-parked";
+
+        store.append("$persistentSubscriptionConfig", ExpectedVersion::Any, vec![
+            json_event("$PersistentConfig", r#"{"entries": [{"stream": "orders", "group": "billing"}, {"stream": "orders", "group": "shipping"}]}"#),
+        ]).unwrap();
+        store.append("$persistentsubscription-orders::billing-checkpoint", ExpectedVersion::Any, vec![
+            json_event("$SubscriptionCheckpoint", "12"),
+            json_event("$SubscriptionCheckpoint", "41"),
+        ]).unwrap();
+        store.append(parked, ExpectedVersion::Any, vec![json_event("parked", "{}"), json_event("parked", "{}"), json_event("parked", "{}")]).unwrap();
+        store.set_metadata(parked, ExpectedVersion::Any, br#"{"$tb": 1}"#.to_vec()).unwrap();
+
+        let service = StoreService::new(store);
+
+        assert_eq!(persistent_subscriptions(&service).wait().unwrap(), vec![
+            ("orders".to_owned(), "billing".to_owned()),
+            ("orders".to_owned(), "shipping".to_owned()),
+        ]);
+
+        assert_eq!(persistent_subscription_info(&service, "orders", "billing").wait().unwrap(), PersistentSubscriptionInfo {
+            stream_id: "orders".to_owned(),
+            group_name: "billing".to_owned(),
+            last_checkpoint: Some(StreamVersion::try_from(41u32).unwrap()),
+            parked: 2,
+        });
+
+        let replayable = parked_events(service.clone(), "orders", "billing").collect().wait().unwrap();
+        assert_eq!(replayable.iter().map(|e| e.original_event_number()).collect::<Vec<_>>(), vec![1, 2]);
+
+        let shipping = persistent_subscription_info(&service, "orders", "shipping").wait().unwrap();
+        assert_eq!((shipping.last_checkpoint, shipping.parked), (None, 0));
+    }
+}
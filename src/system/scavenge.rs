@@ -0,0 +1,207 @@
+use std::io;
+use std::time::Duration;
+
+use futures::{future, Future, Stream, Poll, Async};
+use serde_json::Value;
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_service::Service;
+
+use feed::{self, StreamEvent};
+use package::Package;
+use EventNumber;
+use super::json_data;
+
+/// How a scavenge ended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScavengeOutcome {
+    /// All of the chunks were scavenged
+    Success,
+    /// The scavenge was stopped before it completed
+    Stopped,
+    /// The scavenge failed, see `ScavengeCompleted::error`
+    Failed,
+}
+
+/// Chunks of the database processed by a scavenge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunksScavenged {
+    /// Number of the first chunk
+    pub chunk_start: u64,
+    /// Number of the last chunk
+    pub chunk_end: u64,
+    /// Were the chunks rewritten, which happens only when there was enough to remove
+    pub was_scavenged: bool,
+    /// Bytes saved by scavenging the chunks
+    pub space_saved: i64,
+    /// Why the chunks could not be scavenged, if they could not
+    pub error: Option<String>,
+}
+
+/// The end of a scavenge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScavengeCompleted {
+    /// How the scavenge ended
+    pub outcome: ScavengeOutcome,
+    /// Bytes saved over all of the chunks
+    pub space_saved: i64,
+    /// Why the scavenge failed, if it did
+    pub error: Option<String>,
+}
+
+/// Progress of a scavenge, as followed by `follow_scavenge`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScavengeProgress {
+    /// The scavenge started on the node with the given HTTP endpoint
+    Started(Option<String>),
+    /// Chunks were processed
+    ChunksCompleted(ChunksScavenged),
+    /// The scavenge ended; nothing follows
+    Completed(ScavengeCompleted),
+}
+
+impl ScavengeProgress {
+    fn from_event(e: &StreamEvent) -> io::Result<Option<Self>> {
+        let parse = match &*e.event.event_type {
+            "$scavengeStarted" | "$scavengeChunksCompleted" | "$scavengeCompleted" => json_data(&e.event)?,
+            _ => return Ok(None),
+        };
+
+        let text = |key: &str| parse.get(key).and_then(Value::as_str).map(|s| s.to_owned());
+        let error = |key: &str| text(key).and_then(|e| if e.is_empty() { None } else { Some(e) });
+        let space_saved = parse.get("spaceSaved").and_then(Value::as_i64).unwrap_or(0);
+
+        Ok(Some(match &*e.event.event_type {
+            "$scavengeStarted" => ScavengeProgress::Started(text("nodeEndpoint")),
+            "$scavengeChunksCompleted" => ScavengeProgress::ChunksCompleted(ChunksScavenged {
+                chunk_start: parse.get("chunkStartNumber").and_then(Value::as_u64).unwrap_or(0),
+                chunk_end: parse.get("chunkEndNumber").and_then(Value::as_u64).unwrap_or(0),
+                was_scavenged: parse.get("wasScavenged").and_then(Value::as_bool).unwrap_or(false),
+                space_saved: space_saved,
+                error: error("errorMessage"),
+            }),
+            _ => ScavengeProgress::Completed(ScavengeCompleted {
+                outcome: match text("result").as_ref().map(|result| result.as_str()) {
+                    Some("Success") => ScavengeOutcome::Success,
+                    Some("Stopped") => ScavengeOutcome::Stopped,
+                    _ => ScavengeOutcome::Failed,
+                },
+                space_saved: space_saved,
+                error: error("error"),
+            }),
+        }))
+    }
+}
+
+/// Follows the `$scavenges-<scavenge_id>` stream of a scavenge started for example with the HTTP
+/// API, reading it every `interval` and yielding the progress from the start of the scavenge. The
+/// stream ends after `ScavengeProgress::Completed`.
+pub fn follow_scavenge<S>(service: S, scavenge_id: &str, interval: Duration, handle: &Handle) -> Box<Stream<Item = ScavengeProgress, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    let events = feed::poll_stream(service, format!("$scavenges-{}", scavenge_id), EventNumber::First, interval, handle);
+    let progress = events.and_then(|e| ScavengeProgress::from_event(&e)).filter_map(|progress| progress);
+
+    Box::new(UntilCompleted { inner: progress, completed: false })
+}
+
+/// Waits for the scavenge to complete like `follow_scavenge`, failing with `TimedOut` unless it
+/// has completed within `timeout`. A scavenge which failed or was stopped completes the future as
+/// well.
+pub fn wait_for_scavenge<S>(service: S, scavenge_id: &str, interval: Duration, timeout: Duration, handle: &Handle) -> Box<Future<Item = ScavengeCompleted, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    let timer = match Timeout::new(timeout, handle) {
+        Ok(timer) => timer,
+        Err(e) => return Box::new(future::err(e)),
+    };
+
+    let id = scavenge_id.to_owned();
+    let timed_out = timer.then(move |res| -> io::Result<ScavengeCompleted> {
+        res?;
+        Err(io::Error::new(io::ErrorKind::TimedOut, format!("Scavenge {} did not complete within {:?}", id, timeout)))
+    });
+
+    let completed = follow_scavenge(service, scavenge_id, interval, handle)
+        .filter_map(|progress| match progress {
+            ScavengeProgress::Completed(completed) => Some(completed),
+            _ => None,
+        })
+        .into_future()
+        .map_err(|(e, _)| e)
+        .and_then(|(completed, _)| match completed {
+            Some(completed) => Ok(completed),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Scavenge progress ended without completion")),
+        });
+
+    Box::new(completed.select(timed_out).map(|(completed, _)| completed).map_err(|(e, _)| e))
+}
+
+/// Ends the progress after the completion instead of reading the stream again.
+struct UntilCompleted<S> {
+    inner: S,
+    completed: bool,
+}
+
+impl<S: Stream<Item = ScavengeProgress>> Stream for UntilCompleted<S> {
+    type Item = ScavengeProgress;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<ScavengeProgress>, S::Error> {
+        if self.completed {
+            return Ok(Async::Ready(None));
+        }
+
+        let progress = match self.inner.poll()? {
+            Async::Ready(progress) => progress,
+            Async::NotReady => return Ok(Async::NotReady),
+        };
+
+        if let Some(ScavengeProgress::Completed(_)) = progress {
+            self.completed = true;
+        }
+
+        Ok(Async::Ready(progress))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::Duration;
+    use futures::Stream;
+    use tokio_core::reactor::Core;
+    use server::{MemoryStore, StoreService, StreamStore};
+    use ExpectedVersion;
+    use fixtures::json_event;
+    use super::{ChunksScavenged, ScavengeCompleted, ScavengeOutcome, ScavengeProgress, follow_scavenge, wait_for_scavenge};
+
+    #[test]
+    fn progress_ends_with_completion() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let mut store = MemoryStore::new();
+        store.append("$scavenges-abc", ExpectedVersion::Any, vec![
+            json_event("$scavengeStarted", r#"{"scavengeId": "abc", "nodeEndpoint": "127.0.0.1:2113"}"#),
+            json_event("$scavengeChunksCompleted", r#"{"scavengeId": "abc", "chunkStartNumber": 0, "chunkEndNumber": 1, "wasScavenged": true, "spaceSaved": 4096, "errorMessage": ""}"#),
+            json_event("$scavengeCompleted", r#"{"scavengeId": "abc", "result": "Success", "error": "", "spaceSaved": 4096}"#),
+        ]).unwrap();
+
+        let service = StoreService::new(store);
+
+        let progress = core.run(follow_scavenge(service.clone(), "abc", Duration::from_millis(10), &handle).collect()).unwrap();
+        assert_eq!(progress, vec![
+            ScavengeProgress::Started(Some("127.0.0.1:2113".to_owned())),
+            ScavengeProgress::ChunksCompleted(ChunksScavenged { chunk_start: 0, chunk_end: 1, was_scavenged: true, space_saved: 4096, error: None }),
+            ScavengeProgress::Completed(ScavengeCompleted { outcome: ScavengeOutcome::Success, space_saved: 4096, error: None }),
+        ]);
+
+        let completed = core.run(wait_for_scavenge(service.clone(), "abc", Duration::from_millis(10), Duration::from_secs(5), &handle)).unwrap();
+        assert_eq!(completed.outcome, ScavengeOutcome::Success);
+
+        let e = core.run(wait_for_scavenge(service, "def", Duration::from_millis(10), Duration::from_millis(50), &handle)).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,115 @@
+use std::io;
+use std::net::SocketAddr;
+use std::collections::BTreeMap;
+
+use futures::Future;
+use serde_json::Value;
+use tokio_service::Service;
+
+use package::Package;
+use super::{read_last, json_data};
+
+/// The key metrics of a node from the statistics it collects periodically, see `read_stats`.
+/// The metrics missing from the statistics, which vary between server versions and operating
+/// systems, are `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerStats {
+    /// When the statistics were collected, in milliseconds since the Unix epoch
+    pub collected_epoch: Option<i64>,
+    /// Processor usage of the server process, in percent
+    pub process_cpu: Option<f64>,
+    /// Memory used by the server process, in bytes
+    pub process_memory: Option<u64>,
+    /// Processor usage of the whole machine, in percent
+    pub system_cpu: Option<f64>,
+    /// Free memory of the machine, in bytes
+    pub system_free_memory: Option<u64>,
+    /// Lengths of the internal queues of the server by the name of the queue, such as
+    /// `MainQueue`; long queues tell of an overloaded node
+    pub queues: BTreeMap<String, u64>,
+}
+
+impl ServerStats {
+    /// The length of the longest queue, zero without queues.
+    pub fn longest_queue(&self) -> u64 {
+        self.queues.values().cloned().max().unwrap_or(0)
+    }
+
+    fn parse(stats: &Value) -> io::Result<Self> {
+        let stats = match stats.as_object() {
+            Some(stats) => stats,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Statistics are not an object")),
+        };
+
+        // the statistics are flattened, with the queues keyed like es-queue-MainQueue-length
+        let queues = stats.iter().filter_map(|(key, value)| {
+            if !key.starts_with("es-queue-") || !key.ends_with("-length") || key.len() <= "es-queue--length".len() {
+                return None;
+            }
+
+            let name = &key["es-queue-".len()..key.len() - "-length".len()];
+            value.as_u64().map(|length| (name.to_owned(), length))
+        }).collect();
+
+        Ok(ServerStats {
+            collected_epoch: None,
+            process_cpu: stats.get("proc-cpu").and_then(Value::as_f64),
+            process_memory: stats.get("proc-mem").and_then(Value::as_u64),
+            system_cpu: stats.get("sys-cpu").and_then(Value::as_f64),
+            system_free_memory: stats.get("sys-freeMem").and_then(Value::as_u64),
+            queues: queues,
+        })
+    }
+}
+
+/// Reads the latest statistics the node at the HTTP endpoint `http_addr` has written to its
+/// `$stats-<ip:port>` stream, as a lightweight health probe over the TCP connection. Returns
+/// `None` if the node has not collected statistics yet.
+pub fn read_stats<S>(service: &S, http_addr: SocketAddr) -> Box<Future<Item = Option<ServerStats>, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error>,
+          S::Future: 'static
+{
+    Box::new(read_last(service, format!("$stats-{}", http_addr)).and_then(|last| {
+        let event = match last {
+            Some(e) => e.event,
+            None => return Ok(None),
+        };
+
+        let mut stats = ServerStats::parse(&json_data(&event)?)?;
+        stats.collected_epoch = event.created_epoch;
+        Ok(Some(stats))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+    use server::{MemoryStore, StoreService, StreamStore};
+    use ExpectedVersion;
+    use fixtures::json_event;
+    use super::read_stats;
+
+    #[test]
+    fn latest_statistics() {
+        let collected = |data: &'static str| json_event("$statsCollected", data);
+
+        let mut store = MemoryStore::new();
+        store.append("$stats-127.0.0.1:2113", ExpectedVersion::Any, vec![
+            collected(r#"{"proc-cpu": 90.0}"#),
+            collected(r#"{"proc-cpu": 12.5, "proc-mem": 104857600, "sys-freeMem": 2048,
+                          "es-queue-MainQueue-queueName": "MainQueue", "es-queue-MainQueue-length": 3,
+                          "es-queue-StorageWriterQueue-length": 11}"#),
+        ]).unwrap();
+
+        let service = StoreService::new(store);
+
+        let stats = read_stats(&service, "127.0.0.1:2113".parse().unwrap()).wait().unwrap().unwrap();
+        assert_eq!(stats.process_cpu, Some(12.5));
+        assert_eq!(stats.process_memory, Some(104857600));
+        assert_eq!((stats.system_cpu, stats.system_free_memory), (None, Some(2048)));
+        assert_eq!(stats.queues.keys().collect::<Vec<_>>(), vec!["MainQueue", "StorageWriterQueue"]);
+        assert_eq!(stats.longest_queue(), 11);
+
+        assert_eq!(read_stats(&service, "127.0.0.2:2113".parse().unwrap()).wait().unwrap(), None);
+    }
+}
@@ -0,0 +1,123 @@
+use std::io;
+
+use futures::{future, Future, Stream};
+use serde_json::Value;
+use tokio_service::Service;
+
+use feed;
+use package::Package;
+use EventNumber;
+use super::{read_last, json_data};
+
+/// A user as kept in the `$user-<login>` stream, see `users`. The password hash and salt stored
+/// with the user are left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserDetails {
+    /// The name the user logs in with
+    pub login_name: String,
+    /// Full name of the user
+    pub full_name: String,
+    /// Groups of the user, such as `$admins`
+    pub groups: Vec<String>,
+    /// Is the user disabled
+    pub disabled: bool,
+}
+
+/// Lists the users from the `$users` stream, which has an event for every user created, and the
+/// latest details of each user in the order they were created. Deleted users are left out.
+pub fn users<S>(service: S) -> Box<Future<Item = Vec<UserDetails>, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + Clone + 'static,
+          S::Future: 'static
+{
+    let created = feed::read_forward(service.clone(), "$users".into(), EventNumber::First).collect();
+
+    Box::new(created.and_then(move |created| {
+        let mut logins = Vec::<String>::new();
+
+        for e in created {
+            // the data is the login name, as plain text or a JSON string
+            let login = String::from_utf8_lossy(&e.event.data).trim_matches('"').to_owned();
+
+            if !login.is_empty() && !logins.contains(&login) {
+                logins.push(login);
+            }
+        }
+
+        future::join_all(logins.iter().map(|login| user(&service, login)).collect::<Vec<_>>())
+    }).map(|users| users.into_iter().filter_map(|details| details).collect()))
+}
+
+/// Reads the latest details of the user from the `$user-<login_name>` stream, `None` if the user
+/// does not exist or has been deleted.
+pub fn user<S>(service: &S, login_name: &str) -> Box<Future<Item = Option<UserDetails>, Error = io::Error>>
+    where S: Service<Request = Package, Response = Package, Error = io::Error>,
+          S::Future: 'static
+{
+    Box::new(read_last(service, format!("$user-{}", login_name)).and_then(|last| {
+        let details = match last {
+            Some(e) => json_data(&e.event)?,
+            None => return Ok(None),
+        };
+
+        let text = |key: &str| details.get(key).and_then(Value::as_str).map(|s| s.to_owned());
+
+        let login_name = match text("loginName") {
+            Some(login_name) => login_name,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "User without a login name")),
+        };
+
+        let groups = details.get("groups")
+            .and_then(Value::as_array)
+            .map(|groups| groups.iter().filter_map(Value::as_str).map(|group| group.to_owned()).collect())
+            .unwrap_or_else(Vec::new);
+
+        Ok(Some(UserDetails {
+            login_name: login_name,
+            full_name: text("fullName").unwrap_or_else(String::new),
+            groups: groups,
+            disabled: details.get("disabled").and_then(Value::as_bool).unwrap_or(false),
+        }))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+    use server::{MemoryStore, StoreService, StreamStore};
+    use {ContentType, ExpectedVersion};
+    use fixtures::new_event;
+    use super::{UserDetails, users};
+
+    #[test]
+    fn users_with_their_latest_details() {
+        let mut store = MemoryStore::new();
+
+        store.append("$users", ExpectedVersion::Any, vec![
+            new_event("$User", ContentType::Bytes, "admin"),
+            new_event("$User", ContentType::Bytes, "ann"),
+            new_event("$User", ContentType::Bytes, "gone"),
+        ]).unwrap();
+        store.append("$user-admin", ExpectedVersion::Any, vec![
+            new_event("$user", ContentType::Json, r#"{"loginName": "admin", "fullName": "Event Store Administrator", "groups": ["$admins"], "salt": "x", "hash": "y"}"#),
+        ]).unwrap();
+        store.append("$user-ann", ExpectedVersion::Any, vec![
+            new_event("$user", ContentType::Json, r#"{"loginName": "ann", "fullName": "Ann", "groups": []}"#),
+            new_event("$user", ContentType::Json, r#"{"loginName": "ann", "fullName": "Ann", "groups": ["$ops"], "disabled": true}"#),
+        ]).unwrap();
+
+        assert_eq!(users(StoreService::new(store)).wait().unwrap(), vec![
+            UserDetails {
+                login_name: "admin".to_owned(),
+                full_name: "Event Store Administrator".to_owned(),
+                groups: vec!["$admins".to_owned()],
+                disabled: false,
+            },
+            UserDetails {
+                login_name: "ann".to_owned(),
+                full_name: "Ann".to_owned(),
+                groups: vec!["$ops".to_owned()],
+                disabled: true,
+            },
+        ]);
+    }
+}
@@ -0,0 +1,329 @@
+//! TLS encrypted connections, available with the `tls` feature.
+
+use std::io;
+use std::fmt;
+
+use futures::Future;
+use tokio_core::net::TcpStream;
+
+use transport::AsyncIo;
+
+/// Settings of a TLS encrypted connection, given to `ConnectionSettingsBuilder::tls`. The
+/// defaults are secure: the certificate chain and the hostname are verified against the system
+/// root certificates.
+///
+/// Encrypted connections require the `tls` feature of this crate; otherwise connecting fails.
+///
+/// # Example
+///
+/// ```rust
+/// use eventstore_tcp::{ConnectionSettings, TlsSettings};
+///
+/// let settings = ConnectionSettings::builder()
+///     .tls(TlsSettings::builder("eventstore.example.com")
+///         .pin_sha256([0u8; 32])
+///         .build())
+///     .build();
+///
+/// assert_eq!(settings.tls().unwrap().domain(), "eventstore.example.com");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsSettings {
+    domain: String,
+    root_certificates: Vec<Vec<u8>>,
+    verify_hostname: bool,
+    pinned_sha256: Vec<[u8; 32]>,
+    client_identity: Option<ClientIdentity>,
+}
+
+/// Client certificate with the private key presented during the handshake, for servers requiring
+/// certificate based client authentication.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    pkcs12: Vec<u8>,
+    password: String,
+}
+
+impl ClientIdentity {
+    /// Creates an identity from a DER encoded PKCS #12 archive containing the certificate, the
+    /// private key and optionally the intermediate certificates, encrypted with `password`.
+    pub fn from_pkcs12<S: Into<String>>(der: Vec<u8>, password: S) -> Self {
+        ClientIdentity {
+            pkcs12: der,
+            password: password.into(),
+        }
+    }
+}
+
+impl fmt::Debug for ClientIdentity {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "ClientIdentity({} bytes of PKCS #12, password: ****)", self.pkcs12.len())
+    }
+}
+
+/// Certificate with the private key presented to the clients by the servers of the `server`
+/// module, see `Server::tls`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ServerIdentity {
+    pkcs12: Vec<u8>,
+    password: String,
+}
+
+impl ServerIdentity {
+    /// Creates an identity from a DER encoded PKCS #12 archive containing the certificate, the
+    /// private key and optionally the intermediate certificates, encrypted with `password`.
+    pub fn from_pkcs12<S: Into<String>>(der: Vec<u8>, password: S) -> Self {
+        ServerIdentity {
+            pkcs12: der,
+            password: password.into(),
+        }
+    }
+}
+
+impl fmt::Debug for ServerIdentity {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "ServerIdentity({} bytes of PKCS #12, password: ****)", self.pkcs12.len())
+    }
+}
+
+impl TlsSettings {
+    /// Creates a builder for settings connecting to a server with the given domain name.
+    pub fn builder<S: Into<String>>(domain: S) -> TlsSettingsBuilder {
+        TlsSettingsBuilder::new(domain.into())
+    }
+
+    /// The domain name used for verifying the server certificate.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// DER encoded certificates trusted in addition to the system root certificates.
+    pub fn root_certificates(&self) -> &[Vec<u8>] {
+        &self.root_certificates
+    }
+
+    /// Is the hostname of the server certificate verified.
+    pub fn verify_hostname(&self) -> bool {
+        self.verify_hostname
+    }
+
+    /// SHA-256 fingerprints of the accepted server certificates. When empty, any otherwise valid
+    /// certificate is accepted.
+    pub fn pinned_sha256(&self) -> &[[u8; 32]] {
+        &self.pinned_sha256
+    }
+
+    /// The client certificate presented to the server, if any.
+    pub fn client_identity(&self) -> Option<&ClientIdentity> {
+        self.client_identity.as_ref()
+    }
+}
+
+/// Builder for `TlsSettings`.
+pub struct TlsSettingsBuilder {
+    domain: String,
+    root_certificates: Vec<Vec<u8>>,
+    verify_hostname: Option<bool>,
+    pinned_sha256: Vec<[u8; 32]>,
+    client_identity: Option<ClientIdentity>,
+}
+
+impl TlsSettingsBuilder {
+    fn new(domain: String) -> Self {
+        assert!(domain.len() > 0);
+        TlsSettingsBuilder {
+            domain: domain,
+            root_certificates: Vec::new(),
+            verify_hostname: None,
+            pinned_sha256: Vec::new(),
+            client_identity: None,
+        }
+    }
+
+    /// Trusts the given DER encoded certificate in addition to the system root certificates, for
+    /// example the certificate of a private certificate authority.
+    pub fn add_root_certificate(&mut self, der: Vec<u8>) -> &mut Self {
+        self.root_certificates.push(der);
+        self
+    }
+
+    /// Accept server certificates issued for any hostname. The certificate chain is still
+    /// verified. Meant only for lab setups where the nodes are connected to by IP address.
+    ///
+    /// Defaults to verifying the hostname.
+    pub fn danger_disable_hostname_verification(&mut self) -> &mut Self {
+        self.verify_hostname = Some(false);
+        self
+    }
+
+    /// Accept only server certificates with the given SHA-256 fingerprint of the DER encoding.
+    /// Can be called multiple times to allow rotating the certificate. The pins are checked in
+    /// addition to the certificate verification.
+    pub fn pin_sha256(&mut self, fingerprint: [u8; 32]) -> &mut Self {
+        self.pinned_sha256.push(fingerprint);
+        self
+    }
+
+    /// Presents the given client certificate during the handshake. Clusters using certificates
+    /// for authenticating clients on the secure TCP port require this.
+    ///
+    /// Defaults to no client certificate.
+    pub fn client_identity(&mut self, identity: ClientIdentity) -> &mut Self {
+        self.client_identity = Some(identity);
+        self
+    }
+
+    /// Builds the settings. Values of this builder will be moved into the settings.
+    pub fn build(&mut self) -> TlsSettings {
+        use std::mem;
+
+        TlsSettings {
+            domain: self.domain.clone(),
+            root_certificates: mem::replace(&mut self.root_certificates, Vec::new()),
+            verify_hostname: self.verify_hostname.take().unwrap_or(true),
+            pinned_sha256: mem::replace(&mut self.pinned_sha256, Vec::new()),
+            client_identity: self.client_identity.take(),
+        }
+    }
+}
+
+/// Completes the TCP connection and the TLS handshake.
+#[cfg(feature = "tls")]
+pub fn connect(tcp: Box<Future<Item = TcpStream, Error = io::Error>>, settings: TlsSettings) -> Box<Future<Item = Box<AsyncIo>, Error = io::Error>> {
+    use futures::future;
+
+    let connector = match connector(&settings) {
+        Ok(connector) => connector,
+        Err(e) => return Box::new(future::err(e)),
+    };
+
+    Box::new(tcp
+        .and_then(move |stream| {
+            connector.connect(&settings.domain, stream)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                .and_then(move |stream| {
+                    verify_pins(stream.get_ref(), &settings.pinned_sha256)?;
+                    Ok(Box::new(stream) as Box<AsyncIo>)
+                })
+        }))
+}
+
+/// Fails as this crate was built without the `tls` feature.
+#[cfg(not(feature = "tls"))]
+pub fn connect(_tcp: Box<Future<Item = TcpStream, Error = io::Error>>, _settings: TlsSettings) -> Box<Future<Item = Box<AsyncIo>, Error = io::Error>> {
+    use futures::future;
+
+    Box::new(future::err(io::Error::new(io::ErrorKind::Other, "TLS requires the tls feature of eventstore-tcp")))
+}
+
+/// Completes the TLS handshakes of the connections accepted by a server.
+#[cfg(feature = "tls")]
+pub struct Acceptor(::tokio_tls::TlsAcceptor);
+
+/// Fails to be created as this crate was built without the `tls` feature.
+#[cfg(not(feature = "tls"))]
+pub struct Acceptor(());
+
+#[cfg(feature = "tls")]
+impl Acceptor {
+    /// Creates an acceptor presenting the given identity.
+    pub fn new(identity: &ServerIdentity) -> io::Result<Self> {
+        use native_tls::{Identity, TlsAcceptor};
+
+        let identity = Identity::from_pkcs12(&identity.pkcs12, &identity.password).map_err(other)?;
+        Ok(Acceptor(TlsAcceptor::new(identity).map_err(other)?.into()))
+    }
+
+    /// Completes the handshake of the accepted connection.
+    pub fn accept(&self, stream: TcpStream) -> Box<Future<Item = Box<AsyncIo>, Error = io::Error>> {
+        Box::new(self.0.accept(stream)
+            .map(|stream| Box::new(stream) as Box<AsyncIo>)
+            .map_err(other))
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+impl Acceptor {
+    /// Fails as this crate was built without the `tls` feature.
+    pub fn new(_identity: &ServerIdentity) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Other, "TLS requires the tls feature of eventstore-tcp"))
+    }
+
+    /// Never called, as the acceptor cannot be created.
+    pub fn accept(&self, _stream: TcpStream) -> Box<Future<Item = Box<AsyncIo>, Error = io::Error>> {
+        unreachable!()
+    }
+}
+
+#[cfg(feature = "tls")]
+fn connector(settings: &TlsSettings) -> io::Result<::tokio_tls::TlsConnector> {
+    use native_tls::{Certificate, Identity, TlsConnector};
+
+    let mut builder = TlsConnector::builder();
+
+    for der in &settings.root_certificates {
+        builder.add_root_certificate(Certificate::from_der(der).map_err(other)?);
+    }
+
+    builder.danger_accept_invalid_hostnames(!settings.verify_hostname);
+
+    if let Some(ref identity) = settings.client_identity {
+        builder.identity(Identity::from_pkcs12(&identity.pkcs12, &identity.password).map_err(other)?);
+    }
+
+    Ok(builder.build().map_err(other)?.into())
+}
+
+#[cfg(feature = "tls")]
+fn verify_pins<S: io::Read + io::Write>(stream: &::native_tls::TlsStream<S>, pins: &[[u8; 32]]) -> io::Result<()> {
+    use sha2::{Sha256, Digest};
+
+    if pins.is_empty() {
+        return Ok(());
+    }
+
+    let der = match stream.peer_certificate().map_err(other)? {
+        Some(cert) => cert.to_der().map_err(other)?,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Server did not present a certificate")),
+    };
+
+    let mut hasher = Sha256::default();
+    hasher.input(&der);
+    let fingerprint = hasher.result();
+
+    if pins.iter().any(|pin| &pin[..] == fingerprint.as_slice()) {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "Server certificate does not match any of the pinned fingerprints"))
+    }
+}
+
+#[cfg(feature = "tls")]
+fn other<E: ::std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TlsSettings, ClientIdentity, ServerIdentity};
+
+    #[test]
+    fn secure_defaults() {
+        let settings = TlsSettings::builder("localhost").build();
+        assert!(settings.verify_hostname());
+        assert!(settings.root_certificates().is_empty());
+        assert!(settings.pinned_sha256().is_empty());
+        assert!(settings.client_identity().is_none());
+    }
+
+    #[test]
+    fn identity_debug_hides_password() {
+        let identity = ClientIdentity::from_pkcs12(vec![1, 2, 3], "secret");
+        let debug = format!("{:?}", identity);
+        assert!(!debug.contains("secret"), "{}", debug);
+
+        let identity = ServerIdentity::from_pkcs12(vec![1, 2, 3], "secret");
+        let debug = format!("{:?}", identity);
+        assert!(!debug.contains("secret"), "{}", debug);
+    }
+}
@@ -0,0 +1,332 @@
+//! Explicit transactions for appending to a stream in several batches which become visible at
+//! once, for appends too large for a single `WriteEvents`.
+
+use std::io;
+use std::rc::Rc;
+use std::borrow::Cow;
+
+use futures::{future, Future};
+use tokio_service::Service;
+
+use adapted::{AdaptedMessage, WriteEventsCompleted};
+use builder::EventsBuilder;
+use package::Package;
+use response::{adapt, unexpected};
+use {Builder, EventStoreClient, ExpectedVersion, NewEvent, TransactionId, UsernamePassword};
+
+/// A transaction started on a stream with `EventStoreClient::start_transaction`. The events
+/// written within the transaction are appended to the stream only once it is committed, all at
+/// once, and are checked against the expected version given to the start.
+///
+/// Dropping the handle without committing abandons the transaction: there is no message for
+/// rolling back, so nothing is written and the server forgets the transaction after its own
+/// timeout. The transaction is known only to the node it was started on, so the writes and the
+/// commit fail with `TransactionFailure::InvalidTransaction` after the connection has moved to
+/// another node.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// extern crate tokio_core;
+/// extern crate uuid;
+/// extern crate eventstore_tcp;
+///
+/// use std::borrow::Cow;
+/// use tokio_core::reactor::Core;
+/// use uuid::Uuid;
+/// use eventstore_tcp::{EventStoreClient, ExpectedVersion, NewEvent, ContentType};
+///
+/// fn reading(celsius: f64) -> NewEvent<'static> {
+///     NewEvent {
+///         event_id: Cow::Owned(Uuid::new_v4().as_bytes().to_vec()),
+///         event_type: Cow::Borrowed("reading"),
+///         data_content_type: ContentType::Json.into(),
+///         metadata_content_type: ContentType::Bytes.into(),
+///         data: Cow::Owned(format!("{{\"celsius\":{}}}", celsius).into_bytes()),
+///         metadata: None,
+///     }
+/// }
+///
+/// fn main() {
+///     let mut core = Core::new().unwrap();
+///     let handle = core.handle();
+///
+///     let client = core.run(EventStoreClient::connect(&"127.0.0.1:1113".parse().unwrap(), &handle)).unwrap();
+///     let transaction = core.run(client.start_transaction("sensor-1", ExpectedVersion::Any)).unwrap();
+///
+///     core.run(transaction.write(vec![reading(21.5), reading(21.7)])).unwrap();
+///     core.run(transaction.write(vec![reading(21.6)])).unwrap();
+///
+///     let committed = core.run(transaction.commit()).unwrap();
+///     println!("appended {:?}", committed.event_numbers);
+/// }
+/// ```
+pub struct TransactionHandle<S> {
+    service: Rc<S>,
+    id: TransactionId,
+    stream_id: Cow<'static, str>,
+    /// Sent with the writes and the commit as well, as they do not name the stream
+    credentials: Option<UsernamePassword>,
+    committed: bool,
+}
+
+impl EventStoreClient {
+    /// Starts a transaction on the stream, see `TransactionHandle`. Fails with the
+    /// `TransactionFailure` of the response, such as `AccessDenied`.
+    ///
+    /// With `ConnectionSettingsBuilder::credentials_provider` the credentials for the stream are
+    /// asked for once, and used for the start, the writes and the commit.
+    pub fn start_transaction<I>(&self, stream_id: I, expected_version: ExpectedVersion) -> Box<Future<Item = TransactionHandle<EventStoreClient>, Error = io::Error>>
+        where I: Into<Cow<'static, str>>
+    {
+        let stream_id = stream_id.into();
+
+        let credentials: Box<Future<Item = Option<UsernamePassword>, Error = io::Error>> = match self.settings().credentials_provider() {
+            Some(provider) => Box::new(provider.get_for_stream(Some(&*stream_id)).map(Some)),
+            None => Box::new(future::ok(None)),
+        };
+
+        let client = self.clone();
+
+        Box::new(credentials.and_then(move |credentials| {
+            TransactionHandle::start_with_credentials(client, stream_id, expected_version, credentials)
+        }))
+    }
+}
+
+impl<S> TransactionHandle<S>
+    where S: Service<Request = Package, Response = Package, Error = io::Error> + 'static,
+          S::Future: 'static
+{
+    /// Starts a transaction on the stream through `service`, like
+    /// `EventStoreClient::start_transaction`.
+    pub fn start<I>(service: S, stream_id: I, expected_version: ExpectedVersion) -> Box<Future<Item = Self, Error = io::Error>>
+        where I: Into<Cow<'static, str>>
+    {
+        Self::start_with_credentials(service, stream_id, expected_version, None)
+    }
+
+    /// Starts a transaction like `start`, sending the start, the writes and the commit with
+    /// `credentials`.
+    pub fn start_with_credentials<I>(service: S, stream_id: I, expected_version: ExpectedVersion, credentials: Option<UsernamePassword>) -> Box<Future<Item = Self, Error = io::Error>>
+        where I: Into<Cow<'static, str>>
+    {
+        let stream_id = stream_id.into();
+
+        let package = Builder::transaction_start()
+            .stream_id(stream_id.clone())
+            .expected_version(expected_version)
+            .build_package(credentials.clone(), None);
+
+        let service = Rc::new(service);
+        let started = service.call(package);
+
+        Box::new(started.and_then(move |response| {
+            match adapt(response)? {
+                AdaptedMessage::TransactionStartCompleted(Ok(id)) => Ok(TransactionHandle {
+                    service: service,
+                    id: id,
+                    stream_id: stream_id,
+                    credentials: credentials,
+                    committed: false,
+                }),
+                AdaptedMessage::TransactionStartCompleted(Err(failure)) => Err(io::Error::new(io::ErrorKind::Other, failure)),
+                other => Err(unexpected(other)),
+            }
+        }))
+    }
+
+    /// The id the server assigned to the transaction.
+    pub fn id(&self) -> TransactionId {
+        self.id
+    }
+
+    /// The stream the transaction appends to.
+    pub fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    /// Writes the events within the transaction. Wait for a write before starting the next one
+    /// to keep the events in order. Fails with `InvalidInput` like
+    /// `TransactionWriteBuilder::try_build_package` for invalid events, and with the
+    /// `TransactionFailure` of the response.
+    pub fn write(&self, events: Vec<NewEvent<'static>>) -> Box<Future<Item = (), Error = io::Error>> {
+        let mut builder = Builder::transaction_write(self.id);
+
+        for event in events {
+            builder.push_event(event);
+        }
+
+        let package = match builder.try_build_package(self.credentials.clone(), None) {
+            Ok(package) => package,
+            Err(invalid) => return Box::new(future::err(invalid.into())),
+        };
+
+        Box::new(self.service.call(package).and_then(|response| {
+            match adapt(response)? {
+                AdaptedMessage::TransactionWriteCompleted(_, Ok(())) => Ok(()),
+                AdaptedMessage::TransactionWriteCompleted(_, Err(failure)) => Err(io::Error::new(io::ErrorKind::Other, failure)),
+                other => Err(unexpected(other)),
+            }
+        }))
+    }
+
+    /// Commits the transaction, appending the written events to the stream. Completes with the
+    /// event numbers and positions of the appended events like a `WriteEvents`, and fails with
+    /// the `TransactionFailure` of the response, such as `WrongExpectedVersion`.
+    pub fn commit(mut self) -> Box<Future<Item = WriteEventsCompleted, Error = io::Error>> {
+        self.committed = true;
+
+        let package = Builder::transaction_commit(self.id).build_package(self.credentials.take(), None);
+
+        Box::new(self.service.call(package).and_then(|response| {
+            match adapt(response)? {
+                AdaptedMessage::TransactionCommitCompleted(_, Ok(completed)) => Ok(completed),
+                AdaptedMessage::TransactionCommitCompleted(_, Err(failure)) => Err(io::Error::new(io::ErrorKind::Other, failure)),
+                other => Err(unexpected(other)),
+            }
+        }))
+    }
+}
+
+impl<S> Drop for TransactionHandle<S> {
+    fn drop(&mut self) {
+        if !self.committed {
+            debug!("abandoning transaction {} on {}", self.id, self.stream_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use std::convert::TryFrom;
+    use futures::{future, Future};
+    use tokio_service::Service;
+    use adapted::{AdaptedMessage, TransactionFailure, WriteEventsCompleted, ToMessage};
+    use package::Package;
+    use raw::RawMessage;
+    use {ExpectedVersion, StreamVersion, TransactionId};
+    use fixtures::json_event;
+    use super::TransactionHandle;
+
+    /// Answers the transaction requests of one transaction, recording the requests.
+    #[derive(Clone, Default)]
+    struct Transactions {
+        requests: Rc<RefCell<Vec<RawMessage<'static>>>>,
+    }
+
+    impl Service for Transactions {
+        type Request = Package;
+        type Response = Package;
+        type Error = io::Error;
+        type Future = future::FutureResult<Package, io::Error>;
+
+        fn call(&self, req: Package) -> Self::Future {
+            let id = TransactionId::from(7);
+
+            let response = match req.message {
+                RawMessage::TransactionStart(ref start) if start.event_stream_id == "deleted-1" => {
+                    AdaptedMessage::TransactionStartCompleted(Err(TransactionFailure::StreamDeleted))
+                },
+                RawMessage::TransactionStart(_) => AdaptedMessage::TransactionStartCompleted(Ok(id)),
+                RawMessage::TransactionWrite(_) => AdaptedMessage::TransactionWriteCompleted(id, Ok(())),
+                RawMessage::TransactionCommit(_) => {
+                    let written = self.requests.borrow().iter().map(|req| match *req {
+                        RawMessage::TransactionWrite(ref write) => write.events.len() as u32,
+                        _ => 0,
+                    }).sum::<u32>();
+
+                    AdaptedMessage::TransactionCommitCompleted(id, Ok(WriteEventsCompleted {
+                        event_numbers: StreamVersion::try_from(0u32).unwrap()..StreamVersion::try_from(written).unwrap(),
+                        prepare_position: None,
+                        commit_position: None,
+                    }))
+                },
+                ref other => panic!("unexpected request: {:?}", other),
+            };
+
+            let package = response.to_package(req.correlation_id);
+            self.requests.borrow_mut().push(req.message);
+            future::ok(package)
+        }
+    }
+
+    #[test]
+    fn writes_in_batches_and_commits() {
+        let service = Transactions::default();
+
+        let transaction = TransactionHandle::start(service.clone(), "orders-1", ExpectedVersion::NoStream).wait().unwrap();
+        assert_eq!(transaction.id(), TransactionId::from(7));
+
+        transaction.write(vec![json_event("created", "{}"), json_event("paid", "{}")]).wait().unwrap();
+        transaction.write(vec![json_event("shipped", "{}")]).wait().unwrap();
+        transaction.write(vec![]).wait().unwrap();
+        assert_eq!(transaction.write(vec![json_event("", "{}")]).wait().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        let committed = transaction.commit().wait().unwrap();
+        assert_eq!(committed.event_numbers, StreamVersion::try_from(0u32).unwrap()..StreamVersion::try_from(3u32).unwrap());
+
+        let requests = service.requests.borrow();
+        assert_eq!(requests.iter().map(|req| req.discriminator()).collect::<Vec<_>>(), vec![0x84, 0x86, 0x86, 0x86, 0x88]);
+
+        match requests[1] {
+            RawMessage::TransactionWrite(ref write) => assert_eq!(write.transaction_id, 7),
+            ref other => panic!("unexpected request: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dropping_abandons_and_failures_are_errors() {
+        let service = Transactions::default();
+
+        let transaction = TransactionHandle::start(service.clone(), "orders-1", ExpectedVersion::Any).wait().unwrap();
+        transaction.write(vec![json_event("created", "{}")]).wait().unwrap();
+        drop(transaction);
+
+        assert_eq!(service.requests.borrow().iter().map(|req| req.discriminator()).collect::<Vec<_>>(), vec![0x84, 0x86]);
+
+        assert!(TransactionHandle::start(service.clone(), "deleted-1", ExpectedVersion::Any).wait().is_err());
+    }
+
+    #[test]
+    fn writes_and_commit_use_the_credentials_of_the_start() {
+        use server::ScriptedServer;
+        use transport::scripted_client_with;
+        use {CredentialsMap, EventStoreClient, UsernamePassword};
+
+        let admin = UsernamePassword::new("admin", "changeit");
+        let as_admin = |discriminator: u8| {
+            let admin = admin.clone();
+            move |req: &Package| req.message.discriminator() == discriminator && req.authentication.as_ref() == Some(&admin)
+        };
+
+        let id = TransactionId::from(7);
+        let committed = WriteEventsCompleted {
+            event_numbers: StreamVersion::try_from(0u32).unwrap()..StreamVersion::try_from(1u32).unwrap(),
+            prepare_position: None,
+            commit_position: None,
+        };
+
+        let server = ScriptedServer::new()
+            .expect_matching("start as admin", as_admin(0x84))
+                .respond(AdaptedMessage::TransactionStartCompleted(Ok(id)).to_message().into_owned())
+            .expect_matching("write as admin", as_admin(0x86))
+                .respond(AdaptedMessage::TransactionWriteCompleted(id, Ok(())).to_message().into_owned())
+            .expect_matching("commit as admin", as_admin(0x88))
+                .respond(AdaptedMessage::TransactionCommitCompleted(id, Ok(committed)).to_message().into_owned());
+        let script = server.script();
+
+        let (mut core, client) = scripted_client_with(server, |settings| {
+            settings.credentials_provider(CredentialsMap::new(UsernamePassword::new("app", "1")).prefix("$", admin.clone()));
+        });
+
+        let transaction: TransactionHandle<EventStoreClient> = core.run(client.start_transaction("$settings-1", ExpectedVersion::Any)).unwrap();
+        core.run(transaction.write(vec![json_event("changed", "{}")])).unwrap();
+        core.run(transaction.commit()).unwrap();
+
+        script.assert_complete();
+    }
+}
@@ -0,0 +1,26 @@
+use std::fmt;
+
+/// Identifier of a transaction assigned by the server in `TransactionStartCompleted`, for writing
+/// events within the transaction and committing it. The identifier is only meaningful to the node
+/// which started the transaction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct TransactionId(i64);
+
+impl From<i64> for TransactionId {
+    fn from(id: i64) -> Self {
+        TransactionId(id)
+    }
+}
+
+impl From<TransactionId> for i64 {
+    fn from(id: TransactionId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for TransactionId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
@@ -0,0 +1,262 @@
+use std::io;
+use std::cmp;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::collections::VecDeque;
+
+use futures::{future, task, Async, Future, Poll, Stream};
+use futures::sync::mpsc;
+use tokio_core::reactor::Handle;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use ConnectionSettings;
+use super::{AsyncIo, Transport};
+
+#[cfg(test)]
+use tokio_core::reactor::Core;
+#[cfg(test)]
+use server::ScriptedServer;
+#[cfg(test)]
+use {ConnectionSettingsBuilder, EventStoreClient};
+
+/// Bytes written by one end of a `DuplexStream` but not yet read by the other.
+struct Pipe {
+    buf: VecDeque<u8>,
+    /// Task waiting for bytes to read
+    reader: Option<task::Task>,
+    /// No more bytes will be written
+    closed: bool,
+}
+
+impl Pipe {
+    fn new() -> Arc<Mutex<Pipe>> {
+        Arc::new(Mutex::new(Pipe { buf: VecDeque::new(), reader: None, closed: false }))
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(reader) = self.reader.take() {
+            reader.notify();
+        }
+    }
+}
+
+/// One end of an in-memory connection created with `duplex`: the bytes written to one end are
+/// read from the other. Writes always complete immediately, buffering the bytes until read.
+///
+/// Shutting down or dropping an end ends the reading of the other end, and dropping an end fails
+/// the writes of the other.
+pub struct DuplexStream {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+/// Creates the two connected ends of an in-memory connection, for example for connecting a client
+/// to a server of the `server` module in the same process.
+pub fn duplex() -> (DuplexStream, DuplexStream) {
+    let (a, b) = (Pipe::new(), Pipe::new());
+    (DuplexStream { read: a.clone(), write: b.clone() }, DuplexStream { read: b, write: a })
+}
+
+impl io::Read for DuplexStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut pipe = self.read.lock().expect("Pipe poisoned");
+
+        if pipe.buf.is_empty() {
+            if pipe.closed {
+                return Ok(0);
+            }
+
+            pipe.reader = Some(task::current());
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let len = cmp::min(buf.len(), pipe.buf.len());
+        for (dst, src) in buf.iter_mut().zip(pipe.buf.drain(..len)) {
+            *dst = src;
+        }
+
+        Ok(len)
+    }
+}
+
+impl io::Write for DuplexStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pipe = self.write.lock().expect("Pipe poisoned");
+
+        if pipe.closed {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Other end of the duplex stream is closed"));
+        }
+
+        pipe.buf.extend(buf.iter().cloned());
+        if let Some(reader) = pipe.reader.take() {
+            reader.notify();
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for DuplexStream {}
+
+impl AsyncWrite for DuplexStream {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.write.lock().expect("Pipe poisoned").close();
+        Ok(Async::Ready(()))
+    }
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        // a poisoned pipe has lost its other end anyway
+        if let Ok(mut pipe) = self.write.lock() {
+            pipe.close();
+        }
+        if let Ok(mut pipe) = self.read.lock() {
+            pipe.close();
+        }
+    }
+}
+
+/// `Transport` connecting in memory to a `MemoryIncoming`, ignoring the address of the client.
+/// Deterministic and fast, for testing against the servers of the `server` module.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate futures;
+/// extern crate tokio_core;
+/// extern crate tokio_service;
+/// extern crate eventstore_tcp;
+///
+/// use std::io;
+/// use futures::{Future, Stream};
+/// use tokio_core::reactor::Core;
+/// use eventstore_tcp::{Builder, ConnectionSettings, EventStoreClient, MemoryTransport, RawMessage};
+/// use tokio_service::Service;
+/// use eventstore_tcp::server::ScriptedServer;
+///
+/// # fn main() {
+/// let mut core = Core::new().unwrap();
+/// let handle = core.handle();
+///
+/// let (transport, incoming) = MemoryTransport::new();
+/// let server = ScriptedServer::new().expect(0x03).respond(RawMessage::Pong);
+///
+/// let inner = handle.clone();
+/// handle.spawn(incoming.for_each(move |io| -> io::Result<()> {
+///     inner.spawn(server.serve_connection(io, &inner)?.map_err(|e| panic!("{}", e)));
+///     Ok(())
+/// }).map_err(|e| panic!("{}", e)));
+///
+/// let settings = ConnectionSettings::builder().transport(transport).build();
+/// let addr = "127.0.0.1:1113".parse().unwrap();
+/// let client = core.run(EventStoreClient::connect_with_settings(&addr, &handle, settings)).unwrap();
+///
+/// let pong = core.run(client.call(Builder::ping().build_package(None, None))).unwrap();
+/// assert_eq!(pong.message, RawMessage::Pong);
+/// # }
+/// ```
+pub struct MemoryTransport {
+    connections: Mutex<mpsc::UnboundedSender<DuplexStream>>,
+}
+
+impl MemoryTransport {
+    /// Creates the transport and the connections made with it, for the server to accept.
+    pub fn new() -> (MemoryTransport, MemoryIncoming) {
+        let (tx, rx) = mpsc::unbounded();
+        (MemoryTransport { connections: Mutex::new(tx) }, MemoryIncoming { connections: rx })
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn connect(&self, _addr: &SocketAddr, _handle: &Handle, _settings: &ConnectionSettings) -> Box<Future<Item = Box<AsyncIo>, Error = io::Error>> {
+        let (client, server) = duplex();
+
+        match self.connections.lock().expect("Sender poisoned").unbounded_send(server) {
+            Ok(()) => Box::new(future::ok(Box::new(client) as Box<AsyncIo>)),
+            Err(_) => Box::new(future::err(io::Error::new(io::ErrorKind::ConnectionRefused, "Memory connections are no longer accepted"))),
+        }
+    }
+}
+
+/// The server ends of the connections made with a `MemoryTransport`. Connecting fails once this
+/// has been dropped.
+pub struct MemoryIncoming {
+    connections: mpsc::UnboundedReceiver<DuplexStream>,
+}
+
+impl Stream for MemoryIncoming {
+    type Item = DuplexStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<DuplexStream>, io::Error> {
+        // the receiver does not fail
+        Ok(self.connections.poll().unwrap_or(Async::Ready(None)))
+    }
+}
+
+/// Connects a client over a `MemoryTransport` to the scripted server, which serves every
+/// connection the client makes, for testing the client against a script.
+#[cfg(test)]
+pub fn scripted_client(server: ScriptedServer) -> (Core, EventStoreClient) {
+    scripted_client_with(server, |_| {})
+}
+
+/// Like `scripted_client`, with the settings of the connection changed by `f` first.
+#[cfg(test)]
+pub fn scripted_client_with<F>(server: ScriptedServer, f: F) -> (Core, EventStoreClient)
+    where F: FnOnce(&mut ConnectionSettingsBuilder)
+{
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+
+    let (transport, incoming) = MemoryTransport::new();
+
+    let inner = handle.clone();
+    handle.spawn(incoming.for_each(move |io| -> io::Result<()> {
+        inner.spawn(server.serve_connection(io, &inner)?.map_err(|e| panic!("{}", e)));
+        Ok(())
+    }).map_err(|e| panic!("{}", e)));
+
+    let mut settings = ConnectionSettings::builder();
+    f(&mut settings);
+    let settings = settings.transport(transport).build();
+
+    let client = core.run(EventStoreClient::connect_with_settings(&"127.0.0.1:1113".parse().unwrap(), &handle, settings)).unwrap();
+    (core, client)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+    use futures::{future, Future};
+    use tokio_io::AsyncWrite;
+    use super::duplex;
+
+    #[test]
+    fn bytes_cross_over() {
+        // reading parks the current task
+        future::lazy(|| -> io::Result<()> {
+            let (mut a, mut b) = duplex();
+            let mut buf = [0u8; 8];
+
+            assert_eq!(b.read(&mut buf).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+            a.write_all(b"ping").unwrap();
+            assert_eq!(b.read(&mut buf).unwrap(), 4);
+            assert_eq!(&buf[..4], b"ping");
+
+            a.shutdown().unwrap();
+            assert_eq!(b.read(&mut buf).unwrap(), 0);
+
+            drop(a);
+            assert_eq!(b.write(b"pong").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+            Ok(())
+        }).wait().unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+//! The byte streams the connections of `EventStoreClient` are made over.
+
+use std::io;
+use std::net::SocketAddr;
+
+use futures::Future;
+use tokio_core::reactor::Handle;
+use tokio_core::net::TcpStream;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use tls;
+use ConnectionSettings;
+
+mod memory;
+pub use self::memory::{DuplexStream, duplex, MemoryTransport, MemoryIncoming};
+#[cfg(test)]
+pub use self::memory::{scripted_client, scripted_client_with};
+
+#[cfg(all(unix, feature = "unix"))]
+mod unix;
+#[cfg(all(unix, feature = "unix"))]
+pub use self::unix::UnixTransport;
+
+/// The plain or encrypted stream of a connection.
+pub trait AsyncIo: AsyncRead + AsyncWrite {}
+
+impl<T: AsyncRead + AsyncWrite> AsyncIo for T {}
+
+/// Opens the byte streams the connections are made over, given to
+/// `ConnectionSettingsBuilder::transport`. The framing, heartbeats, correlation of the responses
+/// and reconnecting are all done by the connection on top of the stream, so a transport only needs
+/// to connect; it is asked again for a new stream whenever the connection is lost.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate futures;
+/// extern crate tokio_core;
+/// extern crate eventstore_tcp;
+///
+/// use std::io;
+/// use std::net::SocketAddr;
+/// use futures::Future;
+/// use tokio_core::reactor::Handle;
+/// use eventstore_tcp::{AsyncIo, ConnectionSettings, TcpTransport, Transport};
+///
+/// /// Connects to the address given by the client with the port moved up by one.
+/// struct NextPort;
+///
+/// impl Transport for NextPort {
+///     fn connect(&self, addr: &SocketAddr, handle: &Handle, settings: &ConnectionSettings) -> Box<Future<Item = Box<AsyncIo>, Error = io::Error>> {
+///         let mut addr = *addr;
+///         let port = addr.port() + 1;
+///         addr.set_port(port);
+///         TcpTransport.connect(&addr, handle, settings)
+///     }
+/// }
+///
+/// # fn main() {
+/// let settings = ConnectionSettings::builder()
+///     .transport(NextPort)
+///     .build();
+/// # }
+/// ```
+pub trait Transport {
+    /// Opens a stream to the server at `addr`, configured with `settings`.
+    fn connect(&self, addr: &SocketAddr, handle: &Handle, settings: &ConnectionSettings) -> Box<Future<Item = Box<AsyncIo>, Error = io::Error>>;
+}
+
+/// The default `Transport`: TCP connections with the socket options of the settings, encrypted
+/// if the settings have `ConnectionSettings::tls`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TcpTransport;
+
+impl Transport for TcpTransport {
+    fn connect(&self, addr: &SocketAddr, handle: &Handle, settings: &ConnectionSettings) -> Box<Future<Item = Box<AsyncIo>, Error = io::Error>> {
+        let (nodelay, send_buffer, recv_buffer, keepalive) =
+            (settings.tcp_nodelay(), settings.send_buffer_size(), settings.recv_buffer_size(), settings.tcp_keepalive());
+
+        let tcp: Box<Future<Item = TcpStream, Error = io::Error>> = Box::new(TcpStream::connect(addr, handle).and_then(move |stream| {
+            stream.set_nodelay(nodelay)?;
+            if let Some(size) = send_buffer {
+                stream.set_send_buffer_size(size)?;
+            }
+            if let Some(size) = recv_buffer {
+                stream.set_recv_buffer_size(size)?;
+            }
+            stream.set_keepalive(keepalive)?;
+            Ok(stream)
+        }));
+
+        match settings.tls() {
+            Some(tls_settings) => tls::connect(tcp, tls_settings.clone()),
+            None => Box::new(tcp.map(|stream| Box::new(stream) as Box<AsyncIo>)),
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use futures::{future, Future};
+use tokio_core::reactor::Handle;
+use tokio_uds::UnixStream;
+
+use ConnectionSettings;
+use super::{AsyncIo, Transport};
+
+/// `Transport` connecting to a Unix domain socket at a path instead of the address of the client,
+/// for example a server of the `server` module bound with `tokio_uds::UnixListener` in tests or
+/// a proxy on the same host. The socket and TLS settings are not used. Available with the `unix`
+/// feature on Unix platforms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnixTransport {
+    path: PathBuf,
+}
+
+impl UnixTransport {
+    /// Creates a transport connecting to the socket at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        UnixTransport { path: path.into() }
+    }
+}
+
+impl Transport for UnixTransport {
+    fn connect(&self, _addr: &SocketAddr, handle: &Handle, _settings: &ConnectionSettings) -> Box<Future<Item = Box<AsyncIo>, Error = io::Error>> {
+        Box::new(future::result(UnixStream::connect(&self.path, handle).map(|stream| Box::new(stream) as Box<AsyncIo>)))
+    }
+}
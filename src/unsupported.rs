@@ -0,0 +1,14 @@
+//! Encoding the messages which do not yet have a variant of their own in `RawMessage`.
+
+use std::borrow::Cow;
+
+use quick_protobuf::{MessageWrite, Writer};
+
+use raw::RawMessage;
+
+/// Encodes the message as `RawMessage::Unsupported` of the discriminator.
+pub fn encoded<M: MessageWrite>(discriminator: u8, message: &M) -> RawMessage<'static> {
+    let mut buf = Vec::with_capacity(message.get_size());
+    message.write_message(&mut Writer::new(&mut buf)).expect("Encoding into a Vec failed");
+    RawMessage::Unsupported(discriminator, Cow::Owned(buf))
+}
@@ -0,0 +1,189 @@
+//! Checks of the writes done before they are sent.
+
+use std::io;
+use std::fmt;
+use std::error::Error;
+
+use uuid::Uuid;
+
+use raw::client_messages::NewEvent;
+use operation;
+
+/// The largest event accepted by the server, counting the data and the metadata.
+pub const MAX_EVENT_SIZE: usize = 16 * 1024 * 1024;
+
+/// A mistake in a write found before sending it, see `WriteEventsBuilder::validate`. The server
+/// would answer most of these with a `BadRequest` without any details.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Violation {
+    /// The stream to write to was not set or was empty
+    MissingStreamId,
+    /// The event type of the event at the index is empty
+    EmptyEventType(usize),
+    /// The event id of the event at the index is not 16 bytes or is the nil UUID
+    InvalidEventId(usize),
+    /// The data of the event at the index is claimed to be JSON but is not
+    InvalidJsonData(usize),
+    /// The metadata of the event at the index is claimed to be JSON but is not
+    InvalidJsonMetadata(usize),
+    /// The data and metadata of the event at the index are over `MAX_EVENT_SIZE` bytes
+    TooLarge(usize, usize),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use self::Violation::*;
+        match *self {
+            MissingStreamId => write!(fmt, "stream id is missing"),
+            EmptyEventType(i) => write!(fmt, "event {} has an empty event type", i),
+            InvalidEventId(i) => write!(fmt, "event {} has an invalid event id", i),
+            InvalidJsonData(i) => write!(fmt, "event {} has data which is not JSON", i),
+            InvalidJsonMetadata(i) => write!(fmt, "event {} has metadata which is not JSON", i),
+            TooLarge(i, size) => write!(fmt, "event {} is {} bytes, over {}", i, size, MAX_EVENT_SIZE),
+        }
+    }
+}
+
+/// Every `Violation` found in a write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidWrite {
+    /// The violations in the order of the events
+    pub violations: Vec<Violation>,
+}
+
+impl InvalidWrite {
+    /// Returns the `InvalidWrite` wrapped by the given error, if any.
+    pub fn find(e: &io::Error) -> Option<&InvalidWrite> {
+        operation::find::<InvalidWrite>(e)
+    }
+}
+
+impl fmt::Display for InvalidWrite {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: ", self.description())?;
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                write!(fmt, ", ")?;
+            }
+            write!(fmt, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for InvalidWrite {
+    fn description(&self) -> &str {
+        "Invalid write"
+    }
+}
+
+impl From<InvalidWrite> for io::Error {
+    fn from(invalid: InvalidWrite) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, invalid)
+    }
+}
+
+/// Collects the violations of a write of `events` to the stream, if it has one. A write without
+/// events is valid: the server creates the stream or only checks the expected version.
+pub fn check(stream_id: Option<&str>, events: &[NewEvent]) -> Result<(), InvalidWrite> {
+    let mut violations = Vec::new();
+
+    if let Some(stream_id) = stream_id {
+        if stream_id.is_empty() {
+            violations.push(Violation::MissingStreamId);
+        }
+    }
+
+    for (i, event) in events.iter().enumerate() {
+        if event.event_type.is_empty() {
+            violations.push(Violation::EmptyEventType(i));
+        }
+
+        match Uuid::from_bytes(&event.event_id) {
+            Ok(id) if id != Uuid::nil() => {},
+            _ => violations.push(Violation::InvalidEventId(i)),
+        }
+
+        if event.data_content_type == 1 && !is_json(&event.data) {
+            violations.push(Violation::InvalidJsonData(i));
+        }
+
+        if let Some(ref metadata) = event.metadata {
+            if event.metadata_content_type == 1 && !metadata.is_empty() && !is_json(metadata) {
+                violations.push(Violation::InvalidJsonMetadata(i));
+            }
+        }
+
+        let size = event.data.len() + event.metadata.as_ref().map(|m| m.len()).unwrap_or(0);
+        if size > MAX_EVENT_SIZE {
+            violations.push(Violation::TooLarge(i, size));
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(InvalidWrite { violations: violations })
+    }
+}
+
+/// Parses the payload when `serde_json` is available through the `json` or `cloudevents`
+/// features, otherwise only checks that it is non-empty UTF-8.
+#[cfg(any(feature = "json", feature = "cloudevents"))]
+fn is_json(payload: &[u8]) -> bool {
+    use serde_json;
+    serde_json::from_slice::<serde_json::Value>(payload).is_ok()
+}
+
+#[cfg(not(any(feature = "json", feature = "cloudevents")))]
+fn is_json(payload: &[u8]) -> bool {
+    use std::str;
+    str::from_utf8(payload).map(|s| !s.trim().is_empty()).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use builder::Builder;
+    use {ContentType, TransactionId};
+    use super::Violation;
+
+    #[test]
+    fn all_violations_are_returned() {
+        let mut builder = Builder::write_events();
+        builder.stream_id("")
+            .new_event()
+                .event_type("")
+                .data(vec![0xff, 0xfe])
+                .data_content_type(ContentType::Json)
+            .done()
+            .new_event()
+                .event_type("fine")
+                .data(vec![0u8; super::MAX_EVENT_SIZE + 1])
+            .done();
+
+        let invalid = builder.validate().unwrap_err();
+        assert_eq!(invalid.violations, vec![
+            Violation::MissingStreamId,
+            Violation::EmptyEventType(0),
+            Violation::InvalidJsonData(0),
+            Violation::TooLarge(1, super::MAX_EVENT_SIZE + 1),
+        ]);
+        assert!(invalid.to_string().starts_with("Invalid write: stream id is missing, event 0 has"));
+    }
+
+    #[test]
+    fn valid_writes_are_built() {
+        let package = Builder::write_events()
+            .stream_id("orders-1")
+            .new_event()
+                .event_type("created")
+                .data("{}")
+                .data_content_type(ContentType::Json)
+            .done()
+            .try_build_package(None, None);
+
+        assert!(package.is_ok());
+        assert!(Builder::transaction_write(TransactionId::from(7)).validate().is_ok());
+        assert!(Builder::write_events().stream_id("orders-1").validate().is_ok());
+    }
+}